@@ -6,6 +6,7 @@
 
 use index;
 use codec;
+use types::{Encoding, EncodingRef, DecodeStrict, EncodeStrict, NFC};
 
 macro_rules! unique(
     ($(#[$attr:meta])* var=$var:ident, mod=$($module:ident)::+, val=$val:ident) => (
@@ -17,74 +18,236 @@ macro_rules! unique(
 )
 
 macro_rules! singlebyte(
-    ($(#[$attr:meta])* var=$var:ident, mod=$($module:ident)::+, name=$name:expr) => (
-        singlebyte!($(#[$attr])* var=$var, mod=$($module)::+, name=$name, whatwg=None)
+    ($(#[$attr:meta])* var=$var:ident, mod=$($module:ident)::+, name=$name:expr,
+                       scripts=$scripts:expr) => (
+        singlebyte!($(#[$attr])* var=$var, mod=$($module)::+, name=$name, whatwg=None,
+                    scripts=$scripts, c1=false)
     );
-    ($(#[$attr:meta])* var=$var:ident, mod=$($module:ident)::+, name|whatwg=$name:expr) => (
-        singlebyte!($(#[$attr])* var=$var, mod=$($module)::+, name=$name, whatwg=Some($name))
+    ($(#[$attr:meta])* var=$var:ident, mod=$($module:ident)::+, name|whatwg=$name:expr,
+                       scripts=$scripts:expr) => (
+        singlebyte!($(#[$attr])* var=$var, mod=$($module)::+, name=$name, whatwg=Some($name),
+                    scripts=$scripts, c1=false)
+    );
+    ($(#[$attr:meta])* var=$var:ident, mod=$($module:ident)::+, name=$name:expr,
+                       scripts=$scripts:expr, c1=$c1:expr) => (
+        singlebyte!($(#[$attr])* var=$var, mod=$($module)::+, name=$name, whatwg=None,
+                    scripts=$scripts, c1=$c1)
+    );
+    ($(#[$attr:meta])* var=$var:ident, mod=$($module:ident)::+, name|whatwg=$name:expr,
+                       scripts=$scripts:expr, c1=$c1:expr) => (
+        singlebyte!($(#[$attr])* var=$var, mod=$($module)::+, name=$name, whatwg=Some($name),
+                    scripts=$scripts, c1=$c1)
     );
     ($(#[$attr:meta])* var=$var:ident, mod=$($module:ident)::+,
-                       name=$name:expr, whatwg=$whatwg:expr) => (
+                       name=$name:expr, whatwg=$whatwg:expr, scripts=$scripts:expr,
+                       c1=$c1:expr) => (
+        singlebyte!($(#[$attr])* var=$var, mod=$($module)::+, name=$name, whatwg=$whatwg,
+                    scripts=$scripts, c1=$c1, normalization=Some(NFC))
+    );
+    ($(#[$attr:meta])* var=$var:ident, mod=$($module:ident)::+,
+                       name=$name:expr, whatwg=$whatwg:expr, scripts=$scripts:expr,
+                       c1=$c1:expr, normalization=$norm:expr) => (
         $(#[$attr])* pub static $var: &'static codec::singlebyte::SingleByteEncoding =
             &codec::singlebyte::SingleByteEncoding {
                 name: $name,
                 whatwg_name: $whatwg,
                 index_forward: $($module)::+::forward,
                 index_backward: $($module)::+::backward,
+                latin1_fallback: false,
+                c1_passthrough: $c1,
+                scripts: &$scripts,
+                normalization: $norm,
             };
     )
 )
 
 unique!(#[stable] var=ERROR, mod=codec::error, val=ErrorEncoding)
 unique!(#[stable] var=ASCII, mod=codec::ascii, val=ASCIIEncoding)
-singlebyte!(#[stable] var=IBM866, mod=index::ibm866, name|whatwg="ibm866")
-singlebyte!(#[stable] var=ISO_8859_1, mod=codec::singlebyte::iso_8859_1, name="iso-8859-1")
-singlebyte!(#[stable] var=ISO_8859_2, mod=index::iso_8859_2, name|whatwg="iso-8859-2")
-singlebyte!(#[stable] var=ISO_8859_3, mod=index::iso_8859_3, name|whatwg="iso-8859-3")
-singlebyte!(#[stable] var=ISO_8859_4, mod=index::iso_8859_4, name|whatwg="iso-8859-4")
-singlebyte!(#[stable] var=ISO_8859_5, mod=index::iso_8859_5, name|whatwg="iso-8859-5")
-singlebyte!(#[stable] var=ISO_8859_6, mod=index::iso_8859_6, name|whatwg="iso-8859-6")
-singlebyte!(#[stable] var=ISO_8859_7, mod=index::iso_8859_7, name|whatwg="iso-8859-7")
-singlebyte!(#[stable] var=ISO_8859_8, mod=index::iso_8859_8, name|whatwg="iso-8859-8")
-singlebyte!(#[stable] var=ISO_8859_10, mod=index::iso_8859_10, name|whatwg="iso-8859-10")
-singlebyte!(#[stable] var=ISO_8859_13, mod=index::iso_8859_13, name|whatwg="iso-8859-13")
-singlebyte!(#[stable] var=ISO_8859_14, mod=index::iso_8859_14, name|whatwg="iso-8859-14")
-singlebyte!(#[stable] var=ISO_8859_15, mod=index::iso_8859_15, name|whatwg="iso-8859-15")
-singlebyte!(#[stable] var=ISO_8859_16, mod=index::iso_8859_16, name|whatwg="iso-8859-16")
-singlebyte!(#[stable] var=KOI8_R, mod=index::koi8_r, name|whatwg="koi8-r")
-singlebyte!(#[stable] var=KOI8_U, mod=index::koi8_u, name|whatwg="koi8-u")
+singlebyte!(#[stable] var=IBM866, mod=index::ibm866, name|whatwg="ibm866",
+                      scripts=["Latin", "Cyrillic"])
+singlebyte!(#[stable] var=ISO_8859_1, mod=codec::singlebyte::iso_8859_1, name="iso-8859-1",
+                      scripts=["Latin"], c1=true)
+singlebyte!(#[stable] var=ISO_8859_2, mod=index::iso_8859_2, name|whatwg="iso-8859-2",
+                      scripts=["Latin"], c1=true)
+singlebyte!(#[stable] var=ISO_8859_3, mod=index::iso_8859_3, name|whatwg="iso-8859-3",
+                      scripts=["Latin"], c1=true)
+singlebyte!(#[stable] var=ISO_8859_4, mod=index::iso_8859_4, name|whatwg="iso-8859-4",
+                      scripts=["Latin"], c1=true)
+singlebyte!(#[stable] var=ISO_8859_5, mod=index::iso_8859_5, name|whatwg="iso-8859-5",
+                      scripts=["Latin", "Cyrillic"], c1=true)
+singlebyte!(#[stable] var=ISO_8859_6, mod=index::iso_8859_6, name|whatwg="iso-8859-6",
+                      scripts=["Latin", "Arabic"], c1=true)
+singlebyte!(#[stable] var=ISO_8859_7, mod=index::iso_8859_7, name|whatwg="iso-8859-7",
+                      scripts=["Latin", "Greek"], c1=true)
+singlebyte!(#[stable] var=ISO_8859_8, mod=index::iso_8859_8, name|whatwg="iso-8859-8",
+                      scripts=["Latin", "Hebrew"], c1=true)
+singlebyte!(#[stable] var=ISO_8859_10, mod=index::iso_8859_10, name|whatwg="iso-8859-10",
+                      scripts=["Latin"], c1=true)
+singlebyte!(#[stable] var=ISO_8859_13, mod=index::iso_8859_13, name|whatwg="iso-8859-13",
+                      scripts=["Latin"], c1=true)
+singlebyte!(#[stable] var=ISO_8859_14, mod=index::iso_8859_14, name|whatwg="iso-8859-14",
+                      scripts=["Latin"], c1=true)
+singlebyte!(#[stable] var=ISO_8859_15, mod=index::iso_8859_15, name|whatwg="iso-8859-15",
+                      scripts=["Latin"], c1=true)
+singlebyte!(#[stable] var=ISO_8859_16, mod=index::iso_8859_16, name|whatwg="iso-8859-16",
+                      scripts=["Latin"], c1=true)
+singlebyte!(#[stable] var=KOI8_R, mod=index::koi8_r, name|whatwg="koi8-r",
+                      scripts=["Latin", "Cyrillic"])
+singlebyte!(#[stable] var=KOI8_U, mod=index::koi8_u, name|whatwg="koi8-u",
+                      scripts=["Latin", "Cyrillic"])
+singlebyte!(#[unstable] var=KOI8_RU, mod=index::koi8_ru, name="koi8-ru",
+                        scripts=["Latin", "Cyrillic"])
 singlebyte!(#[stable] var=MAC_ROMAN, mod=index::macintosh,
-                      name="mac-roman", whatwg=Some("macintosh"))
-singlebyte!(#[stable] var=WINDOWS_874, mod=index::windows_874, name|whatwg="windows-874")
-singlebyte!(#[stable] var=WINDOWS_1250, mod=index::windows_1250, name|whatwg="windows-1250")
-singlebyte!(#[stable] var=WINDOWS_1251, mod=index::windows_1251, name|whatwg="windows-1251")
-singlebyte!(#[stable] var=WINDOWS_1252, mod=index::windows_1252, name|whatwg="windows-1252")
-singlebyte!(#[stable] var=WINDOWS_1253, mod=index::windows_1253, name|whatwg="windows-1253")
-singlebyte!(#[stable] var=WINDOWS_1254, mod=index::windows_1254, name|whatwg="windows-1254")
-singlebyte!(#[stable] var=WINDOWS_1255, mod=index::windows_1255, name|whatwg="windows-1255")
-singlebyte!(#[stable] var=WINDOWS_1256, mod=index::windows_1256, name|whatwg="windows-1256")
-singlebyte!(#[stable] var=WINDOWS_1257, mod=index::windows_1257, name|whatwg="windows-1257")
-singlebyte!(#[stable] var=WINDOWS_1258, mod=index::windows_1258, name|whatwg="windows-1258")
+                      name="mac-roman", whatwg=Some("macintosh"),
+                      scripts=["Latin"])
+singlebyte!(#[unstable] var=MIK, mod=index::mik, name="mik",
+                        scripts=["Latin", "Cyrillic"])
+singlebyte!(#[stable] var=WINDOWS_874, mod=index::windows_874, name|whatwg="windows-874",
+                      scripts=["Latin", "Thai"])
+singlebyte!(#[stable] var=WINDOWS_1250, mod=index::windows_1250, name|whatwg="windows-1250",
+                      scripts=["Latin"])
+singlebyte!(#[stable] var=WINDOWS_1251, mod=index::windows_1251, name|whatwg="windows-1251",
+                      scripts=["Latin", "Cyrillic"])
+singlebyte!(#[stable] var=WINDOWS_1252, mod=index::windows_1252, name|whatwg="windows-1252",
+                      scripts=["Latin"])
+singlebyte!(#[stable] var=WINDOWS_1253, mod=index::windows_1253, name|whatwg="windows-1253",
+                      scripts=["Latin", "Greek"])
+singlebyte!(#[stable] var=WINDOWS_1254, mod=index::windows_1254, name|whatwg="windows-1254",
+                      scripts=["Latin"])
+singlebyte!(#[stable] var=WINDOWS_1255, mod=index::windows_1255, name|whatwg="windows-1255",
+                      scripts=["Latin", "Hebrew"])
+singlebyte!(#[stable] var=WINDOWS_1256, mod=index::windows_1256, name|whatwg="windows-1256",
+                      scripts=["Latin", "Arabic"])
+singlebyte!(#[stable] var=WINDOWS_1257, mod=index::windows_1257, name|whatwg="windows-1257",
+                      scripts=["Latin"])
+// windows-1258 has combining marks for Vietnamese tones, and only some of the letters
+// with tones are precomposed, so neither NFC nor NFD works in general.
+singlebyte!(#[stable] var=WINDOWS_1258, mod=index::windows_1258,
+                      name="windows-1258", whatwg=Some("windows-1258"),
+                      scripts=["Latin"], c1=false, normalization=None)
 singlebyte!(#[stable] var=MAC_CYRILLIC, mod=index::x_mac_cyrillic,
-                      name="mac-cyrillic", whatwg=Some("x-mac-cyrillic"))
+                      name="mac-cyrillic", whatwg=Some("x-mac-cyrillic"),
+                      scripts=["Latin", "Cyrillic"])
+unique!(#[experimental] var=ISO_6937, mod=codec::iso_6937, val=ISO6937Encoding)
 unique!(#[stable] var=UTF_8, mod=codec::utf_8, val=UTF8Encoding)
 unique!(#[stable] var=UTF_16LE, mod=codec::utf_16, ty=UTF16LEEncoding, val=UTF16Encoding)
 unique!(#[stable] var=UTF_16BE, mod=codec::utf_16, ty=UTF16BEEncoding, val=UTF16Encoding)
 unique!(#[stable] var=WINDOWS_949, mod=codec::korean, val=Windows949Encoding)
-unique!(#[unstable] var=EUC_JP, mod=codec::japanese, val=EUCJPEncoding)
+#[unstable] pub static EUC_JP: &'static codec::japanese::EUCJPEncoding =
+    &codec::japanese::EUCJPEncoding { jis_roman: false, fullwidth_katakana: false };
+unique!(#[unstable] var=CP51932, mod=codec::japanese, val=CP51932Encoding)
 unique!(#[unstable] var=WINDOWS_31J, mod=codec::japanese, val=Windows31JEncoding)
-unique!(#[unstable] var=ISO_2022_JP, mod=codec::japanese, val=ISO2022JPEncoding)
+#[unstable] pub static ISO_2022_JP: &'static codec::japanese::ISO2022JPEncoding =
+    &codec::japanese::ISO2022JPEncoding { reject_katakana: false };
+unique!(#[experimental] var=ISO_2022_JP_2, mod=codec::japanese, val=ISO2022JP2Encoding)
+unique!(#[experimental] var=JIS_X_0201, mod=codec::japanese, val=JISX0201Encoding)
+unique!(#[experimental] var=MAC_JAPANESE, mod=codec::japanese, val=XMacJapaneseEncoding)
 unique!(#[stable] var=GB18030, mod=codec::simpchinese, val=GB18030Encoding)
 unique!(#[unstable] var=HZ, mod=codec::simpchinese, val=HZEncoding)
 unique!(#[unstable] var=BIG5_2003, mod=codec::tradchinese, val=BigFive2003Encoding)
+unique!(#[experimental] var=BIG5_HKSCS, mod=codec::tradchinese, val=BigFiveHKSCSEncoding)
+unique!(#[experimental] var=CESU_8, mod=codec::cesu_8, val=CESU8Encoding)
+unique!(#[experimental] var=PUNYCODE, mod=codec::punycode, val=PunycodeEncoding)
+unique!(#[experimental] var=SCSU, mod=codec::scsu, val=SCSUEncoding)
 
 pub mod whatwg {
     use codec;
     use index;
 
     singlebyte!(#[stable] var=X_USER_DEFINED, mod=codec::whatwg::x_user_defined,
-                          name="pua-mapped-binary", whatwg=Some("x-user-defined"))
-    singlebyte!(#[stable] var=ISO_8859_8_I, mod=index::iso_8859_8, name|whatwg="iso-8859-8-i")
+                          name="pua-mapped-binary", whatwg=Some("x-user-defined"),
+                          scripts=["Latin"])
+    singlebyte!(#[stable] var=ISO_8859_8_I, mod=index::iso_8859_8, name|whatwg="iso-8859-8-i",
+                          scripts=["Latin", "Hebrew"], c1=true)
     unique!(#[stable] var=REPLACEMENT, mod=codec::whatwg, val=EncoderOnlyUTF8Encoding)
 }
 
+/// Returns a list of all encodings in this module, including those in the `whatwg` module.
+#[experimental]
+pub fn encodings() -> Vec<EncodingRef> {
+    vec!(
+        ERROR as EncodingRef,
+        ASCII as EncodingRef,
+        IBM866 as EncodingRef,
+        ISO_8859_1 as EncodingRef,
+        ISO_8859_2 as EncodingRef,
+        ISO_8859_3 as EncodingRef,
+        ISO_8859_4 as EncodingRef,
+        ISO_8859_5 as EncodingRef,
+        ISO_8859_6 as EncodingRef,
+        ISO_8859_7 as EncodingRef,
+        ISO_8859_8 as EncodingRef,
+        ISO_8859_10 as EncodingRef,
+        ISO_8859_13 as EncodingRef,
+        ISO_8859_14 as EncodingRef,
+        ISO_8859_15 as EncodingRef,
+        ISO_8859_16 as EncodingRef,
+        KOI8_R as EncodingRef,
+        KOI8_U as EncodingRef,
+        KOI8_RU as EncodingRef,
+        MAC_ROMAN as EncodingRef,
+        MIK as EncodingRef,
+        WINDOWS_874 as EncodingRef,
+        WINDOWS_1250 as EncodingRef,
+        WINDOWS_1251 as EncodingRef,
+        WINDOWS_1252 as EncodingRef,
+        WINDOWS_1253 as EncodingRef,
+        WINDOWS_1254 as EncodingRef,
+        WINDOWS_1255 as EncodingRef,
+        WINDOWS_1256 as EncodingRef,
+        WINDOWS_1257 as EncodingRef,
+        WINDOWS_1258 as EncodingRef,
+        MAC_CYRILLIC as EncodingRef,
+        ISO_6937 as EncodingRef,
+        UTF_8 as EncodingRef,
+        UTF_16LE as EncodingRef,
+        UTF_16BE as EncodingRef,
+        WINDOWS_949 as EncodingRef,
+        EUC_JP as EncodingRef,
+        CP51932 as EncodingRef,
+        WINDOWS_31J as EncodingRef,
+        ISO_2022_JP as EncodingRef,
+        ISO_2022_JP_2 as EncodingRef,
+        JIS_X_0201 as EncodingRef,
+        MAC_JAPANESE as EncodingRef,
+        GB18030 as EncodingRef,
+        HZ as EncodingRef,
+        BIG5_2003 as EncodingRef,
+        BIG5_HKSCS as EncodingRef,
+        CESU_8 as EncodingRef,
+        PUNYCODE as EncodingRef,
+        SCSU as EncodingRef,
+        whatwg::X_USER_DEFINED as EncodingRef,
+        whatwg::ISO_8859_8_I as EncodingRef,
+        whatwg::REPLACEMENT as EncodingRef
+    )
+}
+
+/// Checks that every single-byte encoding in `encodings()` maps each byte back to itself,
+/// i.e. that decoding a byte and re-encoding the result gives the original byte.
+/// Returns an error naming the first encoding whose table is not invertible.
+///
+/// This is a diagnostic for hand-edited or regenerated tables and walks every byte of
+/// every encoding, so it should not be called in the normal decoding path.
+/// Multi-byte indices contain intentional duplicates and are checked by their own tests.
+#[experimental]
+pub fn verify_all_tables() -> Result<(), String> {
+    for &encoding in encodings().iter() {
+        if !encoding.is_single_byte() || !encoding.can_decode_direction() { continue; }
+        for i in range(0u, 0x100) {
+            let byte = i as u8;
+            let decoded = match encoding.decode([byte], DecodeStrict) {
+                Ok(decoded) => decoded,
+                Err(_) => continue, // unmapped bytes are fine
+            };
+            match encoding.encode(decoded.as_slice(), EncodeStrict) {
+                Ok(ref encoded) if encoded.as_slice() == [byte].as_slice() => {}
+                _ => {
+                    return Err(format!("inconsistent table in {} at byte 0x{:02X}",
+                                       encoding.name(), byte));
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,194 @@
+// This is a part of rust-encoding.
+// Copyright (c) 2014, Kang Seonghoon.
+// See README.md and LICENSE.txt for details.
+
+//! Decoding and encoding benchmarks across codecs.
+//!
+//! Each encoding is decoded from (or encoded to) three representative buffers:
+//! an ASCII-only text, a text native to the encoding and a mix of both.
+//! They serve as a baseline for optimizations to the `raw_feed` loops.
+
+use test;
+use types::*;
+
+/// Some Western European text to test single-byte encodings.
+//
+// the first sentence of the article "Langue française" from French Wikipedia.
+static FRENCH_TEXT: &'static str =
+    "Le français est une langue indo-européenne de la famille des langues romanes. \
+     Le français s'est formé en France (variété de la « langue d'oïl », \
+     qui est la langue de la partie septentrionale du pays).";
+
+/// Encodes given text and measures the throughput of decoding it back.
+fn bench_decode(bencher: &mut test::Bencher, encoding: EncodingRef, text: &str) {
+    let input = encoding.encode(text, EncodeStrict).ok().expect("cannot encode the bench data");
+    bencher.bytes = input.len() as u64;
+    bencher.iter(|| test::black_box({
+        encoding.decode(input.as_slice(), DecodeStrict)
+    }))
+}
+
+/// Makes three decoding benchmarks for given encoding and its native text.
+macro_rules! decode_benches(
+    ($name:ident, $encoding:expr, $native:expr) => (
+        mod $name {
+            use test;
+            use testutils;
+            use types::{EncodingRef, DecodeStrict};
+            use all;
+
+            #[bench]
+            fn bench_ascii(bencher: &mut test::Bencher) {
+                super::bench_decode(bencher, $encoding as EncodingRef, testutils::ASCII_TEXT)
+            }
+
+            #[bench]
+            fn bench_ascii_decode_to(bencher: &mut test::Bencher) {
+                // same to `bench_ascii` but always runs the decoder
+                let encoding = $encoding as EncodingRef;
+                let input = testutils::ASCII_TEXT.as_bytes();
+                bencher.bytes = input.len() as u64;
+                bencher.iter(|| test::black_box({
+                    let mut ret = String::new();
+                    encoding.decode_to(input, DecodeStrict, &mut ret).map(|_| ret)
+                }))
+            }
+
+            #[bench]
+            fn bench_native(bencher: &mut test::Bencher) {
+                super::bench_decode(bencher, $encoding as EncodingRef, $native)
+            }
+
+            #[bench]
+            fn bench_mixed(bencher: &mut test::Bencher) {
+                let text = [testutils::ASCII_TEXT, $native,
+                            testutils::ASCII_TEXT, $native].concat();
+                super::bench_decode(bencher, $encoding as EncodingRef, text.as_slice())
+            }
+        }
+    )
+)
+
+decode_benches!(windows_1252, all::WINDOWS_1252, super::FRENCH_TEXT)
+decode_benches!(iso_8859_1, all::ISO_8859_1, super::FRENCH_TEXT)
+decode_benches!(utf_8, all::UTF_8, testutils::JAPANESE_TEXT)
+decode_benches!(utf_16le, all::UTF_16LE, testutils::JAPANESE_TEXT)
+decode_benches!(euc_jp, all::EUC_JP, testutils::JAPANESE_TEXT)
+decode_benches!(windows_31j, all::WINDOWS_31J, testutils::JAPANESE_TEXT)
+decode_benches!(iso_2022_jp, all::ISO_2022_JP, testutils::JAPANESE_TEXT)
+decode_benches!(windows_949, all::WINDOWS_949, testutils::KOREAN_TEXT)
+decode_benches!(gb18030, all::GB18030, testutils::SIMPLIFIED_CHINESE_TEXT)
+decode_benches!(big5_2003, all::BIG5_2003, testutils::TRADITIONAL_CHINESE_TEXT)
+
+/// Measures the throughput of encoding given text.
+fn bench_encode(bencher: &mut test::Bencher, encoding: EncodingRef, text: &str) {
+    bencher.bytes = text.len() as u64;
+    bencher.iter(|| test::black_box({
+        encoding.encode(text, EncodeStrict)
+    }))
+}
+
+/// Makes three encoding benchmarks for given encoding and its native text.
+macro_rules! encode_benches(
+    ($name:ident, $encoding:expr, $native:expr) => (
+        mod $name {
+            use test;
+            use testutils;
+            use types::EncodingRef;
+            use all;
+
+            #[bench]
+            fn bench_ascii(bencher: &mut test::Bencher) {
+                super::bench_encode(bencher, $encoding as EncodingRef, testutils::ASCII_TEXT)
+            }
+
+            #[bench]
+            fn bench_native(bencher: &mut test::Bencher) {
+                super::bench_encode(bencher, $encoding as EncodingRef, $native)
+            }
+
+            #[bench]
+            fn bench_mixed(bencher: &mut test::Bencher) {
+                let text = [testutils::ASCII_TEXT, $native,
+                            testutils::ASCII_TEXT, $native].concat();
+                super::bench_encode(bencher, $encoding as EncodingRef, text.as_slice())
+            }
+        }
+    )
+)
+
+encode_benches!(encode_windows_1252, all::WINDOWS_1252, super::FRENCH_TEXT)
+encode_benches!(encode_iso_8859_1, all::ISO_8859_1, super::FRENCH_TEXT)
+encode_benches!(encode_iso_8859_15, all::ISO_8859_15, super::FRENCH_TEXT)
+encode_benches!(encode_euc_jp, all::EUC_JP, testutils::JAPANESE_TEXT)
+encode_benches!(encode_windows_31j, all::WINDOWS_31J, testutils::JAPANESE_TEXT)
+encode_benches!(encode_iso_2022_jp, all::ISO_2022_JP, testutils::JAPANESE_TEXT)
+
+/// Compares `Encoding::validate` against `Encoding::is_valid` with a discarding writer.
+macro_rules! validate_benches(
+    ($name:ident, $encoding:expr, $native:expr) => (
+        mod $name {
+            use test;
+            use testutils;
+            use types::*;
+            use all;
+
+            #[bench]
+            fn bench_validate(bencher: &mut test::Bencher) {
+                let input = $encoding.encode($native, EncodeStrict).ok().unwrap();
+                bencher.bytes = input.len() as u64;
+                bencher.iter(|| test::black_box({
+                    $encoding.validate(input.as_slice()).is_ok()
+                }))
+            }
+
+            #[bench]
+            fn bench_is_valid(bencher: &mut test::Bencher) {
+                let input = $encoding.encode($native, EncodeStrict).ok().unwrap();
+                bencher.bytes = input.len() as u64;
+                bencher.iter(|| test::black_box({
+                    $encoding.is_valid(input.as_slice())
+                }))
+            }
+        }
+    )
+)
+
+validate_benches!(validate_windows_1252, all::WINDOWS_1252, super::FRENCH_TEXT)
+validate_benches!(validate_windows_31j, all::WINDOWS_31J, testutils::JAPANESE_TEXT)
+validate_benches!(validate_euc_jp, all::EUC_JP, testutils::JAPANESE_TEXT)
+
+/// Compares `Encoding::decode_to_chars` against decoding to a string and collecting its chars.
+macro_rules! decode_to_chars_benches(
+    ($name:ident, $encoding:expr, $native:expr) => (
+        mod $name {
+            use test;
+            use testutils;
+            use types::*;
+            use all;
+
+            #[bench]
+            fn bench_decode_to_chars(bencher: &mut test::Bencher) {
+                let input = $encoding.encode($native, EncodeStrict).ok().unwrap();
+                bencher.bytes = input.len() as u64;
+                bencher.iter(|| test::black_box({
+                    $encoding.decode_to_chars(input.as_slice(), DecodeStrict)
+                }))
+            }
+
+            #[bench]
+            fn bench_decode_then_collect(bencher: &mut test::Bencher) {
+                let input = $encoding.encode($native, EncodeStrict).ok().unwrap();
+                bencher.bytes = input.len() as u64;
+                bencher.iter(|| test::black_box({
+                    $encoding.decode(input.as_slice(), DecodeStrict)
+                             .map(|s| s.as_slice().chars().collect::<Vec<char>>())
+                }))
+            }
+        }
+    )
+)
+
+decode_to_chars_benches!(decode_to_chars_windows_1252, all::WINDOWS_1252, super::FRENCH_TEXT)
+decode_to_chars_benches!(decode_to_chars_utf_8, all::UTF_8, testutils::JAPANESE_TEXT)
+decode_to_chars_benches!(decode_to_chars_euc_jp, all::EUC_JP, testutils::JAPANESE_TEXT)
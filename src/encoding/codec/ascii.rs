@@ -16,10 +16,23 @@ use types::*;
 #[deriving(Clone)]
 pub struct ASCIIEncoding;
 
+static ASCII_SCRIPTS: &'static [&'static str] = &["Latin"];
+
 impl Encoding for ASCIIEncoding {
     fn name(&self) -> &'static str { "ascii" }
+    fn covered_scripts(&self) -> &'static [&'static str] { ASCII_SCRIPTS }
+    fn is_single_byte(&self) -> bool { true }
     fn encoder(&self) -> Box<Encoder> { ASCIIEncoder::new() }
     fn decoder(&self) -> Box<Decoder> { ASCIIDecoder::new() }
+
+    fn validate(&self, input: &[u8]) -> Result<uint, CodecError> {
+        match input.iter().position(|&ch| ch >= 0x80) {
+            Some(i) => Err(CodecError {
+                upto: i as int + 1, cause: "invalid sequence".into_maybe_owned(), buffered: 0
+            }),
+            None => Ok(input.len()),
+        }
+    }
 }
 
 /// An encoder for ASCII.
@@ -32,6 +45,7 @@ impl ASCIIEncoder {
 
 impl Encoder for ASCIIEncoder {
     fn from_self(&self) -> Box<Encoder> { ASCIIEncoder::new() }
+    fn clone_box(&self) -> Box<Encoder> { box self.clone() as Box<Encoder> }
     fn is_ascii_compatible(&self) -> bool { true }
 
     fn raw_feed(&mut self, input: &str, output: &mut ByteWriter) -> (uint, Option<CodecError>) {
@@ -42,7 +56,8 @@ impl Encoder for ASCIIEncoder {
                 output.write_bytes(input.as_bytes().slice_to(first_error));
                 let str::CharRange {ch: _, next} = input.char_range_at(first_error);
                 (first_error, Some(CodecError {
-                    upto: next as int, cause: "unrepresentable character".into_maybe_owned()
+                    upto: next as int, cause: "unrepresentable character".into_maybe_owned(),
+                    buffered: 0
                 }))
             }
             None => {
@@ -67,6 +82,7 @@ impl ASCIIDecoder {
 
 impl Decoder for ASCIIDecoder {
     fn from_self(&self) -> Box<Decoder> { ASCIIDecoder::new() }
+    fn clone_box(&self) -> Box<Decoder> { box self.clone() as Box<Decoder> }
     fn is_ascii_compatible(&self) -> bool { true }
 
     fn raw_feed(&mut self, input: &[u8], output: &mut StringWriter) -> (uint, Option<CodecError>) {
@@ -80,7 +96,8 @@ impl Decoder for ASCIIDecoder {
             Some(first_error) => {
                 write_ascii_bytes(output, input.slice_to(first_error));
                 (first_error, Some(CodecError {
-                    upto: first_error as int + 1, cause: "invalid sequence".into_maybe_owned()
+                    upto: first_error as int + 1, cause: "invalid sequence".into_maybe_owned(),
+                    buffered: 0
                 }))
             }
             None => {
@@ -0,0 +1,226 @@
+// This is a part of rust-encoding.
+// Copyright (c) 2014, Kang Seonghoon.
+// See README.md and LICENSE.txt for details.
+
+//! CESU-8, a variant of UTF-8 with surrogate pairs.
+
+use util::UNICODE_SCRIPTS;
+use types::*;
+
+/**
+ * CESU-8 (Compatibility Encoding Scheme for UTF-16: 8-Bit, Unicode Technical Report #26).
+ *
+ * This is same to UTF-8 for characters up to U+FFFF,
+ * but a character beyond U+FFFF is encoded as a UTF-16 surrogate pair
+ * where each surrogate is encoded as a three-byte sequence (six bytes in total).
+ * It is occasionally produced by Java and Oracle databases.
+ *
+ * The decoder combines a pair of high and low surrogate sequences into one character,
+ * and rejects a four-byte UTF-8 sequence or an unpaired surrogate sequence.
+ */
+#[deriving(Clone)]
+pub struct CESU8Encoding;
+
+impl Encoding for CESU8Encoding {
+    fn name(&self) -> &'static str { "cesu-8" }
+    fn covered_scripts(&self) -> &'static [&'static str] { UNICODE_SCRIPTS }
+    fn encoder(&self) -> Box<Encoder> { CESU8Encoder::new() }
+    fn decoder(&self) -> Box<Decoder> { CESU8Decoder::new() }
+}
+
+/// An encoder for CESU-8.
+#[deriving(Clone)]
+pub struct CESU8Encoder;
+
+impl CESU8Encoder {
+    pub fn new() -> Box<Encoder> { box CESU8Encoder as Box<Encoder> }
+}
+
+impl Encoder for CESU8Encoder {
+    fn from_self(&self) -> Box<Encoder> { CESU8Encoder::new() }
+    fn clone_box(&self) -> Box<Encoder> { box self.clone() as Box<Encoder> }
+    fn is_ascii_compatible(&self) -> bool { true }
+
+    fn raw_feed(&mut self, input: &str, output: &mut ByteWriter) -> (uint, Option<CodecError>) {
+        output.writer_hint(input.len());
+
+        fn write_three_bytes(output: &mut ByteWriter, unit: u32) {
+            output.write_byte((0xe0 | (unit >> 12)) as u8);
+            output.write_byte((0x80 | ((unit >> 6) & 0x3f)) as u8);
+            output.write_byte((0x80 | (unit & 0x3f)) as u8);
+        }
+
+        let mut start = 0;
+        for (i, ch) in input.char_indices() {
+            let ch = ch as u32;
+            if ch < 0x10000 { continue; }
+            output.write_bytes(input.slice(start, i).as_bytes());
+            let ch = ch - 0x10000;
+            write_three_bytes(output, 0xd800 | (ch >> 10));
+            write_three_bytes(output, 0xdc00 | (ch & 0x3ff));
+            start = i + 4;
+        }
+        // characters up to U+FFFF are encoded as in UTF-8
+        output.write_bytes(input.slice_from(start).as_bytes());
+        (input.len(), None)
+    }
+
+    fn raw_finish(&mut self, _output: &mut ByteWriter) -> Option<CodecError> {
+        None
+    }
+}
+
+ascii_compatible_stateful_decoder! {
+    #[doc="A decoder for CESU-8."]
+    #[deriving(Clone)]
+    struct CESU8Decoder;
+
+    module cesu8;
+
+    internal pub fn three_bytes(lead: u8, second: u8, third: u8) -> u32 {
+        ((lead as u32 & 0x0f) << 12) | ((second as u32 & 0x3f) << 6) | (third as u32 & 0x3f)
+    }
+
+    initial state S0(ctx) {
+        case b @ 0x00..0x7f => ctx.emit(b as u32);
+        case b @ 0xc2..0xdf => S1(ctx, b);
+        case b @ 0xe0..0xef => S2(ctx, b);
+        case _ => ctx.err("invalid sequence");
+    }
+
+    // the first byte of two-byte sequence
+    state S1(ctx, lead: u8) {
+        case b @ 0x80..0xbf => ctx.emit(((lead as u32 & 0x1f) << 6) | (b as u32 & 0x3f));
+        case _ => ctx.backup_and_err(1, "invalid sequence");
+    }
+
+    // the first byte of three-byte sequence
+    state S2(ctx, lead: u8) {
+        case b @ 0x80..0xbf => if lead == 0xe0 && b < 0xa0 {
+            ctx.backup_and_err(1, "invalid sequence") // overlong
+        } else {
+            S3(ctx, lead, b)
+        };
+        case _ => ctx.backup_and_err(1, "invalid sequence");
+    }
+
+    // the first two bytes of three-byte sequence
+    state S3(ctx, lead: u8, second: u8) {
+        case b @ 0x80..0xbf => match three_bytes(lead, second, b) {
+            hi @ 0xd800..0xdbff => S4(ctx, hi as u16),
+            0xdc00..0xdfff => ctx.err("unpaired low surrogate"),
+            ch => ctx.emit(ch)
+        };
+        case _ => ctx.backup_and_err(1, "invalid sequence");
+    }
+
+    // the high surrogate sequence
+    state S4(ctx, hi: u16) {
+        case 0xed => S5(ctx, hi);
+        case _ => ctx.backup_and_err(1, "unpaired high surrogate");
+    }
+
+    // the high surrogate sequence and the first byte of the low surrogate sequence
+    state S5(ctx, hi: u16) {
+        case b @ 0xb0..0xbf => S6(ctx, hi, b);
+        case _ => ctx.backup_and_err(2, "unpaired high surrogate");
+    }
+
+    // the high surrogate sequence and the first two bytes of the low surrogate sequence
+    state S6(ctx, hi: u16, second: u8) {
+        case b @ 0x80..0xbf => {
+            let lo = three_bytes(0xed, second, b);
+            ctx.emit(0x10000 + ((hi as u32 - 0xd800) << 10) + (lo - 0xdc00))
+        };
+        case _ => ctx.backup_and_err(3, "unpaired high surrogate");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CESU8Encoding;
+    use testutils;
+    use types::*;
+
+    #[test]
+    fn test_encoder_valid() {
+        let mut e = CESU8Encoding.encoder();
+        assert_feed_ok!(e, "A", "", [0x41]);
+        assert_feed_ok!(e, "BC", "", [0x42, 0x43]);
+        assert_feed_ok!(e, "", "", []);
+        assert_feed_ok!(e, "\u00e9\u6f22", "", [0xc3, 0xa9, 0xe6, 0xbc, 0xa2]);
+        assert_feed_ok!(e, "\U0001f600", "", [0xed, 0xa0, 0xbd, 0xed, 0xb8, 0x80]);
+        assert_feed_ok!(e, "a\U00010000b\U0010ffffc", "",
+                        [0x61, 0xed, 0xa0, 0x80, 0xed, 0xb0, 0x80, 0x62,
+                         0xed, 0xaf, 0xbf, 0xed, 0xbf, 0xbf, 0x63]);
+        assert_finish_ok!(e, []);
+    }
+
+    #[test]
+    fn test_decoder_valid() {
+        let mut d = CESU8Encoding.decoder();
+        assert_feed_ok!(d, [0x41], [], "A");
+        assert_feed_ok!(d, [0x42, 0x43], [], "BC");
+        assert_feed_ok!(d, [], [], "");
+        assert_feed_ok!(d, [0xc3, 0xa9, 0xe6, 0xbc, 0xa2], [], "\u00e9\u6f22");
+        assert_feed_ok!(d, [0xed, 0xa0, 0xbd, 0xed, 0xb8, 0x80], [], "\U0001f600");
+        assert_feed_ok!(d, [0x61, 0xed, 0xa0, 0x80, 0xed, 0xb0, 0x80, 0x62,
+                            0xed, 0xaf, 0xbf, 0xed, 0xbf, 0xbf, 0x63], [],
+                        "a\U00010000b\U0010ffffc");
+        assert_finish_ok!(d, "");
+    }
+
+    #[test]
+    fn test_decoder_valid_partial() {
+        let mut d = CESU8Encoding.decoder();
+        assert_feed_ok!(d, [], [0xed], "");
+        assert_feed_ok!(d, [], [0xa0, 0xbd], "");
+        assert_feed_ok!(d, [], [0xed], "");
+        assert_feed_ok!(d, [], [0xb8], "");
+        assert_feed_ok!(d, [0x80], [], "\U0001f600");
+        assert_feed_ok!(d, [0xe6, 0xbc, 0xa2], [0xed, 0xa0], "\u6f22");
+        assert_feed_ok!(d, [0xbd, 0xed, 0xb8, 0x80], [], "\U0001f600");
+        assert_finish_ok!(d, "");
+    }
+
+    #[test]
+    fn test_decoder_invalid() {
+        // four-byte UTF-8 sequences are not allowed
+        let mut d = CESU8Encoding.decoder();
+        assert_feed_err!(d, [], [0xf0], [0x9f, 0x98, 0x80], "");
+        assert_finish_ok!(d, "");
+
+        // overlong sequences
+        let mut d = CESU8Encoding.decoder();
+        assert_feed_err!(d, [], [0xc0], [0x80], "");
+        assert_feed_err!(d, [], [0xe0], [0x80, 0x80], "");
+        assert_finish_ok!(d, "");
+
+        // unpaired surrogates
+        let mut d = CESU8Encoding.decoder();
+        assert_feed_err!(d, [], [0xed, 0xb8, 0x80], [0x41], "");
+        assert_feed_err!(d, [0x41], [0xed, 0xa0, 0xbd], [0x42], "A");
+        assert_feed_err!(d, [], [0xed, 0xa0, 0xbd], [0xed, 0xa0, 0xbd], "");
+        assert_feed_err!(d, [], [0xed, 0xa0, 0xbd], [0xed, 0x9f, 0xbf], "");
+        assert_finish_ok!(d, "");
+    }
+
+    #[test]
+    fn test_decoder_invalid_partial() {
+        let mut d = CESU8Encoding.decoder();
+        assert_feed_ok!(d, [], [0xed, 0xa0, 0xbd], "");
+        assert_finish_err!(d, "");
+
+        let mut d = CESU8Encoding.decoder();
+        assert_feed_ok!(d, [], [0xed, 0xa0, 0xbd, 0xed, 0xb8], "");
+        assert_finish_err!(d, "");
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let s = "caf\u00e9 \u65e5\u672c \U0001f600\U00020000";
+        let encoded = CESU8Encoding.encode(s, EncodeStrict).unwrap();
+        assert_eq!(encoded.len(), s.len() + 4);
+        assert_eq!(CESU8Encoding.decode(encoded.as_slice(), DecodeStrict), Ok(s.to_string()));
+    }
+}
@@ -13,6 +13,8 @@ pub struct ErrorEncoding;
 
 impl Encoding for ErrorEncoding {
     fn name(&self) -> &'static str { "error" }
+    fn can_encode_direction(&self) -> bool { false }
+    fn can_decode_direction(&self) -> bool { false }
     fn encoder(&self) -> Box<Encoder> { ErrorEncoder::new() }
     fn decoder(&self) -> Box<Decoder> { ErrorDecoder::new() }
 }
@@ -27,12 +29,14 @@ impl ErrorEncoder {
 
 impl Encoder for ErrorEncoder {
     fn from_self(&self) -> Box<Encoder> { ErrorEncoder::new() }
+    fn clone_box(&self) -> Box<Encoder> { box self.clone() as Box<Encoder> }
 
     fn raw_feed(&mut self, input: &str, _output: &mut ByteWriter) -> (uint, Option<CodecError>) {
         if input.len() > 0 {
             let str::CharRange {ch: _, next} = input.char_range_at(0);
             (0, Some(CodecError { upto: next as int,
-                                  cause: "unrepresentable character".into_maybe_owned() }))
+                                  cause: "unrepresentable character".into_maybe_owned(),
+                                  buffered: 0 }))
         } else {
             (0, None)
         }
@@ -53,10 +57,12 @@ impl ErrorDecoder {
 
 impl Decoder for ErrorDecoder {
     fn from_self(&self) -> Box<Decoder> { ErrorDecoder::new() }
+    fn clone_box(&self) -> Box<Decoder> { box self.clone() as Box<Decoder> }
 
     fn raw_feed(&mut self, input: &[u8], _output: &mut StringWriter) -> (uint, Option<CodecError>) {
         if input.len() > 0 {
-            (0, Some(CodecError { upto: 1, cause: "invalid sequence".into_maybe_owned() }))
+            (0, Some(CodecError { upto: 1, cause: "invalid sequence".into_maybe_owned(),
+                                  buffered: 0 }))
         } else {
             (0, None)
         }
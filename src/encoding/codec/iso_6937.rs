@@ -0,0 +1,335 @@
+// This is a part of rust-encoding.
+// Copyright (c) 2014, Kang Seonghoon.
+// See README.md and LICENSE.txt for details.
+
+//! ISO/IEC 6937, a Latin encoding with non-spacing diacritics.
+
+use util::StrCharIndex;
+use types::*;
+
+static ISO_6937_SCRIPTS: &'static [&'static str] = &["Latin"];
+
+/// A non-spacing diacritical mark, which precedes the base letter.
+struct Diacritic {
+    /// The spacing form of the diacritic, which is encoded as the diacritic followed by a space.
+    spacing: char,
+    /// Base letters which can be combined with the diacritic.
+    bases: &'static str,
+    /// Composed characters in the same order as `bases`.
+    composed: &'static str,
+}
+
+/// Diacritics for bytes `C1` through `CF`. `C9` and `CC` are not assigned.
+static DIACRITICS: [Option<Diacritic>, ..15] = [
+    // C1: grave accent
+    Some(Diacritic { spacing: '`', bases: "AEIOUaeiou",
+                     composed: "\u00c0\u00c8\u00cc\u00d2\u00d9\u00e0\u00e8\u00ec\u00f2\u00f9" }),
+    // C2: acute accent; the small g with cedilla is written with the acute accent
+    Some(Diacritic { spacing: '\u00b4', bases: "ACEILNORSUYZacegilnorsuyz",
+                     composed: "\u00c1\u0106\u00c9\u00cd\u0139\u0143\u00d3\u0154\u015a\u00da\
+                                \u00dd\u0179\u00e1\u0107\u00e9\u0123\u00ed\u013a\u0144\u00f3\
+                                \u0155\u015b\u00fa\u00fd\u017a" }),
+    // C3: circumflex accent
+    Some(Diacritic { spacing: '^', bases: "ACEGHIJOSUWYaceghijosuwy",
+                     composed: "\u00c2\u0108\u00ca\u011c\u0124\u00ce\u0134\u00d4\u015c\u00db\
+                                \u0174\u0176\u00e2\u0109\u00ea\u011d\u0125\u00ee\u0135\u00f4\
+                                \u015d\u00fb\u0175\u0177" }),
+    // C4: tilde
+    Some(Diacritic { spacing: '~', bases: "AINOUainou",
+                     composed: "\u00c3\u0128\u00d1\u00d5\u0168\u00e3\u0129\u00f1\u00f5\u0169" }),
+    // C5: macron
+    Some(Diacritic { spacing: '\u00af', bases: "AEIOUaeiou",
+                     composed: "\u0100\u0112\u012a\u014c\u016a\u0101\u0113\u012b\u014d\u016b" }),
+    // C6: breve
+    Some(Diacritic { spacing: '\u02d8', bases: "AGUagu",
+                     composed: "\u0102\u011e\u016c\u0103\u011f\u016d" }),
+    // C7: dot above
+    Some(Diacritic { spacing: '\u02d9', bases: "CEGIZcegz",
+                     composed: "\u010a\u0116\u0120\u0130\u017b\u010b\u0117\u0121\u017c" }),
+    // C8: diaeresis
+    Some(Diacritic { spacing: '\u00a8', bases: "AEIOUYaeiouy",
+                     composed: "\u00c4\u00cb\u00cf\u00d6\u00dc\u0178\u00e4\u00eb\u00ef\u00f6\
+                                \u00fc\u00ff" }),
+    // C9: not assigned
+    None,
+    // CA: ring above
+    Some(Diacritic { spacing: '\u02da', bases: "AUau",
+                     composed: "\u00c5\u016e\u00e5\u016f" }),
+    // CB: cedilla
+    Some(Diacritic { spacing: '\u00b8', bases: "CGKLNRSTcklnrst",
+                     composed: "\u00c7\u0122\u0136\u013b\u0145\u0156\u015e\u0162\u00e7\u0137\
+                                \u013c\u0146\u0157\u015f\u0163" }),
+    // CC: not assigned
+    None,
+    // CD: double acute accent
+    Some(Diacritic { spacing: '\u02dd', bases: "OUou",
+                     composed: "\u0150\u0170\u0151\u0171" }),
+    // CE: ogonek
+    Some(Diacritic { spacing: '\u02db', bases: "AEIUaeiu",
+                     composed: "\u0104\u0118\u012e\u0172\u0105\u0119\u012f\u0173" }),
+    // CF: caron
+    Some(Diacritic { spacing: '\u02c7', bases: "CDELNRSTZcdelnrstz",
+                     composed: "\u010c\u010e\u011a\u013d\u0147\u0158\u0160\u0164\u017d\u010d\
+                                \u010f\u011b\u013e\u0148\u0159\u0161\u0165\u017e" }),
+];
+
+/// Characters for bytes `A0` through `FF`, except for diacritics.
+/// `0xffff` denotes an unassigned byte or a diacritic.
+static FORWARD_TABLE: [u16, ..96] = [
+    0x00a0, 0x00a1, 0x00a2, 0x00a3, 0xffff, 0x00a5, 0xffff, 0x00a7, // A0
+    0x00a4, 0x2018, 0x201c, 0x00ab, 0x2190, 0x2191, 0x2192, 0x2193, // A8
+    0x00b0, 0x00b1, 0x00b2, 0x00b3, 0x00d7, 0x00b5, 0x00b6, 0x00b7, // B0
+    0x00f7, 0x2019, 0x201d, 0x00bb, 0x00bc, 0x00bd, 0x00be, 0x00bf, // B8
+    0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0xffff, // C0
+    0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0xffff, // C8
+    0x2015, 0x00b9, 0x00ae, 0x00a9, 0x2122, 0x266a, 0x00ac, 0x00a6, // D0
+    0xffff, 0xffff, 0xffff, 0xffff, 0x215b, 0x215c, 0x215d, 0x215e, // D8
+    0x2126, 0x00c6, 0x0110, 0x00aa, 0x0126, 0xffff, 0x0132, 0x013f, // E0
+    0x0141, 0x00d8, 0x0152, 0x00ba, 0x00de, 0x0166, 0x014a, 0x0149, // E8
+    0x0138, 0x00e6, 0x0111, 0x00f0, 0x0127, 0x0131, 0x0133, 0x0140, // F0
+    0x0142, 0x00f8, 0x0153, 0x00df, 0x00fe, 0x0167, 0x014b, 0x00ad, // F8
+];
+
+/// Returns the diacritic for given byte, if any.
+fn diacritic(b: u8) -> Option<&'static Diacritic> {
+    if b >= 0xc1 && b <= 0xcf { DIACRITICS[(b - 0xc1) as uint].as_ref() } else { None }
+}
+
+/// Returns the character for given single byte, or `0xffff` if the byte is not assigned
+/// or is a diacritic. Bytes `00` through `9F` map to ASCII and C1 controls.
+fn forward(b: u8) -> u32 {
+    if b < 0xa0 { b as u32 } else { FORWARD_TABLE[(b - 0xa0) as uint] as u32 }
+}
+
+/// Returns the character for given diacritic followed by given byte,
+/// or `0xffff` if the pair does not form a character.
+/// The diacritic followed by a space is the spacing form of the diacritic.
+fn compose(diacritic_byte: u8, base: u8) -> u32 {
+    match diacritic(diacritic_byte) {
+        Some(d) if base == 0x20 => d.spacing as u32,
+        Some(d) => match d.bases.as_bytes().iter().position(|&b| b == base) {
+            Some(i) => d.composed.chars().nth(i).unwrap() as u32,
+            None => 0xffff,
+        },
+        None => 0xffff,
+    }
+}
+
+/// Returns one or two bytes for given character, or `None` if it is not representable.
+/// The second byte is 0 for a single byte.
+fn backward(ch: char) -> Option<(u8, u8)> {
+    if ch <= '\u009f' { return Some((ch as u8, 0)); }
+    match FORWARD_TABLE.iter().position(|&c| c != 0xffff && c as u32 == ch as u32) {
+        Some(i) => return Some((0xa0 + i as u8, 0)),
+        None => {}
+    }
+    for (i, d) in DIACRITICS.iter().enumerate() {
+        let d = match *d { Some(ref d) => d, None => continue };
+        let lead = 0xc1 + i as u8;
+        if d.spacing == ch { return Some((lead, 0x20)); }
+        match d.composed.chars().position(|c| c == ch) {
+            Some(j) => return Some((lead, d.bases.as_bytes()[j])),
+            None => {}
+        }
+    }
+    None
+}
+
+/**
+ * ISO/IEC 6937, also known as the basis of ITU-T T.61 (Teletex) and used by
+ * X.509 `TeletexString` and the DVB and teletext standards.
+ *
+ * This is an extension to ASCII where bytes `C1` through `CF` are non-spacing diacritics
+ * which combine with the following base letter into a single character
+ * (e.g. `C2 65` is U+00E9), or form a spacing diacritic when followed by a space.
+ * Other bytes in `A0` through `FF` are single characters as in ISO 8859,
+ * and `80` through `9F` are C1 controls.
+ *
+ * The encoder emits precomposed characters only, so the input should be normalized to NFC.
+ */
+#[deriving(Clone)]
+pub struct ISO6937Encoding;
+
+impl Encoding for ISO6937Encoding {
+    fn name(&self) -> &'static str { "iso-6937" }
+    fn covered_scripts(&self) -> &'static [&'static str] { ISO_6937_SCRIPTS }
+    fn assumes_normalization(&self) -> Option<NormalizationForm> { Some(NFC) }
+    fn encoder(&self) -> Box<Encoder> { ISO6937Encoder::new() }
+    fn decoder(&self) -> Box<Decoder> { ISO6937Decoder::new() }
+}
+
+/// An encoder for ISO/IEC 6937.
+#[deriving(Clone)]
+pub struct ISO6937Encoder;
+
+impl ISO6937Encoder {
+    pub fn new() -> Box<Encoder> { box ISO6937Encoder as Box<Encoder> }
+}
+
+impl Encoder for ISO6937Encoder {
+    fn from_self(&self) -> Box<Encoder> { ISO6937Encoder::new() }
+    fn clone_box(&self) -> Box<Encoder> { box self.clone() as Box<Encoder> }
+    fn is_ascii_compatible(&self) -> bool { true }
+
+    fn raw_feed(&mut self, input: &str, output: &mut ByteWriter) -> (uint, Option<CodecError>) {
+        output.writer_hint(input.len());
+
+        for ((i,j), ch) in input.index_iter() {
+            match backward(ch) {
+                Some((b, 0)) => output.write_byte(b),
+                Some((lead, trail)) => output.write_bytes([lead, trail]),
+                None => {
+                    return (i, Some(CodecError {
+                        upto: j as int, cause: "unrepresentable character".into_maybe_owned(),
+                        buffered: 0
+                    }));
+                }
+            }
+        }
+        (input.len(), None)
+    }
+
+    fn raw_finish(&mut self, _output: &mut ByteWriter) -> Option<CodecError> {
+        None
+    }
+}
+
+ascii_compatible_stateful_decoder! {
+    #[doc="A decoder for ISO/IEC 6937."]
+    #[deriving(Clone)]
+    struct ISO6937Decoder;
+
+    module iso6937;
+
+    internal pub fn map_one_byte(b: u8) -> u32 {
+        ::codec::iso_6937::forward(b)
+    }
+
+    internal pub fn map_two_bytes(diacritic: u8, base: u8) -> u32 {
+        ::codec::iso_6937::compose(diacritic, base)
+    }
+
+    initial state S0(ctx) {
+        case b @ 0xc1..0xc8 | b @ 0xca..0xcb | b @ 0xcd..0xcf => S1(ctx, b);
+        case b => match map_one_byte(b) {
+            0xffff => ctx.err("invalid sequence"),
+            ch => ctx.emit(ch)
+        };
+    }
+
+    // the pending diacritic
+    state S1(ctx, diacritic: u8) {
+        case b => match map_two_bytes(diacritic, b) {
+            0xffff => ctx.backup_and_err(1, "invalid sequence"),
+            ch => ctx.emit(ch)
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ISO6937Encoding;
+    use types::*;
+
+    #[test]
+    fn test_encoder_valid() {
+        let mut e = ISO6937Encoding.encoder();
+        assert_feed_ok!(e, "A", "", [0x41]);
+        assert_feed_ok!(e, "BC", "", [0x42, 0x43]);
+        assert_feed_ok!(e, "", "", []);
+        assert_feed_ok!(e, "caf\u00e9", "", [0x63, 0x61, 0x66, 0xc2, 0x65]);
+        assert_feed_ok!(e, "\u0141\u00f3d\u017a", "", [0xe8, 0xc2, 0x6f, 0x64, 0xc2, 0x7a]);
+        assert_feed_ok!(e, "\u00a3\u00a4\u2126\u00ad", "", [0xa3, 0xa8, 0xe0, 0xff]);
+        assert_feed_ok!(e, "\u00b4\u02c7^", "", [0xc2, 0x20, 0xcf, 0x20, 0x5e]);
+        assert_feed_ok!(e, "\u0085", "", [0x85]);
+        assert_finish_ok!(e, []);
+    }
+
+    #[test]
+    fn test_encoder_invalid() {
+        let mut e = ISO6937Encoding.encoder();
+        assert_feed_err!(e, "A", "\u20ac", "B", [0x41]);
+        assert_feed_err!(e, "", "\u01f5", "", []); // g with acute accent
+        assert_feed_err!(e, "", "\u0301", "", []); // combining characters are not supported
+        assert_finish_ok!(e, []);
+    }
+
+    #[test]
+    fn test_decoder_valid() {
+        let mut d = ISO6937Encoding.decoder();
+        assert_feed_ok!(d, [0x41], [], "A");
+        assert_feed_ok!(d, [0x42, 0x43], [], "BC");
+        assert_feed_ok!(d, [], [], "");
+        assert_feed_ok!(d, [0xc2, 0x65], [], "\u00e9");
+        assert_feed_ok!(d, [0xe8, 0xc2, 0x6f, 0x64, 0xc2, 0x7a], [], "\u0141\u00f3d\u017a");
+        assert_feed_ok!(d, [0xa3, 0xa8, 0xe0, 0xff], [], "\u00a3\u00a4\u2126\u00ad");
+        assert_feed_ok!(d, [0xc2, 0x20, 0xcf, 0x20, 0xc3, 0x20], [], "\u00b4\u02c7^");
+        assert_feed_ok!(d, [0xc8, 0x59, 0xc2, 0x67, 0xcb, 0x47], [], "\u0178\u0123\u0122");
+        assert_feed_ok!(d, [0x85], [], "\u0085");
+        assert_finish_ok!(d, "");
+    }
+
+    #[test]
+    fn test_decoder_valid_partial() {
+        let mut d = ISO6937Encoding.decoder();
+        assert_feed_ok!(d, [], [0xc2], "");
+        assert_feed_ok!(d, [0x65], [], "\u00e9");
+        assert_feed_ok!(d, [0x41], [0xcf], "A");
+        assert_feed_ok!(d, [0x73, 0xc1, 0x61], [0xc8], "\u0161\u00e0");
+        assert_feed_ok!(d, [0x20], [], "\u00a8");
+        assert_finish_ok!(d, "");
+    }
+
+    #[test]
+    fn test_decoder_invalid() {
+        let mut d = ISO6937Encoding.decoder();
+        assert_feed_err!(d, [0x41], [0xa4], [0x42], "A");
+        assert_feed_err!(d, [], [0xc0], [], "");
+        assert_feed_err!(d, [], [0xc9], [0x61], "");
+        assert_feed_err!(d, [0x61], [0xcc], [0x61], "a");
+        assert_feed_err!(d, [], [0xd8], [], "");
+        assert_finish_ok!(d, "");
+
+        // the diacritic followed by a byte which cannot be combined
+        let mut d = ISO6937Encoding.decoder();
+        assert_feed_err!(d, [], [0xc2], [0x31], "");
+        assert_feed_ok!(d, [0x31], [], "1");
+        assert_feed_err!(d, [], [0xc2], [0xc2, 0x65], "");
+        assert_feed_ok!(d, [0xc2, 0x65], [], "\u00e9");
+        assert_feed_ok!(d, [], [0xca], "");
+        assert_feed_err!(d, [], [], [0x65], "");
+        assert_finish_ok!(d, "");
+    }
+
+    #[test]
+    fn test_decoder_invalid_partial() {
+        // the lone diacritic at the end of input
+        let mut d = ISO6937Encoding.decoder();
+        assert_feed_ok!(d, [0x41], [0xc2], "A");
+        assert_finish_err!(d, "");
+
+        let mut d = ISO6937Encoding.decoder();
+        assert_feed_ok!(d, [], [0xcf], "");
+        assert_feed_ok!(d, [], [], "");
+        assert_finish_err!(d, "");
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        for lead in range(0u, 0x100) {
+            for trail in range(0u, 0x100) {
+                let input = [lead as u8, trail as u8];
+                let decoded = match ISO6937Encoding.decode(input, DecodeStrict) {
+                    Ok(decoded) => decoded,
+                    Err(_) => continue,
+                };
+                // the spacing grave accent, circumflex accent and tilde are encoded as ASCII
+                let encoded = ISO6937Encoding.encode(decoded.as_slice(), EncodeStrict).unwrap();
+                match (lead as u8, trail as u8) {
+                    (0xc1, 0x20) | (0xc3, 0x20) | (0xc4, 0x20) => {}
+                    _ => assert_eq!(encoded, input.to_vec()),
+                }
+            }
+        }
+    }
+}
@@ -4,10 +4,55 @@
 
 //! Legacy Japanese encodings based on JIS X 0208 and JIS X 0212.
 
-use util::StrCharIndex;
+use util::{as_char, StrCharIndex, buffered_char_cost};
 use index;
 use types::*;
 
+/// Scripts covered by JIS X 0201 and JIS X 0208, reported by `covered_scripts`.
+static JAPANESE_SCRIPTS: &'static [&'static str] =
+    &["Latin", "Greek", "Cyrillic", "Hiragana", "Katakana", "CJK"];
+
+/// The JIS X 0208 index, where the pointer is `(row - 1) * 94 + (cell - 1)`.
+/// Includes the Microsoft and IBM extensions in the WHATWG index.
+#[deriving(Clone)]
+pub struct JISX0208Index;
+
+impl IndexableEncoding<u16> for JISX0208Index {
+    fn forward(&self, ptr: u16) -> Option<char> {
+        match index::jis0208::forward(ptr) {
+            0xffff => None,
+            ch => Some(as_char(ch)),
+        }
+    }
+
+    fn backward(&self, ch: char) -> Option<u16> {
+        match index::jis0208::backward(ch as u32) {
+            0xffff => None,
+            ptr => Some(ptr),
+        }
+    }
+}
+
+/// The JIS X 0212 index, where the pointer is `(row - 1) * 94 + (cell - 1)`.
+#[deriving(Clone)]
+pub struct JISX0212Index;
+
+impl IndexableEncoding<u16> for JISX0212Index {
+    fn forward(&self, ptr: u16) -> Option<char> {
+        match index::jis0212::forward(ptr) {
+            0xffff => None,
+            ch => Some(as_char(ch)),
+        }
+    }
+
+    fn backward(&self, ch: char) -> Option<u16> {
+        match index::jis0212::backward(ch as u32) {
+            0xffff => None,
+            ptr => Some(ptr),
+        }
+    }
+}
+
 /**
  * EUC-JP. (XXX with asymmetric JIS X 0212 support)
  *
@@ -22,54 +67,173 @@ use types::*;
  * JIS X 0208 in G1 (`[A1-FE] [A1-FE]`),
  * the upper half of JIS X 0212 in G2 (`8E [A1-DF]`), and
  * JIS X 0212 in G3 (`8F [A1-FE] [A1-FE]`).
+ *
+ * ## Yen sign and overline
+ *
+ * By default (as in the WHATWG Encoding standard) G0 is treated as ASCII,
+ * so `5C` and `7E` are decoded to a backslash and tilde respectively,
+ * while the encoder additionally maps U+00A5 (yen sign) to `5C` and U+203E (overline) to `7E`.
+ * When `jis_roman` is set, G0 is treated as JIS X 0201 Roman instead:
+ * `5C` and `7E` are decoded to a yen sign and overline respectively,
+ * and a backslash and tilde cannot be encoded.
+ *
+ * ## Half-width katakana
+ *
+ * By default half-width katakana are encoded to G2 (`8E [A1-DF]`).
+ * When `fullwidth_katakana` is set, the encoder instead converts them to
+ * the corresponding full-width katakana in JIS X 0208 (combining the following voiced
+ * or semi-voiced sound mark if possible) so that the output never contains `8E`.
+ * The decoder is not affected.
  */
 #[deriving(Clone)]
-pub struct EUCJPEncoding;
+pub struct EUCJPEncoding {
+    /// True if `5C` and `7E` represent a yen sign and overline instead of ASCII.
+    pub jis_roman: bool,
+    /// True if the encoder converts half-width katakana to full-width katakana.
+    pub fullwidth_katakana: bool,
+}
 
 impl Encoding for EUCJPEncoding {
     fn name(&self) -> &'static str { "euc-jp" }
     fn whatwg_name(&self) -> Option<&'static str> { Some("euc-jp") }
-    fn encoder(&self) -> Box<Encoder> { EUCJPEncoder::new() }
-    fn decoder(&self) -> Box<Decoder> { EUCJP0212Decoder::new() }
+    fn covered_scripts(&self) -> &'static [&'static str] { JAPANESE_SCRIPTS }
+    fn assumes_normalization(&self) -> Option<NormalizationForm> { Some(NFC) }
+    fn encoder(&self) -> Box<Encoder> {
+        if self.fullwidth_katakana {
+            FullwidthKatakanaEncoder::new(EUCJPEncoder::new(self.jis_roman))
+        } else {
+            EUCJPEncoder::new(self.jis_roman)
+        }
+    }
+    fn decoder(&self) -> Box<Decoder> {
+        if self.jis_roman {
+            JISRomanDecoder::new(EUCJP0212Decoder::new())
+        } else {
+            EUCJP0212Decoder::new()
+        }
+    }
+
+    fn validate(&self, input: &[u8]) -> Result<uint, CodecError> {
+        use codec::japanese::eucjp::internal::{map_two_0208_bytes, map_two_0212_bytes};
+
+        // mirrors the state machine of `EUCJP0212Decoder`, including where the errors end
+        fn err(upto: uint, cause: String) -> Result<uint, CodecError> {
+            Err(CodecError { upto: upto as int, cause: cause.into_maybe_owned(), buffered: 0 })
+        }
+
+        let len = input.len();
+        let mut i = 0;
+        while i < len {
+            let lead = input[i];
+            // the number of bytes in a complete sequence starting with `lead`
+            let seqlen = match lead {
+                0x00..0x7f => 1,
+                0x8e | 0xa1..0xfe => 2,
+                0x8f => 3,
+                _ => return err(i + 1, format!("invalid lead byte 0x{:02X}", lead)),
+            };
+            if i + seqlen > len &&
+               input.slice_from(i + 1).iter().all(|&b| 0xa1 <= b && b <= 0xfe) {
+                return Err(CodecError { upto: len as int,
+                                        cause: "incomplete sequence".into_maybe_owned(),
+                                        buffered: len - i });
+            }
+            match lead {
+                0x00..0x7f => {}
+                0x8e => match input[i + 1] {
+                    0xa1..0xdf => {}
+                    b @ 0xa1..0xfe =>
+                        return err(i + 2, format!("invalid trail byte 0x{:02X} \
+                                                   after lead 0x8E", b)),
+                    b =>
+                        return err(i + 1, format!("invalid trail byte 0x{:02X} \
+                                                   after lead 0x8E", b)),
+                },
+                0x8f => match (input[i + 1], input.get(i + 2).map(|&b| b)) {
+                    (b1 @ 0xa1..0xfe, Some(b2 @ 0xa1..0xfe)) => {
+                        let ch = map_two_0212_bytes(b1, b2);
+                        if ch == 0xffff || ::std::char::from_u32(ch).is_none() {
+                            return err(i + 3, format!("invalid sequence 0x8F 0x{:02X} 0x{:02X}",
+                                                      b1, b2));
+                        }
+                    }
+                    (b1 @ 0xa1..0xfe, Some(b2)) =>
+                        return err(i + 2, format!("invalid trail byte 0x{:02X} \
+                                                   after lead 0x8F 0x{:02X}", b2, b1)),
+                    (b1, _) =>
+                        return err(i + 1, format!("invalid trail byte 0x{:02X} \
+                                                   after lead 0x8F", b1)),
+                },
+                _ => match input[i + 1] {
+                    b @ 0xa1..0xfe => if map_two_0208_bytes(lead, b) == 0xffff {
+                        return err(i + 2, format!("invalid sequence 0x{:02X} 0x{:02X}", lead, b));
+                    },
+                    b =>
+                        return err(i + 1, format!("invalid trail byte 0x{:02X} \
+                                                   after lead 0x{:02X}", b, lead)),
+                },
+            }
+            i += seqlen;
+        }
+        Ok(len)
+    }
 }
 
 /// An encoder for EUC-JP with unused G3 character set.
 #[deriving(Clone)]
-pub struct EUCJPEncoder;
+pub struct EUCJPEncoder {
+    jis_roman: bool,
+}
 
 impl EUCJPEncoder {
-    pub fn new() -> Box<Encoder> { box EUCJPEncoder as Box<Encoder> }
+    pub fn new(jis_roman: bool) -> Box<Encoder> {
+        box EUCJPEncoder { jis_roman: jis_roman } as Box<Encoder>
+    }
 }
 
 impl Encoder for EUCJPEncoder {
-    fn from_self(&self) -> Box<Encoder> { EUCJPEncoder::new() }
-    fn is_ascii_compatible(&self) -> bool { true }
+    fn from_self(&self) -> Box<Encoder> { EUCJPEncoder::new(self.jis_roman) }
+    fn clone_box(&self) -> Box<Encoder> { box self.clone() as Box<Encoder> }
+    fn is_ascii_compatible(&self) -> bool { !self.jis_roman }
 
     fn raw_feed(&mut self, input: &str, output: &mut ByteWriter) -> (uint, Option<CodecError>) {
         output.writer_hint(input.len());
 
         for ((i,j), ch) in input.index_iter() {
             match ch {
+                '\u005c' | '\u007e' if self.jis_roman => {
+                    return (i, Some(CodecError {
+                        upto: j as int, cause: "unrepresentable character".into_maybe_owned(),
+                        buffered: 0
+                    }));
+                }
                 '\u0000'..'\u007f' => { output.write_byte(ch as u8); }
                 '\u00a5' => { output.write_byte(0x5c); }
                 '\u203e' => { output.write_byte(0x7e); }
                 '\uff61'..'\uff9f' => {
-                    output.write_byte(0x8e);
-                    output.write_byte((ch as uint - 0xff61 + 0xa1) as u8);
+                    output.write_bytes([0x8e, (ch as uint - 0xff61 + 0xa1) as u8]);
                 }
-                _ => {
-                    let ptr = index::jis0208::backward(ch as u32);
-                    if ptr == 0xffff {
-                        return (i, Some(CodecError {
-                            upto: j as int, cause: "unrepresentable character".into_maybe_owned()
-                        }));
-                    } else {
+                // hiragana and katakana are contiguous in the row 4 and 5 of JIS X 0208,
+                // so they can skip the index lookup
+                '\u3041'..'\u3093' => {
+                    output.write_bytes([0xa4, (ch as uint - 0x3041 + 0xa1) as u8]);
+                }
+                '\u30a1'..'\u30f6' => {
+                    output.write_bytes([0xa5, (ch as uint - 0x30a1 + 0xa1) as u8]);
+                }
+                _ => match JISX0208Index.backward(ch) {
+                    Some(ptr) => {
                         let lead = ptr / 94 + 0xa1;
                         let trail = ptr % 94 + 0xa1;
-                        output.write_byte(lead as u8);
-                        output.write_byte(trail as u8);
+                        output.write_bytes([lead as u8, trail as u8]);
                     }
-                }
+                    None => {
+                        return (i, Some(CodecError {
+                            upto: j as int, cause: "unrepresentable character".into_maybe_owned(),
+                            buffered: 0
+                        }));
+                    }
+                },
             }
         }
         (input.len(), None)
@@ -99,6 +263,8 @@ ascii_compatible_stateful_decoder! {
         index::jis0208::forward(index as u16)
     }
 
+    // returns 0xffff for unmapped sequences. other results are not limited to the BMP,
+    // so that the supplementary planes of JIS X 0213 can share this state machine.
     internal pub fn map_two_0212_bytes(lead: u8, trail: u8) -> u32 {
         use index;
 
@@ -117,21 +283,22 @@ ascii_compatible_stateful_decoder! {
         case 0x8e => S1(ctx);
         case 0x8f => S2(ctx);
         case b @ 0xa1..0xfe => S3(ctx, b);
-        case _ => ctx.err("invalid sequence");
+        case b => ctx.err(format!("invalid lead byte 0x{:02X}", b));
     }
 
     // euc-jp lead = 0x8e
     state S1(ctx) {
         case b @ 0xa1..0xdf => ctx.emit(0xff61 + b as u32 - 0xa1);
-        case 0xa1..0xfe => ctx.err("invalid sequence");
-        case _ => ctx.backup_and_err(1, "invalid sequence");
+        case b @ 0xa1..0xfe => ctx.err(format!("invalid trail byte 0x{:02X} after lead 0x8E", b));
+        case b => ctx.backup_and_err(1, format!("invalid trail byte 0x{:02X} after lead 0x8E", b));
     }
 
     // euc-jp lead = 0x8f
     // JIS X 0201 half-width katakana
     state S2(ctx) {
         case b @ 0xa1..0xfe => S4(ctx, b);
-        case _ => ctx.backup_and_err(1, "invalid sequence");
+        // only `8F` is consumed, so another `8E` or `8F` starts a new sequence
+        case b => ctx.backup_and_err(1, format!("invalid trail byte 0x{:02X} after lead 0x8F", b));
     }
 
     // euc-jp lead != 0x00, euc-jp jis0212 flag = unset
@@ -139,10 +306,11 @@ ascii_compatible_stateful_decoder! {
     state S3(ctx, lead: u8) {
         case b @ 0xa1..0xfe => match map_two_0208_bytes(lead, b) {
             // do NOT backup, we only backup for out-of-range trails.
-            0xffff => ctx.err("invalid sequence"),
+            0xffff => ctx.err(format!("invalid sequence 0x{:02X} 0x{:02X}", lead, b)),
             ch => ctx.emit(ch as u32)
         };
-        case _ => ctx.backup_and_err(1, "invalid sequence");
+        case b => ctx.backup_and_err(1, format!("invalid trail byte 0x{:02X} after lead 0x{:02X}",
+                                                b, lead));
     }
 
     // euc-jp lead != 0x00, euc-jp jis0212 flag = set
@@ -150,10 +318,70 @@ ascii_compatible_stateful_decoder! {
     state S4(ctx, lead: u8) {
         case b @ 0xa1..0xfe => match map_two_0212_bytes(lead, b) {
             // do NOT backup, we only backup for out-of-range trails.
-            0xffff => ctx.err("invalid sequence"),
-            ch => ctx.emit(ch as u32)
+            0xffff => ctx.err(format!("invalid sequence 0x8F 0x{:02X} 0x{:02X}", lead, b)),
+            ch if ::std::char::from_u32(ch).is_some() => ctx.emit(ch),
+            _ => ctx.err(format!("invalid sequence 0x8F 0x{:02X} 0x{:02X}", lead, b))
         };
-        case _ => ctx.backup_and_err(1, "invalid sequence");
+        case b => ctx.backup_and_err(1, format!("invalid trail byte 0x{:02X} \
+                                                 after lead 0x8F 0x{:02X}", b, lead));
+    }
+}
+
+/// A string writer adapter which maps a backslash and tilde to a yen sign and overline.
+struct JISRomanWriter<'a> {
+    inner: &'a mut StringWriter,
+}
+
+impl<'a> StringWriter for JISRomanWriter<'a> {
+    fn writer_hint(&mut self, expectedlen: uint) {
+        self.inner.writer_hint(expectedlen);
+    }
+
+    fn write_char(&mut self, c: char) {
+        match c {
+            '\u005c' => self.inner.write_char('\u00a5'),
+            '\u007e' => self.inner.write_char('\u203e'),
+            c => self.inner.write_char(c),
+        }
+    }
+
+    fn write_str(&mut self, s: &str) {
+        if s.contains_char('\u005c') || s.contains_char('\u007e') {
+            for c in s.chars() { self.write_char(c); }
+        } else {
+            self.inner.write_str(s);
+        }
+    }
+}
+
+/**
+ * A decoder adapter which treats `5C` and `7E` as JIS X 0201 Roman,
+ * i.e. a yen sign and overline, instead of ASCII.
+ *
+ * This relies on the fact that the wrapped decoder never emits a backslash or tilde
+ * other than from `5C` and `7E`.
+ */
+pub struct JISRomanDecoder {
+    inner: Box<Decoder>,
+}
+
+impl JISRomanDecoder {
+    pub fn new(inner: Box<Decoder>) -> Box<Decoder> {
+        box JISRomanDecoder { inner: inner } as Box<Decoder>
+    }
+}
+
+impl Decoder for JISRomanDecoder {
+    fn from_self(&self) -> Box<Decoder> { JISRomanDecoder::new(self.inner.from_self()) }
+    fn clone_box(&self) -> Box<Decoder> { JISRomanDecoder::new(self.inner.clone_box()) }
+    fn pending_len(&self) -> uint { self.inner.pending_len() }
+
+    fn raw_feed(&mut self, input: &[u8], output: &mut StringWriter) -> (uint, Option<CodecError>) {
+        self.inner.raw_feed(input, &mut JISRomanWriter { inner: output })
+    }
+
+    fn raw_finish(&mut self, output: &mut StringWriter) -> Option<CodecError> {
+        self.inner.raw_finish(&mut JISRomanWriter { inner: output })
     }
 }
 
@@ -161,13 +389,14 @@ ascii_compatible_stateful_decoder! {
 mod eucjp_tests {
     extern crate test;
     use super::EUCJPEncoding;
+    use all::EUC_JP;
     use std::iter::range_inclusive;
     use testutils;
     use types::*;
 
     #[test]
     fn test_encoder_valid() {
-        let mut e = EUCJPEncoding.encoder();
+        let mut e = EUC_JP.encoder();
         assert_feed_ok!(e, "A", "", [0x41]);
         assert_feed_ok!(e, "BC", "", [0x42, 0x43]);
         assert_feed_ok!(e, "", "", []);
@@ -183,14 +412,14 @@ mod eucjp_tests {
     fn test_encoder_double_mapped() {
         // these characters are double-mapped to both EUDC area and Shift_JIS extension area
         // but only the former should be used. (note that U+FFE2 is triple-mapped!)
-        let mut e = EUCJPEncoding.encoder();
+        let mut e = EUC_JP.encoder();
         assert_feed_ok!(e, "\u9ed1\u2170\uffe2", "", [0xfc, 0xee, 0xfc, 0xf1, 0xa2, 0xcc]);
         assert_finish_ok!(e, []);
     }
 
     #[test]
     fn test_encoder_invalid() {
-        let mut e = EUCJPEncoding.encoder();
+        let mut e = EUC_JP.encoder();
         assert_feed_err!(e, "", "\uffff", "", []);
         assert_feed_err!(e, "?", "\uffff", "!", [0x3f]);
         // JIS X 0212 is not supported in the encoder
@@ -200,7 +429,7 @@ mod eucjp_tests {
 
     #[test]
     fn test_decoder_valid() {
-        let mut d = EUCJPEncoding.decoder();
+        let mut d = EUC_JP.decoder();
         assert_feed_ok!(d, [0x41], [], "A");
         assert_feed_ok!(d, [0x42, 0x43], [], "BC");
         assert_feed_ok!(d, [], [], "");
@@ -215,7 +444,7 @@ mod eucjp_tests {
 
     #[test]
     fn test_decoder_valid_partial() {
-        let mut d = EUCJPEncoding.decoder();
+        let mut d = EUC_JP.decoder();
         assert_feed_ok!(d, [], [0xa4], "");
         assert_feed_ok!(d, [0xcb], [0xa4], "\u306b");
         assert_feed_ok!(d, [0xdb], [0xa4], "\u307b");
@@ -239,19 +468,19 @@ mod eucjp_tests {
     #[test]
     fn test_decoder_invalid_lone_lead_immediate_test_finish() {
         for i in range_inclusive(0x8eu8, 0x8f) {
-            let mut d = EUCJPEncoding.decoder();
+            let mut d = EUC_JP.decoder();
             assert_feed_ok!(d, [], [i], ""); // wait for a trail
             assert_finish_err!(d, "");
         }
 
         for i in range_inclusive(0xa1u8, 0xfe) {
-            let mut d = EUCJPEncoding.decoder();
+            let mut d = EUC_JP.decoder();
             assert_feed_ok!(d, [], [i], ""); // wait for a trail
             assert_finish_err!(d, "");
         }
 
         // immediate failures
-        let mut d = EUCJPEncoding.decoder();
+        let mut d = EUC_JP.decoder();
         for i in range_inclusive(0x80u8, 0x8d) {
             assert_feed_err!(d, [], [i], [], "");
         }
@@ -265,7 +494,7 @@ mod eucjp_tests {
     #[test]
     fn test_decoder_invalid_lone_lead_followed_by_space() {
         for i in range_inclusive(0x80u8, 0xff) {
-            let mut d = EUCJPEncoding.decoder();
+            let mut d = EUC_JP.decoder();
             assert_feed_err!(d, [], [i], [0x20], "");
             assert_finish_ok!(d, "");
         }
@@ -274,7 +503,7 @@ mod eucjp_tests {
     #[test]
     fn test_decoder_invalid_lead_followed_by_invalid_trail() {
         for i in range_inclusive(0x80u8, 0xff) {
-            let mut d = EUCJPEncoding.decoder();
+            let mut d = EUC_JP.decoder();
             assert_feed_err!(d, [], [i], [0x80], "");
             assert_feed_err!(d, [], [i], [0xff], "");
             assert_finish_ok!(d, "");
@@ -284,7 +513,7 @@ mod eucjp_tests {
     #[test]
     fn test_decoder_invalid_lone_lead_for_0212_immediate_test_finish() {
         for i in range_inclusive(0xa1u8, 0xfe) {
-            let mut d = EUCJPEncoding.decoder();
+            let mut d = EUC_JP.decoder();
             assert_feed_ok!(d, [], [0x8f, i], ""); // wait for a trail
             assert_finish_err!(d, "");
         }
@@ -293,23 +522,89 @@ mod eucjp_tests {
     #[test]
     fn test_decoder_invalid_lone_lead_for_0212_immediate_test_finish_partial() {
         for i in range_inclusive(0xa1u8, 0xfe) {
-            let mut d = EUCJPEncoding.decoder();
+            let mut d = EUC_JP.decoder();
             assert_feed_ok!(d, [], [0x8f], "");
             assert_feed_ok!(d, [], [i], ""); // wait for a trail
             assert_finish_err!(d, "");
         }
     }
 
+    #[test]
+    fn test_decoder_error_cause() {
+        fn cause(input: &[u8]) -> String {
+            let mut d = EUC_JP.decoder();
+            let (_, err, _) = d.test_feed(input);
+            err.unwrap().cause.as_slice().to_string()
+        }
+        assert_eq!(cause([0x80]).as_slice(), "invalid lead byte 0x80");
+        assert_eq!(cause([0xa4, 0x20]).as_slice(), "invalid trail byte 0x20 after lead 0xA4");
+        assert_eq!(cause([0x8e, 0xe0]).as_slice(), "invalid trail byte 0xE0 after lead 0x8E");
+        assert_eq!(cause([0xa9, 0xa1]).as_slice(), "invalid sequence 0xA9 0xA1");
+        assert_eq!(cause([0x8f, 0xa1, 0xa1]).as_slice(), "invalid sequence 0x8F 0xA1 0xA1");
+        assert_eq!(cause([0x8f, 0xa2, 0x41]).as_slice(),
+                   "invalid trail byte 0x41 after lead 0x8F 0xA2");
+    }
+
+    #[test]
+    fn test_decoder_pending_len() {
+        let mut d = EUC_JP.decoder();
+        assert_eq!(d.pending_len(), 0);
+        assert_feed_ok!(d, [0x41], [0xa4], "A");
+        assert_eq!(d.pending_len(), 1);
+        assert_feed_ok!(d, [0xa2], [], "\u3042");
+        assert_eq!(d.pending_len(), 0);
+        assert_feed_ok!(d, [], [0x8f], "");
+        assert_eq!(d.pending_len(), 1);
+        assert_feed_ok!(d, [], [0xcb], "");
+        assert_eq!(d.pending_len(), 2);
+        assert_feed_ok!(d, [0xc6], [], "\u736c");
+        assert_eq!(d.pending_len(), 0);
+        assert_feed_ok!(d, [], [0x8f, 0xcb], "");
+        assert_eq!(d.pending_len(), 2);
+        d.reset();
+        assert_eq!(d.pending_len(), 0);
+        assert_finish_ok!(d, "");
+    }
+
+    #[test]
+    fn test_decoder_finish_buffered() {
+        let mut d = EUC_JP.decoder();
+        assert_feed_ok!(d, [0x41], [0xa4], "A");
+        let (err, _) = d.test_finish();
+        assert_eq!(err.map(|e| e.buffered), Some(1));
+
+        assert_feed_ok!(d, [], [0x8e], "");
+        let (err, _) = d.test_finish();
+        assert_eq!(err.map(|e| e.buffered), Some(1));
+
+        assert_feed_ok!(d, [], [0x8f], "");
+        let (err, _) = d.test_finish();
+        assert_eq!(err.map(|e| e.buffered), Some(1));
+
+        assert_feed_ok!(d, [], [0x8f, 0xcb], "");
+        let (err, _) = d.test_finish();
+        assert_eq!(err.map(|e| e.buffered), Some(2));
+
+        // the count accumulates across multiple calls
+        assert_feed_ok!(d, [], [0x8f], "");
+        assert_feed_ok!(d, [], [0xcb], "");
+        let (err, _) = d.test_finish();
+        assert_eq!(err.map(|e| e.buffered), Some(2));
+
+        assert_feed_ok!(d, [0xa4, 0xa2], [], "\u3042");
+        assert_finish_ok!(d, "");
+    }
+
     #[test]
     fn test_decoder_invalid_trail_for_0201() {
         for i in range_inclusive(0u8, 0xa0) {
-            let mut d = EUCJPEncoding.decoder();
+            let mut d = EUC_JP.decoder();
             assert_feed_err!(d, [], [0x8e], [i], "");
             assert_finish_ok!(d, "");
         }
 
         for i in range_inclusive(0xe0u8, 0xfe) {
-            let mut d = EUCJPEncoding.decoder();
+            let mut d = EUC_JP.decoder();
             assert_feed_err!(d, [], [0x8e, i], [], "");
             assert_finish_ok!(d, "");
         }
@@ -318,24 +613,41 @@ mod eucjp_tests {
     #[test]
     fn test_decoder_invalid_trail_for_0201_partial() {
         for i in range_inclusive(0u8, 0xa0) {
-            let mut d = EUCJPEncoding.decoder();
+            let mut d = EUC_JP.decoder();
             assert_feed_ok!(d, [], [0x8e], "");
             assert_feed_err!(d, [], [], [i], "");
             assert_finish_ok!(d, "");
         }
 
         for i in range_inclusive(0xe0u8, 0xfe) {
-            let mut d = EUCJPEncoding.decoder();
+            let mut d = EUC_JP.decoder();
             assert_feed_ok!(d, [], [0x8e], "");
             assert_feed_err!(d, [], [i], [], "");
             assert_finish_ok!(d, "");
         }
     }
 
+    #[test]
+    fn test_decoder_invalid_trail_for_0201_across_feeds() {
+        // a pending `8E` is never treated as a JIS X 0208 lead byte
+        let mut d = EUC_JP.decoder();
+        assert_feed_ok!(d, [], [0x8e], "");
+        let (nprocessed, err, buf) = d.test_feed([0x41]);
+        assert_eq!(nprocessed, 0);
+        assert_eq!(buf.as_slice(), "");
+        let err = err.unwrap();
+        assert_eq!(err.upto, 0); // `41` is not consumed
+        assert_eq!(err.cause.as_slice(), "invalid trail byte 0x41 after lead 0x8E");
+        assert_feed_ok!(d, [0x41], [], "A");
+        assert_finish_ok!(d, "");
+
+        assert_eq!(EUC_JP.decode([0x8e, 0x41], DecodeReplace), Ok("\ufffdA".to_string()));
+    }
+
     #[test]
     fn test_decoder_invalid_middle_for_0212() {
         for i in range_inclusive(0u8, 0xa0) {
-            let mut d = EUCJPEncoding.decoder();
+            let mut d = EUC_JP.decoder();
             assert_feed_err!(d, [], [0x8f], [i], "");
             assert_finish_ok!(d, "");
         }
@@ -344,17 +656,40 @@ mod eucjp_tests {
     #[test]
     fn test_decoder_invalid_middle_for_0212_partial() {
         for i in range_inclusive(0u8, 0xa0) {
-            let mut d = EUCJPEncoding.decoder();
+            let mut d = EUC_JP.decoder();
             assert_feed_ok!(d, [], [0x8f], "");
             assert_feed_err!(d, [], [], [i], "");
             assert_finish_ok!(d, "");
         }
     }
 
+    #[test]
+    fn test_decoder_invalid_middle_for_0212_followed_by_lead() {
+        // the error consumes the first `8F` only, and `8F` or `8E` is decoded as a new lead
+        let mut d = EUC_JP.decoder();
+        assert_feed_err!(d, [], [0x8f], [0x8f, 0xa1, 0xa1], "");
+        assert_feed_err!(d, [], [0x8f, 0xa1, 0xa1], [], ""); // unassigned in JIS X 0212
+        assert_feed_err!(d, [], [0x8f], [0x8f, 0xa2, 0xaf], "");
+        assert_feed_ok!(d, [0x8f, 0xa2, 0xaf], [], "\u02d8");
+        assert_finish_ok!(d, "");
+
+        let mut d = EUC_JP.decoder();
+        assert_feed_err!(d, [], [0x8f], [0x8e, 0xa1], "");
+        assert_feed_ok!(d, [0x8e, 0xa1], [], "\uff61");
+        assert_finish_ok!(d, "");
+
+        assert_eq!(EUC_JP.decode([0x8f, 0x8f, 0xa1, 0xa1], DecodeReplace),
+                   Ok("\ufffd\ufffd".to_string()));
+        assert_eq!(EUC_JP.decode([0x8f, 0x8f, 0xa2, 0xaf], DecodeReplace),
+                   Ok("\ufffd\u02d8".to_string()));
+        assert_eq!(EUC_JP.decode([0x8f, 0x8e, 0xa1], DecodeReplace),
+                   Ok("\ufffd\uff61".to_string()));
+    }
+
     #[test]
     fn test_decoder_invalid_trail_for_0212() {
         for i in range_inclusive(0u8, 0xa0) {
-            let mut d = EUCJPEncoding.decoder();
+            let mut d = EUC_JP.decoder();
             assert_feed_err!(d, [], [0x8f, 0xa1], [i], "");
             assert_finish_ok!(d, "");
         }
@@ -363,7 +698,7 @@ mod eucjp_tests {
     #[test]
     fn test_decoder_invalid_trail_for_0212_partial() {
         for i in range_inclusive(0u8, 0xa0) {
-            let mut d = EUCJPEncoding.decoder();
+            let mut d = EUC_JP.decoder();
             assert_feed_ok!(d, [], [0x8f], "");
             assert_feed_ok!(d, [], [0xa1], "");
             assert_feed_err!(d, [], [], [i], "");
@@ -373,16 +708,119 @@ mod eucjp_tests {
 
     #[test]
     fn test_decoder_feed_after_finish() {
-        let mut d = EUCJPEncoding.decoder();
+        let mut d = EUC_JP.decoder();
         assert_feed_ok!(d, [0xa4, 0xa2], [0xa4], "\u3042");
         assert_finish_err!(d, "");
         assert_feed_ok!(d, [0xa4, 0xa2], [], "\u3042");
         assert_finish_ok!(d, "");
     }
 
+    #[test]
+    fn test_decoder_empty_feed() {
+        // an empty feed should never drop or corrupt pending bytes
+        let mut d = EUC_JP.decoder();
+        assert_feed_ok!(d, [], [0xa4], "");
+        assert_feed_ok!(d, [], [], "");
+        assert_feed_ok!(d, [], [], "");
+        assert_feed_ok!(d, [0xcb], [], "\u306b");
+        assert_feed_ok!(d, [], [0x8e], "");
+        assert_feed_ok!(d, [], [], "");
+        assert_feed_ok!(d, [0xc6], [], "\uff86");
+        assert_feed_ok!(d, [], [0x8f], "");
+        assert_feed_ok!(d, [], [], "");
+        assert_feed_ok!(d, [], [0xcb], "");
+        assert_feed_ok!(d, [], [], "");
+        assert_feed_ok!(d, [0xc6], [], "\u736c");
+        assert_feed_ok!(d, [], [], "");
+        assert_finish_ok!(d, "");
+    }
+
+    #[test]
+    fn test_jis_roman() {
+        static JIS_ROMAN: EUCJPEncoding = EUCJPEncoding { jis_roman: true,
+                                                         fullwidth_katakana: false };
+
+        let mut e = JIS_ROMAN.encoder();
+        assert_feed_ok!(e, "A\u00a5\u203e", "", [0x41, 0x5c, 0x7e]);
+        assert_feed_err!(e, "", "\\", "~", []);
+        assert_feed_err!(e, "", "~", "", []);
+        assert_feed_ok!(e, "\u65e5", "", [0xc6, 0xfc]);
+        assert_finish_ok!(e, []);
+
+        let mut d = JIS_ROMAN.decoder();
+        assert_feed_ok!(d, [0x41, 0x5c, 0x7e], [], "A\u00a5\u203e");
+        assert_feed_ok!(d, [0xa4, 0xa2], [0xa4], "\u3042");
+        assert_feed_ok!(d, [0xa2, 0x5c], [], "\u3042\u00a5");
+        assert_feed_err!(d, [], [0xa4], [0x5c], "");
+        assert_feed_ok!(d, [0x5c], [], "\u00a5");
+        assert_finish_ok!(d, "");
+
+        // the default is ASCII
+        assert_eq!(EUC_JP.decode([0x5c, 0x7e], DecodeStrict), Ok("\\~".to_string()));
+        assert_eq!(EUC_JP.encode("\\~\u00a5\u203e", EncodeStrict),
+                   Ok(vec!(0x5c, 0x7e, 0x5c, 0x7e)));
+    }
+
+    #[test]
+    fn test_fullwidth_katakana() {
+        static FULLWIDTH: EUCJPEncoding = EUCJPEncoding { jis_roman: false,
+                                                          fullwidth_katakana: true };
+
+        let mut e = FULLWIDTH.encoder();
+        assert_feed_ok!(e, "A\uff71", "", [0x41, 0xa5, 0xa2]);
+        assert_feed_ok!(e, "", "\uff76", []); // may be combined with the next mark
+        assert_feed_ok!(e, "\uff9e\uff8a\uff9f", "", [0xa5, 0xac, 0xa5, 0xd1]);
+        assert_feed_ok!(e, "\uff71\uff9e", "", [0xa5, 0xa2, 0xa1, 0xab]);
+        assert_feed_ok!(e, "\uff61\u65e5", "\uff8a", [0xa1, 0xa3, 0xc6, 0xfc]);
+        assert_finish_ok!(e, [0xa5, 0xcf]);
+
+        // no `8E` in the output, and the default is unaffected
+        assert_eq!(FULLWIDTH.encode("\uff76\uff9e\uff6f", EncodeStrict),
+                   Ok(vec!(0xa5, 0xac, 0xa5, 0xc3)));
+        assert_eq!(EUC_JP.encode("\uff76\uff9e\uff6f", EncodeStrict),
+                   Ok(vec!(0x8e, 0xb6, 0x8e, 0xde, 0x8e, 0xaf)));
+        assert_eq!(FULLWIDTH.decode([0x8e, 0xb6], DecodeStrict), Ok("\uff76".to_string()));
+    }
+
+    #[test]
+    fn test_encoder_kana_matches_index() {
+        use index;
+
+        let mut e = EUC_JP.encoder();
+        for ch in range_inclusive(0x3041u32, 0x3096).chain(range_inclusive(0x30a1u32, 0x30fa)) {
+            let s = String::from_char(1, ::std::char::from_u32(ch).unwrap());
+            let (_, err, buf) = e.test_feed(s.as_slice());
+            let ptr = index::jis0208::backward(ch);
+            if ptr == 0xffff {
+                assert!(err.is_some(), "U+{:04X} should be unrepresentable", ch);
+            } else {
+                assert!(err.is_none(), "U+{:04X} should be representable", ch);
+                assert_eq!(buf, vec!((ptr / 94 + 0xa1) as u8, (ptr % 94 + 0xa1) as u8));
+            }
+        }
+        assert_finish_ok!(e, []);
+    }
+
+    #[test]
+    fn test_indexable() {
+        use super::{JISX0208Index, JISX0212Index};
+
+        assert_eq!(JISX0208Index.forward(0), Some('\u3000'));
+        assert_eq!(JISX0208Index.forward(1410), Some('\u4e9c')); // row 16, cell 1
+        assert_eq!(JISX0208Index.forward(0xffff), None);
+        assert_eq!(JISX0208Index.backward('\u4e9c'), Some(1410));
+        assert_eq!(JISX0208Index.backward('A'), None);
+
+        assert_eq!(JISX0212Index.forward(0), None);
+        assert_eq!(JISX0212Index.backward('\u4e02'), Some(1410)); // row 16, cell 1
+        assert_eq!(JISX0212Index.forward(1410), Some('\u4e02'));
+        assert_eq!(JISX0212Index.backward('\u4e9c'), None);
+    }
+
     #[bench]
     fn bench_encode_short_text(bencher: &mut test::Bencher) {
-        static Encoding: EUCJPEncoding = EUCJPEncoding;
+        static Encoding: EUCJPEncoding = EUCJPEncoding { jis_roman: false,
+                                                        fullwidth_katakana: false };
         let s = testutils::JAPANESE_TEXT;
         bencher.bytes = s.len() as u64;
         bencher.iter(|| test::black_box({
@@ -390,9 +828,21 @@ mod eucjp_tests {
         }))
     }
 
+    #[bench]
+    fn bench_encode_kana_text(bencher: &mut test::Bencher) {
+        static Encoding: EUCJPEncoding = EUCJPEncoding { jis_roman: false,
+                                                        fullwidth_katakana: false };
+        let s = testutils::KANA_TEXT;
+        bencher.bytes = s.len() as u64;
+        bencher.iter(|| test::black_box({
+            Encoding.encode(s.as_slice(), EncodeStrict)
+        }))
+    }
+
     #[bench]
     fn bench_decode_short_text(bencher: &mut test::Bencher) {
-        static Encoding: EUCJPEncoding = EUCJPEncoding;
+        static Encoding: EUCJPEncoding = EUCJPEncoding { jis_roman: false,
+                                                        fullwidth_katakana: false };
         let s = Encoding.encode(testutils::JAPANESE_TEXT, EncodeStrict).ok().unwrap();
         bencher.bytes = s.len() as u64;
         bencher.iter(|| test::black_box({
@@ -402,118 +852,316 @@ mod eucjp_tests {
 }
 
 /**
- * Windows code page 932, i.e. Shift_JIS with IBM/NEC extensions.
+ * Windows code page 51932, a variant of EUC-JP used by Microsoft products.
  *
- * This is a Japanese encoding for JIS X 0208
- * compatible to the original assignments of JIS X 0201 (`[21-7E A1-DF]`).
- * The 94 by 94 region of JIS X 0208 is sliced, or rather "shifted" into
- * the odd half (odd row number) and even half (even row number),
- * and merged into the 188 by 47 region mapped to `[81-9F E0-EF] [40-7E 80-FC]`.
- * The remaining area, `[80 A0 F0-FF] [40-7E 80-FC]`, has been subjected to
- * numerous extensions incompatible to each other.
- * This particular implementation uses IBM/NEC extensions
- * which assigns more characters to `[F0-FC 80-FC]` and also to the Private Use Area (PUA).
- * It requires some cares to handle
- * since the second byte of JIS X 0208 can have its MSB unset.
+ * This is same to EUC-JP except that it does not contain JIS X 0212 in G3,
+ * so the `8F` lead byte is never valid.
+ * Its JIS X 0208 plane includes NEC special characters in the row 13 (`[AD] [A1-FE]`) and
+ * NEC-selected IBM extensions in the rows 89 to 92 (`[F9-FC] [A1-FE]`),
+ * both of which are already a part of the JIS X 0208 index used by EUC-JP.
  */
 #[deriving(Clone)]
-pub struct Windows31JEncoding;
-
-impl Encoding for Windows31JEncoding {
-    fn name(&self) -> &'static str { "windows-31j" }
-    fn whatwg_name(&self) -> Option<&'static str> { Some("shift_jis") } // WHATWG compatibility
-    fn encoder(&self) -> Box<Encoder> { Windows31JEncoder::new() }
-    fn decoder(&self) -> Box<Decoder> { Windows31JDecoder::new() }
-}
-
-/// An encoder for Shift_JIS with IBM/NEC extensions.
-#[deriving(Clone)]
-pub struct Windows31JEncoder;
-
-impl Windows31JEncoder {
-    pub fn new() -> Box<Encoder> { box Windows31JEncoder as Box<Encoder> }
-}
-
-impl Encoder for Windows31JEncoder {
-    fn from_self(&self) -> Box<Encoder> { Windows31JEncoder::new() }
-    fn is_ascii_compatible(&self) -> bool { true }
-
-    fn raw_feed(&mut self, input: &str, output: &mut ByteWriter) -> (uint, Option<CodecError>) {
-        output.writer_hint(input.len());
-
-        for ((i,j), ch) in input.index_iter() {
-            match ch {
-                '\u0000'..'\u0080' => { output.write_byte(ch as u8); }
-                '\u00a5' => { output.write_byte(0x5c); }
-                '\u203e' => { output.write_byte(0x7e); }
-                '\uff61'..'\uff9f' => { output.write_byte((ch as uint - 0xff61 + 0xa1) as u8); }
-                _ => {
-                    // corresponds to the "index shift_jis pointer" in the WHATWG spec
-                    let ptr = index::jis0208::backward_remapped(ch as u32);
-                    if ptr == 0xffff {
-                        return (i, Some(CodecError {
-                            upto: j as int, cause: "unrepresentable character".into_maybe_owned(),
-                        }));
-                    } else {
-                        let lead = ptr / 188;
-                        let leadoffset = if lead < 0x1f {0x81} else {0xc1};
-                        let trail = ptr % 188;
-                        let trailoffset = if trail < 0x3f {0x40} else {0x41};
-                        output.write_byte((lead + leadoffset) as u8);
-                        output.write_byte((trail + trailoffset) as u8);
-                    }
-                }
-            }
-        }
-        (input.len(), None)
-    }
-
-    fn raw_finish(&mut self, _output: &mut ByteWriter) -> Option<CodecError> {
-        None
-    }
+pub struct CP51932Encoding;
+
+impl Encoding for CP51932Encoding {
+    fn name(&self) -> &'static str { "cp51932" }
+    fn covered_scripts(&self) -> &'static [&'static str] { JAPANESE_SCRIPTS }
+    fn assumes_normalization(&self) -> Option<NormalizationForm> { Some(NFC) }
+    fn encoder(&self) -> Box<Encoder> { EUCJPEncoder::new(false) }
+    fn decoder(&self) -> Box<Decoder> { CP51932Decoder::new() }
 }
 
 ascii_compatible_stateful_decoder! {
-    #[doc="A decoder for Shift_JIS with IBM/NEC extensions."]
+    #[doc="A decoder for Windows code page 51932, i.e. EUC-JP without JIS X 0212."]
     #[deriving(Clone)]
-    struct Windows31JDecoder;
+    struct CP51932Decoder;
 
-    module windows31j;
+    module cp51932;
 
     internal pub fn map_two_0208_bytes(lead: u8, trail: u8) -> u32 {
         use index;
 
         let lead = lead as uint;
         let trail = trail as uint;
-        let leadoffset = if lead < 0xa0 {0x81} else {0xc1};
-        let trailoffset = if trail < 0x7f {0x40} else {0x41};
         let index = match (lead, trail) {
-            (0xf0..0xf9, 0x40..0x7e) | (0xf0..0xf9, 0x80..0xfc) =>
-                return (0xe000 + (lead - 0xf0) * 188 + trail - trailoffset) as u32,
-            (0x81..0x9f, 0x40..0x7e) | (0x81..0x9f, 0x80..0xfc) |
-            (0xe0..0xfc, 0x40..0x7e) | (0xe0..0xfc, 0x80..0xfc) =>
-                (lead - leadoffset) * 188 + trail - trailoffset,
+            (0xa1..0xfe, 0xa1..0xfe) => (lead - 0xa1) * 94 + trail - 0xa1,
             _ => 0xffff,
         };
         index::jis0208::forward(index as u16)
     }
 
-    // shift_jis lead = 0x00
+    // euc-jp lead = 0x00, but 0x8f is not a lead
     initial state S0(ctx) {
-        case b @ 0x00..0x80 => ctx.emit(b as u32);
-        case b @ 0xa1..0xdf => ctx.emit(0xff61 + b as u32 - 0xa1);
-        case b @ 0x81..0x9f | b @ 0xe0..0xfc => S1(ctx, b);
-        case _ => ctx.err("invalid sequence");
+        case b @ 0x00..0x7f => ctx.emit(b as u32);
+        case 0x8e => S1(ctx);
+        case b @ 0xa1..0xfe => S2(ctx, b);
+        case b => ctx.err(format!("invalid lead byte 0x{:02X}", b));
     }
 
-    // shift_jis lead != 0x00
-    state S1(ctx, lead: u8) {
-        case b => match map_two_0208_bytes(lead, b) {
-            0xffff => ctx.backup_and_err(1, "invalid sequence"), // unconditional
-            ch => ctx.emit(ch)
-        };
+    // euc-jp lead = 0x8e
+    state S1(ctx) {
+        case b @ 0xa1..0xdf => ctx.emit(0xff61 + b as u32 - 0xa1);
+        case b @ 0xa1..0xfe => ctx.err(format!("invalid trail byte 0x{:02X} after lead 0x8E", b));
+        case b => ctx.backup_and_err(1, format!("invalid trail byte 0x{:02X} after lead 0x8E", b));
     }
-}
+
+    // euc-jp lead != 0x00, euc-jp jis0212 flag = unset
+    // JIS X 0208 two-byte sequence
+    state S2(ctx, lead: u8) {
+        case b @ 0xa1..0xfe => match map_two_0208_bytes(lead, b) {
+            // do NOT backup, we only backup for out-of-range trails.
+            0xffff => ctx.err(format!("invalid sequence 0x{:02X} 0x{:02X}", lead, b)),
+            ch => ctx.emit(ch as u32)
+        };
+        case b => ctx.backup_and_err(1, format!("invalid trail byte 0x{:02X} after lead 0x{:02X}",
+                                                b, lead));
+    }
+}
+
+#[cfg(test)]
+mod cp51932_tests {
+    use super::CP51932Encoding;
+    use types::*;
+
+    #[test]
+    fn test_encoder_valid() {
+        let mut e = CP51932Encoding.encoder();
+        assert_feed_ok!(e, "A", "", [0x41]);
+        assert_feed_ok!(e, "\u306b\u307b\u3093", "", [0xa4, 0xcb, 0xa4, 0xdb, 0xa4, 0xf3]);
+        assert_feed_ok!(e, "\uff86\uff8e\uff9d", "", [0x8e, 0xc6, 0x8e, 0xce, 0x8e, 0xdd]);
+        assert_feed_ok!(e, "\u2460\u3349", "", [0xad, 0xa1, 0xad, 0xc0]); // NEC row 13
+        assert_feed_ok!(e, "\u9ed1\u2170", "", [0xfc, 0xee, 0xfc, 0xf1]); // NEC-selected IBM
+        assert_finish_ok!(e, []);
+    }
+
+    #[test]
+    fn test_encoder_invalid() {
+        let mut e = CP51932Encoding.encoder();
+        assert_feed_err!(e, "", "\uffff", "", []);
+        // JIS X 0212 is not a part of CP51932
+        assert_feed_err!(e, "", "\u736c", "\u8c78", []);
+        assert_finish_ok!(e, []);
+    }
+
+    #[test]
+    fn test_decoder_valid() {
+        let mut d = CP51932Encoding.decoder();
+        assert_feed_ok!(d, [0x41], [], "A");
+        assert_feed_ok!(d, [0xa4, 0xcb, 0xa4, 0xdb, 0xa4, 0xf3], [], "\u306b\u307b\u3093");
+        assert_feed_ok!(d, [0x8e, 0xc6, 0x8e, 0xce, 0x8e, 0xdd], [], "\uff86\uff8e\uff9d");
+        assert_feed_ok!(d, [0xad, 0xa1, 0xad, 0xc0], [], "\u2460\u3349");
+        assert_feed_ok!(d, [0xfc, 0xee, 0xfc, 0xf1], [], "\u9ed1\u2170");
+        assert_finish_ok!(d, "");
+    }
+
+    #[test]
+    fn test_decoder_valid_partial() {
+        let mut d = CP51932Encoding.decoder();
+        assert_feed_ok!(d, [], [0xa4], "");
+        assert_feed_ok!(d, [0xcb], [0x8e], "\u306b");
+        assert_feed_ok!(d, [0xc6], [], "\uff86");
+        assert_finish_ok!(d, "");
+    }
+
+    #[test]
+    fn test_decoder_no_0212() {
+        let mut d = CP51932Encoding.decoder();
+        assert_feed_err!(d, [], [0x8f], [0xcb, 0xc6], "");
+        assert_feed_err!(d, [0x41], [0x8f], [], "A");
+        assert_finish_ok!(d, "");
+    }
+
+    #[test]
+    fn test_decoder_invalid_partial() {
+        let mut d = CP51932Encoding.decoder();
+        assert_feed_ok!(d, [], [0xa4], "");
+        assert_finish_err!(d, "");
+
+        let mut d = CP51932Encoding.decoder();
+        assert_feed_ok!(d, [], [0x8e], "");
+        assert_finish_err!(d, "");
+    }
+}
+
+/**
+ * Windows code page 932, i.e. Shift_JIS with IBM/NEC extensions.
+ *
+ * This is a Japanese encoding for JIS X 0208
+ * compatible to the original assignments of JIS X 0201 (`[21-7E A1-DF]`).
+ * The 94 by 94 region of JIS X 0208 is sliced, or rather "shifted" into
+ * the odd half (odd row number) and even half (even row number),
+ * and merged into the 188 by 47 region mapped to `[81-9F E0-EF] [40-7E 80-FC]`.
+ * The remaining area, `[80 A0 F0-FF] [40-7E 80-FC]`, has been subjected to
+ * numerous extensions incompatible to each other.
+ * This particular implementation uses IBM/NEC extensions
+ * which assigns more characters to `[F0-FC 80-FC]` and also to the Private Use Area (PUA).
+ * It requires some cares to handle
+ * since the second byte of JIS X 0208 can have its MSB unset.
+ */
+#[deriving(Clone)]
+pub struct Windows31JEncoding;
+
+impl Encoding for Windows31JEncoding {
+    fn name(&self) -> &'static str { "windows-31j" }
+    fn whatwg_name(&self) -> Option<&'static str> { Some("shift_jis") } // WHATWG compatibility
+    fn covered_scripts(&self) -> &'static [&'static str] { JAPANESE_SCRIPTS }
+    fn assumes_normalization(&self) -> Option<NormalizationForm> { Some(NFC) }
+    fn encoder(&self) -> Box<Encoder> { Windows31JEncoder::new() }
+    fn decoder(&self) -> Box<Decoder> { Windows31JDecoder::new() }
+
+    fn validate(&self, input: &[u8]) -> Result<uint, CodecError> {
+        use codec::japanese::windows31j::internal::map_two_0208_bytes;
+
+        // mirrors the state machine of `Windows31JDecoder`, including where the errors end
+        let len = input.len();
+        let mut i = 0;
+        while i < len {
+            match input[i] {
+                0x00..0x80 | 0xa1..0xdf => { i += 1; }
+                lead @ 0x81..0x9f | lead @ 0xe0..0xfc => {
+                    if i + 1 == len {
+                        return Err(CodecError { upto: len as int,
+                                                cause: "incomplete sequence".into_maybe_owned(),
+                                                buffered: 1 });
+                    }
+                    let trail = input[i + 1];
+                    if map_two_0208_bytes(lead, trail) == 0xffff {
+                        // the decoder does not consume the trail byte
+                        return Err(CodecError {
+                            upto: i as int + 1,
+                            cause: format!("invalid trail byte 0x{:02X} after lead 0x{:02X}",
+                                           trail, lead).into_maybe_owned(),
+                            buffered: 0
+                        });
+                    }
+                    i += 2;
+                }
+                b => return Err(CodecError {
+                    upto: i as int + 1,
+                    cause: format!("invalid lead byte 0x{:02X}", b).into_maybe_owned(),
+                    buffered: 0
+                }),
+            }
+        }
+        Ok(len)
+    }
+}
+
+/// An encoder for Shift_JIS with IBM/NEC extensions.
+///
+/// Some characters have multiple representations (e.g. U+FFE2 is in `81 CA`, `EE F9` and
+/// `FA 54`) and the encoder picks one of them as the WHATWG Encoding standard does.
+/// An optional override table can pin such characters to the exact byte sequences
+/// produced by other implementations; it is consulted before any other mapping.
+#[deriving(Clone)]
+pub struct Windows31JEncoder {
+    overrides: Vec<(char, Vec<u8>)>,
+}
+
+impl Windows31JEncoder {
+    pub fn new() -> Box<Encoder> {
+        box Windows31JEncoder { overrides: Vec::new() } as Box<Encoder>
+    }
+
+    /// Creates a new encoder which encodes each character in `overrides`
+    /// to the corresponding byte sequence as is.
+    /// The table is copied, so it can be built at runtime (e.g. from a configuration file).
+    pub fn with_overrides(overrides: &[(char, &[u8])]) -> Box<Encoder> {
+        let overrides = overrides.iter().map(|&(ch, bytes)| (ch, Vec::from_slice(bytes)));
+        box Windows31JEncoder { overrides: overrides.collect() } as Box<Encoder>
+    }
+}
+
+impl Encoder for Windows31JEncoder {
+    fn from_self(&self) -> Box<Encoder> {
+        box Windows31JEncoder { overrides: self.overrides.clone() } as Box<Encoder>
+    }
+    fn clone_box(&self) -> Box<Encoder> { box self.clone() as Box<Encoder> }
+    fn is_ascii_compatible(&self) -> bool { true }
+
+    fn raw_feed(&mut self, input: &str, output: &mut ByteWriter) -> (uint, Option<CodecError>) {
+        output.writer_hint(input.len());
+
+        for ((i,j), ch) in input.index_iter() {
+            match self.overrides.iter().find(|&&(c, _)| c == ch) {
+                Some(&(_, ref bytes)) => { output.write_bytes(bytes.as_slice()); continue; }
+                None => {}
+            }
+            match ch {
+                '\u0000'..'\u0080' => { output.write_byte(ch as u8); }
+                '\u00a5' => { output.write_byte(0x5c); }
+                '\u203e' => { output.write_byte(0x7e); }
+                '\uff61'..'\uff9f' => { output.write_byte((ch as uint - 0xff61 + 0xa1) as u8); }
+                _ => {
+                    // corresponds to the "index shift_jis pointer" in the WHATWG spec
+                    let ptr = index::jis0208::backward_remapped(ch as u32);
+                    if ptr == 0xffff {
+                        return (i, Some(CodecError {
+                            upto: j as int, cause: "unrepresentable character".into_maybe_owned(),
+                            buffered: 0
+                        }));
+                    } else {
+                        let lead = ptr / 188;
+                        let leadoffset = if lead < 0x1f {0x81} else {0xc1};
+                        let trail = ptr % 188;
+                        let trailoffset = if trail < 0x3f {0x40} else {0x41};
+                        output.write_bytes([(lead + leadoffset) as u8,
+                                            (trail + trailoffset) as u8]);
+                    }
+                }
+            }
+        }
+        (input.len(), None)
+    }
+
+    fn raw_finish(&mut self, _output: &mut ByteWriter) -> Option<CodecError> {
+        None
+    }
+}
+
+ascii_compatible_stateful_decoder! {
+    #[doc="A decoder for Shift_JIS with IBM/NEC extensions."]
+    #[deriving(Clone)]
+    struct Windows31JDecoder;
+
+    module windows31j;
+
+    internal pub fn map_two_0208_bytes(lead: u8, trail: u8) -> u32 {
+        use index;
+
+        let lead = lead as uint;
+        let trail = trail as uint;
+        let leadoffset = if lead < 0xa0 {0x81} else {0xc1};
+        let trailoffset = if trail < 0x7f {0x40} else {0x41};
+        let index = match (lead, trail) {
+            (0xf0..0xf9, 0x40..0x7e) | (0xf0..0xf9, 0x80..0xfc) =>
+                return (0xe000 + (lead - 0xf0) * 188 + trail - trailoffset) as u32,
+            (0x81..0x9f, 0x40..0x7e) | (0x81..0x9f, 0x80..0xfc) |
+            (0xe0..0xfc, 0x40..0x7e) | (0xe0..0xfc, 0x80..0xfc) =>
+                (lead - leadoffset) * 188 + trail - trailoffset,
+            _ => 0xffff,
+        };
+        index::jis0208::forward(index as u16)
+    }
+
+    // shift_jis lead = 0x00
+    initial state S0(ctx) {
+        case b @ 0x00..0x80 => ctx.emit(b as u32);
+        case b @ 0xa1..0xdf => ctx.emit(0xff61 + b as u32 - 0xa1);
+        case b @ 0x81..0x9f | b @ 0xe0..0xfc => S1(ctx, b);
+        case b => ctx.err(format!("invalid lead byte 0x{:02X}", b));
+    }
+
+    // shift_jis lead != 0x00
+    state S1(ctx, lead: u8) {
+        case b => match map_two_0208_bytes(lead, b) {
+            // unconditional
+            0xffff => ctx.backup_and_err(1, format!("invalid trail byte 0x{:02X} \
+                                                     after lead 0x{:02X}", b, lead)),
+            ch => ctx.emit(ch)
+        };
+    }
+}
 
 #[cfg(test)]
 mod windows31j_tests {
@@ -555,6 +1203,35 @@ mod windows31j_tests {
         assert_finish_ok!(e, []);
     }
 
+    #[test]
+    fn test_encoder_overrides() {
+        use super::Windows31JEncoder;
+
+        // pins U+2170 (`FA 40` by default, an IBM extension) and U+FFE2 (`81 CA` by default,
+        // in JIS X 0208 row 2) to their codes in the NEC-selected IBM extensions
+        let mut e = Windows31JEncoder::with_overrides([('\u2170', b"\xEE\xEF"),
+                                                       ('\uffe2', b"\xEE\xF9")]);
+        assert_feed_ok!(e, "\u9ed1\u2170\uffe2", "", [0xfc, 0x4b, 0xee, 0xef, 0xee, 0xf9]);
+        assert_feed_ok!(e, "A\u2170\u65e5", "", [0x41, 0xee, 0xef, 0x93, 0xfa]);
+        assert_finish_ok!(e, []);
+
+        let mut e = e.from_self();
+        assert_feed_ok!(e, "\uffe2", "", [0xee, 0xf9]);
+        assert_finish_ok!(e, []);
+
+        // the table is copied, so it need not outlive the encoder
+        let mut e = {
+            let bytes = vec!(0xeeu8, 0xf9);
+            Windows31JEncoder::with_overrides([('\uffe2', bytes.as_slice())])
+        };
+        assert_feed_ok!(e, "\u2170\uffe2", "", [0xfa, 0x40, 0xee, 0xf9]);
+        assert_finish_ok!(e, []);
+
+        // the pinned sequences decode to the same characters
+        assert_eq!(Windows31JEncoding.decode([0xee, 0xef, 0xee, 0xf9], DecodeStrict),
+                   Ok("\u2170\uffe2".to_string()));
+    }
+
     #[test]
     fn test_encoder_invalid() {
         let mut e = Windows31JEncoding.encoder();
@@ -579,6 +1256,56 @@ mod windows31j_tests {
         assert_finish_ok!(d, "");
     }
 
+    #[test]
+    fn test_boundary_bytes() {
+        // 0x80 is a valid single byte for U+0080 in the WHATWG Shift_JIS,
+        // while 0xa0 and 0xfd-0xff are always invalid.
+        let mut d = Windows31JEncoding.decoder();
+        assert_feed_ok!(d, [0x41, 0x80, 0xdf, 0x80, 0xa1], [], "A\u0080\uff9f\u0080\uff61");
+        assert_feed_err!(d, [0x80], [0xa0], [0x80], "\u0080");
+        assert_feed_err!(d, [0xdf], [0xfd], [0xe0, 0x40], "\uff9f");
+        assert_finish_ok!(d, "");
+
+        let mut e = Windows31JEncoding.encoder();
+        assert_feed_ok!(e, "\u0080", "", [0x80]);
+        assert_feed_err!(e, "", "\u0081", "", []);
+        assert_finish_ok!(e, []);
+
+        assert_eq!(Windows31JEncoding.decode([0x80, 0xa0, 0x80], DecodeReplace),
+                   Ok("\u0080\ufffd\u0080".to_string()));
+        assert_eq!(Windows31JEncoding.encode("\u0080", EncodeStrict), Ok(vec!(0x80)));
+    }
+
+    #[test]
+    fn test_decoder_error_cause() {
+        let mut d = Windows31JEncoding.decoder();
+        let (_, err, _) = d.test_feed([0x81, 0x20]);
+        assert_eq!(err.unwrap().cause.as_slice(), "invalid trail byte 0x20 after lead 0x81");
+        let (_, err, _) = d.test_feed([0xa0]);
+        assert_eq!(err.unwrap().cause.as_slice(), "invalid lead byte 0xA0");
+    }
+
+    #[test]
+    fn test_decoder_pending_len() {
+        let mut d = Windows31JEncoding.decoder();
+        assert_feed_ok!(d, [0x41], [0x93], "A");
+        assert_eq!(d.pending_len(), 1);
+        assert_feed_ok!(d, [0xfa], [], "\u65e5");
+        assert_eq!(d.pending_len(), 0);
+        assert_finish_ok!(d, "");
+    }
+
+    #[test]
+    fn test_decoder_finish_buffered() {
+        let mut d = Windows31JEncoding.decoder();
+        assert_feed_ok!(d, [0x41], [0x93], "A");
+        let (err, _) = d.test_finish();
+        assert_eq!(err.map(|e| e.buffered), Some(1));
+
+        assert_feed_ok!(d, [0x93, 0xfa], [], "\u65e5");
+        assert_finish_ok!(d, "");
+    }
+
     #[test]
     fn test_decoder_eudc() {
         let mut d = Windows31JEncoding.decoder();
@@ -677,6 +1404,39 @@ mod windows31j_tests {
         assert_finish_ok!(d, "");
     }
 
+    #[test]
+    fn test_decoder_incomplete_at_end_with_trap() {
+        // the trap also handles an incomplete sequence reported by `raw_finish`
+        let input = [0x41, 0x93];
+        assert!(Windows31JEncoding.decode(input, DecodeStrict).is_err());
+        assert_eq!(Windows31JEncoding.decode(input, DecodeReplace), Ok("A\ufffd".to_string()));
+        assert_eq!(Windows31JEncoding.decode(input, DecodeIgnore), Ok("A".to_string()));
+        assert_eq!(Windows31JEncoding.decode([0x93], DecodeReplace), Ok("\ufffd".to_string()));
+        assert_eq!(Windows31JEncoding.decode_with_offsets(input, DecodeReplace),
+                   Ok(("A\ufffd".to_string(), vec!(0, 1))));
+    }
+
+    #[test]
+    fn test_decoder_empty_feed() {
+        // an empty feed should never drop or corrupt pending bytes
+        let mut d = Windows31JEncoding.decoder();
+        assert_feed_ok!(d, [], [0x93], "");
+        assert_feed_ok!(d, [], [], "");
+        assert_feed_ok!(d, [], [], "");
+        assert_feed_ok!(d, [0xfa], [], "\u65e5");
+        assert_feed_ok!(d, [], [], "");
+        assert_feed_ok!(d, [0x41], [0xf0], "A");
+        assert_feed_ok!(d, [], [], "");
+        assert_feed_ok!(d, [0x40], [], "\ue000");
+        assert_finish_ok!(d, "");
+
+        // the pending lead byte is still reported at the end
+        let mut d = Windows31JEncoding.decoder();
+        assert_feed_ok!(d, [], [0x93], "");
+        assert_feed_ok!(d, [], [], "");
+        assert_finish_err!(d, "");
+    }
+
     #[bench]
     fn bench_encode_short_text(bencher: &mut test::Bencher) {
         static Encoding: Windows31JEncoding = Windows31JEncoding;
@@ -699,48 +1459,215 @@ mod windows31j_tests {
 }
 
 /**
- * ISO-2022-JP.
- *
- * This version of ISO-2022-JP does not correspond to any standardized repertoire of character sets
- * due to the widespread implementation differences. The following character sets are supported:
+ * MacJapanese, i.e. Shift_JIS as used in the classic Mac OS (KanjiTalk). Decoder only.
  *
- * - JIS X 0201-1976 roman (`ESC ( J` or `ESC ( B`; the latter is originally allocated to ASCII
- *   but willfully violated)
- * - JIS X 0201-1976 kana (`ESC ( I`)
- * - JIS X 0208-1983 (`ESC $ B` or `ESC $ @`; the latter is originally allocated to JIS X 0208-1978
- *   but willfully violated)
- * - JIS X 0212-1990 (`ESC $ ( D`, XXX asymmetric support)
+ * This shares the structure of Shift_JIS with Windows code page 932,
+ * but has different single-byte assignments inherited from the Mac character set:
+ * `5C` is a yen sign, `80` is a backslash, and `A0` and `FD-FF` are
+ * a no-break space, a copyright sign, a trade mark sign and a horizontal ellipsis respectively.
+ * Double-byte sequences are decoded according to JIS X 0208 (`[81-84 88-9F E0-EA]`)
+ * and the Apple extensions in the otherwise unassigned rows (`[85-88]` and `[EB-ED]`),
+ * which include enclosed numerals, squared words and vertical forms.
+ * The extensions Apple maps to character sequences are not supported and treated as invalid,
+ * as are the IBM/NEC extensions of Windows code page 932.
  */
 #[deriving(Clone)]
-pub struct ISO2022JPEncoding;
-
-impl Encoding for ISO2022JPEncoding {
-    fn name(&self) -> &'static str { "iso-2022-jp" }
-    fn whatwg_name(&self) -> Option<&'static str> { Some("iso-2022-jp") }
-    fn encoder(&self) -> Box<Encoder> { ISO2022JPEncoder::new() }
-    fn decoder(&self) -> Box<Decoder> { ISO2022JPDecoder::new() }
+pub struct XMacJapaneseEncoding;
+
+impl Encoding for XMacJapaneseEncoding {
+    fn name(&self) -> &'static str { "mac-japanese" }
+    fn covered_scripts(&self) -> &'static [&'static str] { JAPANESE_SCRIPTS }
+    fn can_encode_direction(&self) -> bool { false }
+    fn encoder(&self) -> Box<Encoder> { ::codec::error::ErrorEncoding.encoder() }
+    fn decoder(&self) -> Box<Decoder> { XMacJapaneseDecoder::new() }
 }
 
-#[deriving(PartialEq,Clone)]
-enum ISO2022JPState {
-    ASCII, // U+0000..007F, U+00A5, U+203E
-    Katakana, // JIS X 0201: U+FF61..FF9F
-    Lead, // JIS X 0208
-}
+stateful_decoder! {
+    #[doc="A decoder for MacJapanese."]
+    #[deriving(Clone)]
+    struct XMacJapaneseDecoder;
 
-/// An encoder for ISO-2022-JP without JIS X 0212/0213 support.
-#[deriving(Clone)]
-pub struct ISO2022JPEncoder {
-    st: ISO2022JPState
-}
+    module xmacjapanese;
 
-impl ISO2022JPEncoder {
-    pub fn new() -> Box<Encoder> { box ISO2022JPEncoder { st: ASCII } as Box<Encoder> }
+    ascii_compatible false; // `5C` is a yen sign
+
+    internal pub fn map_two_0208_bytes(lead: u8, trail: u8) -> u32 {
+        use index;
+
+        let lead = lead as uint;
+        let trail = trail as uint;
+        let leadoffset = if lead < 0xa0 {0x81} else {0xc1};
+        let trailoffset = if trail < 0x7f {0x40} else {0x41};
+        let index = match (lead, trail) {
+            (0x81..0x9f, 0x40..0x7e) | (0x81..0x9f, 0x80..0xfc) |
+            (0xe0..0xed, 0x40..0x7e) | (0xe0..0xed, 0x80..0xfc) =>
+                (lead - leadoffset) * 188 + trail - trailoffset,
+            _ => 0xffff,
+        };
+        match index {
+            // JIS X 0208 rows 9-15 and 85-90 are Apple extensions
+            752..1409 | 7896..8459 => index::x_mac_japanese::forward(index as u16),
+            _ => index::jis0208::forward(index as u16),
+        }
+    }
+
+    // shift_jis lead = 0x00
+    initial state S0(ctx) {
+        case b @ 0x00..0x5b | b @ 0x5d..0x7f => ctx.emit(b as u32);
+        case 0x5c => ctx.emit(0xa5);
+        case 0x80 => ctx.emit(0x5c);
+        case 0xa0 => ctx.emit(0xa0);
+        case b @ 0xa1..0xdf => ctx.emit(0xff61 + b as u32 - 0xa1);
+        case 0xfd => ctx.emit(0xa9);
+        case 0xfe => ctx.emit(0x2122);
+        case 0xff => ctx.emit(0x2026);
+        case b => S1(ctx, b); // [81-9F E0-FC]
+        final => ctx.reset();
+    }
+
+    // shift_jis lead != 0x00
+    state S1(ctx, lead: u8) {
+        case b => match map_two_0208_bytes(lead, b) {
+            0xffff if b < 0x40 || b == 0x7f || b > 0xfc =>
+                ctx.backup_and_err(1, format!("invalid trail byte 0x{:02X} \
+                                               after lead 0x{:02X}", b, lead)),
+            0xffff => ctx.err(format!("invalid sequence 0x{:02X} 0x{:02X}", lead, b)),
+            ch => ctx.emit(ch)
+        };
+        final => ctx.err("incomplete sequence");
+    }
+}
+
+#[cfg(test)]
+mod xmacjapanese_tests {
+    use super::XMacJapaneseEncoding;
+    use testutils;
+    use types::*;
+
+    #[test]
+    fn test_decoder_valid() {
+        let mut d = XMacJapaneseEncoding.decoder();
+        assert_feed_ok!(d, [0x41], [], "A");
+        assert_feed_ok!(d, [0x5c, 0x80, 0x7e], [], "\u00a5\\~");
+        assert_feed_ok!(d, [0x82, 0xc9, 0x82, 0xd9, 0x82, 0xf1], [], "\u306b\u307b\u3093");
+        assert_feed_ok!(d, [0xc6, 0xce, 0xdd], [], "\uff86\uff8e\uff9d");
+        assert_feed_ok!(d, [0x93, 0xfa], [0x96], "\u65e5");
+        assert_feed_ok!(d, [0x7b], [], "\u672c");
+        assert_finish_ok!(d, "");
+    }
+
+    #[test]
+    fn test_decoder_apple_single_bytes() {
+        // not in Shift_JIS or Windows code page 932
+        let mut d = XMacJapaneseEncoding.decoder();
+        assert_feed_ok!(d, [0xa0, 0xfd, 0xfe, 0xff], [], "\u00a0\u00a9\u2122\u2026");
+        assert_finish_ok!(d, "");
+        assert!(::all::WINDOWS_31J.decode([0xfd], DecodeStrict).is_err());
+    }
+
+    #[test]
+    fn test_decoder_apple_extensions() {
+        let mut d = XMacJapaneseEncoding.decoder();
+        assert_feed_ok!(d, [0x85, 0x40, 0x85, 0x9f, 0x85, 0xb3], [], "\u2460\u2160\u2170");
+        assert_feed_ok!(d, [0x86, 0x9b, 0x87, 0x40, 0x87, 0xa0], [], "\u2116\u3230\u3322");
+        assert_feed_ok!(d, [0x88, 0x6a, 0x88, 0x9f], [], "\u30f7\u4e9c");
+        assert_feed_ok!(d, [0xeb, 0x69, 0xeb, 0x6a], [], "\ufe35\ufe36");
+        assert_finish_ok!(d, "");
+
+        // mapped to character sequences by Apple
+        assert_feed_err!(d, [], [0x85, 0x91], [], "");
+        assert_feed_err!(d, [], [0xeb, 0x41], [], "");
+        assert_finish_ok!(d, "");
+    }
+
+    #[test]
+    fn test_decoder_no_ibm_nec_extensions() {
+        // NEC row 13 and IBM extensions of Windows code page 932 are not MacJapanese
+        let mut d = XMacJapaneseEncoding.decoder();
+        assert_feed_err!(d, [], [0xed, 0x9f], [], "");
+        assert_feed_err!(d, [], [0xee, 0xef], [], "");
+        assert_feed_err!(d, [], [0xfa, 0x40], [], "");
+        assert_feed_err!(d, [], [0xf0, 0x40], [], "");
+        assert_feed_err!(d, [], [0x81], [0x20], "");
+        assert_finish_ok!(d, "");
+    }
+
+    #[test]
+    fn test_encoder() {
+        assert!(!XMacJapaneseEncoding.can_encode_direction());
+        assert!(XMacJapaneseEncoding.encode("A", EncodeStrict).is_err());
+    }
+}
+
+/**
+ * ISO-2022-JP.
+ *
+ * This version of ISO-2022-JP does not correspond to any standardized repertoire of character sets
+ * due to the widespread implementation differences. The following character sets are supported:
+ *
+ * - JIS X 0201-1976 roman (`ESC ( J` or `ESC ( B`; the latter is originally allocated to ASCII
+ *   but willfully violated)
+ * - JIS X 0201-1976 kana (`ESC ( I`)
+ * - JIS X 0208-1983 (`ESC $ B` or `ESC $ @`; the latter is originally allocated to JIS X 0208-1978
+ *   but willfully violated)
+ * - JIS X 0212-1990 (`ESC $ ( D`, XXX asymmetric support)
+ *
+ * ## Half-width katakana
+ *
+ * RFC 1468 does not allow JIS X 0201 kana, but it is common in the real world
+ * so the decoder accepts `ESC ( I` by default.
+ * When `reject_katakana` is set, the decoder reports an error at each `ESC ( I`
+ * for the strict validation. The encoder is not affected.
+ */
+#[deriving(Clone)]
+pub struct ISO2022JPEncoding {
+    /// True if the decoder rejects the JIS X 0201 kana designation (`ESC ( I`).
+    pub reject_katakana: bool,
+}
+
+impl Encoding for ISO2022JPEncoding {
+    fn name(&self) -> &'static str { "iso-2022-jp" }
+    fn whatwg_name(&self) -> Option<&'static str> { Some("iso-2022-jp") }
+    fn covered_scripts(&self) -> &'static [&'static str] { JAPANESE_SCRIPTS }
+    fn is_stateful(&self) -> bool { true }
+    fn assumes_normalization(&self) -> Option<NormalizationForm> { Some(NFC) }
+    fn encoder(&self) -> Box<Encoder> { ISO2022JPEncoder::new() }
+    fn decoder(&self) -> Box<Decoder> {
+        if self.reject_katakana {
+            ISO2022JPStrictDecoder::new(ISO2022JPDecoder::new())
+        } else {
+            ISO2022JPDecoder::new()
+        }
+    }
+}
+
+#[deriving(PartialEq,Clone)]
+enum ISO2022JPState {
+    ASCII, // U+0000..007F, U+00A5, U+203E
+    Katakana, // JIS X 0201: U+FF61..FF9F
+    Lead, // JIS X 0208
+}
+
+/// An encoder for ISO-2022-JP without JIS X 0212/0213 support.
+#[deriving(Clone)]
+pub struct ISO2022JPEncoder {
+    st: ISO2022JPState
+}
+
+impl ISO2022JPEncoder {
+    pub fn new() -> Box<Encoder> { box ISO2022JPEncoder { st: ASCII } as Box<Encoder> }
 }
 
 impl Encoder for ISO2022JPEncoder {
     fn from_self(&self) -> Box<Encoder> { ISO2022JPEncoder::new() }
-    fn is_ascii_compatible(&self) -> bool { true }
+    fn clone_box(&self) -> Box<Encoder> { box self.clone() as Box<Encoder> }
+    // not ASCII compatible, as the ASCII character is encoded differently depending on the state.
+    fn is_ascii_compatible(&self) -> bool { false }
+
+    fn replacement_bytes(&self) -> Option<Vec<u8>> {
+        let bytes: &[u8] = if self.st == ASCII { b"?" } else { b"\x1b(B?" };
+        Some(Vec::from_slice(bytes))
+    }
 
     fn raw_feed(&mut self, input: &str, output: &mut ByteWriter) -> (uint, Option<CodecError>) {
         output.writer_hint(input.len());
@@ -770,14 +1697,14 @@ impl Encoder for ISO2022JPEncoder {
                     if ptr == 0xffff {
                         self.st = st; // do NOT reset the state!
                         return (i, Some(CodecError {
-                            upto: j as int, cause: "unrepresentable character".into_maybe_owned()
+                            upto: j as int, cause: "unrepresentable character".into_maybe_owned(),
+                            buffered: 0
                         }));
                     } else {
                         ensure_Lead!();
                         let lead = ptr / 94 + 0x21;
                         let trail = ptr % 94 + 0x21;
-                        output.write_byte(lead as u8);
-                        output.write_byte(trail as u8);
+                        output.write_bytes([lead as u8, trail as u8]);
                     }
                 }
             }
@@ -787,7 +1714,13 @@ impl Encoder for ISO2022JPEncoder {
         (input.len(), None)
     }
 
-    fn raw_finish(&mut self, _output: &mut ByteWriter) -> Option<CodecError> {
+    fn raw_finish(&mut self, output: &mut ByteWriter) -> Option<CodecError> {
+        // the encoded text should end in the ASCII state (RFC 1468).
+        // the escape sequence is not repeated as the state is reset here.
+        if self.st != ASCII {
+            output.write_bytes(b"\x1b(B");
+            self.st = ASCII;
+        }
         None
     }
 }
@@ -829,7 +1762,7 @@ stateful_decoder! {
     initial state ASCII(ctx) {
         case 0x1b => EscapeStart(ctx);
         case b @ 0x00..0x7f => ctx.emit(b as u32), ASCII(ctx);
-        case _ => ctx.err("invalid sequence"), ASCII(ctx);
+        case b => ctx.err(format!("invalid byte 0x{:02X}", b)), ASCII(ctx);
         final => ctx.reset();
     }
 
@@ -853,7 +1786,7 @@ stateful_decoder! {
     checkpoint state Katakana(ctx) {
         case 0x1b => EscapeStart(ctx);
         case b @ 0x21..0x5f => ctx.emit(0xff61 + b as u32 - 0x21), Katakana(ctx);
-        case _ => ctx.err("invalid sequence"), Katakana(ctx);
+        case b => ctx.err(format!("invalid byte 0x{:02X}", b)), Katakana(ctx);
         final => ctx.reset();
     }
 
@@ -896,7 +1829,7 @@ stateful_decoder! {
     state Trail0208(ctx, lead: u8) {
         case b =>
             match map_two_0208_bytes(lead, b) {
-                0xffff => ctx.err("invalid sequence"),
+                0xffff => ctx.err(format!("invalid sequence 0x{:02X} 0x{:02X}", lead, b)),
                 ch => ctx.emit(ch as u32)
             },
             Lead0208(ctx);
@@ -907,7 +1840,7 @@ stateful_decoder! {
     state Trail0212(ctx, lead: u8) {
         case b =>
             match map_two_0212_bytes(lead, b) {
-                0xffff => ctx.err("invalid sequence"),
+                0xffff => ctx.err(format!("invalid sequence 0x{:02X} 0x{:02X}", lead, b)),
                 ch => ctx.emit(ch as u32)
             },
             Lead0212(ctx);
@@ -915,16 +1848,115 @@ stateful_decoder! {
     }
 }
 
+/**
+ * A decoder wrapper for ISO-2022-JP which rejects the JIS X 0201 kana designation (`ESC ( I`).
+ *
+ * The designation itself still takes effect after the error,
+ * so the following bytes are decoded as half-width katakana when the error is ignored.
+ * This relies on the fact that `ESC` always starts an escape sequence
+ * unless the wrapped decoder reports an error for it.
+ */
+pub struct ISO2022JPStrictDecoder {
+    inner: Box<Decoder>,
+    // the number of bytes of `ESC (` at the end of the last input
+    escape: uint,
+}
+
+impl ISO2022JPStrictDecoder {
+    pub fn new(inner: Box<Decoder>) -> Box<Decoder> {
+        box ISO2022JPStrictDecoder { inner: inner, escape: 0 } as Box<Decoder>
+    }
+}
+
+impl Decoder for ISO2022JPStrictDecoder {
+    fn from_self(&self) -> Box<Decoder> { ISO2022JPStrictDecoder::new(self.inner.from_self()) }
+    fn clone_box(&self) -> Box<Decoder> {
+        box ISO2022JPStrictDecoder { inner: self.inner.clone_box(), escape: self.escape }
+            as Box<Decoder>
+    }
+    fn is_ascii_compatible(&self) -> bool { false }
+    fn pending_len(&self) -> uint { self.inner.pending_len() }
+
+    fn raw_feed(&mut self, input: &[u8], output: &mut StringWriter) -> (uint, Option<CodecError>) {
+        let mut escape = self.escape;
+        for (i, &b) in input.iter().enumerate() {
+            escape = match (escape, b) {
+                (_, 0x1b) => 1,
+                (1, 0x28) => 2,
+                (2, 0x49) => {
+                    self.escape = 0;
+                    let (processed, err) = self.inner.raw_feed(input.slice_to(i), output);
+                    if err.is_some() { return (processed, err); }
+                    // switches the wrapped decoder to the Katakana state; this never fails
+                    self.inner.raw_feed(input.slice(i, i + 1), output);
+                    return (processed, Some(CodecError {
+                        upto: i as int + 1,
+                        cause: "half-width katakana is not allowed".into_maybe_owned(),
+                        buffered: 0
+                    }));
+                }
+                _ => 0,
+            };
+        }
+        self.escape = escape;
+        self.inner.raw_feed(input, output)
+    }
+
+    fn raw_finish(&mut self, output: &mut StringWriter) -> Option<CodecError> {
+        self.escape = 0;
+        self.inner.raw_finish(output)
+    }
+}
+
 #[cfg(test)]
 mod iso2022jp_tests {
     extern crate test;
     use super::ISO2022JPEncoding;
+    use all::ISO_2022_JP;
     use testutils;
     use types::*;
 
+    #[test]
+    fn test_encoder_char_cost() {
+        let mut e = ISO_2022_JP.encoder();
+        assert_eq!(e.char_cost('A'), Some(1));
+        assert_eq!(e.char_cost('\u3042'), Some(5)); // ESC $ B and two bytes
+        assert_eq!(e.char_cost('\uff71'), Some(4)); // ESC ( I and one byte
+        assert_eq!(e.char_cost('\uac00'), None);
+        assert_feed_ok!(e, "\u3042", "", [0x1b, 0x24, 0x42, 0x24, 0x22]);
+        assert_eq!(e.char_cost('\u3044'), Some(2));
+        assert_eq!(e.char_cost('A'), Some(4)); // ESC ( B and one byte
+        assert_eq!(e.char_cost('\u3044'), Some(2)); // the state is unchanged
+        assert_feed_ok!(e, "\u3044", "", [0x24, 0x24]);
+        assert_finish_ok!(e, [0x1b, 0x28, 0x42]);
+    }
+
+    #[test]
+    fn test_encoder_finish_frame() {
+        let mut e = ISO_2022_JP.encoder();
+        let mut frames = Vec::new();
+        for _ in range(0u, 2) {
+            let mut frame = Vec::new();
+            let (_, err) = e.raw_feed("A\u3042", &mut frame);
+            assert!(err.is_none());
+            assert!(e.finish_frame(&mut frame).is_none());
+            frames.push(frame);
+        }
+        // each frame starts and ends in ASCII
+        assert_eq!(frames, vec!(vec!(0x41, 0x1b, 0x24, 0x42, 0x24, 0x22, 0x1b, 0x28, 0x42),
+                                vec!(0x41, 0x1b, 0x24, 0x42, 0x24, 0x22, 0x1b, 0x28, 0x42)));
+
+        // the frame already in ASCII needs nothing to close
+        let mut frame = Vec::new();
+        let (_, err) = e.raw_feed("B", &mut frame);
+        assert!(err.is_none());
+        assert!(e.finish_frame(&mut frame).is_none());
+        assert_eq!(frame, vec!(0x42));
+    }
+
     #[test]
     fn test_encoder_valid() {
-        let mut e = ISO2022JPEncoding.encoder();
+        let mut e = ISO_2022_JP.encoder();
         assert_feed_ok!(e, "A", "", [0x41]);
         assert_feed_ok!(e, "BC", "", [0x42, 0x43]);
         assert_feed_ok!(e, "\x1b\x24\x42", "", [0x1b, 0x24, 0x42]); // no round-trip guarantee
@@ -952,7 +1984,7 @@ mod iso2022jp_tests {
         static Ae: &'static [u8] = &[0x1b, 0x28, 0x42, 0x20];
         static Be: &'static [u8] = &[0x1b, 0x24, 0x42, 0x25, 0x4d];
         static Ce: &'static [u8] = &[0x1b, 0x28, 0x49, 0x48];
-        let mut e = ISO2022JPEncoding.encoder();
+        let mut e = ISO_2022_JP.encoder();
         let decoded = [ "\x20", Bd, Cd, Ad, Cd, Bd, Ad].concat();
         let encoded = [&[0x20], Be, Ce, Ae, Ce, Be, Ae].concat_vec();
         assert_feed_ok!(e, decoded.as_slice(), "", encoded.as_slice());
@@ -961,7 +1993,7 @@ mod iso2022jp_tests {
 
     #[test]
     fn test_encoder_invalid() {
-        let mut e = ISO2022JPEncoding.encoder();
+        let mut e = ISO_2022_JP.encoder();
         assert_feed_err!(e, "", "\uffff", "", []);
         assert_feed_err!(e, "?", "\uffff", "!", [0x3f]);
         // JIS X 0212 is not supported in the encoder
@@ -969,9 +2001,63 @@ mod iso2022jp_tests {
         assert_finish_ok!(e, []);
     }
 
+    #[test]
+    fn test_encoder_replacement() {
+        let mut e = ISO_2022_JP.encoder();
+        assert_eq!(e.replacement_bytes(), Some(vec!(0x3f)));
+        assert_feed_ok!(e, "\u65e5", "", [0x1b, 0x24, 0x42, 0x46, 0x7c]);
+        assert_eq!(e.replacement_bytes(), Some(vec!(0x1b, 0x28, 0x42, 0x3f)));
+        assert_feed_ok!(e, "\uff86", "", [0x1b, 0x28, 0x49, 0x46]);
+        assert_eq!(e.replacement_bytes(), Some(vec!(0x1b, 0x28, 0x42, 0x3f)));
+        assert_feed_ok!(e, "A", "", [0x1b, 0x28, 0x42, 0x41]);
+        assert_eq!(e.replacement_bytes(), Some(vec!(0x3f)));
+        assert_finish_ok!(e, []);
+
+        // the replacement should be emitted in the ASCII state
+        assert_eq!(ISO_2022_JP.encode("\u65e5\uffff\u672c", EncodeReplace),
+                   Ok(vec!(0x1b, 0x24, 0x42, 0x46, 0x7c, 0x1b, 0x28, 0x42, 0x3f,
+                           0x1b, 0x24, 0x42, 0x4b, 0x5c, 0x1b, 0x28, 0x42)));
+    }
+
+    #[test]
+    fn test_encoder_finish() {
+        let mut e = ISO_2022_JP.encoder();
+        assert_feed_ok!(e, "\u65e5", "", [0x1b, 0x24, 0x42, 0x46, 0x7c]);
+        assert_finish_ok!(e, [0x1b, 0x28, 0x42]);
+        assert_finish_ok!(e, []); // the escape sequence is emitted only once
+
+        assert_feed_ok!(e, "\uff86", "", [0x1b, 0x28, 0x49, 0x46]);
+        assert_finish_ok!(e, [0x1b, 0x28, 0x42]);
+
+        // nothing to emit when the encoder is already in the ASCII state
+        assert_feed_ok!(e, "A", "", [0x41]);
+        assert_finish_ok!(e, []);
+
+        assert_eq!(ISO_2022_JP.encode("\u65e5\u672cA", EncodeStrict),
+                   Ok(vec!(0x1b, 0x24, 0x42, 0x46, 0x7c, 0x4b, 0x5c, 0x1b, 0x28, 0x42, 0x41)));
+        assert_eq!(ISO_2022_JP.encode("A\u65e5\u672c", EncodeStrict),
+                   Ok(vec!(0x41, 0x1b, 0x24, 0x42, 0x46, 0x7c, 0x4b, 0x5c, 0x1b, 0x28, 0x42)));
+    }
+
+    #[test]
+    fn test_encoder_chunked() {
+        // the state is kept across chunks, so no escape sequence at the chunk boundary
+        let mut e = ISO_2022_JP.encoder();
+        assert_feed_ok!(e, "\u306b\u307b", "", [0x1b, 0x24, 0x42, 0x24, 0x4b, 0x24, 0x5b]);
+        assert_feed_ok!(e, "\u3093\u65e5\u672c", "", [0x24, 0x73, 0x46, 0x7c, 0x4b, 0x5c]);
+        assert_finish_ok!(e, [0x1b, 0x28, 0x42]);
+
+        let mut e = ISO_2022_JP.encoder();
+        assert_feed_ok!(e, "\uff86", "", [0x1b, 0x28, 0x49, 0x46]);
+        assert_feed_ok!(e, "\uff8e", "", [0x4e]);
+        assert_feed_ok!(e, "", "", []);
+        assert_feed_ok!(e, "\uff9d", "", [0x5d]);
+        assert_finish_ok!(e, [0x1b, 0x28, 0x42]);
+    }
+
     #[test]
     fn test_decoder_valid() {
-        let mut d = ISO2022JPEncoding.decoder();
+        let mut d = ISO_2022_JP.decoder();
         assert_feed_ok!(d, [0x41], [], "A");
         assert_feed_ok!(d, [0x42, 0x43], [], "BC");
         assert_feed_ok!(d, [0x1b, 0x28, 0x4a,
@@ -994,17 +2080,17 @@ mod iso2022jp_tests {
                             0x58, 0x59, 0x5a], [], "XYZ");
         assert_finish_ok!(d, "");
 
-        let mut d = ISO2022JPEncoding.decoder();
+        let mut d = ISO_2022_JP.decoder();
         assert_feed_ok!(d, [0x1b, 0x24, 0x42,
                             0x24, 0x4b, 0x24, 0x5b, 0x24, 0x73], [], "\u306b\u307b\u3093");
         assert_finish_ok!(d, "");
 
-        let mut d = ISO2022JPEncoding.decoder();
+        let mut d = ISO_2022_JP.decoder();
         assert_feed_ok!(d, [0x1b, 0x28, 0x49,
                             0x46, 0x4e, 0x5d], [], "\uff86\uff8e\uff9d");
         assert_finish_ok!(d, "");
 
-        let mut d = ISO2022JPEncoding.decoder();
+        let mut d = ISO_2022_JP.decoder();
         assert_feed_ok!(d, [0x1b, 0x24, 0x28, 0x44,
                             0x4b, 0x46], [], "\u736c");
         assert_finish_ok!(d, "");
@@ -1023,7 +2109,7 @@ mod iso2022jp_tests {
         static Be: &'static [u8] = &[0x1b, 0x24, 0x42,       0x25, 0x4d];
         static Ce: &'static [u8] = &[0x1b, 0x28, 0x49,       0x48];
         static De: &'static [u8] = &[0x1b, 0x24, 0x28, 0x44, 0x50, 0x4b];
-        let mut d = ISO2022JPEncoding.decoder();
+        let mut d = ISO_2022_JP.decoder();
         let decoded = [ "\x20",Ad,Bd,Bd,Cd,Cd,Ad,Cd,Bd,Ad,Dd,Dd,Bd,Dd,Cd,Dd,Ad].concat();
         let encoded = [&[0x20],Ae,Be,Be,Ce,Ce,Ae,Ce,Be,Ae,De,De,Be,De,Ce,De,Ae].concat_vec();
         assert_feed_ok!(d, encoded.as_slice(), [], decoded.as_slice());
@@ -1032,7 +2118,7 @@ mod iso2022jp_tests {
 
     #[test]
     fn test_decoder_valid_partial() {
-        let mut d = ISO2022JPEncoding.decoder();
+        let mut d = ISO_2022_JP.decoder();
 
         assert_feed_ok!(d, [], [0x1b], "");
         assert_feed_ok!(d, [], [0x28], "");
@@ -1068,7 +2154,7 @@ mod iso2022jp_tests {
     #[test]
     fn test_decoder_carriage_return() {
         // CR in Lead state "resets to ASCII"
-        let mut d = ISO2022JPEncoding.decoder();
+        let mut d = ISO_2022_JP.decoder();
         assert_feed_ok!(d, [0x1b, 0x24, 0x42,
                             0x25, 0x4d,
                             0x0a,
@@ -1080,7 +2166,7 @@ mod iso2022jp_tests {
         assert_finish_ok!(d, "");
 
         // other states don't allow CR
-        let mut d = ISO2022JPEncoding.decoder();
+        let mut d = ISO_2022_JP.decoder();
         assert_feed_err!(d, [0x1b, 0x28, 0x49, 0x48], [0x0a], [], "\uff88"); // Katakana
         assert_feed_err!(d, [0x1b, 0x24, 0x42], [0x25, 0x0a], [], ""); // Trail
         assert_finish_ok!(d, "");
@@ -1088,47 +2174,72 @@ mod iso2022jp_tests {
 
     #[test]
     fn test_decoder_invalid_partial() {
-        let mut d = ISO2022JPEncoding.decoder();
+        let mut d = ISO_2022_JP.decoder();
         assert_feed_ok!(d, [0x1b, 0x24, 0x42, 0x24, 0x4b], [0x24], "\u306b");
         assert_finish_err!(d, "");
 
-        let mut d = ISO2022JPEncoding.decoder();
+        let mut d = ISO_2022_JP.decoder();
         assert_feed_ok!(d, [0x1b, 0x24, 0x28, 0x44, 0x4b, 0x46], [0x50], "\u736c");
         assert_finish_err!(d, "");
     }
 
+    #[test]
+    fn test_decoder_reject_katakana() {
+        static STRICT: ISO2022JPEncoding = ISO2022JPEncoding { reject_katakana: true };
+
+        let mut d = STRICT.decoder();
+        assert_feed_ok!(d, [0x41, 0x1b, 0x24, 0x42, 0x24, 0x4b], [], "A\u306b");
+        assert_feed_err!(d, [], [0x1b, 0x28, 0x49], [0x46], "");
+        assert_feed_ok!(d, [0x46, 0x1b, 0x28, 0x4a, 0x42], [], "\uff86B"); // the mode persists
+        assert_finish_ok!(d, "");
+
+        // the designation split across feeds
+        let mut d = STRICT.decoder();
+        assert_feed_ok!(d, [], [0x1b], "");
+        assert_feed_ok!(d, [], [0x28], "");
+        assert_feed_err!(d, [], [0x49], [0x46], "");
+        assert_feed_ok!(d, [0x46], [], "\uff86");
+        assert_finish_ok!(d, "");
+
+        assert!(STRICT.decode([0x1b, 0x28, 0x49, 0x46, 0x1b, 0x28, 0x42], DecodeStrict).is_err());
+        assert_eq!(STRICT.decode([0x1b, 0x28, 0x49, 0x46, 0x1b, 0x28, 0x42], DecodeReplace),
+                   Ok("\ufffd\uff86".to_string()));
+        assert_eq!(ISO_2022_JP.decode([0x1b, 0x28, 0x49, 0x46, 0x1b, 0x28, 0x42], DecodeStrict),
+                   Ok("\uff86".to_string()));
+    }
+
     #[test]
     fn test_decoder_invalid_partial_escape() {
-        let mut d = ISO2022JPEncoding.decoder();
+        let mut d = ISO_2022_JP.decoder();
         assert_feed_ok!(d, [], [0x1b], "");
         assert_finish_err!(d, "");
 
-        let mut d = ISO2022JPEncoding.decoder();
+        let mut d = ISO_2022_JP.decoder();
         assert_feed_ok!(d, [], [0x1b, 0x24], "");
         assert_finish_err!(d, ""); // no backup
 
-        let mut d = ISO2022JPEncoding.decoder();
+        let mut d = ISO_2022_JP.decoder();
         assert_feed_ok!(d, [], [0x1b, 0x24, 0x28], "");
         assert_finish_err!(d, -1, ""); // backup of -1, not -2
 
-        let mut d = ISO2022JPEncoding.decoder();
+        let mut d = ISO_2022_JP.decoder();
         assert_feed_ok!(d, [], [0x1b, 0x28], "");
         assert_finish_err!(d, ""); // no backup
 
-        assert_eq!(ISO2022JPEncoding.decode([0x1b], DecodeReplace),
+        assert_eq!(ISO_2022_JP.decode([0x1b], DecodeReplace),
                    Ok("\ufffd".to_string()));
-        assert_eq!(ISO2022JPEncoding.decode([0x1b, 0x24], DecodeReplace),
+        assert_eq!(ISO_2022_JP.decode([0x1b, 0x24], DecodeReplace),
                    Ok("\ufffd".to_string()));
-        assert_eq!(ISO2022JPEncoding.decode([0x1b, 0x24, 0x28], DecodeReplace),
+        assert_eq!(ISO_2022_JP.decode([0x1b, 0x24, 0x28], DecodeReplace),
                    Ok("\ufffd\x28".to_string()));
-        assert_eq!(ISO2022JPEncoding.decode([0x1b, 0x28], DecodeReplace),
+        assert_eq!(ISO_2022_JP.decode([0x1b, 0x28], DecodeReplace),
                    Ok("\ufffd".to_string()));
     }
 
     #[test]
     fn test_decoder_invalid_escape() {
         // also tests allowed but never used escape codes in ISO 2022
-        let mut d = ISO2022JPEncoding.decoder();
+        let mut d = ISO_2022_JP.decoder();
         macro_rules! reset(() => (
             assert_feed_ok!(d, [0x41, 0x42, 0x43, 0x1b, 0x24, 0x42, 0x21, 0x21], [], "ABC\u3000")))
 
@@ -1208,7 +2319,7 @@ mod iso2022jp_tests {
 
     #[test]
     fn test_decoder_invalid_out_or_range() {
-        let mut d = ISO2022JPEncoding.decoder();
+        let mut d = ISO_2022_JP.decoder();
         assert_feed_err!(d, [], [0x80], [], "");
         assert_feed_err!(d, [], [0xff], [], "");
         assert_feed_err!(d, [0x1b, 0x24, 0x42], [0x80, 0x21], [], "");
@@ -1226,7 +2337,7 @@ mod iso2022jp_tests {
 
     #[test]
     fn test_decoder_feed_after_finish() {
-        let mut d = ISO2022JPEncoding.decoder();
+        let mut d = ISO_2022_JP.decoder();
         assert_feed_ok!(d, [0x24, 0x22,
                             0x1b, 0x24, 0x42,
                             0x24, 0x22], [0x24], "\x24\x22\u3042");
@@ -1237,9 +2348,24 @@ mod iso2022jp_tests {
         assert_finish_ok!(d, "");
     }
 
+    #[test]
+    fn test_decoder_empty_feed() {
+        // an empty feed should never drop or corrupt pending bytes, even within escapes
+        let mut d = ISO_2022_JP.decoder();
+        assert_feed_ok!(d, [], [0x1b], "");
+        assert_feed_ok!(d, [], [], "");
+        assert_feed_ok!(d, [], [0x24], "");
+        assert_feed_ok!(d, [], [], "");
+        assert_feed_ok!(d, [0x42], [0x46], "");
+        assert_feed_ok!(d, [], [], "");
+        assert_feed_ok!(d, [0x7c], [], "\u65e5");
+        assert_feed_ok!(d, [], [], "");
+        assert_finish_ok!(d, "");
+    }
+
     #[bench]
     fn bench_encode_short_text(bencher: &mut test::Bencher) {
-        static Encoding: ISO2022JPEncoding = ISO2022JPEncoding;
+        static Encoding: ISO2022JPEncoding = ISO2022JPEncoding { reject_katakana: false };
         let s = testutils::JAPANESE_TEXT;
         bencher.bytes = s.len() as u64;
         bencher.iter(|| test::black_box({
@@ -1249,7 +2375,7 @@ mod iso2022jp_tests {
 
     #[bench]
     fn bench_decode_short_text(bencher: &mut test::Bencher) {
-        static Encoding: ISO2022JPEncoding = ISO2022JPEncoding;
+        static Encoding: ISO2022JPEncoding = ISO2022JPEncoding { reject_katakana: false };
         let s = Encoding.encode(testutils::JAPANESE_TEXT, EncodeStrict).ok().unwrap();
         bencher.bytes = s.len() as u64;
         bencher.iter(|| test::black_box({
@@ -1257,3 +2383,803 @@ mod iso2022jp_tests {
         }))
     }
 }
+
+/**
+ * ISO-2022-JP-2. (RFC 1554; decoder only)
+ *
+ * This is a multilingual extension to ISO-2022-JP used in the mail,
+ * and the decoder additionally supports the following character sets:
+ *
+ * - JIS X 0212-1990 (`ESC $ ( D`; ISO-2022-JP-1)
+ * - GB 2312-80 (`ESC $ A`)
+ * - KS X 1001:1992 (`ESC $ ( C`)
+ * - the upper half of ISO-8859-1 (`ESC . A`) and ISO-8859-7 (`ESC . F`) designated to G2,
+ *   invoked by a single shift (`ESC N` followed by one byte)
+ *
+ * The G2 designation persists across the other designations and line breaks.
+ * The encoder is same to the ISO-2022-JP encoder and never uses these extensions,
+ * but note that it may still use JIS X 0201 kana (`ESC ( I`) which is not a part of RFC 1554.
+ */
+#[deriving(Clone)]
+pub struct ISO2022JP2Encoding;
+
+/// Scripts covered by ISO-2022-JP-2, reported by `covered_scripts`.
+static ISO2022JP2_SCRIPTS: &'static [&'static str] =
+    &["Latin", "Greek", "Cyrillic", "Hiragana", "Katakana", "Hangul", "CJK"];
+
+impl Encoding for ISO2022JP2Encoding {
+    fn name(&self) -> &'static str { "iso-2022-jp-2" }
+    fn whatwg_name(&self) -> Option<&'static str> { None }
+    fn covered_scripts(&self) -> &'static [&'static str] { ISO2022JP2_SCRIPTS }
+    fn is_stateful(&self) -> bool { true }
+    fn assumes_normalization(&self) -> Option<NormalizationForm> { Some(NFC) }
+    fn encoder(&self) -> Box<Encoder> { ISO2022JPEncoder::new() }
+    fn decoder(&self) -> Box<Decoder> { ISO2022JP2Decoder::new() }
+}
+
+stateful_decoder! {
+    #[doc="A decoder for ISO-2022-JP-2."]
+    #[deriving(Clone)]
+    struct ISO2022JP2Decoder;
+
+    module iso2022jp2;
+
+    ascii_compatible false;
+
+    // character sets designated to G0
+    internal pub static ROMAN: u8 = 0; // US-ASCII or JIS X 0201-1976 roman
+    internal pub static KATAKANA: u8 = 1; // JIS X 0201-1976 kana
+    internal pub static JIS0208: u8 = 2;
+    internal pub static JIS0212: u8 = 3;
+    internal pub static GB2312: u8 = 4;
+    internal pub static KSC5601: u8 = 5;
+
+    // character sets designated to G2
+    internal pub static NO_G2: u8 = 0;
+    internal pub static LATIN1: u8 = 1; // the upper half of ISO-8859-1
+    internal pub static GREEK: u8 = 2; // the upper half of ISO-8859-7
+
+    internal pub fn map_one_byte(g0: u8, b: u8) -> u32 {
+        if g0 == ROMAN && b <= 0x7f {
+            b as u32
+        } else if g0 == KATAKANA && 0x21 <= b && b <= 0x5f {
+            0xff61 + b as u32 - 0x21
+        } else {
+            0xffff
+        }
+    }
+
+    internal pub fn map_two_bytes(g0: u8, lead: u8, trail: u8) -> u32 {
+        use index;
+
+        let lead = lead as uint;
+        let trail = trail as uint;
+        match (lead, trail) {
+            (0x21..0x7e, 0x21..0x7e) => {}
+            (_, _) => return 0xffff,
+        }
+        if g0 == JIS0208 {
+            index::jis0208::forward(((lead - 0x21) * 94 + trail - 0x21) as u16)
+        } else if g0 == JIS0212 {
+            index::jis0212::forward(((lead - 0x21) * 94 + trail - 0x21) as u16)
+        } else if g0 == GB2312 {
+            // GB 2312 is a subset of GBK, i.e. `[A1-FE] [A1-FE]` in GB 18030
+            index::gb18030::forward(((lead - 1) * 190 + (trail + 0x3f)) as u16)
+        } else if g0 == KSC5601 {
+            // KS X 1001 is a subset of Unified Hangul Code, i.e. `[A1-FE] [A1-FE]` in windows-949
+            let (lead, trail) = (lead + 0x80, trail + 0x80);
+            let index = if lead < 0xc7 {
+                (26 + 26 + 126) * (lead - 0x81) + 26 + 26 + trail - 0x81
+            } else {
+                (26 + 26 + 126) * (0xc7 - 0x81) + (lead - 0xc7) * 94 + trail - 0xa1
+            };
+            index::euc_kr::forward(index as u16)
+        } else {
+            0xffff
+        }
+    }
+
+    internal pub fn map_g2_byte(g2: u8, b: u8) -> u32 {
+        use index;
+
+        if b < 0x20 || b > 0x7f {
+            0xffff
+        } else if g2 == LATIN1 {
+            b as u32 + 0x80
+        } else if g2 == GREEK {
+            index::iso_8859_7::forward(b + 0x80) as u32
+        } else {
+            0xffff
+        }
+    }
+
+    internal pub fn is_double_byte(g0: u8) -> bool {
+        g0 != ROMAN && g0 != KATAKANA
+    }
+
+    // G0 = ROMAN, G2 = NO_G2
+    initial state ASCII(ctx) {
+        case 0x1b => EscapeStart(ctx, ROMAN, NO_G2);
+        case b @ 0x00..0x7f => ctx.emit(b as u32), ASCII(ctx);
+        case b => ctx.err(format!("invalid byte 0x{:02X}", b)), ASCII(ctx);
+        final => ctx.reset();
+    }
+
+    // G0 = ROMAN or KATAKANA
+    checkpoint state SingleByte(ctx, g0: u8, g2: u8) {
+        case 0x1b => EscapeStart(ctx, g0, g2);
+        case b =>
+            match map_one_byte(g0, b) {
+                0xffff => ctx.err(format!("invalid byte 0x{:02X}", b)),
+                ch => ctx.emit(ch)
+            },
+            SingleByte(ctx, g0, g2);
+        final => ctx.reset();
+    }
+
+    // G0 = JIS0208, JIS0212, GB2312 or KSC5601
+    checkpoint state Lead(ctx, g0: u8, g2: u8) {
+        case 0x0a => // return to ASCII
+            ctx.emit(0x000a),
+            if g2 == NO_G2 { ASCII(ctx) } else { SingleByte(ctx, ROMAN, g2) };
+        case 0x1b => EscapeStart(ctx, g0, g2);
+        case b => Trail(ctx, g0, g2, b);
+        final => ctx.reset();
+    }
+
+    // ESC
+    state EscapeStart(ctx, g0: u8, g2: u8) {
+        case 0x24 => EscapeMiddle24(ctx, g2); // ESC $
+        case 0x28 => EscapeMiddle28(ctx, g2); // ESC (
+        case 0x2e => EscapeMiddle2E(ctx, g0, g2); // ESC .
+        case 0x4e => SingleShift2(ctx, g0, g2); // ESC N
+        case _ => ctx.backup_and_err(1, "invalid sequence");
+        final => ctx.err("incomplete sequence");
+    }
+
+    // ESC $
+    state EscapeMiddle24(ctx, g2: u8) {
+        case 0x40 | 0x42 => Lead(ctx, JIS0208, g2); // ESC $ @ or ESC $ B (JIS X 0208)
+        case 0x41 => Lead(ctx, GB2312, g2); // ESC $ A (GB 2312)
+        case 0x28 => EscapeFinal(ctx, g2); // ESC $ (
+        case _ => ctx.backup_and_err(2, "invalid sequence");
+        final => ctx.err("incomplete sequence");
+    }
+
+    // ESC (
+    state EscapeMiddle28(ctx, g2: u8) {
+        case 0x42 | 0x4a => // ESC ( B (ASCII) or ESC ( J (JIS X 0201-1976 roman)
+            if g2 == NO_G2 { ctx.reset() } else { SingleByte(ctx, ROMAN, g2) };
+        case 0x49 => SingleByte(ctx, KATAKANA, g2); // ESC ( I (JIS X 0201-1976 kana)
+        case _ => ctx.backup_and_err(2, "invalid sequence");
+        final => ctx.err("incomplete sequence");
+    }
+
+    // ESC .
+    state EscapeMiddle2E(ctx, g0: u8, g2: u8) {
+        case b @ 0x41 | b @ 0x46 => { // ESC . A (ISO-8859-1) or ESC . F (ISO-8859-7)
+            let g2 = if b == 0x41 { LATIN1 } else { GREEK };
+            if is_double_byte(g0) { Lead(ctx, g0, g2) } else { SingleByte(ctx, g0, g2) }
+        };
+        case _ => ctx.backup_and_err(2, "invalid sequence");
+        final => ctx.err("incomplete sequence");
+    }
+
+    // ESC $ (
+    state EscapeFinal(ctx, g2: u8) {
+        case 0x44 => Lead(ctx, JIS0212, g2); // ESC $ ( D (JIS X 0212-1990)
+        case 0x43 => Lead(ctx, KSC5601, g2); // ESC $ ( C (KS X 1001)
+        case _ => ctx.backup_and_err(3, "invalid sequence");
+        final => ctx.backup_and_err(1, "incomplete sequence");
+    }
+
+    // ESC N
+    state SingleShift2(ctx, g0: u8, g2: u8) {
+        case b =>
+            match map_g2_byte(g2, b) {
+                0xffff => ctx.err(format!("invalid sequence 0x1B 0x4E 0x{:02X}", b)),
+                ch => ctx.emit(ch)
+            },
+            if g0 == ROMAN && g2 == NO_G2 {
+                ASCII(ctx)
+            } else if is_double_byte(g0) {
+                Lead(ctx, g0, g2)
+            } else {
+                SingleByte(ctx, g0, g2)
+            };
+        final => ctx.err("incomplete sequence");
+    }
+
+    state Trail(ctx, g0: u8, g2: u8, lead: u8) {
+        case b =>
+            match map_two_bytes(g0, lead, b) {
+                0xffff => ctx.err(format!("invalid sequence 0x{:02X} 0x{:02X}", lead, b)),
+                ch => ctx.emit(ch)
+            },
+            Lead(ctx, g0, g2);
+        final => ctx.err("incomplete sequence");
+    }
+}
+
+#[cfg(test)]
+mod iso2022jp2_tests {
+    use super::ISO2022JP2Encoding;
+    use types::*;
+
+    #[test]
+    fn test_encoder_valid() {
+        // same to ISO-2022-JP
+        let mut e = ISO2022JP2Encoding.encoder();
+        assert_feed_ok!(e, "A\u306b", "", [0x41, 0x1b, 0x24, 0x42, 0x24, 0x4b]);
+        assert_feed_err!(e, "", "\uac00", "", []);
+        assert_finish_ok!(e, [0x1b, 0x28, 0x42]);
+    }
+
+    #[test]
+    fn test_decoder_valid() {
+        let mut d = ISO2022JP2Encoding.decoder();
+        assert_feed_ok!(d, [0x41, 0x1b, 0x28, 0x4a, 0x42], [], "AB");
+        assert_feed_ok!(d, [0x1b, 0x24, 0x42, 0x24, 0x4b], [], "\u306b");
+        assert_feed_ok!(d, [0x1b, 0x24, 0x28, 0x44, 0x4b, 0x46], [], "\u736c");
+        assert_feed_ok!(d, [0x1b, 0x24, 0x41, 0x30, 0x21], [], "\u554a");
+        assert_feed_ok!(d, [0x1b, 0x24, 0x28, 0x43, 0x30, 0x21, 0x47, 0x4f], [], "\uac00\ud558");
+        assert_feed_ok!(d, [0x1b, 0x28, 0x49, 0x46], [], "\uff86");
+        assert_feed_ok!(d, [0x1b, 0x28, 0x42, 0x43], [], "C");
+        assert_finish_ok!(d, "");
+    }
+
+    #[test]
+    fn test_decoder_single_shift() {
+        let mut d = ISO2022JP2Encoding.decoder();
+        assert_feed_ok!(d, [0x1b, 0x2e, 0x41, 0x1b, 0x4e, 0x69, 0x41], [], "\u00e9A");
+        // G2 is retained across G0 designations and line breaks
+        assert_feed_ok!(d, [0x1b, 0x24, 0x42, 0x24, 0x4b, 0x1b, 0x4e, 0x20, 0x24, 0x4b], [],
+                        "\u306b\u00a0\u306b");
+        assert_feed_ok!(d, [0x0a, 0x1b, 0x4e, 0x7f], [], "\n\u00ff");
+        assert_feed_ok!(d, [0x1b, 0x2e, 0x46, 0x1b, 0x4e, 0x41, 0x1b, 0x4e, 0x79], [],
+                        "\u0391\u03c9");
+        assert_feed_ok!(d, [0x1b, 0x28, 0x42, 0x1b, 0x4e, 0x61], [], "\u03b1");
+        assert_finish_ok!(d, "");
+    }
+
+    #[test]
+    fn test_decoder_single_shift_partial() {
+        let mut d = ISO2022JP2Encoding.decoder();
+        assert_feed_ok!(d, [], [0x1b, 0x2e], "");
+        assert_feed_ok!(d, [0x41], [0x1b], "");
+        assert_feed_ok!(d, [], [0x4e], "");
+        assert_feed_ok!(d, [0x69], [], "\u00e9");
+        assert_finish_ok!(d, "");
+    }
+
+    #[test]
+    fn test_decoder_invalid_single_shift() {
+        // no G2 designation
+        let mut d = ISO2022JP2Encoding.decoder();
+        assert_feed_err!(d, [], [0x1b, 0x4e, 0x41], [0x42], "");
+        assert_feed_ok!(d, [0x42], [], "B");
+        assert_finish_ok!(d, "");
+
+        // unmapped in ISO-8859-7
+        let mut d = ISO2022JP2Encoding.decoder();
+        assert_feed_err!(d, [0x1b, 0x2e, 0x46], [0x1b, 0x4e, 0x7f], [], "");
+        assert_finish_ok!(d, "");
+
+        // unknown G2 designation
+        let mut d = ISO2022JP2Encoding.decoder();
+        assert_feed_err!(d, [], [0x1b], [0x2e, 0x42], "");
+        assert_finish_ok!(d, "");
+
+        let mut d = ISO2022JP2Encoding.decoder();
+        assert_feed_ok!(d, [], [0x1b, 0x4e], "");
+        assert_finish_err!(d, "");
+    }
+
+    #[test]
+    fn test_decoder_invalid_trail() {
+        let mut d = ISO2022JP2Encoding.decoder();
+        assert_feed_err!(d, [0x1b, 0x24, 0x41], [0x30, 0x7f], [], "");
+        assert_feed_ok!(d, [0x30, 0x21], [], "\u554a");
+        assert_feed_ok!(d, [], [0x30], "");
+        assert_finish_err!(d, "");
+    }
+}
+
+/**
+ * JIS X 0201, a single-byte Japanese encoding.
+ *
+ * This is a standalone JIS X 0201-1976 as used in some protocols,
+ * where the lower half is JIS X 0201 Roman (`00` through `7F`;
+ * same to ASCII except for `5C` to a yen sign and `7E` to an overline)
+ * and the upper half contains half-width katakana (`A1` through `DF`).
+ * Other bytes are undefined, and a backslash and tilde cannot be encoded.
+ */
+#[deriving(Clone)]
+pub struct JISX0201Encoding;
+
+/// Scripts covered by JIS X 0201, reported by `covered_scripts`.
+static JISX0201_SCRIPTS: &'static [&'static str] = &["Latin", "Katakana"];
+
+impl Encoding for JISX0201Encoding {
+    fn name(&self) -> &'static str { "jis-x0201" }
+    fn covered_scripts(&self) -> &'static [&'static str] { JISX0201_SCRIPTS }
+    fn is_single_byte(&self) -> bool { true }
+    fn encoder(&self) -> Box<Encoder> { JISX0201Encoder::new() }
+    fn decoder(&self) -> Box<Decoder> { JISX0201Decoder::new() }
+}
+
+/// An encoder for JIS X 0201.
+#[deriving(Clone)]
+pub struct JISX0201Encoder;
+
+impl JISX0201Encoder {
+    pub fn new() -> Box<Encoder> { box JISX0201Encoder as Box<Encoder> }
+}
+
+impl Encoder for JISX0201Encoder {
+    fn from_self(&self) -> Box<Encoder> { JISX0201Encoder::new() }
+    fn clone_box(&self) -> Box<Encoder> { box self.clone() as Box<Encoder> }
+
+    fn raw_feed(&mut self, input: &str, output: &mut ByteWriter) -> (uint, Option<CodecError>) {
+        output.writer_hint(input.len());
+
+        for ((i,j), ch) in input.index_iter() {
+            match ch {
+                '\\' | '~' => {
+                    return (i, Some(CodecError {
+                        upto: j as int, cause: "unrepresentable character".into_maybe_owned(),
+                        buffered: 0
+                    }));
+                }
+                '\u0000'..'\u007f' => { output.write_byte(ch as u8); }
+                '\u00a5' => { output.write_byte(0x5c); }
+                '\u203e' => { output.write_byte(0x7e); }
+                '\uff61'..'\uff9f' => { output.write_byte((ch as uint - 0xff61 + 0xa1) as u8); }
+                _ => {
+                    return (i, Some(CodecError {
+                        upto: j as int, cause: "unrepresentable character".into_maybe_owned(),
+                        buffered: 0
+                    }));
+                }
+            }
+        }
+        (input.len(), None)
+    }
+
+    fn raw_finish(&mut self, _output: &mut ByteWriter) -> Option<CodecError> {
+        None
+    }
+}
+
+/// A decoder for JIS X 0201.
+#[deriving(Clone)]
+pub struct JISX0201Decoder;
+
+impl JISX0201Decoder {
+    pub fn new() -> Box<Decoder> { box JISX0201Decoder as Box<Decoder> }
+}
+
+impl Decoder for JISX0201Decoder {
+    fn from_self(&self) -> Box<Decoder> { JISX0201Decoder::new() }
+    fn clone_box(&self) -> Box<Decoder> { box self.clone() as Box<Decoder> }
+
+    fn raw_feed(&mut self, input: &[u8], output: &mut StringWriter) -> (uint, Option<CodecError>) {
+        output.writer_hint(input.len());
+
+        for (i, &b) in input.iter().enumerate() {
+            match b {
+                0x5c => { output.write_char('\u00a5'); }
+                0x7e => { output.write_char('\u203e'); }
+                0x00..0x7f => { output.write_char(b as char); }
+                0xa1..0xdf => { output.write_char(as_char(0xff61 + b as u32 - 0xa1)); }
+                _ => {
+                    return (i, Some(CodecError {
+                        upto: i as int + 1,
+                        cause: format!("invalid single byte 0x{:02X}", b).into_maybe_owned(),
+                        buffered: 0
+                    }));
+                }
+            }
+        }
+        (input.len(), None)
+    }
+
+    fn raw_finish(&mut self, _output: &mut StringWriter) -> Option<CodecError> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod jisx0201_tests {
+    use super::JISX0201Encoding;
+    use types::*;
+
+    #[test]
+    fn test_name() {
+        assert_eq!(JISX0201Encoding.name(), "jis-x0201");
+    }
+
+    #[test]
+    fn test_encoder() {
+        let mut e = JISX0201Encoding.encoder();
+        assert_feed_ok!(e, "A\u00a5\u203e", "", [0x41, 0x5c, 0x7e]);
+        assert_feed_ok!(e, "\uff61\uff76\uff9f", "", [0xa1, 0xb6, 0xdf]);
+        assert_feed_err!(e, "", "\\", "~", []);
+        assert_feed_err!(e, "", "~", "", []);
+        assert_feed_err!(e, "A", "\u3042", "", [0x41]);
+        assert_feed_err!(e, "", "\u00e9", "", []);
+        assert_finish_ok!(e, []);
+    }
+
+    #[test]
+    fn test_decoder() {
+        let mut d = JISX0201Encoding.decoder();
+        assert_feed_ok!(d, [0x41, 0x5c, 0x7e], [], "A\u00a5\u203e");
+        assert_feed_ok!(d, [0xa1, 0xb6, 0xdf], [], "\uff61\uff76\uff9f");
+        assert_feed_err!(d, [0x41], [0x80], [0x42], "A");
+        assert_feed_err!(d, [], [0xa0], [], "");
+        assert_feed_err!(d, [], [0xe0], [], "");
+        assert_finish_ok!(d, "");
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        for i in range(0u, 256) {
+            let input = [i as u8];
+            match JISX0201Encoding.decode(input, DecodeStrict) {
+                Ok(s) => assert_eq!(JISX0201Encoding.encode(s.as_slice(), EncodeStrict),
+                                    Ok(Vec::from_slice(input))),
+                Err(_) => assert!(i >= 0x80 && (i < 0xa1 || i > 0xdf)),
+            }
+        }
+    }
+}
+
+/// Half-width katakana (U+FF61 through U+FF9F) to full-width katakana.
+/// Voiced and semi-voiced sound marks map to the spacing marks U+309B and U+309C.
+static FULLWIDTH_KATAKANA: [u16, ..63] = [
+    0x3002, 0x300c, 0x300d, 0x3001, 0x30fb, 0x30f2, 0x30a1, 0x30a3, 0x30a5, 0x30a7,
+    0x30a9, 0x30e3, 0x30e5, 0x30e7, 0x30c3, 0x30fc, 0x30a2, 0x30a4, 0x30a6, 0x30a8,
+    0x30aa, 0x30ab, 0x30ad, 0x30af, 0x30b1, 0x30b3, 0x30b5, 0x30b7, 0x30b9, 0x30bb,
+    0x30bd, 0x30bf, 0x30c1, 0x30c4, 0x30c6, 0x30c8, 0x30ca, 0x30cb, 0x30cc, 0x30cd,
+    0x30ce, 0x30cf, 0x30d2, 0x30d5, 0x30d8, 0x30db, 0x30de, 0x30df, 0x30e0, 0x30e1,
+    0x30e2, 0x30e4, 0x30e6, 0x30e8, 0x30e9, 0x30ea, 0x30eb, 0x30ec, 0x30ed, 0x30ef,
+    0x30f3, 0x309b, 0x309c,
+];
+
+/// Returns a full-width counterpart of given half-width katakana, if any.
+pub fn fullwidth_katakana(ch: char) -> Option<char> {
+    match ch {
+        '\uff61'..'\uff9f' => Some(as_char(FULLWIDTH_KATAKANA[ch as uint - 0xff61])),
+        _ => None,
+    }
+}
+
+/// Composes a full-width katakana with a half-width voiced sound mark (U+FF9E)
+/// or semi-voiced sound mark (U+FF9F), if the composed katakana is in JIS X 0208.
+pub fn compose_katakana(base: char, mark: char) -> Option<char> {
+    let base = base as u32;
+    match mark {
+        '\uff9e' => match base {
+            0x30a6 => Some('\u30f4'), // VU
+            0x30ab..0x30c1 if base % 2 == 1 => Some(as_char(base + 1)), // KA through TI
+            0x30c4..0x30c8 if base % 2 == 0 => Some(as_char(base + 1)), // TU through TO
+            0x30cf..0x30db if (base - 0x30cf) % 3 == 0 => Some(as_char(base + 1)), // HA row
+            _ => None,
+        },
+        '\uff9f' => match base {
+            0x30cf..0x30db if (base - 0x30cf) % 3 == 0 => Some(as_char(base + 2)), // HA row
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Composes every half-width katakana followed by a voiced or semi-voiced sound mark
+/// (U+FF9E or U+FF9F) into the corresponding full-width katakana, if it exists in JIS X 0208
+/// (e.g. U+FF76 U+FF9E to U+30AC). Other characters including the half-width katakana
+/// without the composed counterpart are left as is.
+/// This is useful for normalizing the decoded Shift_JIS or EUC-JP text for display.
+pub fn compose_halfwidth_katakana(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    loop {
+        let ch = match chars.next() {
+            Some(ch) => ch,
+            None => break,
+        };
+        let composed = match (fullwidth_katakana(ch), chars.peek()) {
+            (Some(base), Some(&mark)) => compose_katakana(base, mark),
+            (_, _) => None,
+        };
+        match composed {
+            Some(composed) => {
+                ret.push_char(composed);
+                chars.next();
+            }
+            None => { ret.push_char(ch); }
+        }
+    }
+    ret
+}
+
+/**
+ * An encoding adapter which converts half-width katakana to full-width katakana before encoding,
+ * for receivers which cannot handle half-width katakana.
+ *
+ * A half-width katakana followed by a voiced or semi-voiced sound mark (U+FF9E or U+FF9F)
+ * is combined into the corresponding full-width katakana if it exists in JIS X 0208
+ * (e.g. U+FF76 U+FF9E to U+30AC),
+ * otherwise the mark is converted to a spacing mark (U+309B or U+309C).
+ * The decoder is same to that of the base encoding.
+ */
+pub struct FullwidthKatakanaEncoding {
+    /// An encoding to encode the converted string.
+    pub base: EncodingRef,
+}
+
+impl Encoding for FullwidthKatakanaEncoding {
+    fn name(&self) -> &'static str { self.base.name() }
+    fn whatwg_name(&self) -> Option<&'static str> { self.base.whatwg_name() }
+    fn covered_scripts(&self) -> &'static [&'static str] { self.base.covered_scripts() }
+    fn is_single_byte(&self) -> bool { self.base.is_single_byte() }
+    fn is_stateful(&self) -> bool { self.base.is_stateful() }
+    fn bom_bytes(&self) -> Option<&'static [u8]> { self.base.bom_bytes() }
+    fn assumes_normalization(&self) -> Option<NormalizationForm> {
+        self.base.assumes_normalization()
+    }
+    fn encoder(&self) -> Box<Encoder> { FullwidthKatakanaEncoder::new(self.base.encoder()) }
+    fn decoder(&self) -> Box<Decoder> { self.base.decoder() }
+}
+
+/// An encoder adapter which converts half-width katakana to full-width katakana.
+pub struct FullwidthKatakanaEncoder {
+    inner: Box<Encoder>,
+    /// A full-width katakana which may be combined with the next sound mark.
+    pending: Option<char>,
+}
+
+impl FullwidthKatakanaEncoder {
+    pub fn new(inner: Box<Encoder>) -> Box<Encoder> {
+        box FullwidthKatakanaEncoder { inner: inner, pending: None } as Box<Encoder>
+    }
+
+    /// Feeds a single converted character to the inner encoder. Returns false on error.
+    fn feed_char(&mut self, ch: char, output: &mut ByteWriter) -> bool {
+        let s = String::from_char(1, ch);
+        let (_, err) = self.inner.raw_feed(s.as_slice(), output);
+        err.is_none()
+    }
+}
+
+impl Encoder for FullwidthKatakanaEncoder {
+    fn from_self(&self) -> Box<Encoder> { FullwidthKatakanaEncoder::new(self.inner.from_self()) }
+    fn clone_box(&self) -> Box<Encoder> {
+        box FullwidthKatakanaEncoder { inner: self.inner.clone_box(),
+                                       pending: self.pending } as Box<Encoder>
+    }
+    fn is_ascii_compatible(&self) -> bool { self.inner.is_ascii_compatible() }
+
+    // a katakana which may take a sound mark is held back, so count the buffered bytes.
+    fn char_cost(&mut self, ch: char) -> Option<uint> { buffered_char_cost(&*self, ch) }
+
+    fn raw_feed(&mut self, input: &str, output: &mut ByteWriter) -> (uint, Option<CodecError>) {
+        fn unrepresentable(upto: uint) -> Option<CodecError> {
+            Some(CodecError {
+                upto: upto as int, cause: "unrepresentable character".into_maybe_owned(),
+                buffered: 0
+            })
+        }
+
+        let mut run = 0; // the start of characters to be passed through as is
+        let mut pending_start = 0; // zero if the pending character is from the prior call
+        for ((i,j), ch) in input.index_iter() {
+            // the pending character is always immediately followed by the current character
+            match self.pending.take() {
+                Some(base) => match compose_katakana(base, ch) {
+                    Some(composed) => {
+                        if !self.feed_char(composed, output) {
+                            return (pending_start, unrepresentable(j));
+                        }
+                        run = j;
+                        continue;
+                    }
+                    None => {
+                        if !self.feed_char(base, output) {
+                            return (pending_start, unrepresentable(i));
+                        }
+                        run = i;
+                    }
+                },
+                None => {}
+            }
+
+            match fullwidth_katakana(ch) {
+                Some(fullwidth) => {
+                    if run < i {
+                        let (offset, err) = self.inner.raw_feed(input.slice(run, i), output);
+                        match err {
+                            Some(err) => {
+                                return (run + offset, Some(CodecError {
+                                    upto: run as int + err.upto, cause: err.cause,
+                                    buffered: err.buffered
+                                }));
+                            }
+                            None => {}
+                        }
+                    }
+                    if compose_katakana(fullwidth, '\uff9e').is_some() {
+                        self.pending = Some(fullwidth);
+                        pending_start = i;
+                    } else if !self.feed_char(fullwidth, output) {
+                        return (i, unrepresentable(j));
+                    }
+                    run = j;
+                }
+                None => {} // will be passed through as a part of the run
+            }
+        }
+
+        if run < input.len() {
+            let (offset, err) = self.inner.raw_feed(input.slice_from(run), output);
+            match err {
+                Some(err) => {
+                    return (run + offset, Some(CodecError {
+                        upto: run as int + err.upto, cause: err.cause,
+                        buffered: err.buffered
+                    }));
+                }
+                None => {}
+            }
+        }
+        if self.pending.is_some() {
+            (pending_start, None)
+        } else {
+            (input.len(), None)
+        }
+    }
+
+    fn raw_finish(&mut self, output: &mut ByteWriter) -> Option<CodecError> {
+        match self.pending.take() {
+            Some(base) => {
+                if !self.feed_char(base, output) {
+                    return Some(CodecError {
+                        upto: 0, cause: "unrepresentable character".into_maybe_owned(),
+                        buffered: 0
+                    });
+                }
+            }
+            None => {}
+        }
+        self.inner.raw_finish(output)
+    }
+}
+
+#[cfg(test)]
+mod fullwidth_katakana_tests {
+    use super::{FullwidthKatakanaEncoding, fullwidth_katakana, compose_katakana};
+    use super::compose_halfwidth_katakana;
+    use all;
+    use types::*;
+
+    fn sjis() -> FullwidthKatakanaEncoding {
+        FullwidthKatakanaEncoding { base: all::WINDOWS_31J as EncodingRef }
+    }
+
+    #[test]
+    fn test_fullwidth_katakana() {
+        assert_eq!(fullwidth_katakana('\uff61'), Some('\u3002'));
+        assert_eq!(fullwidth_katakana('\uff76'), Some('\u30ab'));
+        assert_eq!(fullwidth_katakana('\uff9d'), Some('\u30f3'));
+        assert_eq!(fullwidth_katakana('\uff9e'), Some('\u309b'));
+        assert_eq!(fullwidth_katakana('\uff9f'), Some('\u309c'));
+        assert_eq!(fullwidth_katakana('\uff60'), None);
+        assert_eq!(fullwidth_katakana('\uffa0'), None);
+        assert_eq!(fullwidth_katakana('\u30ab'), None);
+    }
+
+    #[test]
+    fn test_compose_katakana() {
+        // dakuten
+        assert_eq!(compose_katakana('\u30ab', '\uff9e'), Some('\u30ac')); // KA -> GA
+        assert_eq!(compose_katakana('\u30c1', '\uff9e'), Some('\u30c2')); // TI -> DI
+        assert_eq!(compose_katakana('\u30c4', '\uff9e'), Some('\u30c5')); // TU -> DU
+        assert_eq!(compose_katakana('\u30c8', '\uff9e'), Some('\u30c9')); // TO -> DO
+        assert_eq!(compose_katakana('\u30db', '\uff9e'), Some('\u30dc')); // HO -> BO
+        assert_eq!(compose_katakana('\u30a6', '\uff9e'), Some('\u30f4')); // U -> VU
+        assert_eq!(compose_katakana('\u30a2', '\uff9e'), None); // A
+        assert_eq!(compose_katakana('\u30c3', '\uff9e'), None); // small TU
+        assert_eq!(compose_katakana('\u30ef', '\uff9e'), None); // WA (VA is not in JIS X 0208)
+
+        // handakuten
+        assert_eq!(compose_katakana('\u30cf', '\uff9f'), Some('\u30d1')); // HA -> PA
+        assert_eq!(compose_katakana('\u30db', '\uff9f'), Some('\u30dd')); // HO -> PO
+        assert_eq!(compose_katakana('\u30ab', '\uff9f'), None); // KA
+        assert_eq!(compose_katakana('\u30d0', '\uff9f'), None); // BA
+
+        assert_eq!(compose_katakana('\u30ab', '\u309b'), None); // full-width mark
+    }
+
+    #[test]
+    fn test_compose_halfwidth_katakana() {
+        // dakuten
+        assert_eq!(compose_halfwidth_katakana("\uff76\uff9e\uff77\uff9e").as_slice(),
+                   "\u30ac\u30ae"); // GA GI
+        assert_eq!(compose_halfwidth_katakana("\uff73\uff9e").as_slice(), "\u30f4"); // VU
+
+        // handakuten
+        assert_eq!(compose_halfwidth_katakana("\uff8a\uff9f\uff8e\uff9f").as_slice(),
+                   "\u30d1\u30dd"); // PA PO
+
+        // no valid composition
+        assert_eq!(compose_halfwidth_katakana("\uff71\uff9e").as_slice(), "\uff71\uff9e");
+        assert_eq!(compose_halfwidth_katakana("\uff76\uff9f").as_slice(), "\uff76\uff9f");
+        assert_eq!(compose_halfwidth_katakana("\uff9e\uff76").as_slice(), "\uff9e\uff76");
+        assert_eq!(compose_halfwidth_katakana("\u30ab\uff9e").as_slice(), "\u30ab\uff9e");
+
+        // other characters are not affected
+        assert_eq!(compose_halfwidth_katakana("A\uff76\uff9eB\uff76").as_slice(),
+                   "A\u30acB\uff76");
+        assert_eq!(compose_halfwidth_katakana("").as_slice(), "");
+    }
+
+    #[test]
+    fn test_encoder() {
+        let mut e = sjis().encoder();
+        assert_feed_ok!(e, "A", "", [0x41]);
+        assert_feed_ok!(e, "\uff71", "", [0x83, 0x41]); // A, cannot be voiced
+        assert_feed_ok!(e, "\uff76\uff9e\uff77\uff9e", "", [0x83, 0x4b, 0x83, 0x4d]); // GA GI
+        assert_feed_ok!(e, "\uff8a\uff9f", "", [0x83, 0x70]); // PA
+        assert_feed_ok!(e, "\uff73\uff9e", "", [0x83, 0x94]); // VU
+        assert_feed_ok!(e, "\uff71\uff9e", "", [0x83, 0x41, 0x81, 0x4a]); // A + spacing mark
+        assert_feed_ok!(e, "\uff9e\uff9f", "", [0x81, 0x4a, 0x81, 0x4b]);
+        assert_feed_ok!(e, "\uff61\uff62\uff63", "", [0x81, 0x42, 0x81, 0x75, 0x81, 0x76]);
+        assert_feed_ok!(e, "\u65e5", "", [0x93, 0xfa]); // not affected
+        assert_finish_ok!(e, []);
+    }
+
+    #[test]
+    fn test_encoder_partial() {
+        let mut e = sjis().encoder();
+        assert_feed_ok!(e, "", "\uff76", []);
+        assert_feed_ok!(e, "\uff9e", "", [0x83, 0x4b]); // GA
+        assert_feed_ok!(e, "A", "\uff8a", [0x41]);
+        assert_feed_ok!(e, "", "", []);
+        assert_feed_ok!(e, "\uff9f", "\uff8a", [0x83, 0x70]); // PA
+        assert_feed_ok!(e, "A", "", [0x83, 0x6e, 0x41]); // HA
+        assert_feed_ok!(e, "", "\uff76", []);
+        assert_finish_ok!(e, [0x83, 0x4a]); // KA
+    }
+
+    #[test]
+    fn test_encoder_char_cost() {
+        let mut e = sjis().encoder();
+        assert_eq!(e.char_cost('A'), Some(1));
+        assert_eq!(e.char_cost('\uff76'), Some(2)); // KA, held back until the next character
+        assert_eq!(e.char_cost('\uff71'), Some(2)); // A, cannot be voiced
+        assert_eq!(e.char_cost('\uac00'), None);
+        assert_feed_ok!(e, "", "\uff76", []);
+        assert_eq!(e.char_cost('\uff9e'), Some(0)); // KA becomes GA
+        assert_eq!(e.char_cost('\uff9f'), Some(2)); // KA + spacing mark
+        assert_eq!(e.char_cost('A'), Some(1));
+        assert_finish_ok!(e, [0x83, 0x4a]); // KA
+    }
+
+    #[test]
+    fn test_encoder_invalid() {
+        let mut e = sjis().encoder();
+        assert_feed_err!(e, "\uff76", "\uffff", "", [0x83, 0x4a]);
+        assert_feed_err!(e, "A\uff71", "\uffff", "\uff71", [0x41, 0x83, 0x41]);
+        assert_finish_ok!(e, []);
+
+        assert_eq!(sjis().encode("\uff76\uffff\uff9e", EncodeReplace),
+                   Ok(vec!(0x83, 0x4a, 0x3f, 0x81, 0x4a)));
+    }
+
+    #[test]
+    fn test_decoder() {
+        // the decoder is not affected
+        assert_eq!(sjis().decode([0xb6, 0xde, 0x83, 0x4b], DecodeStrict),
+                   Ok("\uff76\uff9e\u30ac".to_string()));
+    }
+}
@@ -8,6 +8,10 @@ use util::StrCharIndex;
 use index;
 use types::*;
 
+/// Scripts covered by KS X 1001 and its Unified Hangul Code extension.
+static KOREAN_SCRIPTS: &'static [&'static str] =
+    &["Latin", "Greek", "Cyrillic", "Hiragana", "Katakana", "Hangul", "CJK"];
+
 /**
  * Windows code page 949.
  *
@@ -25,6 +29,8 @@ pub struct Windows949Encoding;
 impl Encoding for Windows949Encoding {
     fn name(&self) -> &'static str { "windows-949" }
     fn whatwg_name(&self) -> Option<&'static str> { Some("euc-kr") } // WHATWG compatibility
+    fn covered_scripts(&self) -> &'static [&'static str] { KOREAN_SCRIPTS }
+    fn assumes_normalization(&self) -> Option<NormalizationForm> { Some(NFC) }
     fn encoder(&self) -> Box<Encoder> { Windows949Encoder::new() }
     fn decoder(&self) -> Box<Decoder> { Windows949Decoder::new() }
 }
@@ -39,6 +45,7 @@ impl Windows949Encoder {
 
 impl Encoder for Windows949Encoder {
     fn from_self(&self) -> Box<Encoder> { Windows949Encoder::new() }
+    fn clone_box(&self) -> Box<Encoder> { box self.clone() as Box<Encoder> }
     fn is_ascii_compatible(&self) -> bool { true }
 
     fn raw_feed(&mut self, input: &str, output: &mut ByteWriter) -> (uint, Option<CodecError>) {
@@ -51,7 +58,8 @@ impl Encoder for Windows949Encoder {
                 let ptr = index::euc_kr::backward(ch as u32);
                 if ptr == 0xffff {
                     return (i, Some(CodecError {
-                        upto: j as int, cause: "unrepresentable character".into_maybe_owned()
+                        upto: j as int, cause: "unrepresentable character".into_maybe_owned(),
+                        buffered: 0
                     }));
                 } else if ptr < (26 + 26 + 126) * (0xc7 - 0x81) {
                     let lead = ptr / (26 + 26 + 126) + 0x81;
@@ -0,0 +1,408 @@
+// This is a part of rust-encoding.
+// Copyright (c) 2014, Kang Seonghoon.
+// See README.md and LICENSE.txt for details.
+
+//! Punycode, a bootstring encoding for internationalized domain names.
+
+use std::{char, mem};
+use util::{UNICODE_SCRIPTS, buffered_char_cost};
+use types::*;
+
+// parameters for Punycode, as specified in RFC 3492 section 5.
+static BASE: u32 = 36;
+static TMIN: u32 = 1;
+static TMAX: u32 = 26;
+static SKEW: u32 = 38;
+static DAMP: u32 = 700;
+static INITIAL_BIAS: u32 = 72;
+static INITIAL_N: u32 = 0x80;
+
+/// The ACE prefix for Punycode-encoded labels used by IDNA.
+static ACE_PREFIX: &'static str = "xn--";
+
+/// Bias adaptation function (RFC 3492 section 6.1).
+fn adapt(delta: u32, numpoints: u32, firsttime: bool) -> u32 {
+    let mut delta = if firsttime {delta / DAMP} else {delta / 2};
+    delta += delta / numpoints;
+    let mut k = 0;
+    while delta > ((BASE - TMIN) * TMAX) / 2 {
+        delta /= BASE - TMIN;
+        k += BASE;
+    }
+    k + (BASE - TMIN + 1) * delta / (delta + SKEW)
+}
+
+/// Returns the threshold `t` for the digit position `k`.
+fn threshold(k: u32, bias: u32) -> u32 {
+    if k <= bias {
+        TMIN
+    } else if k >= bias + TMAX {
+        TMAX
+    } else {
+        k - bias
+    }
+}
+
+fn encode_digit(d: u32) -> u8 {
+    if d < 26 {0x61 + d as u8} else {0x30 + (d - 26) as u8} // a..z, 0..9
+}
+
+fn decode_digit(b: u8) -> Option<u32> {
+    match b {
+        0x30..0x39 => Some((b - 0x30) as u32 + 26),
+        0x41..0x5a => Some((b - 0x41) as u32),
+        0x61..0x7a => Some((b - 0x61) as u32),
+        _ => None,
+    }
+}
+
+/// Encodes given label into Punycode without the ACE prefix.
+/// Returns `None` on the overflow.
+fn encode_label(input: &[char]) -> Option<Vec<u8>> {
+    let mut output = Vec::new();
+    let mut n = INITIAL_N;
+    let mut delta: u32 = 0;
+    let mut bias = INITIAL_BIAS;
+
+    for &c in input.iter() {
+        if (c as u32) < 0x80 { output.push(c as u8); }
+    }
+    let nbasic = output.len() as u32;
+    if nbasic > 0 { output.push(0x2d); } // `-`
+
+    let mut h = nbasic;
+    while h < input.len() as u32 {
+        let m = input.iter().map(|&c| c as u32).filter(|&c| c >= n).min().unwrap();
+        let newdelta = (m - n) as u64 * (h + 1) as u64 + delta as u64;
+        if newdelta > 0xffffffff { return None; }
+        delta = newdelta as u32;
+        n = m;
+
+        for &c in input.iter() {
+            let c = c as u32;
+            if c < n {
+                if delta == 0xffffffff { return None; }
+                delta += 1;
+            }
+            if c == n {
+                let mut q = delta;
+                let mut k = BASE;
+                loop {
+                    let t = threshold(k, bias);
+                    if q < t { break; }
+                    output.push(encode_digit(t + (q - t) % (BASE - t)));
+                    q = (q - t) / (BASE - t);
+                    k += BASE;
+                }
+                output.push(encode_digit(q));
+                bias = adapt(delta, h + 1, h == nbasic);
+                delta = 0;
+                h += 1;
+            }
+        }
+
+        if delta == 0xffffffff { return None; }
+        delta += 1;
+        n += 1;
+    }
+    Some(output)
+}
+
+/// Decodes given Punycode label without the ACE prefix.
+/// The input should consist of ASCII bytes only.
+/// Returns `None` if the input is invalid or overflows.
+fn decode_label(input: &[u8]) -> Option<Vec<char>> {
+    // everything before the last delimiter is a basic code point.
+    let (mut output, input) = match input.iter().rposition(|&b| b == 0x2d) {
+        Some(pos) => (input.slice_to(pos).iter().map(|&b| b as char).collect::<Vec<char>>(),
+                      input.slice_from(pos + 1)),
+        None => (Vec::new(), input),
+    };
+
+    let mut n = INITIAL_N;
+    let mut i: u32 = 0;
+    let mut bias = INITIAL_BIAS;
+    let mut pos = 0;
+    while pos < input.len() {
+        let oldi = i;
+        let mut w: u32 = 1;
+        let mut k = BASE;
+        loop {
+            if pos >= input.len() { return None; }
+            let digit = match decode_digit(input[pos]) {
+                Some(digit) => digit,
+                None => { return None; }
+            };
+            pos += 1;
+            let newi = digit as u64 * w as u64 + i as u64;
+            if newi > 0xffffffff { return None; }
+            i = newi as u32;
+            let t = threshold(k, bias);
+            if digit < t { break; }
+            let neww = w as u64 * (BASE - t) as u64;
+            if neww > 0xffffffff { return None; }
+            w = neww as u32;
+            k += BASE;
+        }
+
+        let len = output.len() as u32 + 1;
+        bias = adapt(i - oldi, len, oldi == 0);
+        let newn = n as u64 + (i / len) as u64;
+        if newn > 0xffffffff { return None; }
+        n = newn as u32;
+        i %= len;
+
+        match char::from_u32(n) {
+            Some(c) => { output.insert(i as uint, c); }
+            None => { return None; }
+        }
+        i += 1;
+    }
+    Some(output)
+}
+
+/**
+ * Punycode, as specified in RFC 3492.
+ *
+ * This is a bootstring encoding used by IDNA to represent a Unicode label of
+ * internationalized domain names in the ASCII-only DNS, rather than a mapping between
+ * characters and bytes. The encoder turns a label with any non-ASCII character into
+ * the `xn--` prefixed ASCII form, and leaves a label only with ASCII characters as is.
+ * The decoder reverses it; the `xn--` prefix is matched case-insensitively,
+ * and the input without the prefix should be ASCII and is left as is.
+ *
+ * Since the whole label is required to produce the output,
+ * both the encoder and decoder accumulate the input and emit the output
+ * only at the `raw_finish` call.
+ * The entire input is treated as a single label, so dots are not special.
+ */
+#[deriving(Clone)]
+pub struct PunycodeEncoding;
+
+impl Encoding for PunycodeEncoding {
+    fn name(&self) -> &'static str { "punycode" }
+    fn covered_scripts(&self) -> &'static [&'static str] { UNICODE_SCRIPTS }
+    fn is_stateful(&self) -> bool { true }
+    fn encoder(&self) -> Box<Encoder> { PunycodeEncoder::new() }
+    fn decoder(&self) -> Box<Decoder> { PunycodeDecoder::new() }
+}
+
+/// An encoder for Punycode.
+#[deriving(Clone)]
+pub struct PunycodeEncoder {
+    label: Vec<char>,
+}
+
+impl PunycodeEncoder {
+    pub fn new() -> Box<Encoder> { box PunycodeEncoder { label: Vec::new() } as Box<Encoder> }
+}
+
+impl Encoder for PunycodeEncoder {
+    fn from_self(&self) -> Box<Encoder> { PunycodeEncoder::new() }
+    fn clone_box(&self) -> Box<Encoder> { box self.clone() as Box<Encoder> }
+
+    // the label is buffered and encoded as a whole, so there are no separate replacement bytes.
+    fn replacement_bytes(&self) -> Option<Vec<u8>> { None }
+
+    // likewise, a character costs as much as the encoded label grows by.
+    fn char_cost(&mut self, ch: char) -> Option<uint> { buffered_char_cost(&*self, ch) }
+
+    fn raw_feed(&mut self, input: &str, _output: &mut ByteWriter) -> (uint, Option<CodecError>) {
+        for ch in input.chars() {
+            self.label.push(ch);
+        }
+        (input.len(), None)
+    }
+
+    fn raw_finish(&mut self, output: &mut ByteWriter) -> Option<CodecError> {
+        let label = mem::replace(&mut self.label, Vec::new());
+        if label.iter().all(|&c| (c as u32) < 0x80) {
+            for &c in label.iter() {
+                output.write_byte(c as u8);
+            }
+            return None;
+        }
+
+        match encode_label(label.as_slice()) {
+            Some(encoded) => {
+                output.write_bytes(ACE_PREFIX.as_bytes());
+                output.write_bytes(encoded.as_slice());
+                None
+            }
+            None => Some(CodecError {
+                upto: 0, cause: "label too long".into_maybe_owned(),
+                buffered: 0
+            }),
+        }
+    }
+}
+
+/// A decoder for Punycode.
+#[deriving(Clone)]
+pub struct PunycodeDecoder {
+    label: Vec<u8>,
+}
+
+impl PunycodeDecoder {
+    pub fn new() -> Box<Decoder> { box PunycodeDecoder { label: Vec::new() } as Box<Decoder> }
+}
+
+impl Decoder for PunycodeDecoder {
+    fn from_self(&self) -> Box<Decoder> { PunycodeDecoder::new() }
+    fn clone_box(&self) -> Box<Decoder> { box self.clone() as Box<Decoder> }
+
+    fn raw_feed(&mut self, input: &[u8], _output: &mut StringWriter) -> (uint, Option<CodecError>) {
+        for (i, &b) in input.iter().enumerate() {
+            if b >= 0x80 {
+                return (i, Some(CodecError {
+                    upto: i as int + 1, cause: "invalid sequence".into_maybe_owned(),
+                    buffered: 0
+                }));
+            }
+            self.label.push(b);
+        }
+        (input.len(), None)
+    }
+
+    fn raw_finish(&mut self, output: &mut StringWriter) -> Option<CodecError> {
+        let label = mem::replace(&mut self.label, Vec::new());
+        let prefix = ACE_PREFIX.as_bytes();
+        let is_ace = label.len() >= prefix.len() &&
+                     label.iter().zip(prefix.iter()).all(|(&b, &p)| {
+                         let b = match b { 0x41..0x5a => b + 0x20, _ => b };
+                         b == p
+                     });
+        if !is_ace {
+            for &b in label.iter() {
+                output.write_char(b as char);
+            }
+            return None;
+        }
+
+        match decode_label(label.slice_from(prefix.len())) {
+            Some(decoded) => {
+                for &c in decoded.iter() {
+                    output.write_char(c);
+                }
+                None
+            }
+            None => Some(CodecError {
+                upto: 0, cause: "invalid sequence".into_maybe_owned(),
+                buffered: 0
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PunycodeEncoding;
+    use types::*;
+
+    #[test]
+    fn test_encoder() {
+        let mut e = PunycodeEncoding.encoder();
+        assert_feed_ok!(e, "b\xfc", "", []);
+        assert_feed_ok!(e, "cher", "", []);
+        assert_finish_ok!(e, "xn--bcher-kva".as_bytes());
+
+        assert_feed_ok!(e, "example", "", []);
+        assert_finish_ok!(e, "example".as_bytes());
+
+        assert_feed_ok!(e, "", "", []);
+        assert_finish_ok!(e, []);
+
+        assert_eq!(e.replacement_bytes(), None);
+    }
+
+    #[test]
+    fn test_encoder_char_cost() {
+        let mut e = PunycodeEncoding.encoder();
+        assert_eq!(e.char_cost('a'), Some(1));
+        assert_eq!(e.char_cost('\xfc'), Some(7)); // xn--tda
+        assert_feed_ok!(e, "b", "", []);
+        assert_eq!(e.char_cost('\xfc'), Some(8)); // b -> xn--b-eha
+        assert_feed_ok!(e, "\xfccher", "", []);
+        assert_eq!(e.char_cost('a'), Some(1)); // xn--bcher-kva -> xn--bchera-3ya
+        assert_finish_ok!(e, "xn--bcher-kva".as_bytes());
+    }
+
+    #[test]
+    fn test_encoder_rfc3492_samples() {
+        fn check(input: &str, expected: &str) {
+            let expected = "xn--".to_string().append(expected);
+            assert_eq!(PunycodeEncoding.encode(input, EncodeStrict), Ok(expected.into_bytes()));
+        }
+
+        // (A) Arabic (Egyptian)
+        check("\u0644\u064a\u0647\u0645\u0627\u0628\u062a\u0643\u0644\
+               \u0645\u0648\u0634\u0639\u0631\u0628\u064a\u061f",
+              "egbpdaj6bu4bxfgehfvwxn");
+        // (B) Chinese (simplified)
+        check("\u4ed6\u4eec\u4e3a\u4ec0\u4e48\u4e0d\u8bf4\u4e2d\u6587",
+              "ihqwcrb4cv8a8dqg056pqjye");
+        // (L) 3<nen>B<gumi><kinpachi><sensei>
+        check("3\u5e74B\u7d44\u91d1\u516b\u5148\u751f", "3B-ww4c5e180e575a65lsy2b");
+        check("m\xfcnchen", "mnchen-3ya");
+        check("\U0001f600", "e28h");
+    }
+
+    #[test]
+    fn test_decoder() {
+        let mut d = PunycodeEncoding.decoder();
+        assert_feed_ok!(d, "xn--bc".as_bytes(), [], "");
+        assert_feed_ok!(d, "her-kva".as_bytes(), [], "");
+        assert_finish_ok!(d, "b\xfccher");
+
+        assert_feed_ok!(d, "XN--".as_bytes(), [], "");
+        assert_feed_ok!(d, "wgv71a119e".as_bytes(), [], "");
+        assert_finish_ok!(d, "\u65e5\u672c\u8a9e");
+
+        assert_feed_ok!(d, "example".as_bytes(), [], "");
+        assert_finish_ok!(d, "example");
+
+        assert_feed_ok!(d, [], [], "");
+        assert_finish_ok!(d, "");
+    }
+
+    #[test]
+    fn test_decoder_invalid() {
+        let mut d = PunycodeEncoding.decoder();
+        assert_feed_err!(d, [0x78], [0xc3], [0xbc], "");
+        assert_finish_ok!(d, "x");
+
+        // incomplete delta
+        assert_feed_ok!(d, "xn--bcher-kv".as_bytes(), [], "");
+        assert_finish_err!(d, "");
+
+        // invalid digit
+        assert_feed_ok!(d, "xn--bcher-k!a".as_bytes(), [], "");
+        assert_finish_err!(d, "");
+
+        // overflow
+        assert_feed_ok!(d, "xn--99999999999".as_bytes(), [], "");
+        assert_finish_err!(d, "");
+
+        // the decoder should be usable after the error
+        assert_feed_ok!(d, "xn--ab-".as_bytes(), [], "");
+        assert_finish_ok!(d, "ab");
+    }
+
+    #[test]
+    fn test_decoder_rfc3492_samples() {
+        fn check(input: &str, expected: &str) {
+            let input = "xn--".to_string().append(input);
+            assert_eq!(PunycodeEncoding.decode(input.as_bytes(), DecodeStrict),
+                       Ok(expected.to_string()));
+        }
+
+        check("egbpdaj6bu4bxfgehfvwxn",
+              "\u0644\u064a\u0647\u0645\u0627\u0628\u062a\u0643\u0644\
+               \u0645\u0648\u0634\u0639\u0631\u0628\u064a\u061f");
+        check("ihqwcrb4cv8a8dqg056pqjye",
+              "\u4ed6\u4eec\u4e3a\u4ec0\u4e48\u4e0d\u8bf4\u4e2d\u6587");
+        check("3B-ww4c5e180e575a65lsy2b", "3\u5e74B\u7d44\u91d1\u516b\u5148\u751f");
+        check("mnchen-3ya", "m\xfcnchen");
+        check("e28h", "\U0001f600");
+    }
+}
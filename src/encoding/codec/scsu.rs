@@ -0,0 +1,369 @@
+// This is a part of rust-encoding.
+// Copyright (c) 2014, Kang Seonghoon.
+// See README.md and LICENSE.txt for details.
+
+//! Standard Compression Scheme for Unicode.
+
+use util::{as_char, UNICODE_SCRIPTS};
+use types::*;
+use codec;
+
+/// Offsets of the static windows.
+static STATIC_WINDOWS: [u32, ..8] =
+    [0x0000, 0x0080, 0x0100, 0x0300, 0x2000, 0x2080, 0x2100, 0x3000];
+
+/// Initial offsets of the dynamic windows.
+static INITIAL_DYNAMIC_WINDOWS: [u32, ..8] =
+    [0x0080, 0x00c0, 0x0400, 0x0600, 0x0900, 0x3040, 0x30a0, 0xff00];
+
+/// Returns the offset of the dynamic window for given window offset byte, if any.
+fn window_offset(x: u8) -> Option<u32> {
+    match x {
+        0x01..0x67 => Some(x as u32 * 0x80),
+        0x68..0xa7 => Some(x as u32 * 0x80 + 0xac00),
+        0xf9 => Some(0x00c0),
+        0xfa => Some(0x0250),
+        0xfb => Some(0x0370),
+        0xfc => Some(0x0530),
+        0xfd => Some(0x3040),
+        0xfe => Some(0x30a0),
+        0xff => Some(0xff60),
+        _ => None, // 0x00 and 0xa8..0xf8 are reserved
+    }
+}
+
+/**
+ * SCSU, the Standard Compression Scheme for Unicode (Unicode Technical Standard #6).
+ *
+ * This is a compression scheme for Unicode text which uses a single byte per character
+ * for most small alphabetic scripts, by switching between a number of "windows" to
+ * the Unicode code space. It is occasionally used by databases and ICU-produced data.
+ *
+ * Only the decoder is implemented; the encoder returns an error for every character.
+ */
+#[deriving(Clone)]
+pub struct SCSUEncoding;
+
+impl Encoding for SCSUEncoding {
+    fn name(&self) -> &'static str { "scsu" }
+    fn covered_scripts(&self) -> &'static [&'static str] { UNICODE_SCRIPTS }
+    fn is_stateful(&self) -> bool { true }
+    fn can_encode_direction(&self) -> bool { false }
+    fn encoder(&self) -> Box<Encoder> { codec::error::ErrorEncoding.encoder() }
+    fn decoder(&self) -> Box<Decoder> { SCSUDecoder::new() }
+}
+
+/// A complete command or character in SCSU.
+enum Action {
+    /// Emits given Unicode scalar value.
+    EmitChar(u32),
+    /// Emits given UTF-16 code unit, which may be a part of the surrogate pair.
+    EmitUnit(u16),
+    /// Selects the dynamic window and switches to the single-byte mode.
+    SelectWindow(uint),
+    /// Defines the dynamic window with given offset, selects it
+    /// and switches to the single-byte mode.
+    DefineWindow(uint, u32),
+    /// Switches to the Unicode mode.
+    UnicodeMode,
+    /// Reserved or invalid command.
+    Reserved,
+}
+
+/// Returns an action for the `SDX` or `UDX` command with given two bytes.
+fn define_extended_window(hi: u8, lo: u8) -> Action {
+    let offset = 0x10000 + ((((hi & 0x1f) as u32) << 8) | (lo as u32)) * 0x80;
+    DefineWindow((hi >> 5) as uint, offset)
+}
+
+/// A decoder for SCSU.
+pub struct SCSUDecoder {
+    /// Current offsets of the dynamic windows.
+    windows: [u32, ..8],
+    /// The index to the active dynamic window.
+    active: uint,
+    /// True when the decoder is in the Unicode mode.
+    unicode: bool,
+    /// Bytes of the incomplete command or character.
+    pending: Vec<u8>,
+    /// The upper surrogate waiting for the lower surrogate, or 0 if none.
+    leadsurrogate: u16,
+    /// The number of bytes of the command which emitted `leadsurrogate`, or 0 if none.
+    leadsurrogatelen: uint,
+}
+
+impl Clone for SCSUDecoder {
+    fn clone(&self) -> SCSUDecoder {
+        SCSUDecoder { windows: self.windows, active: self.active, unicode: self.unicode,
+                      pending: self.pending.clone(), leadsurrogate: self.leadsurrogate,
+                      leadsurrogatelen: self.leadsurrogatelen }
+    }
+}
+
+impl SCSUDecoder {
+    pub fn new() -> Box<Decoder> {
+        box SCSUDecoder { windows: INITIAL_DYNAMIC_WINDOWS, active: 0, unicode: false,
+                          pending: Vec::new(), leadsurrogate: 0,
+                          leadsurrogatelen: 0 } as Box<Decoder>
+    }
+
+    /// Interprets given bytes as a single command or character in the current mode.
+    /// Returns `None` if more bytes are required.
+    fn interpret(&self, seq: &[u8]) -> Option<Action> {
+        let b = seq[0];
+        if self.unicode {
+            match (b, seq.len()) {
+                (0xe0..0xe7, _) => Some(SelectWindow((b - 0xe0) as uint)), // UCn
+                (0xe8..0xef, 2) => Some(match window_offset(seq[1]) { // UDn
+                    Some(offset) => DefineWindow((b - 0xe8) as uint, offset),
+                    None => Reserved,
+                }),
+                (0xf0, 3) => Some(EmitUnit(((seq[1] as u16) << 8) | (seq[2] as u16))), // UQU
+                (0xf1, 3) => Some(define_extended_window(seq[1], seq[2])), // UDX
+                (0xf2, _) => Some(Reserved),
+                (0xe8..0xf1, _) => None,
+                (_, 2) => Some(EmitUnit(((b as u16) << 8) | (seq[1] as u16))),
+                (_, _) => None,
+            }
+        } else {
+            match (b, seq.len()) {
+                (0x00, _) | (0x09, _) | (0x0a, _) | (0x0d, _) | (0x20..0x7f, _) =>
+                    Some(EmitChar(b as u32)),
+                (0x80..0xff, _) => Some(EmitChar(self.windows[self.active] + (b - 0x80) as u32)),
+                (0x01..0x08, 2) => { // SQn
+                    let window = (b - 0x01) as uint;
+                    let c = seq[1];
+                    if c < 0x80 {
+                        Some(EmitChar(STATIC_WINDOWS[window] + c as u32))
+                    } else {
+                        Some(EmitChar(self.windows[window] + (c - 0x80) as u32))
+                    }
+                }
+                (0x0b, 3) => Some(define_extended_window(seq[1], seq[2])), // SDX
+                (0x0c, _) => Some(Reserved),
+                (0x0e, 3) => Some(EmitUnit(((seq[1] as u16) << 8) | (seq[2] as u16))), // SQU
+                (0x0f, _) => Some(UnicodeMode), // SCU
+                (0x10..0x17, _) => Some(SelectWindow((b - 0x10) as uint)), // SCn
+                (0x18..0x1f, 2) => Some(match window_offset(seq[1]) { // SDn
+                    Some(offset) => DefineWindow((b - 0x18) as uint, offset),
+                    None => Reserved,
+                }),
+                (_, _) => None,
+            }
+        }
+    }
+}
+
+impl Decoder for SCSUDecoder {
+    fn from_self(&self) -> Box<Decoder> { SCSUDecoder::new() }
+    fn clone_box(&self) -> Box<Decoder> { box self.clone() as Box<Decoder> }
+    fn pending_len(&self) -> uint { self.pending.len() + self.leadsurrogatelen }
+
+    fn raw_feed(&mut self, input: &[u8], output: &mut StringWriter) -> (uint, Option<CodecError>) {
+        output.writer_hint(input.len());
+
+        let mut processed = 0;
+        for (i, &b) in input.iter().enumerate() {
+            self.pending.push(b);
+            let action = match self.interpret(self.pending.as_slice()) {
+                Some(action) => action,
+                None => continue,
+            };
+            // the start of the current command, which can be in the prior inputs
+            let start = (i + 1) as int - self.pending.len() as int;
+            let len = self.pending.len();
+            self.pending.clear();
+
+            // the upper surrogate should be immediately followed by the lower surrogate
+            if self.leadsurrogate != 0 {
+                let upper = self.leadsurrogate;
+                self.leadsurrogate = 0;
+                self.leadsurrogatelen = 0;
+                match action {
+                    EmitUnit(lower @ 0xdc00..0xdfff) => {
+                        let ch = ((upper as u32 - 0xd800) << 10) + (lower as u32 - 0xdc00);
+                        output.write_char(as_char(ch + 0x10000));
+                        processed = i + 1;
+                        continue;
+                    }
+                    _ => {
+                        return (processed, Some(CodecError {
+                            upto: start, cause: "invalid sequence".into_maybe_owned(),
+                            buffered: 0
+                        }));
+                    }
+                }
+            }
+
+            match action {
+                EmitChar(ch) => { output.write_char(as_char(ch)); }
+                EmitUnit(upper @ 0xd800..0xdbff) => {
+                    self.leadsurrogate = upper;
+                    self.leadsurrogatelen = len;
+                    continue;
+                }
+                EmitUnit(0xdc00..0xdfff) | Reserved => {
+                    return (processed, Some(CodecError {
+                        upto: i as int + 1, cause: "invalid sequence".into_maybe_owned(),
+                        buffered: 0
+                    }));
+                }
+                EmitUnit(ch) => { output.write_char(as_char(ch)); }
+                SelectWindow(window) => {
+                    self.active = window;
+                    self.unicode = false;
+                }
+                DefineWindow(window, offset) => {
+                    self.windows[window] = offset;
+                    self.active = window;
+                    self.unicode = false;
+                }
+                UnicodeMode => { self.unicode = true; }
+            }
+            processed = i + 1;
+        }
+        (processed, None)
+    }
+
+    fn raw_finish(&mut self, _output: &mut StringWriter) -> Option<CodecError> {
+        let incomplete = !self.pending.is_empty() || self.leadsurrogate != 0;
+        let buffered = self.pending.len() + self.leadsurrogatelen;
+        self.windows = INITIAL_DYNAMIC_WINDOWS;
+        self.active = 0;
+        self.unicode = false;
+        self.pending.clear();
+        self.leadsurrogate = 0;
+        self.leadsurrogatelen = 0;
+        if incomplete {
+            Some(CodecError {
+                upto: 0, cause: "incomplete sequence".into_maybe_owned(), buffered: buffered
+            })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SCSUEncoding;
+    use types::*;
+
+    #[test]
+    fn test_decoder_single_byte_mode() {
+        let mut d = SCSUEncoding.decoder();
+        // UTS #6 section 9.1: German
+        assert_feed_ok!(d, [0xd6, 0x6c, 0x20, 0x66, 0x6c, 0x69, 0x65, 0xdf, 0x74], [],
+                        "\xd6l flie\xdft");
+        assert_finish_ok!(d, "");
+
+        // UTS #6 section 9.2: Russian (SC2)
+        assert_feed_ok!(d, [0x12, 0x9c, 0xbe, 0xc1, 0xba, 0xb2, 0xb0], [],
+                        "\u041c\u043e\u0441\u043a\u0432\u0430");
+        assert_finish_ok!(d, "");
+
+        // SQn from the static and dynamic windows
+        assert_feed_ok!(d, [0x01, 0x41, 0x05, 0x20, 0x03, 0x80], [], "A\u2020\u0400");
+        // SDn
+        assert_feed_ok!(d, [0x18, 0xfd, 0x82, 0x84], [], "\u3042\u3044");
+        // SDX
+        assert_feed_ok!(d, [0x0b, 0xe1, 0xec, 0x80, 0x81], [], "\U0001f600\U0001f601");
+        // SQU
+        assert_feed_ok!(d, [0x0e, 0x4e, 0x00, 0x0e, 0xd8, 0x3d, 0x0e, 0xde, 0x00], [],
+                        "\u4e00\U0001f600");
+        assert_finish_ok!(d, "");
+    }
+
+    #[test]
+    fn test_decoder_unicode_mode() {
+        let mut d = SCSUEncoding.decoder();
+        // SCU and UC0
+        assert_feed_ok!(d, [0x0f, 0x4e, 0x00, 0x4e, 0x8c, 0xe0, 0x41], [], "\u4e00\u4e8cA");
+        // UQU
+        assert_feed_ok!(d, [0x0f, 0xf0, 0xe0, 0x00, 0xe0, 0x41], [], "\ue000A");
+        // UD3
+        assert_feed_ok!(d, [0x0f, 0xeb, 0xfe, 0x82], [], "\u30a2");
+        // surrogate pair
+        assert_feed_ok!(d, [0x0f, 0xd8, 0x3d, 0xde, 0x00], [], "\U0001f600");
+        assert_finish_ok!(d, "");
+    }
+
+    #[test]
+    fn test_decoder_partial() {
+        let mut d = SCSUEncoding.decoder();
+        assert_feed_ok!(d, [], [0x0e, 0x4e], "");
+        assert_feed_ok!(d, [0x00], [], "\u4e00");
+        assert_feed_ok!(d, [0x0f], [0xd8, 0x3d], "");
+        assert_feed_ok!(d, [0xde, 0x00], [], "\U0001f600");
+        assert_feed_ok!(d, [0xe0], [], "");
+        assert_feed_ok!(d, [], [0x18], "");
+        assert_feed_ok!(d, [0xfd, 0x82], [], "\u3042");
+        assert_finish_ok!(d, "");
+
+        // the window state persists across feeds, but not across finishes
+        assert_feed_ok!(d, [0x12], [], "");
+        assert_feed_ok!(d, [0x9c], [], "\u041c");
+        assert_finish_ok!(d, "");
+        assert_feed_ok!(d, [0xc0], [], "\xc0");
+        assert_finish_ok!(d, "");
+    }
+
+    #[test]
+    fn test_decoder_invalid() {
+        let mut d = SCSUEncoding.decoder();
+        assert_feed_err!(d, [0x41], [0x0c], [0x42], "A");
+        assert_feed_err!(d, [], [0x18, 0x00], [0x42], "");
+        assert_feed_err!(d, [], [0x0e, 0xdc, 0x00], [], "");
+        assert_feed_err!(d, [0x0f], [0xd8, 0x3d], [0x00, 0x41], "");
+        assert_feed_ok!(d, [0x00, 0x41], [], "A"); // still in the Unicode mode
+        assert_feed_err!(d, [], [0xf2], [], "");
+        assert_finish_ok!(d, "");
+
+        assert_feed_ok!(d, [], [0x0e, 0x4e], "");
+        assert_finish_err!(d, "");
+        assert_feed_ok!(d, [0x0f], [0xd8, 0x3d], "");
+        assert_finish_err!(d, "");
+    }
+
+    #[test]
+    fn test_decoder_finish_buffered() {
+        let mut d = SCSUEncoding.decoder();
+        assert_feed_ok!(d, [], [0x0e, 0x4e], "");
+        let (err, _) = d.test_finish();
+        assert_eq!(err.map(|e| e.buffered), Some(2));
+
+        // the pending upper surrogate counts as the bytes of its command
+        assert_feed_ok!(d, [0x0f], [0xd8, 0x3d], "");
+        let (err, _) = d.test_finish();
+        assert_eq!(err.map(|e| e.buffered), Some(2));
+
+        assert_feed_ok!(d, [], [0x0e, 0xd8, 0x3d], "");
+        let (err, _) = d.test_finish();
+        assert_eq!(err.map(|e| e.buffered), Some(3));
+
+        assert_feed_ok!(d, [0x0f], [0xd8, 0x3d, 0xde], "");
+        let (err, _) = d.test_finish();
+        assert_eq!(err.map(|e| e.buffered), Some(3));
+    }
+
+    #[test]
+    fn test_decoder_pending_len() {
+        let mut d = SCSUEncoding.decoder();
+        assert_eq!(d.pending_len(), 0);
+        assert_feed_ok!(d, [], [0x0e, 0x4e], "");
+        assert_eq!(d.pending_len(), 2);
+        assert_feed_ok!(d, [0x00], [], "\u4e00");
+        assert_eq!(d.pending_len(), 0);
+
+        // the decoder is not idle while the upper surrogate waits for the lower one
+        assert_feed_ok!(d, [0x0f], [0xd8, 0x3d], "");
+        assert_eq!(d.pending_len(), 2);
+        assert!(d.needs_more());
+        assert_feed_ok!(d, [], [0xde], "");
+        assert_eq!(d.pending_len(), 3);
+        assert_feed_ok!(d, [0x00], [], "\U0001f600");
+        assert_eq!(d.pending_len(), 0);
+        assert!(!d.needs_more());
+        assert_finish_ok!(d, "");
+    }
+}
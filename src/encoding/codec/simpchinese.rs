@@ -4,10 +4,14 @@
 
 //! Legacy simplified Chinese encodings based on GB 2312 and GB 18030.
 
-use util::StrCharIndex;
+use util::{StrCharIndex, UNICODE_SCRIPTS};
 use index;
 use types::*;
 
+/// Scripts covered by GB 2312, used by HZ.
+static GB2312_SCRIPTS: &'static [&'static str] =
+    &["Latin", "Greek", "Cyrillic", "Hiragana", "Katakana", "Bopomofo", "CJK"];
+
 /**
  * GB 18030-2005.
  *
@@ -37,6 +41,7 @@ pub struct GB18030Encoding;
 impl Encoding for GB18030Encoding {
     fn name(&self) -> &'static str { "gb18030" }
     fn whatwg_name(&self) -> Option<&'static str> { Some("gb18030") }
+    fn covered_scripts(&self) -> &'static [&'static str] { UNICODE_SCRIPTS }
     fn encoder(&self) -> Box<Encoder> { GB18030Encoder::new() }
     fn decoder(&self) -> Box<Decoder> { GB18030Decoder::new() }
 }
@@ -51,14 +56,22 @@ impl GB18030Encoder {
 
 impl Encoder for GB18030Encoder {
     fn from_self(&self) -> Box<Encoder> { GB18030Encoder::new() }
+    fn clone_box(&self) -> Box<Encoder> { box self.clone() as Box<Encoder> }
     fn is_ascii_compatible(&self) -> bool { true }
 
     fn raw_feed(&mut self, input: &str, output: &mut ByteWriter) -> (uint, Option<CodecError>) {
         output.writer_hint(input.len());
 
-        for ch in input.chars() {
+        for ((i,j), ch) in input.index_iter() {
             if ch < '\u0080' {
                 output.write_byte(ch as u8);
+            } else if ch == '\ue5e5' {
+                // GB 18030-2005 has this at `A3 A0`, but the index maps that to U+3000
+                // and the four-byte ranges do not cover it either
+                return (i, Some(CodecError {
+                    upto: j as int, cause: "unrepresentable character".into_maybe_owned(),
+                    buffered: 0
+                }));
             } else {
                 let ptr = index::gb18030::backward(ch as u32);
                 if ptr == 0xffff {
@@ -201,6 +214,62 @@ mod gb18030_tests {
         assert_finish_ok!(d, "");
     }
 
+    #[test]
+    fn test_user_defined_areas() {
+        use std::iter::range_inclusive;
+
+        // each user-defined area maps to consecutive PUA code points row by row,
+        // returns the code point next to the last one
+        fn check_area(lead_first: uint, lead_last: uint, trail_ranges: &[(uint, uint)],
+                      first: u32) -> u32 {
+            let mut expected = first;
+            for lead in range_inclusive(lead_first, lead_last) {
+                for &(trail_first, trail_last) in trail_ranges.iter() {
+                    for trail in range_inclusive(trail_first, trail_last) {
+                        // `A3 A0` would be U+E5E5, but the index maps it to U+3000 (see below)
+                        if (lead, trail) == (0xa3, 0xa0) { expected += 1; continue; }
+                        let input = [lead as u8, trail as u8];
+                        let s = String::from_char(1, ::std::char::from_u32(expected).unwrap());
+                        assert_eq!(GB18030Encoding.decode(input, DecodeStrict), Ok(s.clone()));
+                        assert_eq!(GB18030Encoding.encode(s.as_slice(), EncodeStrict),
+                                   Ok(input.to_vec()));
+                        expected += 1;
+                    }
+                }
+            }
+            expected
+        }
+
+        assert_eq!(check_area(0xaa, 0xaf, [(0xa1, 0xfe)], 0xe000), 0xe234);
+        assert_eq!(check_area(0xf8, 0xfe, [(0xa1, 0xfe)], 0xe234), 0xe4c6);
+        assert_eq!(check_area(0xa1, 0xa7, [(0x40, 0x7e), (0x80, 0xa0)], 0xe4c6), 0xe766);
+
+        // the WHATWG index maps `A3 A0` to U+3000 as a duplicate of `A1 A1`,
+        // so U+E5E5 cannot be encoded at all
+        let mut d = GB18030Encoding.decoder();
+        assert_feed_ok!(d, [0xa3, 0xa0, 0xa1, 0xa1], [], "\u3000\u3000");
+        assert_finish_ok!(d, "");
+
+        let mut e = GB18030Encoding.encoder();
+        assert_feed_ok!(e, "\u3000", "", [0xa1, 0xa1]);
+        assert_feed_err!(e, "\ue5e4", "\ue5e5", "\ue5e6", [0xa3, 0x9f]);
+        assert_finish_ok!(e, []);
+    }
+
+    #[test]
+    fn test_pua() {
+        // some two-byte sequences outside of the user-defined areas also map to PUA
+        let mut d = GB18030Encoding.decoder();
+        assert_feed_ok!(d, [0xa2, 0xab], [], "\ue766");
+        assert_feed_ok!(d, [0xa6, 0xd9, 0xfe, 0x51], [], "\ue78d\ue816");
+        assert_finish_ok!(d, "");
+
+        let mut e = GB18030Encoding.encoder();
+        assert_feed_ok!(e, "\ue766", "", [0xa2, 0xab]);
+        assert_feed_ok!(e, "\ue78d\ue816", "", [0xa6, 0xd9, 0xfe, 0x51]);
+        assert_finish_ok!(e, []);
+    }
+
     #[test]
     fn test_decoder_valid_partial() {
         let mut d = GB18030Encoding.decoder();
@@ -336,6 +405,9 @@ pub struct HZEncoding;
 impl Encoding for HZEncoding {
     fn name(&self) -> &'static str { "hz" }
     fn whatwg_name(&self) -> Option<&'static str> { Some("hz-gb-2312") }
+    fn covered_scripts(&self) -> &'static [&'static str] { GB2312_SCRIPTS }
+    fn is_stateful(&self) -> bool { true }
+    fn assumes_normalization(&self) -> Option<NormalizationForm> { Some(NFC) }
     fn encoder(&self) -> Box<Encoder> { HZEncoder::new() }
     fn decoder(&self) -> Box<Decoder> { HZDecoder::new() }
 }
@@ -352,8 +424,13 @@ impl HZEncoder {
 
 impl Encoder for HZEncoder {
     fn from_self(&self) -> Box<Encoder> { HZEncoder::new() }
+    fn clone_box(&self) -> Box<Encoder> { box self.clone() as Box<Encoder> }
     fn is_ascii_compatible(&self) -> bool { false }
 
+    fn replacement_bytes(&self) -> Option<Vec<u8>> {
+        if self.escaped { Some(Vec::from_slice(b"~}?")) } else { Some(Vec::from_slice(b"?")) }
+    }
+
     fn raw_feed(&mut self, input: &str, output: &mut ByteWriter) -> (uint, Option<CodecError>) {
         output.writer_hint(input.len());
 
@@ -375,7 +452,8 @@ impl Encoder for HZEncoder {
                 if ptr == 0xffff {
                     self.escaped = escaped; // do NOT reset the state!
                     return (i, Some(CodecError {
-                        upto: j as int, cause: "unrepresentable character".into_maybe_owned()
+                        upto: j as int, cause: "unrepresentable character".into_maybe_owned(),
+                        buffered: 0
                     }));
                 } else {
                     let lead = ptr / 190;
@@ -383,7 +461,8 @@ impl Encoder for HZEncoder {
                     if lead < 0x21 - 1 || trail < 0x21 + 0x3f { // GBK extension, ignored
                         self.escaped = escaped; // do NOT reset the state!
                         return (i, Some(CodecError {
-                            upto: j as int, cause: "unrepresentable character".into_maybe_owned()
+                            upto: j as int, cause: "unrepresentable character".into_maybe_owned(),
+                            buffered: 0
                         }));
                     } else {
                         ensure_escaped!();
@@ -504,6 +583,17 @@ mod hz_tests {
         assert_finish_ok!(e, []);
     }
 
+    #[test]
+    fn test_encoder_replacement() {
+        let mut e = HZEncoding.encoder();
+        assert_eq!(e.replacement_bytes(), Some(Vec::from_slice(b"?")));
+        assert_feed_ok!(e, "\u4e2d", "", b"~{VP");
+        assert_eq!(e.replacement_bytes(), Some(Vec::from_slice(b"~}?")));
+        assert_finish_ok!(e, []);
+        assert_eq!(HZEncoding.encode("\u4e2d\uffff\u534e", EncodeReplace),
+                   Ok(Vec::from_slice(b"~{VP~}?~{;*")));
+    }
+
     #[test]
     fn test_decoder_valid() {
         let mut d = HZEncoding.decoder();
@@ -4,58 +4,128 @@
 
 //! Common codec implementation for single-byte encodings.
 
+use std::collections::HashMap;
+use std::sync::Arc;
 use util::{as_char, StrCharIndex};
 use types::*;
 
 /// A common framework for single-byte encodings based on ASCII.
+#[deriving(Clone)]
 pub struct SingleByteEncoding {
     pub name: &'static str,
     pub whatwg_name: Option<&'static str>,
     pub index_forward: extern "Rust" fn(u8) -> u16,
     pub index_backward: extern "Rust" fn(u32) -> u8,
+    /// If true, the decoder maps any undefined byte to the code point of the same value
+    /// (as in ISO 8859-1) instead of returning an error, so the decoding never fails.
+    /// This is useful for the lossy ingestion of legacy data; the encoder is not affected.
+    pub latin1_fallback: bool,
+    /// If true, the encoder maps C1 controls (U+0080 through U+009F) to the byte of
+    /// the same value without consulting `index_backward`, as in the ISO 8859 family.
+    /// This should be false when bytes `80` through `9F` are printables (e.g. windows-125x).
+    pub c1_passthrough: bool,
+    /// Scripts reported by `covered_scripts`.
+    pub scripts: &'static [&'static str],
+    /// The normalization form reported by `assumes_normalization`.
+    /// This is `Some(NFC)` unless the encoding has combining characters (e.g. windows-1258).
+    pub normalization: Option<NormalizationForm>,
 }
 
 impl Encoding for SingleByteEncoding {
     fn name(&self) -> &'static str { self.name }
     fn whatwg_name(&self) -> Option<&'static str> { self.whatwg_name }
-    fn encoder(&self) -> Box<Encoder> { SingleByteEncoder::new(self.index_backward) }
-    fn decoder(&self) -> Box<Decoder> { SingleByteDecoder::new(self.index_forward) }
+    fn covered_scripts(&self) -> &'static [&'static str] { self.scripts }
+    fn is_single_byte(&self) -> bool { true }
+    fn assumes_normalization(&self) -> Option<NormalizationForm> { self.normalization }
+    fn encoder(&self) -> Box<Encoder> { SingleByteEncoder::new(self.clone(), self.c1_passthrough) }
+    fn decoder(&self) -> Box<Decoder> { SingleByteDecoder::new(self.clone(), self.latin1_fallback) }
+
+    fn validate(&self, input: &[u8]) -> Result<uint, CodecError> {
+        if self.latin1_fallback { return Ok(input.len()); }
+        match input.iter().position(|&b| self.forward(b).is_none()) {
+            Some(i) => Err(CodecError {
+                upto: i as int + 1,
+                cause: format!("invalid single byte 0x{:02X}", input[i]).into_maybe_owned(),
+                buffered: 0
+            }),
+            None => Ok(input.len()),
+        }
+    }
 }
 
-/// An encoder for single-byte encodings based on ASCII.
+/// Maps a byte to a character and back. Bytes `00` through `7F` always map to ASCII.
+/// This is not affected by `latin1_fallback` or `c1_passthrough`.
+impl IndexableEncoding<u8> for SingleByteEncoding {
+    fn forward(&self, ptr: u8) -> Option<char> {
+        if ptr <= 0x7f { return Some(ptr as char); }
+        match (self.index_forward)(ptr) {
+            0xffff => None,
+            ch => Some(as_char(ch)),
+        }
+    }
+
+    fn backward(&self, ch: char) -> Option<u8> {
+        if ch <= '\u007f' { return Some(ch as u8); }
+        match (self.index_backward)(ch as u32) {
+            0 => None,
+            b => Some(b),
+        }
+    }
+}
+
+/**
+ * An encoder for single-byte encodings based on ASCII.
+ *
+ * ## Specialization
+ *
+ * This type is specialized with index type `I`,
+ * which maps bytes `80` through `FF` (e.g. `SingleByteEncoding` or `TableSingleByteEncoding`).
+ */
 #[deriving(Clone)]
-pub struct SingleByteEncoder {
-    index_backward: extern "Rust" fn(u32) -> u8,
+pub struct SingleByteEncoder<I> {
+    index: I,
+    c1_passthrough: bool,
 }
 
-impl SingleByteEncoder {
-    pub fn new(index_backward: extern "Rust" fn(u32) -> u8) -> Box<Encoder> {
-        box SingleByteEncoder { index_backward: index_backward } as Box<Encoder>
+impl<I:IndexableEncoding<u8>+Clone+'static> SingleByteEncoder<I> {
+    pub fn new(index: I, c1_passthrough: bool) -> Box<Encoder> {
+        box SingleByteEncoder { index: index, c1_passthrough: c1_passthrough } as Box<Encoder>
     }
 }
 
-impl Encoder for SingleByteEncoder {
-    fn from_self(&self) -> Box<Encoder> { SingleByteEncoder::new(self.index_backward) }
+impl<I:IndexableEncoding<u8>+Clone+'static> Encoder for SingleByteEncoder<I> {
+    fn from_self(&self) -> Box<Encoder> {
+        SingleByteEncoder::new(self.index.clone(), self.c1_passthrough)
+    }
+    fn clone_box(&self) -> Box<Encoder> { box self.clone() as Box<Encoder> }
     fn is_ascii_compatible(&self) -> bool { true }
 
     fn raw_feed(&mut self, input: &str, output: &mut ByteWriter) -> (uint, Option<CodecError>) {
         output.writer_hint(input.len());
 
+        // the backward indices are direct two-level tables or hash maps,
+        // so the remaining cost is mostly per-character calls for ASCII.
+        // we copy each run of ASCII characters at once and look up the other characters only.
+        let mut asciistart = 0;
         for ((i,j), ch) in input.index_iter() {
-            if ch <= '\u007f' {
+            if ch <= '\u007f' { continue; }
+            output.write_bytes(input.slice(asciistart, i).as_bytes());
+            asciistart = j;
+            if self.c1_passthrough && ch <= '\u009f' {
                 output.write_byte(ch as u8);
-                continue;
             } else {
-                let index = (self.index_backward)(ch as u32);
-                if index != 0 {
-                    output.write_byte(index);
-                } else {
-                    return (i, Some(CodecError {
-                        upto: j as int, cause: "unrepresentable character".into_maybe_owned()
-                    }));
+                match self.index.backward(ch) {
+                    Some(b) => output.write_byte(b),
+                    None => {
+                        return (i, Some(CodecError {
+                            upto: j as int, cause: "unrepresentable character".into_maybe_owned(),
+                            buffered: 0
+                        }));
+                    }
                 }
             }
         }
+        output.write_bytes(input.slice_from(asciistart).as_bytes());
         (input.len(), None)
     }
 
@@ -64,20 +134,31 @@ impl Encoder for SingleByteEncoder {
     }
 }
 
-/// A decoder for single-byte encodings based on ASCII.
+/**
+ * A decoder for single-byte encodings based on ASCII.
+ *
+ * ## Specialization
+ *
+ * This type is specialized with index type `I`,
+ * which maps bytes `80` through `FF` (e.g. `SingleByteEncoding` or `TableSingleByteEncoding`).
+ */
 #[deriving(Clone)]
-pub struct SingleByteDecoder {
-    index_forward: extern "Rust" fn(u8) -> u16,
+pub struct SingleByteDecoder<I> {
+    index: I,
+    latin1_fallback: bool,
 }
 
-impl SingleByteDecoder {
-    pub fn new(index_forward: extern "Rust" fn(u8) -> u16) -> Box<Decoder> {
-        box SingleByteDecoder { index_forward: index_forward } as Box<Decoder>
+impl<I:IndexableEncoding<u8>+Clone+'static> SingleByteDecoder<I> {
+    pub fn new(index: I, latin1_fallback: bool) -> Box<Decoder> {
+        box SingleByteDecoder { index: index, latin1_fallback: latin1_fallback } as Box<Decoder>
     }
 }
 
-impl Decoder for SingleByteDecoder {
-    fn from_self(&self) -> Box<Decoder> { SingleByteDecoder::new(self.index_forward) }
+impl<I:IndexableEncoding<u8>+Clone+'static> Decoder for SingleByteDecoder<I> {
+    fn from_self(&self) -> Box<Decoder> {
+        SingleByteDecoder::new(self.index.clone(), self.latin1_fallback)
+    }
+    fn clone_box(&self) -> Box<Decoder> { box self.clone() as Box<Decoder> }
     fn is_ascii_compatible(&self) -> bool { true }
 
     fn raw_feed(&mut self, input: &[u8], output: &mut StringWriter) -> (uint, Option<CodecError>) {
@@ -89,13 +170,17 @@ impl Decoder for SingleByteDecoder {
             if input[i] <= 0x7f {
                 output.write_char(input[i] as char);
             } else {
-                let ch = (self.index_forward)(input[i]);
-                if ch != 0xffff {
-                    output.write_char(as_char(ch));
-                } else {
-                    return (i, Some(CodecError {
-                        upto: i as int + 1, cause: "invalid sequence".into_maybe_owned()
-                    }));
+                match self.index.forward(input[i]) {
+                    Some(ch) => output.write_char(ch),
+                    None if self.latin1_fallback => output.write_char(input[i] as char),
+                    None => {
+                        return (i, Some(CodecError {
+                            upto: i as int + 1,
+                            cause: format!("invalid single byte 0x{:02X}",
+                                           input[i]).into_maybe_owned(),
+                            buffered: 0
+                        }));
+                    }
                 }
             }
             i += 1;
@@ -108,16 +193,86 @@ impl Decoder for SingleByteDecoder {
     }
 }
 
+/// A single-byte encoding based on ASCII with the mapping for upper bytes supplied at runtime,
+/// e.g. loaded from a Unicode mapping file.
+/// It behaves same to `SingleByteEncoding` except that the index is an owned table.
+#[experimental]
+pub struct TableSingleByteEncoding {
+    name: &'static str,
+    forward: [char, ..128],
+    backward: Arc<HashMap<char, u8>>,
+}
+
+impl TableSingleByteEncoding {
+    /// Creates a new encoding from the table for bytes 0x80 through 0xFF.
+    /// `table[i]` is the character for the byte `0x80 + i`, or U+FFFF if undefined.
+    /// If the same character appears more than once, the encoder uses the first byte.
+    pub fn new(name: &'static str, table: [char, ..128]) -> TableSingleByteEncoding {
+        let mut backward = HashMap::new();
+        for (i, &ch) in table.iter().enumerate() {
+            if ch != '\uffff' && !backward.contains_key(&ch) {
+                backward.insert(ch, (i + 0x80) as u8);
+            }
+        }
+        TableSingleByteEncoding { name: name, forward: table, backward: Arc::new(backward) }
+    }
+}
+
+impl Clone for TableSingleByteEncoding {
+    fn clone(&self) -> TableSingleByteEncoding {
+        TableSingleByteEncoding { name: self.name, forward: self.forward,
+                                  backward: self.backward.clone() }
+    }
+}
+
+/// Maps a byte to a character and back. Bytes `00` through `7F` always map to ASCII.
+impl IndexableEncoding<u8> for TableSingleByteEncoding {
+    fn forward(&self, ptr: u8) -> Option<char> {
+        if ptr <= 0x7f { return Some(ptr as char); }
+        match self.forward[(ptr - 0x80) as uint] {
+            '\uffff' => None,
+            ch => Some(ch),
+        }
+    }
+
+    fn backward(&self, ch: char) -> Option<u8> {
+        if ch <= '\u007f' { return Some(ch as u8); }
+        self.backward.find(&ch).map(|&b| b)
+    }
+}
+
+impl Encoding for TableSingleByteEncoding {
+    fn name(&self) -> &'static str { self.name }
+    fn is_single_byte(&self) -> bool { true }
+    fn encoder(&self) -> Box<Encoder> { SingleByteEncoder::new(self.clone(), false) }
+    fn decoder(&self) -> Box<Decoder> { SingleByteDecoder::new(self.clone(), false) }
+}
+
 /// Algorithmic mapping for ISO 8859-1.
 pub mod iso_8859_1 {
     #[inline] pub fn forward(code: u8) -> u16 { code as u16 }
     #[inline] pub fn backward(code: u32) -> u8 { if (code & !0x7f) == 0x80 {code as u8} else {0} }
+
+    /// Decodes ISO 8859-1 `input` directly into a vector of characters.
+    /// Every byte maps to the code point of the same value, so this never fails and
+    /// avoids the UTF-8 re-encoding done by `Encoding::decode`.
+    /// Useful for consumers which process the decoded text character by character.
+    pub fn decode_to_chars(input: &[u8]) -> Vec<char> {
+        input.iter().map(|&b| b as char).collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use all::ISO_8859_2;
+    extern crate test;
+    use all::{ISO_8859_1, ISO_8859_2, ISO_8859_6, ISO_8859_7, WINDOWS_1251, WINDOWS_1253,
+              WINDOWS_1256};
+    use std::iter::range_inclusive;
+    use index;
+    use testutils;
     use types::*;
+    use util::as_char;
+    use super::{SingleByteEncoding, TableSingleByteEncoding, iso_8859_1};
 
     #[test]
     fn test_encoder_non_bmp() {
@@ -125,5 +280,316 @@ mod tests {
         assert_feed_err!(e, "A", "\uFFFF", "B", [0x41]);
         assert_feed_err!(e, "A", "\U00010000", "B", [0x41]);
     }
-}
 
+    #[test]
+    fn test_encoder_ascii_runs() {
+        // runs of ASCII characters are copied at once around the looked up characters
+        let mut e = ISO_8859_2.encoder();
+        assert_feed_ok!(e, "", "", []);
+        assert_feed_ok!(e, "abc", "", [0x61, 0x62, 0x63]);
+        assert_feed_ok!(e, "\u0104", "", [0xa1]);
+        assert_feed_ok!(e, "ab\u0104\u0104cd\u0104e", "",
+                        [0x61, 0x62, 0xa1, 0xa1, 0x63, 0x64, 0xa1, 0x65]);
+        assert_feed_err!(e, "ab\u0104c", "\u00e0", "de", [0x61, 0x62, 0xa1, 0x63]);
+        assert_finish_ok!(e, []);
+    }
+
+    #[test]
+    fn test_iso_8859_6_undefined() {
+        // 0xa1 is one of many undefined positions in ISO 8859-6; the decoder reports them
+        // as errors, so that the WHATWG behavior (U+FFFD) is `DecodeReplace`.
+        let mut d = ISO_8859_6.decoder();
+        assert_feed_ok!(d, [0x41, 0xc7, 0xc8], [], "A\u0627\u0628");
+        assert_feed_err!(d, [0xc7], [0xa1], [0x41], "\u0627");
+        assert_feed_err!(d, [], [0xff], [], "");
+        assert_finish_ok!(d, "");
+
+        assert_eq!(ISO_8859_6.decode([0xc7, 0xa1, 0xc8], DecodeReplace),
+                   Ok("\u0627\ufffd\u0628".to_string()));
+        assert!(ISO_8859_6.decode([0xc7, 0xa1, 0xc8], DecodeStrict).is_err());
+
+        let mut e = ISO_8859_6.encoder();
+        assert_feed_ok!(e, "A\u0627\u0628", "", [0x41, 0xc7, 0xc8]);
+        assert_feed_err!(e, "\u0627", "\u00e9", "A", [0xc7]);
+        assert_finish_ok!(e, []);
+        assert_eq!(ISO_8859_6.encode("\u0627\u00e9", EncodeReplace), Ok(vec!(0xc7, 0x3f)));
+    }
+
+    #[test]
+    fn test_koi8_ru_and_mik() {
+        use all::{KOI8_U, KOI8_RU, MIK};
+
+        // Belarusian, which requires U+045E and U+040E absent in KOI8-U
+        let text = "\u040e\u0441\u0451 \u045e \u043f\u0430\u0440\u0430\u0434\u043a\u0443";
+        let encoded = KOI8_RU.encode(text, EncodeStrict).unwrap();
+        assert_eq!(encoded, vec!(0xbe, 0xd3, 0xa3, 0x20, 0xae, 0x20,
+                                 0xd0, 0xc1, 0xd2, 0xc1, 0xc4, 0xcb, 0xd5));
+        assert_eq!(KOI8_RU.decode(encoded.as_slice(), DecodeStrict), Ok(text.to_string()));
+        assert!(KOI8_U.encode(text, EncodeStrict).is_err());
+
+        // KOI8-RU is otherwise same to KOI8-U
+        let text = "\u0490\u0430\u043d\u043e\u043a \u0457 \u0454";
+        assert_eq!(KOI8_RU.encode(text, EncodeStrict), KOI8_U.encode(text, EncodeStrict));
+
+        // Bulgarian
+        let text = "\u0417\u0434\u0440\u0430\u0432\u0435\u0439, \u0441\u0432\u044f\u0442! \u2116 5";
+        let encoded = MIK.encode(text, EncodeStrict).unwrap();
+        assert_eq!(encoded, vec!(0x87, 0xa4, 0xb0, 0xa0, 0xa2, 0xa5, 0xa9, 0x2c, 0x20,
+                                 0xb1, 0xa2, 0xbf, 0xb2, 0x21, 0x20, 0xd5, 0x20, 0x35));
+        assert_eq!(MIK.decode(encoded.as_slice(), DecodeStrict), Ok(text.to_string()));
+        assert_eq!(MIK.decode([0xc0, 0xc4, 0xd9, 0xe0, 0xff], DecodeStrict),
+                   Ok("\u2514\u2500\u2518\u03b1\u00a0".to_string()));
+    }
+
+    #[test]
+    fn test_decoder_error_cause() {
+        let mut d = ISO_8859_6.decoder();
+        let (_, err, _) = d.test_feed([0x41, 0xa1]);
+        assert_eq!(err.unwrap().cause.as_slice(), "invalid single byte 0xA1");
+        assert_eq!(ISO_8859_6.validate([0xc7, 0xff]).unwrap_err().cause.as_slice(),
+                   "invalid single byte 0xFF");
+    }
+
+    #[test]
+    fn test_windows_1256() {
+        // unlike ISO 8859-6, every position is assigned,
+        // including Latin letters for French loanwords in the Arabic letter block
+        for i in range_inclusive(0x80u8, 0xff) {
+            assert!(WINDOWS_1256.decode([i], DecodeStrict).is_ok());
+        }
+
+        let mut d = WINDOWS_1256.decoder();
+        assert_feed_ok!(d, [0xc8, 0xe0, 0xc7, 0xe9], [], "\u0628\u00e0\u0627\u00e9");
+        assert_feed_ok!(d, [0xe7, 0xf4, 0x81, 0xff], [], "\u00e7\u00f4\u067e\u06d2");
+        assert_finish_ok!(d, "");
+
+        // only the nominal letters are mapped; the presentation forms are not representable
+        // and should be normalized (e.g. with NFKC) before encoding
+        let mut e = WINDOWS_1256.encoder();
+        assert_feed_ok!(e, "\u0628\u00e0\u0627\u00e9", "", [0xc8, 0xe0, 0xc7, 0xe9]);
+        assert_feed_err!(e, "\u0628", "\ufe8f", "\u0627", [0xc8]); // BEH ISOLATED FORM
+        assert_feed_err!(e, "", "\ufefb", "", []); // LIGATURE LAM WITH ALEF ISOLATED FORM
+        assert_finish_ok!(e, []);
+    }
+
+    #[test]
+    fn test_windows_1255() {
+        use all::WINDOWS_1255;
+
+        // points are separate characters following the letter, and need no special handling
+        let text = "\u05e9\u05c1\u05b8\u05dc\u05d5\u05b9\u05dd"; // shalom with niqqud
+        let mut d = WINDOWS_1255.decoder();
+        assert_feed_ok!(d, [0xf9, 0xd1, 0xc8, 0xec, 0xe5, 0xc9, 0xed], [], text);
+        assert_finish_ok!(d, "");
+        let mut e = WINDOWS_1255.encoder();
+        assert_feed_ok!(e, text, "", [0xf9, 0xd1, 0xc8, 0xec, 0xe5, 0xc9, 0xed]);
+        assert_feed_ok!(e, "\u20aa5", "", [0xa4, 0x35]); // NEW SHEQEL SIGN
+        assert_finish_ok!(e, []);
+
+        // undefined positions are errors, i.e. U+FFFD with `DecodeReplace` as in WHATWG
+        let undefined = [0xca, 0xd9, 0xda, 0xdb, 0xdc, 0xdd, 0xde, 0xdf, 0xfb, 0xfc, 0xff];
+        for i in range_inclusive(0x80u8, 0xff) {
+            assert_eq!(WINDOWS_1255.decode([i], DecodeStrict).is_err(), undefined.contains(&i));
+        }
+        let mut d = WINDOWS_1255.decoder();
+        assert_feed_err!(d, [0xe0], [0xca], [0xe1], "\u05d0");
+        assert_finish_ok!(d, "");
+        assert_eq!(WINDOWS_1255.decode([0xe0, 0xc8, 0xd9, 0xe1], DecodeReplace),
+                   Ok("\u05d0\u05b8\ufffd\u05d1".to_string()));
+    }
+
+    #[test]
+    fn test_windows_1251_0x98() {
+        // 0x98 is the only position not assigned to a Cyrillic letter or symbol in windows-1251.
+        // The WHATWG index maps it to U+0098 (and so do other windows code pages for
+        // their unassigned C1 positions), so it round-trips instead of being decoded to U+FFFD.
+        let mut d = WINDOWS_1251.decoder();
+        assert_feed_ok!(d, [0xc0, 0x98, 0xff], [], "\u0410\u0098\u044f");
+        assert_finish_ok!(d, "");
+
+        let mut e = WINDOWS_1251.encoder();
+        assert_feed_ok!(e, "\u0410\u0098\u044f", "", [0xc0, 0x98, 0xff]);
+        assert_finish_ok!(e, []);
+    }
+
+    #[test]
+    fn test_decoder_latin1_fallback() {
+        let lossy = SingleByteEncoding { latin1_fallback: true, ..*WINDOWS_1253 };
+
+        // 0xaa, 0xd2 and 0xff are undefined in windows-1253
+        let mut d = WINDOWS_1253.decoder();
+        assert_feed_err!(d, [0x41], [0xaa], [0xc1], "A");
+        assert_feed_err!(d, [], [0xd2], [], "");
+        assert_finish_ok!(d, "");
+
+        let mut d = lossy.decoder();
+        assert_feed_ok!(d, [0x41, 0xaa, 0xc1], [], "A\xaa\u0391");
+        assert_feed_ok!(d, [0xd2, 0xff], [], "\xd2\xff");
+        assert_finish_ok!(d, "");
+        assert_eq!(lossy.decode([0x81, 0xaa, 0xd2], DecodeStrict),
+                   Ok("\x81\xaa\xd2".to_string()));
+    }
+
+    #[test]
+    fn test_encoder_c1_passthrough() {
+        // an ISO 8859-7 table without C1 controls
+        fn backward(code: u32) -> u8 {
+            if code < 0xa0 { 0 } else { index::iso_8859_7::backward(code) }
+        }
+        let strict = SingleByteEncoding { index_backward: backward, c1_passthrough: false,
+                                          ..*ISO_8859_7 };
+        let passthrough = SingleByteEncoding { index_backward: backward, c1_passthrough: true,
+                                               ..*ISO_8859_7 };
+
+        let mut e = strict.encoder();
+        assert_feed_err!(e, "A", "\u0085", "\u0391", [0x41]);
+        assert_finish_ok!(e, []);
+
+        let mut e = passthrough.encoder();
+        assert_feed_ok!(e, "A\u0080\u0085\u009f\u0391", "", [0x41, 0x80, 0x85, 0x9f, 0xc1]);
+        assert_feed_err!(e, "\u00a9", "\u00e9", "", [0xa9]);
+        assert_finish_ok!(e, []);
+
+        // C1 controls round-trip in the ISO 8859 family
+        let c1: String = range(0x80u8, 0xa0).map(|b| b as char).collect();
+        let bytes: Vec<u8> = range(0x80u8, 0xa0).collect();
+        assert_eq!(ISO_8859_7.encode(c1.as_slice(), EncodeStrict), Ok(bytes.clone()));
+        assert_eq!(ISO_8859_7.decode(bytes.as_slice(), DecodeStrict), Ok(c1));
+    }
+
+    #[test]
+    fn test_table_single_byte() {
+        let mut table = ['\uffff', ..128];
+        table[0] = '\u20ac';
+        table[1] = '\U0001f600';
+        table[2] = '\u00e9';
+        table[3] = '\u00e9'; // duplicate, encoded to the first byte
+        let encoding = TableSingleByteEncoding::new("x-custom", table);
+        assert_eq!(encoding.name(), "x-custom");
+
+        let mut d = encoding.decoder();
+        assert_feed_ok!(d, [0x41, 0x80, 0x81], [], "A\u20ac\U0001f600");
+        assert_feed_ok!(d, [0x82, 0x83], [], "\u00e9\u00e9");
+        assert_feed_err!(d, [0x42], [0x84], [0x43], "B");
+        assert_feed_err!(d, [], [0xff], [], "");
+        assert_finish_ok!(d, "");
+
+        let mut e = encoding.encoder();
+        assert_feed_ok!(e, "A\u20ac\U0001f600", "", [0x41, 0x80, 0x81]);
+        assert_feed_ok!(e, "\u00e9", "", [0x82]);
+        assert_feed_err!(e, "B", "\u00e8", "C", [0x42]);
+        assert_finish_ok!(e, []);
+    }
+
+    #[test]
+    fn test_table_single_byte_same_as_static() {
+        let mut table = ['\uffff', ..128];
+        for i in range(0u, 128) {
+            let ch = index::windows_1253::forward((i + 0x80) as u8);
+            if ch != 0xffff { table[i] = as_char(ch); }
+        }
+        let encoding = TableSingleByteEncoding::new("windows-1253", table);
+
+        for i in range(0u, 256) {
+            let input = [i as u8];
+            assert_eq!(encoding.decode(input, DecodeStrict).ok(),
+                       WINDOWS_1253.decode(input, DecodeStrict).ok());
+            match WINDOWS_1253.decode(input, DecodeStrict) {
+                Ok(s) => assert_eq!(encoding.encode(s.as_slice(), EncodeStrict),
+                                    Ok(Vec::from_slice(input))),
+                Err(_) => {}
+            }
+        }
+    }
+
+    #[test]
+    fn test_indexable() {
+        assert_eq!(WINDOWS_1251.forward(0x41), Some('A'));
+        assert_eq!(WINDOWS_1251.forward(0xc0), Some('\u0410'));
+        assert_eq!(WINDOWS_1251.forward(0x98), None);
+        assert_eq!(WINDOWS_1251.backward('\u0410'), Some(0xc0));
+        assert_eq!(WINDOWS_1251.backward('\u00e9'), None);
+        assert_eq!(ISO_8859_6.forward(0xa1), None);
+
+        for i in range(0u, 256) {
+            let b = i as u8;
+            let decoded = ISO_8859_6.decode([b], DecodeStrict).ok();
+            assert_eq!(ISO_8859_6.forward(b).map(|ch| String::from_char(1, ch)), decoded);
+            match ISO_8859_6.forward(b) {
+                Some(ch) => assert_eq!(ISO_8859_6.backward(ch), Some(b)),
+                None => {}
+            }
+        }
+
+        let mut table = ['\uffff', ..128];
+        table[0] = '\u20ac';
+        table[1] = '\u20ac'; // duplicate, maps back to the first byte
+        let encoding = TableSingleByteEncoding::new("x-custom", table);
+        assert_eq!(encoding.forward(0x41), Some('A'));
+        assert_eq!(encoding.forward(0x81), Some('\u20ac'));
+        assert_eq!(encoding.forward(0x82), None);
+        assert_eq!(encoding.backward('\u20ac'), Some(0x80));
+        assert_eq!(encoding.backward('\uffff'), None);
+    }
+
+    #[test]
+    fn test_codecs_generic_over_index() {
+        use super::{SingleByteEncoder, SingleByteDecoder};
+
+        // maps bytes 80 through FF to U+0400 through U+047F
+        #[deriving(Clone)]
+        struct ShiftedIndex;
+
+        impl IndexableEncoding<u8> for ShiftedIndex {
+            fn forward(&self, ptr: u8) -> Option<char> {
+                if ptr <= 0x7f { return Some(ptr as char); }
+                ::std::char::from_u32(ptr as u32 - 0x80 + 0x400)
+            }
+            fn backward(&self, ch: char) -> Option<u8> {
+                match ch as u32 {
+                    0x00..0x7f => Some(ch as u8),
+                    0x400..0x47f => Some((ch as u32 - 0x400 + 0x80) as u8),
+                    _ => None,
+                }
+            }
+        }
+
+        let mut e = SingleByteEncoder::new(ShiftedIndex, false);
+        assert_feed_ok!(e, "A\u0400\u047f", "", [0x41, 0x80, 0xff]);
+        assert_feed_err!(e, "", "\u0480", "", []);
+        assert_finish_ok!(e, []);
+
+        let mut d = SingleByteDecoder::new(ShiftedIndex, false);
+        assert_feed_ok!(d, [0x41, 0x80, 0xff], [], "A\u0400\u047f");
+        assert_finish_ok!(d, "");
+    }
+
+    #[test]
+    fn test_iso_8859_1_decode_to_chars() {
+        assert_eq!(iso_8859_1::decode_to_chars([]), vec!());
+        assert_eq!(iso_8859_1::decode_to_chars([0x63, 0x61, 0x66, 0xe9, 0x00, 0x80, 0xff]),
+                   vec!('c', 'a', 'f', '\xe9', '\x00', '\x80', '\xff'));
+
+        let input = testutils::KOREAN_TEXT.as_bytes();
+        let decoded = ISO_8859_1.decode(input, DecodeStrict).unwrap();
+        assert_eq!(iso_8859_1::decode_to_chars(input), decoded.as_slice().chars().collect());
+    }
+
+    #[bench]
+    fn bench_iso_8859_1_decode(bencher: &mut test::Bencher) {
+        let s = testutils::KOREAN_TEXT.as_bytes();
+        bencher.bytes = s.len() as u64;
+        bencher.iter(|| test::black_box({
+            ISO_8859_1.decode(s, DecodeStrict)
+        }))
+    }
+
+    #[bench]
+    fn bench_iso_8859_1_decode_to_chars(bencher: &mut test::Bencher) {
+        let s = testutils::KOREAN_TEXT.as_bytes();
+        bencher.bytes = s.len() as u64;
+        bencher.iter(|| test::black_box({
+            iso_8859_1::decode_to_chars(s)
+        }))
+    }
+}
@@ -4,10 +4,14 @@
 
 //! Legacy traditional Chinese encodings.
 
-use util::StrCharIndex;
+use util::{StrCharIndex, buffered_char_cost};
 use index;
 use types::*;
 
+/// Scripts covered by Big5-2003.
+static BIG5_SCRIPTS: &'static [&'static str] =
+    &["Latin", "Greek", "Cyrillic", "Hiragana", "Katakana", "Bopomofo", "CJK"];
+
 /**
  * Big5-2003 with common extensions. (XXX with asymmetric HKSCS-2008 support)
  *
@@ -28,6 +32,8 @@ pub struct BigFive2003Encoding;
 impl Encoding for BigFive2003Encoding {
     fn name(&self) -> &'static str { "big5-2003" }
     fn whatwg_name(&self) -> Option<&'static str> { Some("big5") } // WHATWG compatibility
+    fn covered_scripts(&self) -> &'static [&'static str] { BIG5_SCRIPTS }
+    fn assumes_normalization(&self) -> Option<NormalizationForm> { Some(NFC) }
     fn encoder(&self) -> Box<Encoder> { BigFive2003Encoder::new() }
     fn decoder(&self) -> Box<Decoder> { BigFive2003HKSCS2008Decoder::new() }
 }
@@ -42,6 +48,7 @@ impl BigFive2003Encoder {
 
 impl Encoder for BigFive2003Encoder {
     fn from_self(&self) -> Box<Encoder> { BigFive2003Encoder::new() }
+    fn clone_box(&self) -> Box<Encoder> { box self.clone() as Box<Encoder> }
     fn is_ascii_compatible(&self) -> bool { true }
 
     fn raw_feed(&mut self, input: &str, output: &mut ByteWriter) -> (uint, Option<CodecError>) {
@@ -55,7 +62,8 @@ impl Encoder for BigFive2003Encoder {
                 if ptr == 0xffff || ptr < (0xa1 - 0x81) * 157 {
                     // no HKSCS extension (XXX doesn't HKSCS include 0xFA40..0xFEFE?)
                     return (i, Some(CodecError {
-                        upto: j as int, cause: "unrepresentable character".into_maybe_owned()
+                        upto: j as int, cause: "unrepresentable character".into_maybe_owned(),
+                        buffered: 0
                     }));
                 }
                 let lead = ptr / 157 + 0x81;
@@ -73,6 +81,113 @@ impl Encoder for BigFive2003Encoder {
     }
 }
 
+/**
+ * Big5-HKSCS. (Big5-2003 with HKSCS-2008 in both directions)
+ *
+ * This is same to `BigFive2003Encoding` except that the encoder also emits
+ * the Hong Kong Supplementary Character Set (HKSCS), which includes lots of characters
+ * outside of the Basic Multilingual Plane.
+ * Four HKSCS characters decode to two code points (a letter followed by a combining mark);
+ * the encoder combines such pairs back to them.
+ * Some standard Big5 characters also have HKSCS compatibility codes,
+ * and the encoder emits the standard codes for them as the Encoding Standard does.
+ */
+#[deriving(Clone)]
+pub struct BigFiveHKSCSEncoding;
+
+impl Encoding for BigFiveHKSCSEncoding {
+    fn name(&self) -> &'static str { "big5-hkscs" }
+    fn covered_scripts(&self) -> &'static [&'static str] { BIG5_SCRIPTS }
+    fn assumes_normalization(&self) -> Option<NormalizationForm> { Some(NFC) }
+    fn encoder(&self) -> Box<Encoder> { BigFiveHKSCSEncoder::new() }
+    fn decoder(&self) -> Box<Decoder> { BigFive2003HKSCS2008Decoder::new() }
+}
+
+/// An encoder for Big5-HKSCS.
+#[deriving(Clone)]
+pub struct BigFiveHKSCSEncoder {
+    /// U+00CA or U+00EA, which may be combined with the next combining mark.
+    pending: Option<char>,
+}
+
+impl BigFiveHKSCSEncoder {
+    pub fn new() -> Box<Encoder> { box BigFiveHKSCSEncoder { pending: None } as Box<Encoder> }
+}
+
+/// Writes a Big5 sequence for given pointer.
+fn write_big5_pointer(ptr: u16, output: &mut ByteWriter) {
+    let lead = ptr / 157 + 0x81;
+    let trail = ptr % 157;
+    let trailoffset = if trail < 0x3f {0x40} else {0x62};
+    output.write_byte(lead as u8);
+    output.write_byte((trail + trailoffset) as u8);
+}
+
+impl Encoder for BigFiveHKSCSEncoder {
+    fn from_self(&self) -> Box<Encoder> { BigFiveHKSCSEncoder::new() }
+    fn clone_box(&self) -> Box<Encoder> { box self.clone() as Box<Encoder> }
+    fn is_ascii_compatible(&self) -> bool { true }
+
+    // `Ê` and `ê` are held back until the next character, so count the buffered bytes.
+    fn char_cost(&mut self, ch: char) -> Option<uint> { buffered_char_cost(&*self, ch) }
+
+    fn raw_feed(&mut self, input: &str, output: &mut ByteWriter) -> (uint, Option<CodecError>) {
+        output.writer_hint(input.len());
+
+        let mut pending_start = 0; // zero if the pending character is from the prior call
+        for ((i,j), ch) in input.index_iter() {
+            match self.pending.take() {
+                Some(base) => {
+                    // two-code-point mappings; see `BigFive2003HKSCS2008Decoder`
+                    let ptr = match (base, ch) {
+                        ('\u00ca', '\u0304') => 1133,
+                        ('\u00ca', '\u030c') => 1135,
+                        ('\u00ea', '\u0304') => 1164,
+                        ('\u00ea', '\u030c') => 1166,
+                        (_, _) => 0xffff,
+                    };
+                    if ptr != 0xffff {
+                        write_big5_pointer(ptr, output);
+                        continue;
+                    }
+                    write_big5_pointer(index::big5::backward_remapped(base as u32), output);
+                }
+                None => {}
+            }
+
+            if ch < '\u0080' {
+                output.write_byte(ch as u8);
+            } else if ch == '\u00ca' || ch == '\u00ea' {
+                self.pending = Some(ch);
+                pending_start = i;
+            } else {
+                let ptr = index::big5::backward_remapped(ch as u32);
+                if ptr == 0xffff {
+                    return (i, Some(CodecError {
+                        upto: j as int, cause: "unrepresentable character".into_maybe_owned(),
+                        buffered: 0
+                    }));
+                }
+                write_big5_pointer(ptr, output);
+            }
+        }
+
+        if self.pending.is_some() {
+            (pending_start, None)
+        } else {
+            (input.len(), None)
+        }
+    }
+
+    fn raw_finish(&mut self, output: &mut ByteWriter) -> Option<CodecError> {
+        match self.pending.take() {
+            Some(base) => write_big5_pointer(index::big5::backward_remapped(base as u32), output),
+            None => {}
+        }
+        None
+    }
+}
+
 ascii_compatible_stateful_decoder! {
     #[doc="A decoder for Big5-2003 with HKSCS-2008 extension."]
     #[deriving(Clone)]
@@ -248,3 +363,105 @@ mod bigfive2003_tests {
     }
 }
 
+#[cfg(test)]
+mod bigfivehkscs_tests {
+    use super::BigFiveHKSCSEncoding;
+    use types::*;
+
+    #[test]
+    fn test_encoder_valid() {
+        let mut e = BigFiveHKSCSEncoding.encoder();
+        assert_feed_ok!(e, "A", "", [0x41]);
+        assert_feed_ok!(e, "\u4e2d\u83ef\u6c11\u570b", "",
+                        [0xa4, 0xa4, 0xb5, 0xd8, 0xa5, 0xc1, 0xb0, 0xea]);
+        assert_feed_ok!(e, "\u3eec", "", [0x87, 0x7e]); // HKSCS-2008 addition
+        assert_feed_ok!(e, "\U00027267", "", [0x87, 0x45]); // outside of BMP
+        assert_finish_ok!(e, []);
+    }
+
+    #[test]
+    fn test_encoder_prefers_standard_codes() {
+        // these have HKSCS compatibility codes as well, but standard codes should be used
+        let mut e = BigFiveHKSCSEncoding.encoder();
+        assert_feed_ok!(e, "\u8005", "", [0xaa, 0xcc]);
+        assert_feed_ok!(e, "\u90fd\u8d77\u62d0", "", [0xb3, 0xa3, 0xb0, 0x5f, 0xa9, 0xe4]);
+        assert_finish_ok!(e, []);
+
+        // compatibility codes are still decoded
+        assert_eq!(BigFiveHKSCSEncoding.decode([0x8e, 0xcd], DecodeStrict),
+                   Ok("\u8005".to_string()));
+    }
+
+    #[test]
+    fn test_remapped_index() {
+        use std::iter::range_inclusive;
+        use index::big5::{forward, backward_remapped};
+
+        static STANDARD_MIN: u16 = (0xa1 - 0x81) * 157;
+        for i in range_inclusive(0u16, STANDARD_MIN - 1) {
+            let j = forward(i);
+            if j == 0xffff || j < 4 { continue; } // skip two-code-point mappings
+            let ii = backward_remapped(j);
+            if ii != i {
+                assert!(ii >= STANDARD_MIN);
+                assert_eq!(forward(ii), j);
+            }
+        }
+    }
+
+    #[test]
+    fn test_encoder_two_code_points() {
+        let mut e = BigFiveHKSCSEncoding.encoder();
+        assert_feed_ok!(e, "\xca\u0304\xca\u030c\xea\u0304\xea\u030c", "",
+                        [0x88, 0x62, 0x88, 0x64, 0x88, 0xa3, 0x88, 0xa5]);
+        assert_feed_ok!(e, "\xcaA\xea", "", [0x88, 0x66, 0x41, 0x88, 0xa7]);
+        assert_finish_ok!(e, []);
+
+        let mut e = BigFiveHKSCSEncoding.encoder();
+        assert_feed_ok!(e, "", "\xca", []);
+        assert_feed_ok!(e, "\u0304", "\xea", [0x88, 0x62]);
+        assert_feed_ok!(e, "", "", []);
+        assert_feed_ok!(e, "\u030c", "", [0x88, 0xa5]);
+        assert_feed_ok!(e, "", "\xea", []);
+        assert_finish_ok!(e, [0x88, 0xa7]);
+    }
+
+    #[test]
+    fn test_encoder_char_cost() {
+        let mut e = BigFiveHKSCSEncoding.encoder();
+        assert_eq!(e.char_cost('A'), Some(1));
+        assert_eq!(e.char_cost('\xca'), Some(2)); // held back until the next character
+        assert_eq!(e.char_cost('\uffff'), None);
+        assert_feed_ok!(e, "", "\xca", []);
+        assert_eq!(e.char_cost('\u0304'), Some(0)); // 88 66 becomes 88 62
+        assert_eq!(e.char_cost('A'), Some(1));
+        assert_eq!(e.char_cost('\xea'), Some(2));
+        assert_finish_ok!(e, [0x88, 0x66]);
+    }
+
+    #[test]
+    fn test_encoder_invalid() {
+        let mut e = BigFiveHKSCSEncoding.encoder();
+        assert_feed_err!(e, "", "\uffff", "", []);
+        assert_feed_err!(e, "?", "\uffff", "!", [0x3f]);
+        assert_feed_err!(e, "\xca", "\uffff", "", [0x88, 0x66]);
+        assert_finish_ok!(e, []);
+    }
+
+    #[test]
+    fn test_decoder_valid() {
+        let mut d = BigFiveHKSCSEncoding.decoder();
+        assert_feed_ok!(d, [0x87, 0x7e], [], "\u3eec");
+        assert_feed_ok!(d, [0x87, 0x45], [], "\U00027267"); // outside of BMP
+        assert_feed_ok!(d, [0x88, 0x62, 0x88, 0x66], [], "\xca\u0304\xca");
+        assert_finish_ok!(d, "");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let s = "\u4e2d\u3eec\U00027267\xca\u030c\xea";
+        let encoded = BigFiveHKSCSEncoding.encode(s, EncodeStrict).unwrap();
+        assert_eq!(BigFiveHKSCSEncoding.decode(encoded.as_slice(), DecodeStrict),
+                   Ok(s.to_string()));
+    }
+}
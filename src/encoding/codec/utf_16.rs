@@ -4,7 +4,7 @@
 
 //! UTF-16.
 
-use util::{as_char, StrCharIndex};
+use util::{as_char, StrCharIndex, UNICODE_SCRIPTS};
 use types::*;
 
 /// An implementation type for little endian.
@@ -23,6 +23,7 @@ pub struct Big;
 trait Endian {
     fn name(_endian: Option<Self>) -> &'static str;
     fn whatwg_name(_endian: Option<Self>) -> Option<&'static str>;
+    fn bom(_endian: Option<Self>) -> &'static [u8];
     fn write_two_bytes(_endian: Option<Self>, output: &mut ByteWriter, msb: u8, lsb: u8);
     fn concat_two_bytes(_endian: Option<Self>, lead: u16, trail: u8) -> u16;
 }
@@ -32,6 +33,7 @@ impl Endian for Little {
     fn whatwg_name(_endian: Option<Little>) -> Option<&'static str> {
         Some("utf-16") // WHATWG compatibility
     }
+    fn bom(_endian: Option<Little>) -> &'static [u8] { b"\xFF\xFE" }
     fn write_two_bytes(_endian: Option<Little>, output: &mut ByteWriter, msb: u8, lsb: u8) {
         output.write_byte(lsb);
         output.write_byte(msb);
@@ -44,6 +46,7 @@ impl Endian for Little {
 impl Endian for Big {
     fn name(_endian: Option<Big>) -> &'static str { "utf-16be" }
     fn whatwg_name(_endian: Option<Big>) -> Option<&'static str> { Some("utf-16be") }
+    fn bom(_endian: Option<Big>) -> &'static [u8] { b"\xFE\xFF" }
     fn write_two_bytes(_endian: Option<Big>, output: &mut ByteWriter, msb: u8, lsb: u8) {
         output.write_byte(msb);
         output.write_byte(lsb);
@@ -79,6 +82,8 @@ pub type UTF16BEEncoding = UTF16Encoding<Big>;
 impl<E:Endian+Clone+'static> Encoding for UTF16Encoding<E> {
     fn name(&self) -> &'static str { Endian::name(None::<E>) }
     fn whatwg_name(&self) -> Option<&'static str> { Endian::whatwg_name(None::<E>) }
+    fn covered_scripts(&self) -> &'static [&'static str] { UNICODE_SCRIPTS }
+    fn bom_bytes(&self) -> Option<&'static [u8]> { Some(Endian::bom(None::<E>)) }
     fn encoder(&self) -> Box<Encoder> { UTF16Encoder::new(None::<E>) }
     fn decoder(&self) -> Box<Decoder> { UTF16Decoder::new(None::<E>) }
 }
@@ -100,6 +105,7 @@ impl<E:Endian+Clone+'static> UTF16Encoder<E> {
 
 impl<E:Endian+Clone+'static> Encoder for UTF16Encoder<E> {
     fn from_self(&self) -> Box<Encoder> { UTF16Encoder::new(None::<E>) }
+    fn clone_box(&self) -> Box<Encoder> { box self.clone() as Box<Encoder> }
 
     fn raw_feed(&mut self, input: &str, output: &mut ByteWriter) -> (uint, Option<CodecError>) {
         output.writer_hint(input.len() * 2);
@@ -122,7 +128,8 @@ impl<E:Endian+Clone+'static> Encoder for UTF16Encoder<E> {
                 }
                 _ => {
                     return (i, Some(CodecError {
-                        upto: j as int, cause: "unrepresentable character".into_maybe_owned()
+                        upto: j as int, cause: "unrepresentable character".into_maybe_owned(),
+                        buffered: 0
                     }));
                 }
             }
@@ -154,8 +161,20 @@ impl<E:Endian+Clone+'static> UTF16Decoder<E> {
     }
 }
 
+impl<E:Endian+Clone+'static> Clone for UTF16Decoder<E> {
+    fn clone(&self) -> UTF16Decoder<E> {
+        UTF16Decoder { leadbyte: self.leadbyte, leadsurrogate: self.leadsurrogate }
+    }
+}
+
 impl<E:Endian+Clone+'static> Decoder for UTF16Decoder<E> {
     fn from_self(&self) -> Box<Decoder> { UTF16Decoder::new(None::<E>) }
+    fn clone_box(&self) -> Box<Decoder> { box self.clone() as Box<Decoder> }
+
+    fn pending_len(&self) -> uint {
+        (if self.leadsurrogate != 0xffff {2} else {0}) +
+        (if self.leadbyte != 0xffff {1} else {0})
+    }
 
     fn raw_feed(&mut self, input: &[u8], output: &mut StringWriter) -> (uint, Option<CodecError>) {
         output.writer_hint(input.len() / 2); // when every codepoint is U+0000..007F
@@ -184,7 +203,8 @@ impl<E:Endian+Clone+'static> Decoder for UTF16Decoder<E> {
                     }
                     _ => {
                         return (processed, Some(CodecError {
-                            upto: i as int - 2, cause: "invalid sequence".into_maybe_owned()
+                            upto: i as int - 2, cause: "invalid sequence".into_maybe_owned(),
+                            buffered: 0
                         }));
                     }
                 }
@@ -196,7 +216,8 @@ impl<E:Endian+Clone+'static> Decoder for UTF16Decoder<E> {
                     }
                     0xdc00..0xdfff => {
                         return (processed, Some(CodecError {
-                            upto: i as int, cause: "invalid sequence".into_maybe_owned()
+                            upto: i as int, cause: "invalid sequence".into_maybe_owned(),
+                            buffered: 0
                         }));
                     }
                     _ => {
@@ -226,7 +247,8 @@ impl<E:Endian+Clone+'static> Decoder for UTF16Decoder<E> {
                     self.leadbyte = 0xffff;
                     self.leadsurrogate = 0xffff;
                     return (processed, Some(CodecError {
-                        upto: i as int - 2, cause: "invalid sequence".into_maybe_owned()
+                        upto: i as int - 2, cause: "invalid sequence".into_maybe_owned(),
+                        buffered: 0
                     }));
                 }
             }
@@ -258,14 +280,16 @@ impl<E:Endian+Clone+'static> Decoder for UTF16Decoder<E> {
                         }
                         _ => {
                             return (processed, Some(CodecError {
-                                upto: i as int - 1, cause: "invalid sequence".into_maybe_owned()
+                                upto: i as int - 1, cause: "invalid sequence".into_maybe_owned(),
+                                buffered: 0
                             }));
                         }
                     }
                 }
                 0xdc00..0xdfff => {
                     return (processed, Some(CodecError {
-                        upto: i as int + 1, cause: "invalid sequence".into_maybe_owned()
+                        upto: i as int + 1, cause: "invalid sequence".into_maybe_owned(),
+                        buffered: 0
                     }));
                 }
                 _ => {
@@ -279,12 +303,13 @@ impl<E:Endian+Clone+'static> Decoder for UTF16Decoder<E> {
     }
 
     fn raw_finish(&mut self, _output: &mut StringWriter) -> Option<CodecError> {
-        let leadbyte = self.leadbyte;
-        let leadsurrogate = self.leadsurrogate;
+        let buffered = self.pending_len();
         self.leadbyte = 0xffff;
         self.leadsurrogate = 0xffff;
-        if leadbyte != 0xffff || leadsurrogate != 0xffff {
-            Some(CodecError { upto: 0, cause: "incomplete sequence".into_maybe_owned() })
+        if buffered > 0 {
+            Some(CodecError {
+                upto: 0, cause: "incomplete sequence".into_maybe_owned(), buffered: buffered
+            })
         } else {
             None
         }
@@ -401,6 +426,20 @@ mod tests {
         assert_finish_ok!(d, "");
     }
 
+    #[test]
+    fn test_decoder_pending_len() {
+        let mut d = UTF_16BE.decoder();
+        assert_feed_ok!(d, [], [0xd8], "");
+        assert_eq!(d.pending_len(), 1);
+        assert_feed_ok!(d, [], [0x08], "");
+        assert_eq!(d.pending_len(), 2);
+        assert_feed_ok!(d, [], [0xdf], "");
+        assert_eq!(d.pending_len(), 3);
+        assert_feed_ok!(d, [0x45], [], "\U00012345");
+        assert_eq!(d.pending_len(), 0);
+        assert_finish_ok!(d, "");
+    }
+
     #[test]
     fn test_decoder_valid_partial_non_bmp() {
         let mut d = UTF_16BE.decoder();
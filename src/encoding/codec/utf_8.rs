@@ -25,6 +25,7 @@
 //! UTF-8, the universal encoding.
 
 use std::{str, mem};
+use util::UNICODE_SCRIPTS;
 use types::*;
 
 /**
@@ -41,6 +42,12 @@ use types::*;
  *
  * The UTF-8 scanner used by this module is heavily based on Bjoern Hoehrmann's
  * [Flexible and Economical UTF-8 Decoder](http://bjoern.hoehrmann.de/utf-8/decoder/dfa/).
+ *
+ * The decoder reports each "maximal subpart" of an ill-formed sequence as one error,
+ * as recommended by the Unicode Standard and required by the WHATWG Encoding Standard.
+ * Overlong sequences, surrogates and codepoints beyond U+10FFFF are rejected
+ * at the first byte that cannot be a part of the valid sequence,
+ * and an incomplete sequence at the end of the input is buffered until the next feed.
  */
 #[deriving(Clone)]
 pub struct UTF8Encoding;
@@ -48,6 +55,8 @@ pub struct UTF8Encoding;
 impl Encoding for UTF8Encoding {
     fn name(&self) -> &'static str { "utf-8" }
     fn whatwg_name(&self) -> Option<&'static str> { Some("utf-8") }
+    fn covered_scripts(&self) -> &'static [&'static str] { UNICODE_SCRIPTS }
+    fn bom_bytes(&self) -> Option<&'static [u8]> { Some(b"\xEF\xBB\xBF") }
     fn encoder(&self) -> Box<Encoder> { UTF8Encoder::new() }
     fn decoder(&self) -> Box<Decoder> { UTF8Decoder::new() }
 }
@@ -62,7 +71,9 @@ impl UTF8Encoder {
 
 impl Encoder for UTF8Encoder {
     fn from_self(&self) -> Box<Encoder> { UTF8Encoder::new() }
+    fn clone_box(&self) -> Box<Encoder> { box self.clone() as Box<Encoder> }
     fn is_ascii_compatible(&self) -> bool { true }
+    fn char_cost(&mut self, ch: char) -> Option<uint> { Some(ch.len_utf8_bytes()) }
 
     fn raw_feed(&mut self, input: &str, output: &mut ByteWriter) -> (uint, Option<CodecError>) {
         let input: &[u8] = input.as_bytes();
@@ -144,7 +155,9 @@ macro_rules! next_state(($state:expr, $ch:expr) => (
 
 impl Decoder for UTF8Decoder {
     fn from_self(&self) -> Box<Decoder> { UTF8Decoder::new() }
+    fn clone_box(&self) -> Box<Decoder> { box self.clone() as Box<Decoder> }
     fn is_ascii_compatible(&self) -> bool { true }
+    fn pending_len(&self) -> uint { self.queuelen }
 
     fn raw_feed(&mut self, input: &[u8], output: &mut StringWriter) -> (uint, Option<CodecError>) {
         output.writer_hint(input.len());
@@ -177,7 +190,8 @@ impl Decoder for UTF8Decoder {
                 self.queuelen = 0;
                 write_bytes(output, input.slice(0, processed));
                 return (processed, Some(CodecError {
-                    upto: upto as int, cause: "invalid sequence".into_maybe_owned()
+                    upto: upto as int, cause: "invalid sequence".into_maybe_owned(),
+                    buffered: 0
                 }));
             }
         }
@@ -204,7 +218,9 @@ impl Decoder for UTF8Decoder {
         self.state = INITIAL_STATE;
         self.queuelen = 0;
         if state != ACCEPT_STATE {
-            Some(CodecError { upto: 0, cause: "incomplete sequence".into_maybe_owned() })
+            Some(CodecError {
+                upto: 0, cause: "incomplete sequence".into_maybe_owned(), buffered: queuelen
+            })
         } else {
             assert!(queuelen == 0);
             None
@@ -611,6 +627,67 @@ mod tests {
         assert_finish_ok!(d, "");
     }
 
+    #[test]
+    fn test_maximal_subpart_replacement() {
+        // each maximal subpart of an ill-formed sequence should result in exactly one U+FFFD.
+        static CASES: &'static [(&'static [u8], &'static str)] = &[
+            (b"\xc0\x80", "\ufffd\ufffd"), // overlong NUL
+            (b"\xc1\xbf", "\ufffd\ufffd"),
+            (b"\xe0\x80\x80", "\ufffd\ufffd\ufffd"), // overlong 3-byte sequence
+            (b"\xe0\x9f\xbf", "\ufffd\ufffd\ufffd"),
+            (b"\xf0\x80\x80\x80", "\ufffd\ufffd\ufffd\ufffd"), // overlong 4-byte sequence
+            (b"\xf0\x8f\xbf\xbf", "\ufffd\ufffd\ufffd\ufffd"),
+            (b"\xed\xa0\x80", "\ufffd\ufffd\ufffd"), // surrogates
+            (b"\xed\xbf\xbf", "\ufffd\ufffd\ufffd"),
+            (b"\xf4\x90\x80\x80", "\ufffd\ufffd\ufffd\ufffd"), // beyond U+10FFFF
+            (b"\xf5\x80\x80\x80", "\ufffd\ufffd\ufffd\ufffd"),
+            (b"\xf8\x88\x80\x80\x80", "\ufffd\ufffd\ufffd\ufffd\ufffd"),
+            (b"\xfe\xff", "\ufffd\ufffd"),
+            (b"\x80\xbf", "\ufffd\ufffd"), // lone continuation bytes
+            (b"\xc2", "\ufffd"), // truncated sequences
+            (b"\xe1\x80", "\ufffd"),
+            (b"\xf1\x80\x80", "\ufffd"),
+            (b"\xc2A", "\ufffdA"),
+            (b"\xe1\x80A", "\ufffdA"),
+            (b"\xf1\x80\x80A", "\ufffdA"),
+            (b"\xe1\x80\xc2\xa9", "\ufffd\u00a9"),
+            (b"\xf1\x80\x80\xe1\x80\xc2", "\ufffd\ufffd\ufffd"),
+            // the example from the Unicode Standard, section 3.9
+            (b"a\xf1\x80\x80\xe1\x80\xc2b\x80c\x80\xbfd",
+             "a\ufffd\ufffd\ufffdb\ufffdc\ufffd\ufffdd"),
+        ];
+
+        for &(input, expected) in CASES.iter() {
+            assert_eq!(UTF8Encoding.decode(input, DecodeReplace), Ok(expected.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_pending_len() {
+        let mut d = UTF8Encoding.decoder();
+        assert_feed_ok!(d, [], [0xf0, 0x90], "");
+        assert_eq!(d.pending_len(), 2);
+        assert_feed_ok!(d, [], [0x8d], "");
+        assert_eq!(d.pending_len(), 3);
+        assert_feed_ok!(d, [0x88], [], "\U00010348");
+        assert_eq!(d.pending_len(), 0);
+        assert_finish_ok!(d, "");
+    }
+
+    #[test]
+    fn test_needs_more() {
+        let mut d = UTF8Encoding.decoder();
+        assert!(!d.needs_more());
+        assert_feed_ok!(d, [0x41], [0xe2, 0x82], "A");
+        assert!(d.needs_more());
+        assert_feed_ok!(d, [0xac], [], "\u20ac");
+        assert!(!d.needs_more());
+        assert_feed_ok!(d, [], [0xe2], "");
+        assert!(d.needs_more());
+        assert_finish_err!(d, "");
+        assert!(!d.needs_more());
+    }
+
     #[test]
     fn test_feed_after_finish() {
         let mut d = UTF8Encoding.decoder();
@@ -5,6 +5,7 @@
 //! Asymmetric or special encoding constructions required by the WHATWG Encoding standard.
 
 use codec;
+use util::UNICODE_SCRIPTS;
 use types::*;
 
 /// Replacement encoding used to solve a particular attack vector due to mismatching server and
@@ -15,6 +16,8 @@ pub struct EncoderOnlyUTF8Encoding;
 impl Encoding for EncoderOnlyUTF8Encoding {
     fn name(&self) -> &'static str { "encoder-only-utf-8" }
     fn whatwg_name(&self) -> Option<&'static str> { Some("replacement") } // WHATWG compatibility
+    fn covered_scripts(&self) -> &'static [&'static str] { UNICODE_SCRIPTS }
+    fn can_decode_direction(&self) -> bool { false }
     fn encoder(&self) -> Box<Encoder> { codec::utf_8::UTF8Encoding.encoder() }
     fn decoder(&self) -> Box<Decoder> { codec::error::ErrorEncoding.decoder() }
 }
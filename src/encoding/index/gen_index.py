@@ -6,7 +6,12 @@ import urllib
 import sys
 
 def whatwg_index(name, comments):
-    for line in urllib.urlopen('http://encoding.spec.whatwg.org/index-%s.txt' % name):
+    try:
+        # indices not in the Encoding Standard are kept in the same format
+        f = open('index-%s.txt' % name)
+    except IOError:
+        f = urllib.urlopen('http://encoding.spec.whatwg.org/index-%s.txt' % name)
+    for line in f:
         line = line.strip()
         if not line: continue
         if line.startswith('#'):
@@ -75,7 +80,7 @@ def generate_single_byte_index(name):
         print >>f
         print >>f, '#[inline]'
         print >>f, 'pub fn forward(code: u8) -> u16 {'
-        print >>f, '    FORWARD_TABLE[(code - 0x80) as uint]'
+        print >>f, '    if code < 0x80 { code as u16 } else { FORWARD_TABLE[(code - 0x80) as uint] }'
         print >>f, '}'
         print >>f
         print >>f, "static BACKWARD_TABLE_LOWER: &'static [u8] = &["
@@ -153,6 +158,24 @@ def generate_multi_byte_index(name):
             else:
                 remap.append(0xffff)
 
+    # Big5 has HKSCS compatibility codes below the standard region for some standard
+    # characters, and the default backward function favors them (they come first);
+    # an encoder preferring standard codes needs a separate mapping to their counterparts.
+    # unlike JIS X 0208, most codes in the range have no counterparts and remap to themselves.
+    if name == 'big5':
+        STANDARD_MIN = (0xa1 - 0x81) * 157
+
+        invstandard = {}
+        for key, value in sorted(data.items()):
+            if value not in invstandard and key >= STANDARD_MIN:
+                invstandard[value] = key
+
+        remapped = [key for key, value in data.items()
+                    if key < STANDARD_MIN and invdata.get(value) == key and value in invstandard]
+        REMAP_MIN = min(remapped)
+        REMAP_MAX = max(remapped)
+        remap = [invstandard.get(data.get(i), i) for i in xrange(REMAP_MIN, REMAP_MAX+1)]
+
     minkey = min(data)
     maxkey = max(data) + 1
     with open('%s.rs' % name.replace('-', '_'), 'wb') as f:
@@ -227,7 +250,7 @@ def generate_multi_byte_index(name):
         print >>f
         print >>f, '#[cfg(test)]'
         print >>f, 'multi_byte_tests!('
-        if remap:
+        if remap and name != 'big5':
             print >>f, '    remap = %d .. %d,' % (REMAP_MIN, REMAP_MAX)
         if dups:
             print >>f, '    dups = ['
@@ -328,7 +351,9 @@ INDICES = {
     'iso-8859-16':    generate_single_byte_index,
     'koi8-r':         generate_single_byte_index,
     'koi8-u':         generate_single_byte_index,
+    'koi8-ru':        generate_single_byte_index,
     'macintosh':      generate_single_byte_index,
+    'mik':            generate_single_byte_index,
     'windows-874':    generate_single_byte_index,
     'windows-1250':   generate_single_byte_index,
     'windows-1251':   generate_single_byte_index,
@@ -346,6 +371,7 @@ INDICES = {
     'gb18030':        generate_multi_byte_index,
     'jis0208':        generate_multi_byte_index,
     'jis0212':        generate_multi_byte_index,
+    'x-mac-japanese': generate_multi_byte_index,
 
     'gb18030-ranges': generate_multi_byte_range_lbound_index,
 }
@@ -21,7 +21,7 @@ static FORWARD_TABLE: &'static [u16] = &[
 
 #[inline]
 pub fn forward(code: u8) -> u16 {
-    FORWARD_TABLE[(code - 0x80) as uint]
+    if code < 0x80 { code as u16 } else { FORWARD_TABLE[(code - 0x80) as uint] }
 }
 
 static BACKWARD_TABLE_LOWER: &'static [u8] = &[
@@ -5,15 +5,22 @@
 //! An interface for retrieving an encoding (or a set of encodings) from a string/numeric label.
 
 use std::ascii::StrAsciiExt;
+use std::collections::HashMap;
 use all;
 use types::EncodingRef;
 
+/// Strips leading and trailing ASCII whitespaces and makes the label ASCII lowercase,
+/// as required by "get an encoding" algorithm.
+fn normalize_label(label: &str) -> String {
+    label.trim_chars(&[' ', '\n', '\r', '\t', '\x0C']).to_ascii_lower()
+}
+
 /// Returns an encoding from given label, defined in the WHATWG Encoding standard, if any.
 /// Implements "get an encoding" algorithm: http://encoding.spec.whatwg.org/#decode
 #[stable]
 pub fn encoding_from_whatwg_label(label: &str) -> Option<EncodingRef> {
     // FIXME(rust#10683): temp needed as workaround
-    let trimmed = label.trim_chars(&[' ', '\n', '\r', '\t', '\x0C']).to_ascii_lower();
+    let trimmed = normalize_label(label);
     match trimmed.as_slice() {
         "unicode-1-1-utf-8" |
         "utf-8" |
@@ -312,6 +319,7 @@ pub fn encoding_from_windows_code_page(cp: uint) -> Option<EncodingRef> {
         52936 => Some(all::HZ as EncodingRef),
         950 => Some(all::BIG5_2003 as EncodingRef),
         20932 => Some(all::EUC_JP as EncodingRef),
+        51932 => Some(all::CP51932 as EncodingRef),
         50220 => Some(all::ISO_2022_JP as EncodingRef),
         932 => Some(all::WINDOWS_31J as EncodingRef),
         949 => Some(all::WINDOWS_949 as EncodingRef),
@@ -321,10 +329,85 @@ pub fn encoding_from_windows_code_page(cp: uint) -> Option<EncodingRef> {
     }
 }
 
+/// Returns an encoding whose `Encoding::name` is exactly given name, if any.
+/// Unlike `encoding_from_whatwg_label`, the name is case-sensitive and
+/// no whitespace trimming or alias expansion is done.
+#[experimental]
+pub fn encoding_from_name(name: &str) -> Option<EncodingRef> {
+    all::encodings().move_iter().find(|encoding| encoding.name() == name)
+}
+
+/// A label resolver which extends labels in the WHATWG Encoding standard
+/// with application-defined aliases and encodings.
+///
+/// Encodings implemented outside of this crate (e.g. a vendor codec in another crate)
+/// can be registered so that they are found by the same lookup functions.
+/// The built-in label table and the list in `all::encodings` are never modified;
+/// aliases and encodings are only visible through the resolver they were added to.
+#[experimental]
+pub struct LabelResolver {
+    aliases: HashMap<String, EncodingRef>,
+    registered: Vec<EncodingRef>,
+}
+
+impl LabelResolver {
+    /// Creates a new resolver which initially knows labels in the WHATWG Encoding standard only.
+    pub fn new() -> LabelResolver {
+        LabelResolver { aliases: HashMap::new(), registered: Vec::new() }
+    }
+
+    /// Registers an encoding, which is then listed by `encodings` and found by `lookup_name`.
+    /// Its name and WHATWG name (if any) are also added as aliases, as in `add_alias`.
+    /// An encoding registered later overrides any prior encoding with the same name.
+    pub fn register(&mut self, encoding: EncodingRef) {
+        self.add_alias(encoding.name(), encoding);
+        match encoding.whatwg_name() {
+            Some(name) => self.add_alias(name, encoding),
+            None => {}
+        }
+        self.registered.push(encoding);
+    }
+
+    /// Returns a list of all encodings in `all::encodings` followed by registered encodings.
+    pub fn encodings(&self) -> Vec<EncodingRef> {
+        let mut ret = all::encodings();
+        ret.push_all(self.registered.as_slice());
+        ret
+    }
+
+    /// Returns an encoding whose `Encoding::name` is exactly given name,
+    /// either registered or built in, if any. See also `encoding_from_name`.
+    pub fn lookup_name(&self, name: &str) -> Option<EncodingRef> {
+        match self.registered.iter().rev().find(|encoding| encoding.name() == name) {
+            Some(&encoding) => Some(encoding),
+            None => encoding_from_name(name),
+        }
+    }
+
+    /// Adds an alias to given encoding, overriding any prior alias or standard label.
+    /// The alias is matched in the same way as standard labels,
+    /// i.e. ASCII case-insensitively and ignoring leading and trailing whitespaces.
+    pub fn add_alias(&mut self, label: &str, encoding: EncodingRef) {
+        self.aliases.insert(normalize_label(label), encoding);
+    }
+
+    /// Returns an encoding from given label, either added as an alias or
+    /// defined in the WHATWG Encoding standard, if any.
+    pub fn lookup(&self, label: &str) -> Option<EncodingRef> {
+        match self.aliases.find(&normalize_label(label)) {
+            Some(&encoding) => Some(encoding),
+            None => encoding_from_whatwg_label(label),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     extern crate test;
-    use super::encoding_from_whatwg_label;
+    use super::{encoding_from_whatwg_label, encoding_from_windows_code_page, encoding_from_name};
+    use super::LabelResolver;
+    use all;
+    use types::{EncodingRef, DecodeStrict};
 
     #[test]
     fn test_encoding_from_whatwg_label() {
@@ -338,6 +421,109 @@ mod tests {
                 "Case-insensitive matching should be ASCII only. Kelvin sign does not match k.")
     }
 
+    #[test]
+    fn test_encoding_from_whatwg_label_whitespace() {
+        assert_eq!(encoding_from_whatwg_label("  UTF-8  ").unwrap().name(), "utf-8");
+        assert_eq!(encoding_from_whatwg_label("\tShift_JIS\n").unwrap().name(), "windows-31j");
+        assert_eq!(encoding_from_whatwg_label("\x0CLATIN1\r").unwrap().name(), "windows-1252");
+        assert!(encoding_from_whatwg_label("").is_none());
+        assert!(encoding_from_whatwg_label(" ").is_none());
+        assert!(encoding_from_whatwg_label("\t\n\x0C\r ").is_none());
+        assert!(encoding_from_whatwg_label("utf- 8").is_none(),
+                "Internal whitespace should not be removed")
+        assert!(encoding_from_whatwg_label("shift jis").is_none(),
+                "Internal whitespace should not be removed")
+        assert!(encoding_from_whatwg_label("\x0Butf-8").is_none(),
+                "Vertical tab is not an ASCII whitespace in the Encoding standard")
+    }
+
+    #[test]
+    fn test_latin1_is_windows_1252() {
+        // web content labelled as ISO-8859-1 is decoded as windows-1252 (smart quotes etc.)
+        for &label in ["latin1", "iso-8859-1", "ISO_8859-1", "l1", "us-ascii"].iter() {
+            let encoding = encoding_from_whatwg_label(label).unwrap();
+            assert_eq!(encoding.decode([0x80, 0x93, 0x94, 0x9f], DecodeStrict),
+                       Ok("\u20ac\u201c\u201d\u0178".to_string()));
+        }
+
+        // the true ISO-8859-1 is still available outside of the WHATWG labels
+        let encoding = encoding_from_windows_code_page(28591).unwrap();
+        assert_eq!(encoding.name(), "iso-8859-1");
+        assert_eq!(encoding.decode([0x80, 0x93, 0x94, 0x9f], DecodeStrict),
+                   Ok("\u0080\u0093\u0094\u009f".to_string()));
+    }
+
+    #[test]
+    fn test_encoding_from_name() {
+        for &encoding in all::encodings().iter() {
+            let found = encoding_from_name(encoding.name()).unwrap();
+            assert_eq!(found.name(), encoding.name());
+            assert!(!encoding.name().contains_char('_'),
+                    "{} should be hyphenated", encoding.name());
+        }
+        assert_eq!(encoding_from_name("windows-31j").unwrap().name(), "windows-31j");
+        assert_eq!(encoding_from_name("jis-x0201").unwrap().name(), "jis-x0201");
+        assert!(encoding_from_name("jis_x0201").is_none());
+        assert_eq!(encoding_from_name("iso-8859-1").unwrap().name(), "iso-8859-1");
+        assert!(encoding_from_name("shift_jis").is_none(), "Labels should not be expanded");
+        assert!(encoding_from_name("UTF-8").is_none(), "Names should be case-sensitive");
+        assert!(encoding_from_name(" utf-8").is_none(), "Whitespaces should not be trimmed");
+        assert!(encoding_from_name("").is_none());
+    }
+
+    #[test]
+    fn test_label_resolver() {
+        let mut resolver = LabelResolver::new();
+        assert_eq!(resolver.lookup("shift_jis").unwrap().name(), "windows-31j");
+        assert!(resolver.lookup("x-internal-sjis").is_none());
+
+        resolver.add_alias("X-Internal-SJIS", all::WINDOWS_31J as EncodingRef);
+        resolver.add_alias("latin1", all::ISO_8859_1 as EncodingRef);
+        assert_eq!(resolver.lookup("x-internal-sjis").unwrap().name(), "windows-31j");
+        assert_eq!(resolver.lookup(" X-INTERNAL-SJIS\t").unwrap().name(), "windows-31j");
+        assert_eq!(resolver.lookup("latin1").unwrap().name(), "iso-8859-1");
+        assert_eq!(resolver.lookup("utf-8").unwrap().name(), "utf-8");
+
+        // the global table is not affected
+        assert!(encoding_from_whatwg_label("x-internal-sjis").is_none());
+        assert_eq!(encoding_from_whatwg_label("latin1").unwrap().name(), "windows-1252");
+    }
+
+    #[test]
+    fn test_label_resolver_register() {
+        use types::{Encoding, Encoder, Decoder};
+
+        // an encoding which would be defined in the external crate
+        struct VendorEncoding;
+        impl Encoding for VendorEncoding {
+            fn name(&self) -> &'static str { "x-vendor" }
+            fn whatwg_name(&self) -> Option<&'static str> { Some("x-vendor-web") }
+            fn encoder(&self) -> Box<Encoder> { all::ASCII.encoder() }
+            fn decoder(&self) -> Box<Decoder> { all::ASCII.decoder() }
+        }
+        static VENDOR: VendorEncoding = VendorEncoding;
+
+        let mut resolver = LabelResolver::new();
+        let nbuiltins = resolver.encodings().len();
+        assert!(resolver.lookup_name("x-vendor").is_none());
+        assert!(resolver.lookup("x-vendor").is_none());
+
+        resolver.register(&VENDOR as EncodingRef);
+        assert_eq!(resolver.lookup_name("x-vendor").unwrap().name(), "x-vendor");
+        assert!(resolver.lookup_name("X-Vendor").is_none());
+        assert_eq!(resolver.lookup(" X-Vendor ").unwrap().name(), "x-vendor");
+        assert_eq!(resolver.lookup("x-vendor-web").unwrap().name(), "x-vendor");
+        assert_eq!(resolver.lookup_name("utf-8").unwrap().name(), "utf-8");
+        let encodings = resolver.encodings();
+        assert_eq!(encodings.len(), nbuiltins + 1);
+        assert_eq!(encodings.last().unwrap().name(), "x-vendor");
+
+        // the global table is not affected
+        assert!(encoding_from_name("x-vendor").is_none());
+        assert!(encoding_from_whatwg_label("x-vendor-web").is_none());
+        assert_eq!(all::encodings().len(), nbuiltins);
+    }
+
     #[bench]
     fn bench_encoding_from_whatwg_label(bencher: &mut test::Bencher) {
         bencher.iter(|| test::black_box({
@@ -132,6 +132,11 @@ At each step of `raw_feed`, it receives a slice of byte sequence
 and emits any decoded characters to a generic `StringWriter` (normally `String`).
 Otherwise it is identical to `Encoder`s.
 
+`Encoding`s have no mutable state and `EncodingRef` is both `Send` and `Share`,
+so a single encoding can be shared by any number of tasks.
+`Encoder`s and `Decoder`s, on the other hand, keep the state of an ongoing conversion
+and cannot be shared; each task should get its own codec with `encoder` or `decoder`.
+
 One should prefer `Encoding::{encode,decode}` as a primary interface.
 `Encoder` and `Decoder` is experimental and can change substantially.
 See the additional documents on `encoding::types` module for more information on them.
@@ -159,6 +164,17 @@ Rust-encoding covers all encodings specified by WHATWG Encoding Standard and som
     * HZ
     * Big5-2003 with HKSCS-2008 extensions
 * ISO 8859-1 (distinct from Windows code page 1252)
+* KOI8-RU (KOI8-U with Belarusian letters) and MIK (Bulgarian DOS code page)
+* ISO/IEC 6937 (Latin with non-spacing diacritics, as used by T.61 and X.509 `TeletexString`)
+* Big5-HKSCS (Big5-2003 with HKSCS-2008 extensions in both directions)
+* Windows code page 51932 (EUC-JP without JIS X 0212)
+* ISO-2022-JP-2 with JIS X 0212, GB 2312, KS X 1001 and ISO 8859-1/7 (decoder only;
+  the encoder is same to ISO-2022-JP)
+* JIS X 0201 (Roman and half-width katakana) as a standalone single-byte encoding
+* MacJapanese (Shift_JIS variant of the classic Mac OS, decoder only)
+* CESU-8 (UTF-8 variant encoding characters beyond U+FFFF as surrogate pairs)
+* Punycode for internationalized domain name labels (RFC 3492)
+* Standard Compression Scheme for Unicode (SCSU, decoder only)
 
 Parenthesized names refer to the encoding's primary name assigned by WHATWG Encoding Standard.
 
@@ -183,14 +199,24 @@ Whenever in doubt, look at the source code and specifications for detailed expla
 #[cfg(test)] extern crate test;
 
 pub use self::types::{CodecError, ByteWriter, StringWriter,
-                      Encoder, Decoder, EncodingRef, Encoding,
+                      Encoder, Decoder, EncodingRef, Encoding, IndexableEncoding,
+                      NormalizationForm, NFC, NFD,
+                      LineIter, EncodeReport, PositionedDecodeError,
                       EncoderTrapFunc, DecoderTrapFunc, DecoderTrap,
-                      DecodeStrict, DecodeReplace, DecodeIgnore,
+                      DecodeStrict, DecodeReplace, DecodeIgnore, DecodeReplaceWith,
+                      DecodePreserveBytes, PreservedBytesEncoding, PreservedBytesEncoder,
                       EncoderTrap, EncodeStrict, EncodeReplace,
-                      EncodeIgnore, EncodeNcrEscape, decode}; // reexport
+                      EncodeIgnore, EncodeNcrEscape, EncodeReplaceWithByte,
+                      decode, decode_with_declared_bom_policy, decode_with_declared,
+                      decode_html_auto, detect_bom, RestartableDecoder, BOMSniffingDecoder,
+                      decode_japanese_auto, decode_capped, decode_cstr,
+                      encodings_agree_on,
+                      expand_numeric_entities, encode_chars,
+                      choose_encoding, encode_smallest, encode_html}; // reexport
 
 mod util;
 #[cfg(test)] mod testutils;
+#[cfg(test)] mod benches;
 
 pub mod types;
 
@@ -212,7 +238,9 @@ pub mod index {
     pub mod iso_8859_16;
     pub mod koi8_r;
     pub mod koi8_u;
+    pub mod koi8_ru;
     pub mod macintosh;
+    pub mod mik;
     pub mod windows_874;
     pub mod windows_1250;
     pub mod windows_1251;
@@ -230,6 +258,7 @@ pub mod index {
     pub mod gb18030_ranges;
     pub mod jis0208;
     pub mod jis0212;
+    pub mod x_mac_japanese;
 }
 
 /// Codec implementations.
@@ -238,12 +267,16 @@ pub mod codec {
     pub mod error;
     pub mod ascii;
     pub mod singlebyte;
+    pub mod iso_6937;
     pub mod utf_8;
     pub mod utf_16;
+    pub mod cesu_8;
     pub mod korean;
     pub mod japanese;
     pub mod simpchinese;
     pub mod tradchinese;
+    pub mod punycode;
+    pub mod scsu;
     pub mod whatwg;
 }
 
@@ -273,5 +306,398 @@ mod tests {
         test_one([0xFF, 0xFE, 0xE9, 0x00], "é", "utf-16le");
         test_one([0xE9, 0x00], "é\x00", "iso-8859-1");
     }
-}
 
+    #[test]
+    fn test_bom_sniffing_decoder() {
+        // the BOM fed one byte at a time is assembled and stripped
+        let mut d = BOMSniffingDecoder::new(all::WINDOWS_1252 as EncodingRef);
+        assert_feed_ok!(d, [], [0xFF], "");
+        assert_feed_ok!(d, [0xFE], [], "");
+        assert_feed_ok!(d, [0x41, 0x00], [0xE9], "A");
+        assert_feed_ok!(d, [0x00], [], "\u00e9");
+        assert_finish_ok!(d, "");
+
+        let mut d = BOMSniffingDecoder::new(all::WINDOWS_1252 as EncodingRef);
+        assert_feed_ok!(d, [], [0xEF], "");
+        assert_feed_ok!(d, [], [0xBB], "");
+        assert_feed_ok!(d, [0xBF, 0xC3, 0xA9], [], "\u00e9");
+        assert_finish_ok!(d, "");
+
+        let mut d = BOMSniffingDecoder::new(all::WINDOWS_1252 as EncodingRef);
+        assert_feed_ok!(d, [], [0xFE], "");
+        assert_feed_ok!(d, [0xFF, 0x00, 0x41], [], "A");
+        assert_finish_ok!(d, "");
+
+        // the buffered bytes turn out not to be a BOM
+        let mut d = BOMSniffingDecoder::new(all::WINDOWS_1252 as EncodingRef);
+        assert_feed_ok!(d, [], [0xEF], "");
+        assert_feed_ok!(d, [], [0xBB], "");
+        assert_feed_ok!(d, [0x41], [], "\u00ef\u00bbA");
+        assert_finish_ok!(d, "");
+
+        let mut d = BOMSniffingDecoder::new(all::WINDOWS_1252 as EncodingRef);
+        assert_feed_ok!(d, [], [0xFF], "");
+        assert_feed_ok!(d, [0xFF, 0x41], [], "\u00ff\u00ffA");
+        assert_finish_ok!(d, "");
+
+        let mut d = BOMSniffingDecoder::new(all::WINDOWS_1252 as EncodingRef);
+        assert_feed_ok!(d, [0x41, 0xEF], [], "A\u00ef");
+        assert_finish_ok!(d, "");
+
+        // the input ends within a partial BOM
+        let mut d = BOMSniffingDecoder::new(all::WINDOWS_1252 as EncodingRef);
+        assert_feed_ok!(d, [], [0xEF, 0xBB], "");
+        assert_finish_ok!(d, "\u00ef\u00bb");
+
+        // an error in the fallback encoding can point to the pending bytes
+        let mut d = BOMSniffingDecoder::new(all::UTF_8 as EncodingRef);
+        assert_feed_ok!(d, [], [0xEF], "");
+        assert_feed_err!(d, [], [], [0x41], "");
+        assert_feed_ok!(d, [0x41], [], "A");
+        assert_finish_ok!(d, "");
+
+        let mut d = BOMSniffingDecoder::new(all::UTF_8 as EncodingRef);
+        assert_feed_ok!(d, [], [0xFE], "");
+        assert_finish_err!(d, "");
+    }
+
+    #[test]
+    fn test_decode_in_tasks() {
+        use std::task::spawn;
+        use testutils;
+
+        let encoding: EncodingRef = all::WINDOWS_949;
+        let input = encoding.encode(testutils::KOREAN_TEXT, EncodeStrict).unwrap();
+
+        // every task decodes the same input with its own decoder, in differently sized chunks
+        let (tx, rx) = channel();
+        for i in range(1u, 9) {
+            let tx = tx.clone();
+            let input = input.clone();
+            spawn(proc() {
+                let mut d = encoding.decoder();
+                let mut ret = String::new();
+                for chunk in input.as_slice().chunks(i) {
+                    let (_, err) = d.raw_feed(chunk, &mut ret);
+                    assert!(err.is_none());
+                }
+                assert!(d.raw_finish(&mut ret).is_none());
+                tx.send(ret);
+            });
+        }
+        for _ in range(1u, 9) {
+            assert_eq!(rx.recv().as_slice(), testutils::KOREAN_TEXT);
+        }
+    }
+
+    #[test]
+    fn test_decode_with_declared_bom_policy() {
+        fn test_one(input: &[u8], bom_wins: bool, expected_result: &str, expected_encoding: &str) {
+            let (result, used_encoding) = decode_with_declared_bom_policy(
+                input, DecodeStrict, all::UTF_16LE as EncodingRef, bom_wins);
+            let result = result.unwrap();
+            assert_eq!(used_encoding.name(), expected_encoding);
+            assert_eq!(result.as_slice(), expected_result);
+        }
+
+        // the conflicting BOM
+        test_one([0xFE, 0xFF, 0x00, 0x41], true, "A", "utf-16be");
+        test_one([0xFE, 0xFF, 0x00, 0x41], false, "\ufffe\u4100", "utf-16le");
+
+        // the BOM of the declared encoding is always stripped
+        test_one([0xFF, 0xFE, 0x41, 0x00], true, "A", "utf-16le");
+        test_one([0xFF, 0xFE, 0x41, 0x00], false, "A", "utf-16le");
+
+        test_one([0x41, 0x00], true, "A", "utf-16le");
+        test_one([0x41, 0x00], false, "A", "utf-16le");
+    }
+
+    #[test]
+    fn test_decode_with_declared() {
+        fn test_one(declared: EncodingRef, input: &[u8],
+                    expected_result: &str, expected_encoding: &str) {
+            let (result, used_encoding) = decode_with_declared(declared, input);
+            assert_eq!(used_encoding.name(), expected_encoding);
+            assert_eq!(result.as_slice(), expected_result);
+        }
+
+        // the BOM of the declared encoding is stripped
+        test_one(all::UTF_8, [0xEF, 0xBB, 0xBF, 0xC3, 0xA9], "\u00e9", "utf-8");
+        test_one(all::UTF_16BE, [0xFE, 0xFF, 0x00, 0xE9], "\u00e9", "utf-16be");
+
+        // the BOM overrides the declared encoding
+        test_one(all::WINDOWS_1252, [0xEF, 0xBB, 0xBF, 0xC3, 0xA9], "\u00e9", "utf-8");
+        test_one(all::UTF_8, [0xFF, 0xFE, 0xE9, 0x00], "\u00e9", "utf-16le");
+        test_one(all::UTF_16LE, [0xFE, 0xFF, 0x00, 0xE9], "\u00e9", "utf-16be");
+
+        // no BOM
+        test_one(all::WINDOWS_1252, [0xC3, 0xA9], "\u00c3\u00a9", "windows-1252");
+        test_one(all::UTF_8, [0xC3, 0xA9, 0xFF], "\u00e9\ufffd", "utf-8");
+        test_one(all::UTF_8, [], "", "utf-8");
+    }
+
+    #[test]
+    fn test_decode_japanese_auto() {
+        fn test_one(input: &[u8], expected_result: &str, expected_encoding: &str) {
+            let (result, used_encoding) = decode_japanese_auto(input);
+            assert_eq!(used_encoding.name(), expected_encoding);
+            assert_eq!(result.as_slice(), expected_result);
+        }
+
+        test_one([0x41, 0x42, 0x43], "ABC", "utf-8");
+        test_one([0xE6, 0x97, 0xA5, 0xE6, 0x9C, 0xAC], "\u65e5\u672c", "utf-8");
+        test_one([0x93, 0xFA, 0x96, 0x7B], "\u65e5\u672c", "windows-31j");
+        test_one([0x93, 0xFA, 0xA0], "\u65e5\ufffd", "windows-31j");
+    }
+
+    #[test]
+    fn test_decode_capped() {
+        let input = [0x41, 0x93, 0xFA, 0x96, 0x7B, 0x42];
+        let mut d = all::WINDOWS_31J.decoder();
+        assert_eq!(decode_capped(&mut *d, input, DecodeStrict, 2),
+                   Ok(("A\u65e5".to_string(), 3)));
+        assert_eq!(decode_capped(&mut *d, input.slice_from(3), DecodeStrict, 2),
+                   Ok(("\u672cB".to_string(), 3)));
+        assert_eq!(decode_capped(&mut *d, [], DecodeStrict, 2),
+                   Ok(("".to_string(), 0)));
+
+        // an incomplete sequence is kept in the decoder
+        let mut d = all::WINDOWS_31J.decoder();
+        assert_eq!(decode_capped(&mut *d, [0x41, 0x93], DecodeStrict, 2),
+                   Ok(("A".to_string(), 2)));
+        assert_eq!(decode_capped(&mut *d, [0xFA, 0x42], DecodeStrict, 1),
+                   Ok(("\u65e5".to_string(), 1)));
+
+        // replacement characters count toward the cap
+        let mut d = all::WINDOWS_31J.decoder();
+        assert_eq!(decode_capped(&mut *d, [0xA0, 0x41], DecodeReplace, 1),
+                   Ok(("\ufffd".to_string(), 1)));
+        let mut d = all::WINDOWS_31J.decoder();
+        assert!(decode_capped(&mut *d, [0xA0, 0x41], DecodeStrict, 1).is_err());
+
+        // an invalid sequence backing up into the previous call resumes from this call
+        let mut d = all::GB18030.decoder();
+        assert_eq!(decode_capped(&mut *d, [0x81, 0x30, 0x81], DecodeReplace, 2),
+                   Ok(("".to_string(), 3)));
+        assert_eq!(decode_capped(&mut *d, [0x20, 0x41], DecodeReplace, 2),
+                   Ok(("\ufffd ".to_string(), 1)));
+        assert_eq!(decode_capped(&mut *d, [0x41], DecodeReplace, 2),
+                   Ok(("A".to_string(), 1)));
+    }
+
+    #[test]
+    fn test_decode_cstr() {
+        assert_eq!(decode_cstr(all::ASCII, [0x41, 0x42, 0x00, 0xFF, 0x43], DecodeStrict),
+                   Ok("AB".to_string()));
+        assert_eq!(decode_cstr(all::UTF_8, [0xE6, 0x97, 0xA5, 0x00, 0xE6, 0x97], DecodeStrict),
+                   Ok("\u65e5".to_string()));
+        assert_eq!(decode_cstr(all::WINDOWS_31J, [0x93, 0xFA, 0x00, 0x00, 0x96], DecodeStrict),
+                   Ok("\u65e5".to_string()));
+        assert_eq!(decode_cstr(all::EUC_JP, [0xC6, 0xFC, 0x41], DecodeStrict),
+                   Ok("\u65e5A".to_string()));
+        assert_eq!(decode_cstr(all::EUC_JP, [0x00, 0xC6, 0xFC], DecodeStrict),
+                   Ok("".to_string()));
+
+        // an incomplete sequence before the terminator is an error
+        assert!(decode_cstr(all::WINDOWS_31J, [0x41, 0x93, 0x00, 0xFA], DecodeStrict).is_err());
+        assert_eq!(decode_cstr(all::WINDOWS_31J, [0x41, 0x93, 0x00, 0xFA], DecodeReplace),
+                   Ok("A\ufffd".to_string()));
+    }
+
+    #[test]
+    fn test_encodings_agree_on() {
+        // every ASCII-compatible encoding agrees on ASCII
+        assert!(encodings_agree_on(all::WINDOWS_1252, all::UTF_8, b"plain text"));
+        assert!(encodings_agree_on(all::KOI8_R, all::WINDOWS_31J, b""));
+        assert!(!encodings_agree_on(all::UTF_8, all::UTF_16LE, b"ab"));
+
+        // encodings diverge on a high byte
+        assert!(!encodings_agree_on(all::WINDOWS_1252, all::ISO_8859_15, b"\xa4"));
+        assert!(!encodings_agree_on(all::WINDOWS_1252, all::ISO_8859_2, b"caf\xe8"));
+        assert!(encodings_agree_on(all::WINDOWS_1252, all::ISO_8859_15, b"caf\xe9"));
+        assert!(encodings_agree_on(all::EUC_JP, all::CP51932, b"\xc6\xfc\xcb\xdc"));
+
+        // an error in either encoding means a disagreement
+        assert!(!encodings_agree_on(all::UTF_8, all::UTF_8, b"\xff"));
+        assert!(!encodings_agree_on(all::WINDOWS_1252, all::UTF_8, b"\xc3\xa9"));
+    }
+
+    #[test]
+    fn test_agrees_with_on_ascii() {
+        assert!(all::WINDOWS_1252.agrees_with_on_ascii(all::UTF_8));
+        assert!(all::KOI8_R.agrees_with_on_ascii(all::WINDOWS_31J));
+        assert!(!all::UTF_8.agrees_with_on_ascii(all::UTF_16LE));
+        assert!(!all::UTF_16BE.agrees_with_on_ascii(all::UTF_16BE));
+        assert!(!all::ISO_2022_JP.agrees_with_on_ascii(all::ASCII));
+    }
+
+    #[test]
+    fn test_is_single_byte() {
+        for &encoding in all::encodings().iter() {
+            let expected = match encoding.name() {
+                "error" | "utf-8" | "utf-16le" | "utf-16be" | "windows-949" | "euc-jp" |
+                "cp51932" | "windows-31j" | "iso-2022-jp" | "iso-2022-jp-2" | "mac-japanese" |
+                "gb18030" | "hz" | "big5-2003" | "big5-hkscs" | "cesu-8" | "punycode" |
+                "scsu" | "iso-6937" | "encoder-only-utf-8" => false,
+                _ => true,
+            };
+            assert!(encoding.is_single_byte() == expected,
+                    "{} should{} be single-byte", encoding.name(), if expected {""} else {" not"});
+        }
+        assert!(all::ASCII.is_single_byte());
+        assert!(all::ISO_8859_1.is_single_byte());
+        assert!(all::WINDOWS_1252.is_single_byte());
+        assert!(!all::WINDOWS_31J.is_single_byte());
+    }
+
+    #[test]
+    fn test_direction() {
+        for &encoding in all::encodings().iter() {
+            let (can_encode, can_decode) = match encoding.name() {
+                "error" => (false, false),
+                "scsu" | "mac-japanese" => (false, true),
+                "encoder-only-utf-8" => (true, false),
+                _ => (true, true),
+            };
+            assert!(encoding.can_encode_direction() == can_encode, "{} should{} support encoding",
+                    encoding.name(), if can_encode {""} else {" not"});
+            assert!(encoding.can_decode_direction() == can_decode, "{} should{} support decoding",
+                    encoding.name(), if can_decode {""} else {" not"});
+        }
+
+        // the unsupported direction is a stub which returns an error for any input
+        let replacement = all::whatwg::REPLACEMENT;
+        assert_eq!(replacement.encode("\uac00", EncodeStrict), Ok(vec!(0xea, 0xb0, 0x80)));
+        assert!(replacement.decode([0x41], DecodeStrict).is_err());
+        assert_eq!(replacement.decode([], DecodeStrict), Ok("".to_string()));
+    }
+
+    #[test]
+    fn test_assumes_normalization() {
+        assert_eq!(all::ISO_8859_1.assumes_normalization(), Some(NFC));
+        assert_eq!(all::KOI8_R.assumes_normalization(), Some(NFC));
+        assert_eq!(all::WINDOWS_949.assumes_normalization(), Some(NFC));
+        assert_eq!(all::WINDOWS_31J.assumes_normalization(), Some(NFC));
+        assert_eq!(all::WINDOWS_1258.assumes_normalization(), None);
+        assert_eq!(all::UTF_8.assumes_normalization(), None);
+        assert_eq!(all::GB18030.assumes_normalization(), None);
+
+        // the decomposed sequence is not representable even though the precomposed one is
+        assert!(all::ISO_8859_1.encode("caf\u00e9", EncodeStrict).is_ok());
+        assert!(all::ISO_8859_1.encode("cafe\u0301", EncodeStrict).is_err());
+        assert!(all::WINDOWS_949.encode("\uac00", EncodeStrict).is_ok());
+        assert!(all::WINDOWS_949.encode("\u1100\u1161", EncodeStrict).is_err());
+    }
+
+    #[test]
+    fn test_covered_scripts() {
+        assert_eq!(all::ISO_8859_2.covered_scripts().to_vec(), vec!("Latin"));
+        assert_eq!(all::ISO_8859_5.covered_scripts().to_vec(), vec!("Latin", "Cyrillic"));
+        assert!(all::ERROR.covered_scripts().is_empty());
+        for &encoding in [all::WINDOWS_31J as EncodingRef, all::EUC_JP as EncodingRef,
+                          all::ISO_2022_JP as EncodingRef].iter() {
+            let scripts = encoding.covered_scripts();
+            for &script in ["Latin", "Hiragana", "Katakana", "CJK"].iter() {
+                assert!(scripts.contains(&script), "{} should cover {}", encoding.name(), script);
+            }
+            assert!(!scripts.contains(&"Hangul"));
+        }
+        assert!(all::UTF_8.covered_scripts().contains(&"Hangul"));
+        assert!(all::WINDOWS_949.covered_scripts().contains(&"Hangul"));
+    }
+
+    #[test]
+    fn test_verify_all_tables() {
+        assert_eq!(all::verify_all_tables(), Ok(()));
+    }
+
+    #[test]
+    fn test_choose_encoding() {
+        let candidates = [all::ASCII as EncodingRef, all::ISO_8859_1 as EncodingRef,
+                          all::WINDOWS_1252 as EncodingRef, all::UTF_8 as EncodingRef];
+        let name = |input: &str| choose_encoding(input, candidates).map(|e| e.name());
+        assert_eq!(name("plain"), Some("ascii"));
+        assert_eq!(name("caf\u00e9"), Some("iso-8859-1"));
+        assert_eq!(name("\u201ccaf\u00e9\u201d"), Some("windows-1252"));
+        assert_eq!(name("\u65e5\u672c"), Some("utf-8"));
+        assert_eq!(choose_encoding("\u65e5\u672c", candidates.slice_to(3)).map(|e| e.name()),
+                   None);
+
+        // encodings which cannot encode are skipped even for the empty string
+        assert!(choose_encoding("", [all::ERROR as EncodingRef]).is_none());
+        assert_eq!(choose_encoding("", []).map(|e| e.name()), None);
+    }
+
+    #[test]
+    fn test_encode_smallest() {
+        let candidates = [all::UTF_8 as EncodingRef, all::UTF_16LE as EncodingRef,
+                          all::ASCII as EncodingRef, all::WINDOWS_949 as EncodingRef];
+        let smallest = |input: &str| {
+            encode_smallest(input, candidates).map(|(e, output)| (e.name(), output))
+        };
+
+        // ASCII produces the same bytes as UTF-8, so the earlier candidate wins
+        assert_eq!(smallest("plain"), Some(("utf-8", b"plain".to_vec())));
+        assert_eq!(smallest("\ud55c\uae00 ok"),
+                   Some(("windows-949", vec!(0xc7, 0xd1, 0xb1, 0xdb, 0x20, 0x6f, 0x6b))));
+        assert_eq!(smallest("\u3042\u3044"), Some(("utf-16le", vec!(0x42, 0x30, 0x44, 0x30))));
+        assert!(encode_smallest("\ud55c", [all::ASCII as EncodingRef]).is_none());
+        assert!(encode_smallest("", [all::ERROR as EncodingRef]).is_none());
+        assert!(encode_smallest("", []).is_none());
+    }
+
+    #[test]
+    fn test_boundary_bytes_do_not_fail() {
+        // every lead and trail byte combination should result in either a character or an error
+        for &encoding in all::encodings().iter() {
+            if encoding.is_single_byte() { continue; }
+            for lead in range(0x80u, 0x100) {
+                for trail in range(0u, 0x100) {
+                    let input = [lead as u8, trail as u8];
+                    assert!(encoding.decode(input, DecodeReplace).is_ok());
+                }
+            }
+            for &input in [&[0xfe, 0xfe, 0xfe, 0xfe], &[0xff, 0xff, 0xff, 0xff],
+                           &[0x8f, 0xfe, 0xfe], &[0xfe, 0x39, 0xfe, 0x39],
+                           &[0x84, 0x39, 0xfe, 0x39], &[0xe3, 0x32, 0x9a, 0x35]].iter() {
+                assert!(encoding.decode(input, DecodeReplace).is_ok());
+            }
+        }
+
+        assert!(all::EUC_JP.decode([0xfe, 0xfe], DecodeStrict).is_err());
+        assert!(all::EUC_JP.decode([0x8f, 0xfe, 0xfe], DecodeStrict).is_err());
+        assert!(all::WINDOWS_949.decode([0xfe, 0xfe], DecodeStrict).is_err());
+        assert!(all::WINDOWS_31J.decode([0xfc, 0xfc], DecodeStrict).is_err());
+        assert_eq!(all::GB18030.decode([0xfe, 0xfe], DecodeStrict), Ok("\ue4c5".to_string()));
+    }
+
+    #[test]
+    fn test_bom_bytes() {
+        let bom = |encoding: EncodingRef| encoding.bom_bytes().map(|bom| bom.to_vec());
+        assert_eq!(bom(all::UTF_8), Some(vec!(0xEF, 0xBB, 0xBF)));
+        assert_eq!(bom(all::UTF_16LE), Some(vec!(0xFF, 0xFE)));
+        assert_eq!(bom(all::UTF_16BE), Some(vec!(0xFE, 0xFF)));
+        assert_eq!(bom(all::WINDOWS_1252), None);
+        assert_eq!(bom(all::GB18030), None); // U+FEFF is encodable, but not used as a BOM
+
+        // the BOM is U+FEFF in the encoding itself
+        for &encoding in all::encodings().iter() {
+            match encoding.bom_bytes() {
+                Some(bom) => assert_eq!(encoding.encode("\ufeff", EncodeStrict),
+                                        Ok(bom.to_vec())),
+                None => {}
+            }
+        }
+    }
+
+    #[test]
+    fn test_detect_bom() {
+        fn detect(input: &[u8]) -> Option<&'static str> { detect_bom(input).map(|e| e.name()) }
+        assert_eq!(detect([0xEF, 0xBB, 0xBF, 0x41]), Some("utf-8"));
+        assert_eq!(detect([0xFF, 0xFE, 0x41, 0x00]), Some("utf-16le"));
+        assert_eq!(detect([0xFE, 0xFF, 0x00, 0x41]), Some("utf-16be"));
+        assert_eq!(detect([0xEF, 0xBB]), None);
+        assert_eq!(detect([0x41, 0xEF, 0xBB, 0xBF]), None);
+        assert_eq!(detect([]), None);
+    }
+}
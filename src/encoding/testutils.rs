@@ -122,6 +122,15 @@ pub static JAPANESE_TEXT: &'static str =
      日本人や日系人、日本がかつて統治した地域の一部の住民など、約1億3千万人以上と考えられる。\
      統計によって前後する可能性はあるが、この数は世界の母語話者数で上位10位以内に入る人数である。";
 
+/// Some Japanese text mostly in hiragana and katakana to test.
+//
+// the iroha poem, written in hiragana and then in katakana.
+pub static KANA_TEXT: &'static str =
+    "いろはにほへと、ちりぬるを、わかよたれそ、つねならむ、\
+     うゐのおくやま、けふこえて、あさきゆめみし、ゑひもせす。\
+     イロハニホヘト、チリヌルヲ、ワカヨタレソ、ツネナラム、\
+     ウヰノオクヤマ、ケフコエテ、アサキユメミシ、ヱヒモセス。";
+
 /// Some simplified Chinese text to test.
 //
 // the first paragraphs of the article "Chinese Language" from Chinese Wikipedia.
@@ -198,6 +207,14 @@ macro_rules! single_byte_tests(
                 }
             }
 
+            #[test]
+            fn test_forward_ascii() {
+                // out of the table, but should not index past it
+                for i in range_inclusive(0u8, 0x7f) {
+                    assert_eq!(forward(i), i as u16);
+                }
+            }
+
             #[bench]
             fn bench_forward_sequential_128(bencher: &mut test::Bencher) {
                 bencher.iter(|| {
@@ -52,7 +52,9 @@
  * It still has to feed the input bytes starting at the second offset again.
  */
 
+use std::{str, char, cmp, mem};
 use std::str::SendStr;
+use util::StrCharIndex;
 
 /// Error information from either encoder or decoder.
 #[experimental]
@@ -66,6 +68,11 @@ pub struct CodecError {
     pub upto: int,
     /// A human-readable cause of the error.
     pub cause: SendStr,
+    /// For the `finish` call, the number of bytes of the incomplete sequence
+    /// that had been buffered in the decoder (e.g. 1 for a dangling Shift_JIS lead byte).
+    /// The caller can use this to decide whether to wait for more input or to discard them.
+    /// This is always zero for errors from the `feed` call.
+    pub buffered: uint,
 }
 
 /// Byte writer used by `Encoder`s. In most cases this will be an owned vector of `u8`.
@@ -83,7 +90,13 @@ pub trait ByteWriter {
     fn write_byte(&mut self, b: u8);
 
     /// Writes a number of bytes.
-    fn write_bytes(&mut self, v: &[u8]);
+    /// Encoders should prefer this over successive `write_byte` calls for multibyte sequences.
+    /// By default this calls `write_byte` for each byte.
+    fn write_bytes(&mut self, v: &[u8]) {
+        for &b in v.iter() {
+            self.write_byte(b);
+        }
+    }
 }
 
 impl ByteWriter for Vec<u8> {
@@ -116,6 +129,16 @@ pub trait StringWriter {
 
     /// Writes a string.
     fn write_str(&mut self, s: &str);
+
+    /// Writes a number of characters.
+    /// This is useful when a single input sequence maps to multiple characters,
+    /// e.g. a base character followed by a combining character.
+    /// By default this method calls `write_char` for each character.
+    fn write_chars(&mut self, cs: &[char]) {
+        for &c in cs.iter() {
+            self.write_char(c);
+        }
+    }
 }
 
 impl StringWriter for String {
@@ -133,6 +156,208 @@ impl StringWriter for String {
     }
 }
 
+/// Collects decoded characters without building an intermediate UTF-8 string,
+/// for consumers working on `char` arrays (see also `Encoding::decode_to_chars`).
+impl StringWriter for Vec<char> {
+    fn writer_hint(&mut self, expectedlen: uint) {
+        // the hint is in bytes, which is usually an upper bound for the number of characters
+        self.reserve_additional(expectedlen);
+    }
+
+    fn write_char(&mut self, c: char) {
+        self.push(c);
+    }
+
+    fn write_str(&mut self, s: &str) {
+        self.extend(s.chars());
+    }
+
+    fn write_chars(&mut self, cs: &[char]) {
+        self.push_all(cs);
+    }
+}
+
+/// String writer which discards the output and only counts the number of characters written.
+#[experimental]
+pub struct NullStringWriter {
+    /// The number of characters written so far.
+    pub nchars: uint,
+}
+
+impl NullStringWriter {
+    /// Creates a new writer.
+    pub fn new() -> NullStringWriter {
+        NullStringWriter { nchars: 0 }
+    }
+}
+
+impl StringWriter for NullStringWriter {
+    fn write_char(&mut self, _c: char) {
+        self.nchars += 1;
+    }
+
+    fn write_str(&mut self, s: &str) {
+        self.nchars += s.char_len();
+    }
+}
+
+/// Byte writer which discards the output without any work. Internal use only.
+struct DiscardingByteWriter;
+
+impl ByteWriter for DiscardingByteWriter {
+    fn write_byte(&mut self, _b: u8) {}
+    fn write_bytes(&mut self, _v: &[u8]) {}
+}
+
+/// Byte writer which discards the output and only counts the number of bytes written.
+/// Internal use only.
+struct CountingByteWriter {
+    len: uint,
+}
+
+impl ByteWriter for CountingByteWriter {
+    fn write_byte(&mut self, _b: u8) { self.len += 1; }
+    fn write_bytes(&mut self, v: &[u8]) { self.len += v.len(); }
+}
+
+/// A free list of string buffers, which can be used to recycle the output buffers
+/// when decoding lots of short strings (e.g. with `Encoding::decode_to`).
+#[experimental]
+pub struct StringPool {
+    free: Vec<String>,
+}
+
+impl StringPool {
+    /// Creates a new empty pool.
+    pub fn new() -> StringPool {
+        StringPool { free: Vec::new() }
+    }
+
+    /// Returns the number of buffers available for reuse.
+    pub fn len(&self) -> uint {
+        self.free.len()
+    }
+
+    /// Takes an empty buffer from the pool, or allocates a new one if the pool is empty.
+    pub fn take(&mut self) -> String {
+        match self.free.pop() {
+            Some(buf) => buf,
+            None => String::new(),
+        }
+    }
+
+    /// Returns a buffer to the pool. The contents are discarded but its capacity is kept.
+    pub fn recycle(&mut self, mut buf: String) {
+        buf.truncate(0);
+        self.free.push(buf);
+    }
+}
+
+/// Byte writer which forwards the output to the inner writer,
+/// while keeping the number of bytes written and their CRC-32 (as in zlib and PNG).
+/// This avoids the second pass over the encoded output when a checksum is required.
+#[experimental]
+pub struct ChecksumByteWriter<'a> {
+    inner: &'a mut ByteWriter,
+    len: uint,
+    crc: u32,
+}
+
+impl<'a> ChecksumByteWriter<'a> {
+    /// Creates a new writer wrapping given writer.
+    pub fn new(inner: &'a mut ByteWriter) -> ChecksumByteWriter<'a> {
+        ChecksumByteWriter { inner: inner, len: 0, crc: 0xffffffff }
+    }
+
+    /// Returns the number of bytes written and their CRC-32.
+    pub fn finish(self) -> (uint, u32) {
+        (self.len, !self.crc)
+    }
+
+    fn update(&mut self, v: &[u8]) {
+        let mut crc = self.crc;
+        for &b in v.iter() {
+            crc ^= b as u32;
+            for _ in range(0u, 8) {
+                crc = if crc & 1 != 0 {(crc >> 1) ^ 0xedb88320} else {crc >> 1};
+            }
+        }
+        self.crc = crc;
+        self.len += v.len();
+    }
+}
+
+impl<'a> ByteWriter for ChecksumByteWriter<'a> {
+    fn writer_hint(&mut self, expectedlen: uint) {
+        self.inner.writer_hint(expectedlen);
+    }
+
+    fn write_byte(&mut self, b: u8) {
+        self.inner.write_byte(b);
+        self.update([b]);
+    }
+
+    fn write_bytes(&mut self, v: &[u8]) {
+        self.inner.write_bytes(v);
+        self.update(v);
+    }
+}
+
+/// Returns true if given byte is an unreserved character in URIs (RFC 3986),
+/// i.e. an ASCII letter, a digit, `-`, `.`, `_` or `~`.
+fn is_unreserved(b: u8) -> bool {
+    match b {
+        b'A'..b'Z' | b'a'..b'z' | b'0'..b'9' | b'-' | b'.' | b'_' | b'~' => true,
+        _ => false,
+    }
+}
+
+/// Byte writer which forwards the output to the inner writer
+/// while percent-encoding (e.g. `%E6%97%A5`) every byte not allowed by given predicate.
+/// The hexadecimal digits are in upper case.
+#[experimental]
+pub struct PercentEncodingWriter<'a> {
+    inner: &'a mut ByteWriter,
+    is_unescaped: fn(u8) -> bool,
+}
+
+impl<'a> PercentEncodingWriter<'a> {
+    /// Creates a new writer wrapping given writer,
+    /// which leaves only the unreserved characters of URIs (RFC 3986) unescaped.
+    pub fn new(inner: &'a mut ByteWriter) -> PercentEncodingWriter<'a> {
+        PercentEncodingWriter::with_predicate(inner, is_unreserved)
+    }
+
+    /// Creates a new writer wrapping given writer,
+    /// which leaves the bytes for which `is_unescaped` returns true unescaped.
+    pub fn with_predicate(inner: &'a mut ByteWriter,
+                          is_unescaped: fn(u8) -> bool) -> PercentEncodingWriter<'a> {
+        PercentEncodingWriter { inner: inner, is_unescaped: is_unescaped }
+    }
+}
+
+impl<'a> ByteWriter for PercentEncodingWriter<'a> {
+    fn writer_hint(&mut self, expectedlen: uint) {
+        self.inner.writer_hint(expectedlen);
+    }
+
+    fn write_byte(&mut self, b: u8) {
+        static HEX_DIGITS: &'static [u8] = b"0123456789ABCDEF";
+        if (self.is_unescaped)(b) {
+            self.inner.write_byte(b);
+        } else {
+            self.inner.write_bytes([b'%', HEX_DIGITS[(b >> 4) as uint],
+                                    HEX_DIGITS[(b & 15) as uint]]);
+        }
+    }
+
+    fn write_bytes(&mut self, v: &[u8]) {
+        for &b in v.iter() {
+            self.write_byte(b);
+        }
+    }
+}
+
 /// Encoder converting a Unicode string into a byte sequence.
 /// This is a lower level interface, and normally `Encoding::encode` should be used instead.
 #[experimental]
@@ -140,6 +365,16 @@ pub trait Encoder {
     /// Creates a fresh `Encoder` instance which parameters are same as `self`.
     fn from_self(&self) -> Box<Encoder>;
 
+    /// Creates a copy of this `Encoder` including its current state,
+    /// so that encoders can be kept in a pool as trait objects.
+    fn clone_box(&self) -> Box<Encoder>;
+
+    /// Resets the encoder to the initial state, discarding any pending state.
+    /// By default this calls `raw_finish` and throws its output and error away.
+    fn reset(&mut self) {
+        let _ = self.raw_finish(&mut DiscardingByteWriter);
+    }
+
     /// Returns true if this encoding is compatible to ASCII,
     /// i.e. U+0000 through U+007F always map to bytes 00 through 7F and nothing else.
     fn is_ascii_compatible(&self) -> bool { false }
@@ -157,6 +392,66 @@ pub trait Encoder {
     /// `remaining` value of the error information, if any, is always an empty string.
     fn raw_finish(&mut self, output: &mut ByteWriter) -> Option<CodecError>;
 
+    /// Ends the current frame of a protocol which encodes each message independently,
+    /// pushes any byte sequence required to close the current state
+    /// (e.g. the escape sequence back to ASCII in ISO-2022-JP) at the end of the given output,
+    /// and leaves the encoder in the initial state ready for the next frame.
+    /// This is a no-op for stateless encoders.
+    /// By default this calls `raw_finish`, which every encoder can be reused after.
+    fn finish_frame(&mut self, output: &mut ByteWriter) -> Option<CodecError> {
+        self.raw_finish(output)
+    }
+
+    /// Returns a byte sequence that the `EncodeReplace` trap would emit
+    /// in the current state of the encoder, or `None` if the encoder cannot emit one
+    /// (e.g. it cannot encode the replacement or it buffers the output until `raw_finish`).
+    /// The `EncodeReplace` trap writes this sequence, so overriding this changes the trap too.
+    /// By default this is a question mark (`?`) encoded by a copy of the encoder.
+    fn replacement_bytes(&self) -> Option<Vec<u8>> {
+        let mut encoder = self.clone_box();
+        let mut ret = Vec::new();
+        match encoder.raw_feed(REPLACEMENT_STR, &mut ret) {
+            (_, None) => Some(ret),
+            (_, Some(_)) => None,
+        }
+    }
+
+    /// Returns the number of bytes `raw_feed` would emit for given character
+    /// in the current state of the encoder, or `None` if it is unrepresentable.
+    /// This includes any escape sequence required to switch the mode (e.g. in ISO-2022-JP),
+    /// but not the sequence `raw_finish` may emit later.
+    /// The state of the encoder is not changed; the caller should feed the character
+    /// to advance the state.
+    /// By default this feeds the character to a copy of the encoder.
+    /// Encoders which hold characters back until the next character or `raw_finish`
+    /// override this to count the held back bytes as well.
+    #[experimental]
+    fn char_cost(&mut self, ch: char) -> Option<uint> {
+        let mut encoder = self.clone_box();
+        let mut output = CountingByteWriter { len: 0 };
+        match encoder.raw_feed(String::from_char(1, ch).as_slice(), &mut output) {
+            (_, None) => Some(output.len),
+            (_, Some(_)) => None,
+        }
+    }
+
+    /// Feeds given complete string to the encoder and finishes it at once,
+    /// pushes the an encoded byte sequence at the end of the given output,
+    /// and returns optional error information (None means success).
+    /// This is a shortcut to `raw_feed` followed by `raw_finish`;
+    /// `upto` value of the error information, if any, is relative to the given input
+    /// even when the error came from `raw_finish`.
+    /// The encoder is not finished when `raw_feed` returns an error.
+    fn feed_all(&mut self, input: &str, output: &mut ByteWriter) -> Option<CodecError> {
+        match self.raw_feed(input, output) {
+            (_, Some(err)) => Some(err),
+            (_, None) => self.raw_finish(output).map(|err| CodecError {
+                upto: input.len() as int + err.upto, cause: err.cause,
+                buffered: err.buffered
+            }),
+        }
+    }
+
     /// Normalizes the input for testing. Internal use only.
     #[cfg(test)]
     fn test_norm_input<'r>(&self, input: &'r str) -> &'r str { input }
@@ -193,10 +488,33 @@ pub trait Decoder {
     /// Creates a fresh `Decoder` instance which parameters are same as `self`.
     fn from_self(&self) -> Box<Decoder>;
 
+    /// Creates a copy of this `Decoder` including its current state,
+    /// so that decoders can be kept in a pool as trait objects.
+    fn clone_box(&self) -> Box<Decoder>;
+
+    /// Resets the decoder to the initial state, discarding any pending bytes.
+    /// By default this calls `raw_finish` and throws its output and error away.
+    fn reset(&mut self) {
+        let _ = self.raw_finish(&mut NullStringWriter::new());
+    }
+
     /// Returns true if this encoding is compatible to ASCII,
     /// i.e. bytes 00 through 7F always map to U+0000 through U+007F and nothing else.
     fn is_ascii_compatible(&self) -> bool { false }
 
+    /// Returns the number of bytes of an incomplete sequence buffered in the decoder,
+    /// which is 0 when the decoder is idle (e.g. 1 after the lead byte of Shift_JIS).
+    /// This is same to `CodecError::buffered` of the error `raw_finish` would return now.
+    /// By default this returns 0.
+    #[experimental]
+    fn pending_len(&self) -> uint { 0 }
+
+    /// Returns true if the input so far ended in the middle of a sequence,
+    /// i.e. `raw_finish` would return an error now but more input may complete it.
+    /// This is same to `pending_len() > 0`.
+    #[experimental]
+    fn needs_more(&self) -> bool { self.pending_len() > 0 }
+
     /// Feeds given portion of byte sequence to the encoder,
     /// pushes the a decoded string at the end of the given output,
     /// and returns an offset to the first unprocessed byte
@@ -209,6 +527,23 @@ pub trait Decoder {
     /// and returns optional error information (None means success).
     fn raw_finish(&mut self, output: &mut StringWriter) -> Option<CodecError>;
 
+    /// Feeds given complete byte sequence to the decoder and finishes it at once,
+    /// pushes the a decoded string at the end of the given output,
+    /// and returns optional error information (None means success).
+    /// This is a shortcut to `raw_feed` followed by `raw_finish`;
+    /// `upto` value of the error information, if any, is relative to the given input
+    /// even when the error came from `raw_finish`.
+    /// The decoder is not finished when `raw_feed` returns an error.
+    fn feed_all(&mut self, input: &[u8], output: &mut StringWriter) -> Option<CodecError> {
+        match self.raw_feed(input, output) {
+            (_, Some(err)) => Some(err),
+            (_, None) => self.raw_finish(output).map(|err| CodecError {
+                upto: input.len() as int + err.upto, cause: err.cause,
+                buffered: err.buffered
+            }),
+        }
+    }
+
     /// Normalizes the input for testing. Internal use only.
     #[cfg(test)]
     fn test_norm_input<'r>(&self, input: &'r [u8]) -> &'r [u8] { input }
@@ -243,12 +578,25 @@ pub trait Decoder {
     }
 }
 
+/// Unicode normalization forms, returned by `Encoding::assumes_normalization`.
+#[experimental]
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub enum NormalizationForm {
+    /// Normalization Form C (canonical decomposition followed by canonical composition).
+    NFC,
+    /// Normalization Form D (canonical decomposition).
+    NFD,
+}
+
 /// A trait object using dynamic dispatch which is a sendable reference to the encoding,
 /// for code where the encoding is not known at compile-time.
 #[stable]
 pub type EncodingRef = &'static Encoding+Send+Share;
 
 /// Character encoding.
+///
+/// Built-in encodings are immutable and can be shared across tasks via `EncodingRef`,
+/// while `encoder` and `decoder` return a fresh codec with its own state for each call.
 #[stable]
 pub trait Encoding {
     /// Returns the canonical name of given encoding.
@@ -262,6 +610,65 @@ pub trait Encoding {
     #[unstable]
     fn whatwg_name(&self) -> Option<&'static str> { None }
 
+    /// Returns a coarse list of scripts which given encoding can represent, e.g. for
+    /// a charset picker. This is a static annotation and not computed from the actual tables.
+    /// The list may contain `"Latin"`, `"Greek"`, `"Cyrillic"`, `"Hebrew"`, `"Arabic"`,
+    /// `"Thai"`, `"Hiragana"`, `"Katakana"`, `"Bopomofo"`, `"Hangul"` and `"CJK"`
+    /// (for CJK Unified Ideographs), and it is empty when unknown.
+    #[experimental]
+    fn covered_scripts(&self) -> &'static [&'static str] { NO_SCRIPTS }
+
+    /// Returns true if every character is encoded to exactly one byte,
+    /// so that the encoded bytes can be split anywhere without breaking characters.
+    /// Otherwise `find_safe_truncation` should be used to split them.
+    /// By default this returns false.
+    #[experimental]
+    fn is_single_byte(&self) -> bool { false }
+
+    /// Returns true if the meaning of bytes depends on preceding bytes other than
+    /// the current multibyte sequence, e.g. escape sequences in ISO-2022-JP.
+    /// The encoded bytes of such encodings cannot be decoded independently after splitting.
+    /// By default this returns false.
+    #[experimental]
+    fn is_stateful(&self) -> bool { false }
+
+    /// Returns true if given encoding decodes every input entirely in ASCII
+    /// to the same string as this encoding, i.e. both decoders are ASCII-compatible.
+    /// This does not look at any input; see `encodings_agree_on` for the general comparison.
+    #[experimental]
+    fn agrees_with_on_ascii(&self, other: &Encoding) -> bool {
+        self.decoder().is_ascii_compatible() && other.decoder().is_ascii_compatible()
+    }
+
+    /// Returns the Byte Order Mark (BOM) identifying this encoding,
+    /// i.e. U+FEFF encoded in this encoding (e.g. `EF BB BF` for UTF-8), if any.
+    /// By default this returns `None`.
+    #[experimental]
+    fn bom_bytes(&self) -> Option<&'static [u8]> { None }
+
+    /// Returns the Unicode normalization form the encoder expects, if any.
+    /// Most legacy encodings only have precomposed characters (e.g. `\u00e9` but no `\u0301`),
+    /// so the input should be normalized to NFC before encoding for the best coverage.
+    /// This is only a hint and the encoder never normalizes the input by itself.
+    /// By default this returns `None`, meaning that the normalization doesn't matter
+    /// (e.g. for Unicode encodings) or is unknown.
+    #[experimental]
+    fn assumes_normalization(&self) -> Option<NormalizationForm> { None }
+
+    /// Returns true if the encoder actually encodes the input.
+    /// An encoding which does not support encoding still returns an encoder from `encoder`,
+    /// but it returns an error for every character.
+    /// By default this returns true.
+    #[experimental]
+    fn can_encode_direction(&self) -> bool { true }
+
+    /// Returns true if the decoder actually decodes the input.
+    /// An encoding which does not support decoding (e.g. WHATWG "replacement" encoding)
+    /// still returns a decoder from `decoder`, but it returns an error for every byte.
+    /// By default this returns true.
+    #[experimental]
+    fn can_decode_direction(&self) -> bool { true }
+
     /// Creates a new encoder.
     #[experimental]
     fn encoder(&self) -> Box<Encoder>;
@@ -276,11 +683,23 @@ pub trait Encoding {
     /// or a failure to return the error.
     #[stable]
     fn encode(&self, input: &str, trap: EncoderTrap) -> Result<Vec<u8>,SendStr> {
+        self.encode_with_loss_count(input, trap).map(|(ret, _)| ret)
+    }
+
+    /// Same to `encode` but also returns the number of characters handled by `trap`,
+    /// e.g. the number of characters dropped by `EncodeIgnore`
+    /// or replaced with `?` by `EncodeReplace`.
+    /// This can be used to report the fidelity of a lossy conversion.
+    /// The count is always zero on success with `EncodeStrict`.
+    #[experimental]
+    fn encode_with_loss_count(&self, input: &str,
+                              trap: EncoderTrap) -> Result<(Vec<u8>, uint),SendStr> {
         // we don't need to keep `unprocessed` here;
         // `raw_feed` should process as much input as possible.
         let mut encoder = self.encoder();
         let mut remaining = 0;
         let mut ret = Vec::new();
+        let mut losses = 0;
 
         loop {
             let (offset, err) = encoder.raw_feed(input.slice_from(remaining), &mut ret);
@@ -288,128 +707,881 @@ pub trait Encoding {
             match err {
                 Some(err) => {
                     remaining = (remaining as int + err.upto) as uint;
-                    if !trap.trap(encoder, input.slice(unprocessed, remaining), &mut ret) {
+                    let problem = input.slice(unprocessed, remaining);
+                    if !trap.trap(encoder, problem, &mut ret) {
                         return Err(err.cause);
                     }
+                    losses += problem.char_len();
                 }
                 None => {
                     remaining = input.len();
                     match encoder.raw_finish(&mut ret) {
                         Some(err) => {
                             remaining = (remaining as int + err.upto) as uint;
-                            if !trap.trap(encoder, input.slice(unprocessed, remaining), &mut ret) {
+                            let problem = input.slice(unprocessed, remaining);
+                            if !trap.trap(encoder, problem, &mut ret) {
                                 return Err(err.cause);
                             }
+                            losses += problem.char_len();
                         }
                         None => {}
                     }
-                    if remaining >= input.len() { return Ok(ret); }
+                    if remaining >= input.len() { return Ok((ret, losses)); }
                 }
             }
         }
     }
 
-    /// An easy-to-use interface to `Decoder`.
-    /// On the decoder error `trap` is called,
-    /// which may return a replacement string to continue processing,
-    /// or a failure to return the error.
-    #[stable]
-    fn decode(&self, input: &[u8], trap: DecoderTrap) -> Result<String,SendStr> {
-        // we don't need to keep `unprocessed` here;
-        // `raw_feed` should process as much input as possible.
-        let mut decoder = self.decoder();
-        let mut remaining = 0;
-        let mut ret = String::new();
+    /// Same to `encode` but stops before the output exceeds `max_bytes` bytes,
+    /// e.g. for a fixed-width database column.
+    /// Returns the encoded bytes and true if the input had to be truncated.
+    /// The output always ends at a character boundary and includes any sequence
+    /// the encoder emits at the end (e.g. `ESC ( B` in ISO-2022-JP) within the limit.
+    /// Replacements from `trap` are counted as the output of the character they replace.
+    ///
+    /// Each character is tried on a copy of the encoder,
+    /// so this is only suitable for reasonably short strings.
+    #[experimental]
+    fn encode_truncated(&self, input: &str, max_bytes: uint,
+                        trap: EncoderTrap) -> Result<(Vec<u8>, bool),SendStr> {
+        let mut encoder = self.encoder();
+        let mut ret = Vec::new();
+        let mut buf = Vec::new();
+        let mut truncated = false;
+
+        for ((i, j), _) in input.index_iter() {
+            let ch = input.slice(i, j);
+            let mut next = encoder.clone_box();
+            buf.truncate(0);
+            match next.raw_feed(ch, &mut buf) {
+                (_, Some(err)) => {
+                    if !trap.trap(next, ch, &mut buf) { return Err(err.cause); }
+                }
+                (_, None) => {}
+            }
+
+            // the output should be finishable right after this character
+            let mut closing = CountingByteWriter { len: 0 };
+            let _ = next.clone_box().raw_finish(&mut closing);
+            if ret.len() + buf.len() + closing.len > max_bytes {
+                truncated = true;
+                break;
+            }
+            ret.push_all(buf.as_slice());
+            encoder = next;
+        }
+
+        match encoder.raw_finish(&mut ret) {
+            Some(err) => {
+                if !trap.trap(encoder, "", &mut ret) { return Err(err.cause); }
+            }
+            None => {}
+        }
+        Ok((ret, truncated))
+    }
 
+    /// Checks if given string can be encoded without any loss, without producing any output.
+    /// Returns a report with the number of bytes the representable characters would use
+    /// and the byte offset and the character of every unrepresentable character.
+    /// Nothing is allocated when every character is representable.
+    #[experimental]
+    fn encode_report(&self, input: &str) -> EncodeReport {
+        let mut encoder = self.encoder();
+        let mut output = CountingByteWriter { len: 0 };
+        let mut failures = Vec::new();
+        let mut remaining = 0;
         loop {
-            let (offset, err) = decoder.raw_feed(input.slice_from(remaining), &mut ret);
+            let (offset, err) = encoder.raw_feed(input.slice_from(remaining), &mut output);
             let unprocessed = remaining + offset;
             match err {
                 Some(err) => {
                     remaining = (remaining as int + err.upto) as uint;
-                    if !trap.trap(decoder, input.slice(unprocessed, remaining), &mut ret) {
-                        return Err(err.cause);
+                    for (i, ch) in input.slice(unprocessed, remaining).char_indices() {
+                        failures.push((unprocessed + i, ch));
                     }
                 }
                 None => {
                     remaining = input.len();
-                    match decoder.raw_finish(&mut ret) {
+                    match encoder.raw_finish(&mut output) {
                         Some(err) => {
                             remaining = (remaining as int + err.upto) as uint;
-                            if !trap.trap(decoder, input.slice(unprocessed, remaining), &mut ret) {
-                                return Err(err.cause);
+                            for (i, ch) in input.slice(unprocessed, remaining).char_indices() {
+                                failures.push((unprocessed + i, ch));
                             }
                         }
                         None => {}
                     }
-                    if remaining >= input.len() { return Ok(ret); }
+                    if remaining >= input.len() { break; }
                 }
             }
         }
+        EncodeReport { nbytes: output.len, failures: failures }
     }
-}
 
-/// A type of the bare function in `EncoderTrap` values.
-#[unstable]
-pub type EncoderTrapFunc =
-    extern "Rust" fn(encoder: &mut Encoder, input: &str, output: &mut ByteWriter) -> bool;
+    /// Same to `encode` but encodes UTF-16 code units (e.g. from Windows APIs or JavaScript).
+    /// Unlike `&str`, the input may contain unpaired surrogates, which are always rejected
+    /// regardless of `trap` with the cause "unpaired surrogate".
+    /// The `upto` field of the error is an offset to the code unit right after
+    /// the offending unit or the unrepresentable character.
+    #[experimental]
+    fn encode_utf16(&self, input: &[u16], trap: EncoderTrap) -> Result<Vec<u8>,CodecError> {
+        let input = try!(utf16_units_to_string(input));
+        let input = input.as_slice();
+        let units_upto = |upto: uint| -> int {
+            input.slice_to(upto).chars().map(|c| if (c as u32) < 0x10000 {1} else {2})
+                                .fold(0, |a, b| a + b)
+        };
 
-/// A type of the bare function in `DecoderTrap` values.
-#[unstable]
-pub type DecoderTrapFunc =
-    extern "Rust" fn(decoder: &mut Decoder, input: &[u8], output: &mut StringWriter) -> bool;
+        let mut encoder = self.encoder();
+        let mut remaining = 0;
+        let mut ret = Vec::new();
+        loop {
+            let (offset, err) = encoder.raw_feed(input.slice_from(remaining), &mut ret);
+            let unprocessed = remaining + offset;
+            let err = match err {
+                Some(err) => err,
+                None => {
+                    remaining = input.len();
+                    match encoder.raw_finish(&mut ret) {
+                        Some(err) => err,
+                        None => return Ok(ret),
+                    }
+                }
+            };
+            remaining = (remaining as int + err.upto) as uint;
+            if !trap.trap(encoder, input.slice(unprocessed, remaining), &mut ret) {
+                return Err(CodecError { upto: units_upto(remaining), ..err });
+            }
+            if remaining >= input.len() { return Ok(ret); }
+        }
+    }
 
-/// Trap, which handles decoder errors.
-#[stable]
-pub enum DecoderTrap {
-    /// Immediately fails on errors.
-    /// Corresponds to WHATWG "fatal" error algorithm.
-    DecodeStrict,
-    /// Replaces an error with a U+FFFD (decoder).
-    /// Corresponds to WHATWG "replacement" error algorithm.
-    DecodeReplace,
-    /// Silently ignores an error, effectively replacing it with an empty sequence.
-    DecodeIgnore,
-    /// Calls given function to handle decoder errors.
-    /// The function is given the current decoder, input and output writer,
-    /// and should return true only when it is fine to keep going.
-    #[unstable] DecoderTrap(DecoderTrapFunc),
-}
+    /// Same to `encode` but prepends a Byte Order Mark (BOM) if `emit_bom` is true.
+    /// The BOM is U+FEFF encoded in this encoding (e.g. `EF BB BF` for UTF-8),
+    /// and `emit_bom` is ignored for encodings without a BOM (see `bom_bytes`).
+    /// Encoders themselves never emit the BOM.
+    #[experimental]
+    fn encode_with_bom(&self, input: &str, emit_bom: bool,
+                       trap: EncoderTrap) -> Result<Vec<u8>,SendStr> {
+        match self.bom_bytes() {
+            Some(bom) if emit_bom => {
+                let mut ret = Vec::from_slice(bom);
+                ret.push_all(try!(self.encode(input, trap)).as_slice());
+                Ok(ret)
+            }
+            _ => self.encode(input, trap),
+        }
+    }
 
-impl DecoderTrap {
-    /// Handles a decoder error. May write to the output writer.
-    /// Returns true only when it is fine to keep going.
-    fn trap(&self, decoder: &mut Decoder, input: &[u8], output: &mut StringWriter) -> bool {
-        match *self {
-            DecodeStrict => false,
-            DecodeReplace => { output.write_char('\ufffd'); true },
-            DecodeIgnore => true,
-            DecoderTrap(func) => func(decoder, input, output),
+    /// An easy-to-use interface to `Decoder`.
+    /// On the decoder error `trap` is called,
+    /// which may return a replacement string to continue processing,
+    /// or a failure to return the error.
+    /// If the decoder is ASCII compatible and the input is entirely in ASCII,
+    /// the input is copied to the result as is without running the decoder.
+    #[stable]
+    fn decode(&self, input: &[u8], trap: DecoderTrap) -> Result<String,SendStr> {
+        let decoder = self.decoder();
+        if decoder.is_ascii_compatible() && input.iter().all(|&b| b < 0x80) {
+            // ASCII is also valid in UTF-8
+            let input: &str = unsafe { mem::transmute(input) };
+            return Ok(input.to_string());
+        }
+
+        let mut ret = String::new();
+        match decode_with_decoder(decoder, input, trap, false, &mut ret) {
+            Ok(()) => Ok(ret),
+            Err(err) => Err(err),
         }
     }
-}
 
-#[stable]
-pub enum EncoderTrap {
-    /// Immediately fails on errors.
-    /// Corresponds to WHATWG "fatal" error algorithm.
-    EncodeStrict,
-    /// Replaces an error with `?` in given encoding.
-    /// Note that this fails when `?` cannot be represented in given encoding.
-    /// Corresponds to WHATWG "URL" error algorithms.
-    EncodeReplace,
-    /// Silently ignores an error, effectively replacing it with an empty sequence.
-    EncodeIgnore,
-    /// Replaces an error with XML numeric character references (e.g. `&#1234;`).
-    /// The encoder trap fails when NCRs cannot be represented in given encoding.
+    /// Same to `decode` but writes the result to given string writer,
+    /// which may be a buffer reused across calls (see also `StringPool`).
+    /// The writer may contain a partial result on the failure.
+    #[experimental]
+    fn decode_to(&self, input: &[u8], trap: DecoderTrap,
+                 ret: &mut StringWriter) -> Result<(),SendStr> {
+        decode_with_decoder(self.decoder(), input, trap, false, ret)
+    }
+
+    /// Same to `decode` but returns a vector of characters,
+    /// which are collected directly without an intermediate UTF-8 string.
+    #[experimental]
+    fn decode_to_chars(&self, input: &[u8], trap: DecoderTrap) -> Result<Vec<char>,SendStr> {
+        let mut ret = Vec::new();
+        match decode_with_decoder(self.decoder(), input, trap, false, &mut ret) {
+            Ok(()) => Ok(ret),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Same to `decode` but, if `coalesce_errors` is true,
+    /// the trap is called only once for a run of consecutive problematic sequences
+    /// instead of once per sequence.
+    /// For example, several invalid bytes in a row result in a single U+FFFD
+    /// with `DecodeReplace`.
+    #[experimental]
+    fn decode_coalescing(&self, input: &[u8], trap: DecoderTrap,
+                         coalesce_errors: bool) -> Result<String,SendStr> {
+        let mut ret = String::new();
+        match decode_with_decoder(self.decoder(), input, trap, coalesce_errors, &mut ret) {
+            Ok(()) => Ok(ret),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Returns true if given byte sequence can be decoded without any error,
+    /// including an incomplete sequence at the end of input.
+    /// The decoded string is discarded without being allocated.
+    #[experimental]
+    fn is_valid(&self, input: &[u8]) -> bool {
+        let mut decoder = self.decoder();
+        let mut output = NullStringWriter::new();
+        decoder.feed_all(input, &mut output).is_none()
+    }
+
+    /// Checks if given byte sequence can be decoded without any error,
+    /// including an incomplete sequence at the end of input, without producing any output.
+    /// Returns the length of `input` if it is valid,
+    /// or the first error whose `upto` is relative to `input` otherwise.
+    /// By default this runs the decoder with a `NullStringWriter`;
+    /// encodings can override this with a loop which never touches a writer.
+    #[experimental]
+    fn validate(&self, input: &[u8]) -> Result<uint, CodecError> {
+        let mut decoder = self.decoder();
+        let mut output = NullStringWriter::new();
+        match decoder.feed_all(input, &mut output) {
+            Some(err) => Err(err),
+            None => Ok(input.len()),
+        }
+    }
+
+    /// Returns the largest length not exceeding `max_len`, such that
+    /// truncating `input` to that length does not split any multibyte sequence.
+    /// It is the input length when `max_len` is no less than that.
+    /// Invalid sequences are treated as complete sequences.
+    /// This is useful for writing the encoded data into fixed-size records.
+    #[experimental]
+    fn find_safe_truncation(&self, input: &[u8], max_len: uint) -> uint {
+        if max_len >= input.len() { return input.len(); }
+
+        let input = input.slice_to(max_len);
+        let mut decoder = self.decoder();
+        let mut output = NullStringWriter::new();
+        let mut remaining = 0;
+        loop {
+            // the decoder always starts at the sequence boundary here,
+            // so the first unprocessed byte is never before `remaining`.
+            let (offset, err) = decoder.raw_feed(input.slice_from(remaining), &mut output);
+            match err {
+                Some(err) => {
+                    remaining = (remaining as int + err.upto) as uint;
+                }
+                None => {
+                    return remaining + offset;
+                }
+            }
+        }
+    }
+
+    /// Splits `input` into consecutive chunks of about `approx_chunk` bytes
+    /// which can be decoded independently, and returns the end offset of each chunk.
+    /// The last offset is always `input.len()`, so there is at least one chunk.
+    /// Chunks never end in the middle of a multibyte sequence (as in `find_safe_truncation`)
+    /// and may be longer than `approx_chunk` only when a single sequence is.
+    ///
+    /// Stateful encodings (where `is_stateful` is true, e.g. ISO-2022-JP) are not supported
+    /// and always result in a single chunk, as the state at each offset cannot be known
+    /// without decoding everything before it.
+    #[experimental]
+    fn split_at_boundaries(&self, input: &[u8], approx_chunk: uint) -> Vec<uint> {
+        let mut splits = Vec::new();
+        if !self.is_stateful() && approx_chunk > 0 {
+            let mut start = 0;
+            while input.len() - start > approx_chunk {
+                let remaining = input.slice_from(start);
+                let mut len = if self.is_single_byte() {
+                    approx_chunk
+                } else {
+                    self.find_safe_truncation(remaining, approx_chunk)
+                };
+                // the chunk is shorter than the first sequence, so extend it to the whole sequence
+                let mut max_len = approx_chunk;
+                while len == 0 {
+                    max_len += 1;
+                    len = self.find_safe_truncation(remaining, max_len);
+                }
+                start += len;
+                if start == input.len() { break; }
+                splits.push(start);
+            }
+        }
+        splits.push(input.len());
+        splits
+    }
+
+    /// Decodes given byte sequence until the first error, without any trap.
+    /// Returns the decoded prefix and the error if any.
+    /// The prefix ends right before the problematic sequence, and
+    /// the `upto` field of the error is an offset to the input where the caller may resume.
+    /// An incomplete sequence at the end of input is also reported as an error.
+    #[experimental]
+    fn decode_partial(&self, input: &[u8]) -> (String, Option<CodecError>) {
+        let mut decoder = self.decoder();
+        let mut ret = String::new();
+        let err = decoder.feed_all(input, &mut ret);
+        (ret, err)
+    }
+
+    /// Same to `decode_partial` but returns the decoded string only on success,
+    /// so that it can be directly unwrapped.
+    /// On the error, returns the decoded prefix along with the error.
+    #[experimental]
+    fn decode_strict(&self, input: &[u8]) -> Result<String, (String, CodecError)> {
+        match self.decode_partial(input) {
+            (ret, None) => Ok(ret),
+            (ret, Some(err)) => Err((ret, err)),
+        }
+    }
+
+    /// Decodes the entire input, skipping every problematic sequence as in `DecodeIgnore`,
+    /// and returns the recovered string with all errors encountered.
+    /// Unlike the errors from `raw_feed`, the `upto` field of each returned error is
+    /// an offset to the whole `input`, pointing right after the problematic sequence.
+    /// This is useful for reporting every invalid sequence in one pass.
+    #[experimental]
+    fn decode_collecting_errors(&self, input: &[u8]) -> (String, Vec<CodecError>) {
+        let mut decoder = self.decoder();
+        let mut ret = String::new();
+        let mut errors = Vec::new();
+        let mut remaining = 0;
+        loop {
+            let (_, err) = decoder.raw_feed(input.slice_from(remaining), &mut ret);
+            match err {
+                Some(err) => {
+                    remaining = (remaining as int + err.upto) as uint;
+                    errors.push(CodecError { upto: remaining as int, ..err });
+                }
+                None => {
+                    match decoder.raw_finish(&mut ret) {
+                        Some(err) => {
+                            remaining = (input.len() as int + err.upto) as uint;
+                            errors.push(CodecError { upto: remaining as int, ..err });
+                            if remaining >= input.len() { break; }
+                        }
+                        None => { break; }
+                    }
+                }
+            }
+        }
+        (ret, errors)
+    }
+
+    /// Returns an iterator which lazily decodes given input and yields one line at a time.
+    /// The lines are split by U+000A after decoding, so a byte 0A in the multibyte sequence
+    /// (e.g. in UTF-16) doesn't split the line. The line terminator is not included,
+    /// and the final line is yielded even when it doesn't end with the terminator.
+    /// Any decoder error is replaced with U+FFFD as in `DecodeReplace`.
+    #[experimental]
+    fn decode_lines<'a>(&self, input: &'a [u8]) -> LineIter<'a> {
+        LineIter { decoder: self.decoder(), input: input, pos: 0,
+                   buf: String::new(), scanned: 0, done: false }
+    }
+
+    /// Same to `decode` but also returns, for each decoded character,
+    /// the byte offset in `input` where the sequence producing that character began.
+    /// Characters produced by the same sequence (or by the trap for that sequence)
+    /// share the same offset.
+    /// Runs of bytes which are known to be one character each
+    /// (the whole input in single-byte encodings, ASCII bytes in ASCII-compatible encodings)
+    /// are fed at once, but other bytes are fed one at a time
+    /// since the offsets of characters inside a multibyte sequence cannot be observed otherwise.
+    /// It also allocates the offset vector,
+    /// so it is slower than `decode` and should be used only when offsets are needed.
+    #[experimental]
+    fn decode_with_offsets(&self, input: &[u8], trap: DecoderTrap)
+                          -> Result<(String, Vec<uint>), SendStr> {
+        let mut decoder = self.decoder();
+        let single_byte = self.is_single_byte();
+        let ascii_compatible = decoder.is_ascii_compatible();
+        let mut ret = String::new();
+        let mut offsets = Vec::new();
+        {
+            let mut output = OffsetRecordingWriter { inner: &mut ret, offsets: &mut offsets,
+                                                     offset: 0, consecutive: false };
+            let mut remaining = 0;
+            loop {
+                if remaining < input.len() {
+                    // no sequence is pending when the offset has caught up with the input
+                    let end = if output.offset < remaining {
+                        remaining + 1
+                    } else if single_byte {
+                        input.len()
+                    } else if ascii_compatible {
+                        input.slice_from(remaining).iter().position(|&b| b >= 0x80)
+                             .map_or(input.len(), |i| cmp::max(remaining + i, remaining + 1))
+                    } else {
+                        remaining + 1
+                    };
+
+                    // each character in the run advances the offset by one byte
+                    output.consecutive = end > remaining + 1;
+                    let (offset, err) = decoder.raw_feed(input.slice(remaining, end),
+                                                         &mut output);
+                    if !output.consecutive && offset > 0 { output.offset = remaining + offset; }
+                    output.consecutive = false;
+                    match err {
+                        Some(err) => {
+                            remaining = (remaining as int + err.upto) as uint;
+                            if !trap.trap(decoder, input.slice(output.offset, remaining),
+                                          &mut output) {
+                                return Err(err.cause);
+                            }
+                            output.offset = remaining;
+                        }
+                        None => { remaining = end; }
+                    }
+                } else {
+                    match decoder.raw_finish(&mut output) {
+                        Some(err) => {
+                            remaining = (input.len() as int + err.upto) as uint;
+                            if !trap.trap(decoder, input.slice(output.offset, remaining),
+                                          &mut output) {
+                                return Err(err.cause);
+                            }
+                            output.offset = remaining;
+                            if remaining >= input.len() { break; }
+                        }
+                        None => { break; }
+                    }
+                }
+            }
+        }
+        Ok((ret, offsets))
+    }
+
+    /// Same to `decode` with `DecodeStrict` but, on the error, also returns
+    /// the line and column of the problematic sequence counted in decoded characters.
+    /// This is useful for reporting the error in the source code in legacy encodings,
+    /// and costs an additional scan over the decoded string on the error.
+    #[experimental]
+    fn decode_with_positions(&self, input: &[u8]) -> Result<String, PositionedDecodeError> {
+        let mut decoder = self.decoder();
+        let mut ret = String::new();
+        let (offset, err) = decoder.raw_feed(input, &mut ret);
+        let err = match err {
+            Some(err) => err,
+            None => match decoder.raw_finish(&mut ret) {
+                Some(err) => err,
+                None => { return Ok(ret); }
+            },
+        };
+
+        // the output ends right before the problematic sequence
+        let s = ret.as_slice();
+        let (line, linestart) = match s.rfind('\n') {
+            Some(i) => (s.slice_to(i).chars().filter(|&c| c == '\n').count() + 2, i + 1),
+            None => (1, 0),
+        };
+        Err(PositionedDecodeError { offset: offset, line: line,
+                                    column: s.slice_from(linestart).char_len() + 1,
+                                    cause: err.cause })
+    }
+}
+
+/// A table-driven mapping between pointers (e.g. bytes or index offsets) and characters,
+/// as used by the single-byte and double-byte encodings.
+/// Unlike the raw index functions, where unmapped entries are denoted by sentinels
+/// (e.g. `0xffff` in forward tables and `0` in single-byte backward tables),
+/// this always returns `None` for them.
+#[experimental]
+pub trait IndexableEncoding<Ptr> {
+    /// Returns the character for given pointer, or `None` if the pointer is unmapped.
+    fn forward(&self, ptr: Ptr) -> Option<char>;
+
+    /// Returns the pointer for given character, or `None` if the character is unmapped.
+    fn backward(&self, ch: char) -> Option<Ptr>;
+}
+
+/// The result of `Encoding::encode_report`.
+#[experimental]
+#[deriving(Clone, PartialEq, Show)]
+pub struct EncodeReport {
+    /// The number of bytes which the representable characters would be encoded to.
+    pub nbytes: uint,
+    /// The byte offset to the input and the character of every unrepresentable character.
+    pub failures: Vec<(uint, char)>,
+}
+
+impl EncodeReport {
+    /// Returns true if every character in the input is representable.
+    pub fn is_fully_representable(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// The error from `Encoding::decode_with_positions`.
+#[experimental]
+#[deriving(Clone, PartialEq, Show)]
+pub struct PositionedDecodeError {
+    /// The byte offset to the input where the problematic sequence starts.
+    pub offset: uint,
+    /// The line number of the problematic sequence, starting from 1.
+    /// Lines are separated by U+000A in the decoded string.
+    pub line: uint,
+    /// The column number of the problematic sequence in characters, starting from 1.
+    pub column: uint,
+    /// A human-readable cause of the error.
+    pub cause: SendStr,
+}
+
+/// The number of input bytes `LineIter` feeds to the decoder at once.
+static LINE_ITER_CHUNK_SIZE: uint = 256;
+
+/// An iterator which lazily decodes the input and yields one line at a time.
+/// Created by `Encoding::decode_lines`.
+#[experimental]
+pub struct LineIter<'a> {
+    decoder: Box<Decoder>,
+    input: &'a [u8],
+    pos: uint,
+    buf: String,
+    scanned: uint, // the prefix of `buf` known to have no newline
+    done: bool,
+}
+
+impl<'a> LineIter<'a> {
+    /// Decodes the next chunk of input into the buffer, or finishes the decoder.
+    /// The decoder keeps its state between chunks.
+    fn feed(&mut self) {
+        let len = self.input.len();
+        if self.pos < len {
+            let end = cmp::min(self.pos + LINE_ITER_CHUNK_SIZE, len);
+            let (_, err) = self.decoder.raw_feed(self.input.slice(self.pos, end), &mut self.buf);
+            match err {
+                Some(err) => {
+                    self.buf.push_char('\ufffd');
+                    self.pos = (self.pos as int + err.upto) as uint;
+                }
+                None => { self.pos = end; }
+            }
+        } else {
+            match self.decoder.raw_finish(&mut self.buf) {
+                Some(err) => {
+                    self.buf.push_char('\ufffd');
+                    self.pos = (len as int + err.upto) as uint;
+                }
+                None => {}
+            }
+            if self.pos >= len { self.done = true; }
+        }
+    }
+}
+
+impl<'a> Iterator<String> for LineIter<'a> {
+    fn next(&mut self) -> Option<String> {
+        loop {
+            match self.buf.as_slice().slice_from(self.scanned).find('\n') {
+                Some(i) => {
+                    let i = self.scanned + i;
+                    self.scanned = 0;
+                    let rest = self.buf.as_slice().slice_from(i + 1).to_string();
+                    let mut line = mem::replace(&mut self.buf, rest);
+                    line.truncate(i);
+                    return Some(line);
+                }
+                None => { self.scanned = self.buf.len(); }
+            }
+            if self.done {
+                if self.buf.is_empty() { return None; }
+                self.scanned = 0;
+                return Some(mem::replace(&mut self.buf, String::new()));
+            }
+            self.feed();
+        }
+    }
+}
+
+/// Converts UTF-16 code units to a string, rejecting any unpaired surrogate.
+/// The error points to the code unit right after the offending unit.
+fn utf16_units_to_string(input: &[u16]) -> Result<String,CodecError> {
+    let mut ret = String::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        let u = input[i] as u32;
+        let ch = match u {
+            0xd800..0xdbff => match input.get(i + 1) {
+                Some(&v) if 0xdc00 <= v && v <= 0xdfff => {
+                    i += 1;
+                    0x10000 + ((u - 0xd800) << 10) + (v as u32 - 0xdc00)
+                }
+                _ => 0xffffffff,
+            },
+            0xdc00..0xdfff => 0xffffffff,
+            _ => u,
+        };
+        i += 1;
+        match char::from_u32(ch) {
+            Some(ch) => ret.push_char(ch),
+            None => return Err(CodecError { upto: i as int,
+                                            cause: "unpaired surrogate".into_maybe_owned(),
+                                            buffered: 0 }),
+        }
+    }
+    Ok(ret)
+}
+
+/// Decodes the entire input with given decoder, and calls the trap on errors.
+/// If `coalesce_errors` is true, the trap is not called for an error
+/// which immediately follows the previous error.
+fn decode_with_decoder(mut decoder: Box<Decoder>, input: &[u8], trap: DecoderTrap,
+                       coalesce_errors: bool, ret: &mut StringWriter) -> Result<(),SendStr> {
+    // we don't need to keep `unprocessed` here;
+    // `raw_feed` should process as much input as possible.
+    let mut remaining = 0;
+    // the end of the last problematic sequence, used to coalesce consecutive errors
+    let mut last_error_end = None;
+
+    loop {
+        let (offset, err) = decoder.raw_feed(input.slice_from(remaining), ret);
+        let unprocessed = remaining + offset;
+        match err {
+            Some(err) => {
+                remaining = (remaining as int + err.upto) as uint;
+                let coalesced = coalesce_errors && last_error_end == Some(unprocessed);
+                if !coalesced && !trap.trap(decoder, input.slice(unprocessed, remaining), ret) {
+                    return Err(err.cause);
+                }
+                last_error_end = Some(remaining);
+            }
+            None => {
+                remaining = input.len();
+                match decoder.raw_finish(ret) {
+                    Some(err) => {
+                        remaining = (remaining as int + err.upto) as uint;
+                        let coalesced = coalesce_errors && last_error_end == Some(unprocessed);
+                        if !coalesced &&
+                           !trap.trap(decoder, input.slice(unprocessed, remaining), ret) {
+                            return Err(err.cause);
+                        }
+                        last_error_end = Some(remaining);
+                    }
+                    None => {}
+                }
+                if remaining >= input.len() { return Ok(()); }
+            }
+        }
+    }
+}
+
+/// A type of the bare function in `EncoderTrap` values.
+#[unstable]
+pub type EncoderTrapFunc =
+    extern "Rust" fn(encoder: &mut Encoder, input: &str, output: &mut ByteWriter) -> bool;
+
+/// A type of the bare function in `DecoderTrap` values.
+#[unstable]
+pub type DecoderTrapFunc =
+    extern "Rust" fn(decoder: &mut Decoder, input: &[u8], output: &mut StringWriter) -> bool;
+
+/// Trap, which handles decoder errors.
+#[stable]
+pub enum DecoderTrap {
+    /// Immediately fails on errors.
+    /// Corresponds to WHATWG "fatal" error algorithm.
+    DecodeStrict,
+    /// Replaces an error with a U+FFFD (decoder).
+    /// Corresponds to WHATWG "replacement" error algorithm.
+    DecodeReplace,
+    /// Silently ignores an error, effectively replacing it with an empty sequence.
+    DecodeIgnore,
+    /// Replaces an error with given string, which is inserted verbatim for each error.
+    /// An empty string behaves same to `DecodeIgnore`.
+    #[experimental] DecodeReplaceWith(String),
+    /// Replaces each byte of an error with a Private Use Area character U+F700 plus the byte
+    /// (e.g. `80` becomes U+F780, as in `x-user-defined`),
+    /// so that `PreservedBytesEncoding` can restore the original bytes.
+    #[experimental] DecodePreserveBytes,
+    /// Calls given function to handle decoder errors.
+    /// The function is given the current decoder, input and output writer,
+    /// and should return true only when it is fine to keep going.
+    #[unstable] DecoderTrap(DecoderTrapFunc),
+}
+
+impl DecoderTrap {
+    /// Handles a decoder error. May write to the output writer.
+    /// Returns true only when it is fine to keep going.
+    fn trap(&self, decoder: &mut Decoder, input: &[u8], output: &mut StringWriter) -> bool {
+        match *self {
+            DecodeStrict => false,
+            DecodeReplace => { output.write_char('\ufffd'); true },
+            DecodeIgnore => true,
+            DecodeReplaceWith(ref s) => { output.write_str(s.as_slice()); true },
+            DecodePreserveBytes => {
+                for &b in input.iter() { output.write_char(preserved_byte_to_char(b)); }
+                true
+            },
+            DecoderTrap(func) => func(decoder, input, output),
+        }
+    }
+}
+
+/// Maps a byte preserved by `DecodePreserveBytes` to the Private Use Area.
+fn preserved_byte_to_char(b: u8) -> char {
+    char::from_u32(0xf700 + b as u32).unwrap()
+}
+
+/// Maps a character back to the byte preserved by `DecodePreserveBytes`, if any.
+fn char_to_preserved_byte(ch: char) -> Option<u8> {
+    if (ch as u32) & !0xff == 0xf700 { Some(ch as u8) } else { None }
+}
+
+/**
+ * An encoding adapter which restores the bytes preserved by the `DecodePreserveBytes` trap,
+ * so that invalid input can round-trip through Unicode text without any loss.
+ *
+ * The encoder writes U+F700 through U+F7FF as the corresponding byte `00` through `FF`
+ * as is and passes other characters to the base encoder.
+ * The base encoder is finished before each such byte, so any buffered output
+ * (e.g. a character waiting for a combining mark) comes first
+ * and a stateful encoding (e.g. ISO-2022-JP) returns to its initial mode.
+ * Therefore these Private Use Area characters cannot be encoded otherwise,
+ * even when the base encoding can represent them (e.g. UTF-8).
+ * The decoder is same to that of the base encoding.
+ */
+#[experimental]
+pub struct PreservedBytesEncoding {
+    /// An encoding to encode other characters.
+    pub base: EncodingRef,
+}
+
+impl Encoding for PreservedBytesEncoding {
+    fn name(&self) -> &'static str { self.base.name() }
+    fn whatwg_name(&self) -> Option<&'static str> { self.base.whatwg_name() }
+    fn covered_scripts(&self) -> &'static [&'static str] { self.base.covered_scripts() }
+    fn is_single_byte(&self) -> bool { self.base.is_single_byte() }
+    fn is_stateful(&self) -> bool { self.base.is_stateful() }
+    fn bom_bytes(&self) -> Option<&'static [u8]> { self.base.bom_bytes() }
+    fn encoder(&self) -> Box<Encoder> { PreservedBytesEncoder::new(self.base.encoder()) }
+    fn decoder(&self) -> Box<Decoder> { self.base.decoder() }
+}
+
+/// An encoder adapter which restores the bytes preserved by the `DecodePreserveBytes` trap.
+#[experimental]
+pub struct PreservedBytesEncoder {
+    inner: Box<Encoder>,
+}
+
+impl PreservedBytesEncoder {
+    pub fn new(inner: Box<Encoder>) -> Box<Encoder> {
+        box PreservedBytesEncoder { inner: inner } as Box<Encoder>
+    }
+}
+
+impl Encoder for PreservedBytesEncoder {
+    fn from_self(&self) -> Box<Encoder> { PreservedBytesEncoder::new(self.inner.from_self()) }
+    fn clone_box(&self) -> Box<Encoder> { PreservedBytesEncoder::new(self.inner.clone_box()) }
+    fn is_ascii_compatible(&self) -> bool { self.inner.is_ascii_compatible() }
+
+    fn raw_feed(&mut self, input: &str, output: &mut ByteWriter) -> (uint, Option<CodecError>) {
+        let mut run = 0; // the start of characters to be passed to the inner encoder
+        for ((i,j), ch) in input.index_iter() {
+            match char_to_preserved_byte(ch) {
+                Some(b) => {
+                    if run < i {
+                        let (offset, err) = self.inner.raw_feed(input.slice(run, i), output);
+                        match err {
+                            Some(err) => {
+                                return (run + offset, Some(CodecError {
+                                    upto: run as int + err.upto, cause: err.cause,
+                                    buffered: err.buffered
+                                }));
+                            }
+                            None => {}
+                        }
+                    }
+                    match self.inner.raw_finish(output) {
+                        Some(err) => {
+                            return (run, Some(CodecError {
+                                upto: i as int + err.upto, cause: err.cause,
+                                buffered: err.buffered
+                            }));
+                        }
+                        None => {}
+                    }
+                    output.write_byte(b);
+                    run = j;
+                }
+                None => {}
+            }
+        }
+
+        let (offset, err) = self.inner.raw_feed(input.slice_from(run), output);
+        (run + offset, err.map(|err| CodecError {
+            upto: run as int + err.upto, cause: err.cause, buffered: err.buffered
+        }))
+    }
+
+    fn raw_finish(&mut self, output: &mut ByteWriter) -> Option<CodecError> {
+        self.inner.raw_finish(output)
+    }
+}
+
+#[stable]
+pub enum EncoderTrap {
+    /// Immediately fails on errors.
+    /// Corresponds to WHATWG "fatal" error algorithm.
+    EncodeStrict,
+    /// Replaces an error with `?` in given encoding.
+    /// Note that this fails when `?` cannot be represented in given encoding.
+    /// Corresponds to WHATWG "URL" error algorithms.
+    EncodeReplace,
+    /// Silently ignores an error, effectively replacing it with an empty sequence.
+    EncodeIgnore,
+    /// Replaces an error with XML numeric character references (e.g. `&#1234;`).
+    /// The encoder trap fails when NCRs cannot be represented in given encoding.
     /// Corresponds to WHATWG "<form>" error algorithms.
     EncodeNcrEscape,
+    /// Replaces each unrepresentable character with given byte (e.g. `0x20` for a space),
+    /// so that fixed-width text keeps its column alignment.
+    /// The byte is written as is without going through the encoder,
+    /// so it should be meaningful in the current state of a stateful encoding.
+    #[experimental] EncodeReplaceWithByte(u8),
     /// Calls given function to handle encoder errors.
     /// The function is given the current encoder, input and output writer,
     /// and should return true only when it is fine to keep going.
     #[unstable] EncoderTrap(EncoderTrapFunc),
 }
 
+/// The default return value of `Encoding::covered_scripts`.
+static NO_SCRIPTS: &'static [&'static str] = &[];
+
+/// A string to replace any unrepresentable character with the `EncodeReplace` trap.
+static REPLACEMENT_STR: &'static str = "?";
+
+/// Formats an XML numeric character reference (e.g. `&#1234;`) for given character
+/// into given buffer without any allocation, and returns the formatted slice.
+/// The buffer is large enough for the longest reference, `&#1114111;`.
+fn format_ncr<'a>(ch: char, buf: &'a mut [u8, ..12]) -> &'a [u8] {
+    let mut digits = [0u8, ..7];
+    let mut ndigits = 0;
+    let mut code = ch as u32;
+    loop {
+        digits[ndigits] = '0' as u8 + (code % 10) as u8;
+        ndigits += 1;
+        code /= 10;
+        if code == 0 { break; }
+    }
+
+    buf[0] = '&' as u8;
+    buf[1] = '#' as u8;
+    for i in range(0, ndigits) {
+        buf[2 + i] = digits[ndigits - 1 - i];
+    }
+    buf[2 + ndigits] = ';' as u8;
+    buf.slice_to(3 + ndigits)
+}
+
 impl EncoderTrap {
     /// Handles an encoder error. May write to the output writer.
     /// Returns true only when it is fine to keep going.
@@ -429,14 +1601,28 @@ impl EncoderTrap {
 
         match *self {
             EncodeStrict => false,
-            EncodeReplace => reencode(encoder, "?", output, "Replace"),
+            EncodeReplace => match encoder.replacement_bytes() {
+                Some(bytes) => {
+                    // the encoder should be in the state after the replacement
+                    encoder.raw_feed(REPLACEMENT_STR, &mut DiscardingByteWriter);
+                    output.write_bytes(bytes.as_slice());
+                    true
+                }
+                None => false,
+            },
             EncodeIgnore => true,
             EncodeNcrEscape => {
-                let mut escapes = String::new();
+                let mut buf = [0u8, ..12];
                 for ch in input.chars() {
-                    escapes.push_str(format!("&#{:d};", ch as int).as_slice());
+                    let escape = format_ncr(ch, &mut buf);
+                    let escape = str::from_utf8(escape).expect("NCR should be in ASCII");
+                    if !reencode(encoder, escape, output, "NcrEscape") { return false; }
                 }
-                reencode(encoder, escapes.as_slice(), output, "NcrEscape")
+                true
+            },
+            EncodeReplaceWithByte(b) => {
+                for _ in input.chars() { output.write_byte(b); }
+                true
             },
             EncoderTrap(func) => func(encoder, input, output),
         }
@@ -449,15 +1635,743 @@ impl EncoderTrap {
 #[unstable]
 pub fn decode(input: &[u8], trap: DecoderTrap, fallback_encoding: EncodingRef)
            -> (Result<String,SendStr>, EncodingRef) {
+    match bom_encoding(input) {
+        Some((encoding, bomlen)) => (encoding.decode(input.slice_from(bomlen), trap), encoding),
+        None => (fallback_encoding.decode(input, trap), fallback_encoding),
+    }
+}
+
+/// Returns the encoding indicated by the Byte Order Mark (BOM) at the beginning of given input
+/// and the length of the BOM, if any.
+fn bom_encoding(input: &[u8]) -> Option<(EncodingRef, uint)> {
     use all::{UTF_8, UTF_16LE, UTF_16BE};
     if input.starts_with([0xEF, 0xBB, 0xBF]) {
-        (UTF_8.decode(input.slice_from(3), trap), UTF_8 as EncodingRef)
+        Some((UTF_8 as EncodingRef, 3))
     } else if input.starts_with([0xFE, 0xFF]) {
-        (UTF_16BE.decode(input.slice_from(2), trap), UTF_16BE as EncodingRef)
+        Some((UTF_16BE as EncodingRef, 2))
     } else if input.starts_with([0xFF, 0xFE]) {
-        (UTF_16LE.decode(input.slice_from(2), trap), UTF_16LE as EncodingRef)
+        Some((UTF_16LE as EncodingRef, 2))
     } else {
-        (fallback_encoding.decode(input, trap), fallback_encoding)
+        None
+    }
+}
+
+/// Returns true if given bytes are a proper prefix of some BOM recognized by `bom_encoding`,
+/// so that more input is needed to tell if the input starts with a BOM or not.
+fn is_partial_bom(bytes: &[u8]) -> bool {
+    static PARTIAL_BOMS: &'static [&'static [u8]] = &[b"", b"\xEF", b"\xEF\xBB", b"\xFE", b"\xFF"];
+    PARTIAL_BOMS.iter().any(|&bom| bom == bytes)
+}
+
+/// A decoder which looks for a Byte Order Mark (BOM) at the beginning of the input
+/// and decodes the remaining input in the indicated encoding as `decode` does,
+/// or in the fallback encoding when there is no BOM. The BOM itself is stripped.
+///
+/// The BOM may be split across multiple calls to `raw_feed` (e.g. `FF` and then `FE`).
+/// Such a partial BOM is buffered and counted as unprocessed until it is completed,
+/// or until it turns out not to be a BOM, in which case the buffered bytes are decoded
+/// in the fallback encoding as a part of the content.
+#[experimental]
+pub struct BOMSniffingDecoder {
+    fallback: EncodingRef,
+    /// The leading bytes of the input which may be a part of the BOM.
+    pending: Vec<u8>,
+    /// The decoder for the determined encoding, if any.
+    inner: Option<Box<Decoder>>,
+}
+
+impl BOMSniffingDecoder {
+    /// Creates a new decoder with given fallback encoding.
+    pub fn new(fallback: EncodingRef) -> Box<Decoder> {
+        box BOMSniffingDecoder { fallback: fallback, pending: Vec::new(), inner: None }
+            as Box<Decoder>
+    }
+
+    /// Feeds given input to the determined decoder.
+    /// `start` is the offset of the input with respect to the caller's current input,
+    /// which can be negative when the input contains the pending bytes from the prior calls.
+    fn feed_inner(&mut self, input: &[u8], start: int,
+                  output: &mut StringWriter) -> (uint, Option<CodecError>) {
+        let (offset, err) = self.inner.get_mut_ref().raw_feed(input, output);
+        let offset = cmp::max(offset as int + start, 0) as uint;
+        (offset, err.map(|err| CodecError { upto: err.upto + start, ..err }))
+    }
+}
+
+impl Decoder for BOMSniffingDecoder {
+    fn from_self(&self) -> Box<Decoder> { BOMSniffingDecoder::new(self.fallback) }
+
+    fn clone_box(&self) -> Box<Decoder> {
+        box BOMSniffingDecoder {
+            fallback: self.fallback,
+            pending: self.pending.clone(),
+            inner: self.inner.as_ref().map(|d| d.clone_box()),
+        } as Box<Decoder>
+    }
+
+    fn pending_len(&self) -> uint {
+        match self.inner {
+            Some(ref d) => d.pending_len(),
+            None => self.pending.len(),
+        }
+    }
+
+    fn raw_feed(&mut self, input: &[u8], output: &mut StringWriter) -> (uint, Option<CodecError>) {
+        if self.inner.is_some() {
+            return self.feed_inner(input, 0, output);
+        }
+
+        let nprior = self.pending.len();
+        let mut i = 0;
+        loop {
+            match bom_encoding(self.pending.as_slice()) {
+                Some((encoding, _)) => {
+                    // the complete BOM, which is stripped
+                    self.pending.clear();
+                    self.inner = Some(encoding.decoder());
+                    return self.feed_inner(input.slice_from(i), i as int, output);
+                }
+                None if !is_partial_bom(self.pending.as_slice()) => {
+                    self.inner = Some(self.fallback.decoder());
+                    break;
+                }
+                None if i < input.len() => {
+                    self.pending.push(input[i]);
+                    i += 1;
+                }
+                None => {
+                    // the input so far may be a part of the BOM
+                    return (0, None);
+                }
+            }
+        }
+
+        // not a BOM, so the pending bytes from the prior calls are decoded as a content
+        let mut prior = mem::replace(&mut self.pending, Vec::new());
+        if nprior == 0 {
+            self.feed_inner(input, 0, output)
+        } else {
+            prior.truncate(nprior);
+            prior.push_all(input);
+            self.feed_inner(prior.as_slice(), -(nprior as int), output)
+        }
+    }
+
+    fn raw_finish(&mut self, output: &mut StringWriter) -> Option<CodecError> {
+        match self.inner.take() {
+            Some(mut d) => d.raw_finish(output),
+            None => {
+                // the input ended within a partial BOM, which is decoded as a content
+                let pending = mem::replace(&mut self.pending, Vec::new());
+                let mut d = self.fallback.decoder();
+                d.feed_all(pending.as_slice(), output).map(|err| CodecError {
+                    upto: err.upto - pending.len() as int, ..err
+                })
+            }
+        }
+    }
+}
+
+/// Returns the encoding whose Byte Order Mark (BOM) is at the beginning of given input, if any.
+/// Every encoding in `all::encodings()` with `bom_bytes` is checked,
+/// and the longest BOM wins when more than one matches.
+/// The BOM is not consumed; see `decode` for decoding the input with the BOM.
+#[experimental]
+pub fn detect_bom(input: &[u8]) -> Option<EncodingRef> {
+    use all;
+    let mut found: Option<(EncodingRef, uint)> = None;
+    for &encoding in all::encodings().iter() {
+        match encoding.bom_bytes() {
+            Some(bom) if input.starts_with(bom) => match found {
+                Some((_, len)) if len >= bom.len() => {}
+                _ => { found = Some((encoding, bom.len())); }
+            },
+            _ => {}
+        }
+    }
+    found.map(|(encoding, _)| encoding)
+}
+
+/// Same to `decode` but with the encoding declared by the caller (e.g. from a `Content-Type`),
+/// which may contradict the Byte Order Mark (BOM).
+/// Return the result and the used encoding.
+///
+/// If `bom_wins` is true, the BOM overrides the declared encoding as browsers do,
+/// so this is same to `decode` with `declared_encoding` as a fallback.
+/// Otherwise the declared encoding is always used and the BOM is stripped only when
+/// it is the BOM of the declared encoding; a conflicting BOM is decoded as a part of the data.
+/// For example `FE FF 00 41` declared as UTF-16LE decodes to `A` in UTF-16BE
+/// when `bom_wins` is true, and to U+FFFE U+4100 in UTF-16LE otherwise.
+#[experimental]
+pub fn decode_with_declared_bom_policy(input: &[u8], trap: DecoderTrap,
+                                       declared_encoding: EncodingRef, bom_wins: bool)
+                                      -> (Result<String,SendStr>, EncodingRef) {
+    if bom_wins { return decode(input, trap, declared_encoding); }
+    let input = match bom_encoding(input) {
+        Some((encoding, bomlen)) if encoding.name() == declared_encoding.name() =>
+            input.slice_from(bomlen),
+        _ => input,
+    };
+    (declared_encoding.decode(input, trap), declared_encoding)
+}
+
+/// Decodes the input declared to be in given encoding (e.g. by HTTP `Content-Type`)
+/// as browsers do, i.e. the "decode" algorithm of the WHATWG Encoding standard.
+/// A UTF-8 or UTF-16 Byte Order Mark (BOM) overrides the declared encoding and is stripped,
+/// and any decoder error is replaced with U+FFFD as in `DecodeReplace`.
+/// Return the decoded string and the encoding actually used.
+#[experimental]
+pub fn decode_with_declared(declared_encoding: EncodingRef,
+                            input: &[u8]) -> (String, EncodingRef) {
+    let (result, encoding) = decode(input, DecodeReplace, declared_encoding);
+    (result.ok().expect("DecodeReplace should never fail"), encoding)
+}
+
+/// Finds a `charset=` declaration (e.g. `<meta charset="euc-kr">` or `<meta http-equiv=
+/// "Content-Type" content="text/html; charset=euc-kr">`) within the first 1024 bytes of HTML,
+/// and returns the encoding for the first recognized label.
+/// The declaration is always in ASCII, so this works on raw bytes before decoding.
+fn sniff_html_charset(input: &[u8]) -> Option<EncodingRef> {
+    use all::{UTF_8, WINDOWS_1252};
+    use label::encoding_from_whatwg_label;
+
+    fn is_space(b: u8) -> bool {
+        match b { b'\t' | b'\n' | b'\x0c' | b'\r' | b' ' => true, _ => false }
+    }
+
+    static CHARSET: &'static [u8] = b"charset";
+    let head = input.slice_to(cmp::min(input.len(), 1024));
+    for i in range(0, head.len()) {
+        if i + CHARSET.len() > head.len() { break; }
+        let matched = head.slice(i, i + CHARSET.len()).iter().zip(CHARSET.iter())
+                          .all(|(&a, &b)| a == b || (a >= b'A' && a <= b'Z' && a + 0x20 == b));
+        if !matched { continue; }
+
+        let mut j = i + CHARSET.len();
+        while j < head.len() && is_space(head[j]) { j += 1; }
+        if j >= head.len() || head[j] != b'=' { continue; }
+        j += 1;
+        while j < head.len() && is_space(head[j]) { j += 1; }
+        let quote = if j < head.len() && (head[j] == b'"' || head[j] == b'\'') {
+            j += 1;
+            Some(head[j - 1])
+        } else {
+            None
+        };
+        let start = j;
+        while j < head.len() {
+            let b = head[j];
+            if Some(b) == quote || is_space(b) || b == b';' || b == b'>' || b == b'/' {
+                break;
+            }
+            if quote.is_none() && (b == b'"' || b == b'\'') { break; }
+            j += 1;
+        }
+
+        let label = match str::from_utf8(head.slice(start, j)) {
+            Some(label) => label,
+            None => continue,
+        };
+        match encoding_from_whatwg_label(label) {
+            // a UTF-16 document cannot declare itself in ASCII, so it is actually UTF-8
+            Some(encoding) if encoding.name().starts_with("utf-16") =>
+                return Some(UTF_8 as EncodingRef),
+            // likewise, x-user-defined in the declaration means windows-1252
+            Some(encoding) if encoding.whatwg_name() == Some("x-user-defined") =>
+                return Some(WINDOWS_1252 as EncodingRef),
+            Some(encoding) => return Some(encoding),
+            None => {}
+        }
+    }
+    None
+}
+
+/// Decodes an HTML document with the encoding declared in the document itself,
+/// i.e. a `charset=` declaration in `<meta>` or `Content-Type` within the first 1024 bytes.
+/// As in `decode`, the Byte Order Mark (BOM) takes precedence over the declaration,
+/// and `fallback_encoding` is used when there is neither.
+/// Errors are replaced with U+FFFD as browsers do.
+/// Return the result and the used encoding.
+#[experimental]
+pub fn decode_html_auto(input: &[u8], fallback_encoding: EncodingRef) -> (String, EncodingRef) {
+    let encoding = sniff_html_charset(input).unwrap_or(fallback_encoding);
+    let (result, encoding) = decode(input, DecodeReplace, encoding);
+    (result.ok().expect("DecodeReplace should never fail"), encoding)
+}
+
+/**
+ * A streaming decoder which can restart with another encoding until it commits to one,
+ * e.g. for an HTML parser which may find a `<meta charset>` declaration late in the input.
+ *
+ * Before the commit, the input is only buffered and nothing is decoded,
+ * so changing the encoding with `restart` effectively re-decodes every byte seen so far.
+ * The decoder commits to the current encoding when `commit` or `finish` is called,
+ * or when more than `max_buffered` bytes have been buffered.
+ * After the commit the input is decoded as it arrives, the buffer is discarded
+ * and the encoding cannot be changed anymore.
+ * Any decoder error is replaced with U+FFFD as in `DecodeReplace`.
+ */
+#[experimental]
+pub struct RestartableDecoder {
+    encoding: EncodingRef,
+    /// The decoder for `encoding`, or `None` until the commit.
+    decoder: Option<Box<Decoder>>,
+    /// The whole input before the commit, or the unprocessed input after the commit
+    /// which may have to be fed again on the error.
+    buf: Vec<u8>,
+    max_buffered: uint,
+}
+
+impl RestartableDecoder {
+    /// Creates a new decoder with the tentative encoding,
+    /// which commits after more than `max_buffered` bytes are buffered.
+    pub fn new(encoding: EncodingRef, max_buffered: uint) -> RestartableDecoder {
+        RestartableDecoder { encoding: encoding, decoder: None,
+                             buf: Vec::new(), max_buffered: max_buffered }
+    }
+
+    /// Returns the current encoding, which is tentative until the commit.
+    pub fn encoding(&self) -> EncodingRef { self.encoding }
+
+    /// Returns true if the decoder has committed to the current encoding.
+    pub fn is_committed(&self) -> bool { self.decoder.is_some() }
+
+    /// Returns the input buffered so far before the commit,
+    /// which can be scanned for the encoding declaration. Empty after the commit.
+    pub fn buffered<'a>(&'a self) -> &'a [u8] {
+        if self.is_committed() { &[] } else { self.buf.as_slice() }
+    }
+
+    /// Changes the encoding used to decode the input seen so far and the future input.
+    /// Returns false without any change if the decoder has already committed.
+    pub fn restart(&mut self, encoding: EncodingRef) -> bool {
+        if self.is_committed() { return false; }
+        self.encoding = encoding;
+        true
+    }
+
+    /// Commits to the current encoding and decodes the buffered input.
+    /// Does nothing if the decoder has already committed.
+    pub fn commit(&mut self, output: &mut StringWriter) {
+        if self.is_committed() { return; }
+        self.decoder = Some(self.encoding.decoder());
+        let buf = mem::replace(&mut self.buf, Vec::new());
+        self.feed_committed(buf.as_slice(), output);
+    }
+
+    /// Feeds given portion of input.
+    /// The input is buffered before the commit, and decoded to `output` after the commit.
+    pub fn feed(&mut self, input: &[u8], output: &mut StringWriter) {
+        if self.is_committed() {
+            self.feed_committed(input, output);
+        } else {
+            self.buf.push_all(input);
+            if self.buf.len() > self.max_buffered {
+                self.commit(output);
+            }
+        }
+    }
+
+    /// Commits if not yet, and finishes decoding at the end of input.
+    /// The decoder can be reused for the next input with the same encoding.
+    pub fn finish(&mut self, output: &mut StringWriter) {
+        self.commit(output);
+        loop {
+            let err = self.decoder.get_mut_ref().raw_finish(output);
+            match err {
+                Some(err) => {
+                    output.write_char('\ufffd');
+                    let buf = mem::replace(&mut self.buf, Vec::new());
+                    let remaining = (buf.len() as int + err.upto) as uint;
+                    if remaining >= buf.len() { break; }
+                    self.feed_committed(buf.slice_from(remaining), output);
+                }
+                None => { break; }
+            }
+        }
+        self.buf.clear();
+    }
+
+    /// Feeds given input to the committed decoder.
+    /// `self.buf` keeps the unprocessed bytes, which the error may require to feed again.
+    fn feed_committed(&mut self, input: &[u8], output: &mut StringWriter) {
+        let mut data = mem::replace(&mut self.buf, Vec::new());
+        let mut remaining = data.len();
+        let mut unprocessed = 0;
+        data.push_all(input);
+        let decoder = self.decoder.get_mut_ref();
+        loop {
+            let (offset, err) = decoder.raw_feed(data.slice_from(remaining), output);
+            if offset > 0 { unprocessed = remaining + offset; }
+            match err {
+                Some(err) => {
+                    output.write_char('\ufffd');
+                    remaining = (remaining as int + err.upto) as uint;
+                    unprocessed = remaining;
+                }
+                None => { break; }
+            }
+        }
+        self.buf = Vec::from_slice(data.slice_from(unprocessed));
+    }
+}
+
+/// A string writer which counts the number of characters written to the inner writer,
+/// and signals that it is full once `max_chars` characters have been written.
+/// Characters written after that are still passed through, so that no sequence gets lost.
+struct CappedStringWriter<'a> {
+    inner: &'a mut StringWriter,
+    nchars: uint,
+    max_chars: uint,
+}
+
+impl<'a> CappedStringWriter<'a> {
+    /// Returns true if the writer has reached the cap.
+    fn is_full(&self) -> bool { self.nchars >= self.max_chars }
+
+    /// Returns the number of characters that can be written before reaching the cap.
+    fn room(&self) -> uint { if self.is_full() {0} else {self.max_chars - self.nchars} }
+}
+
+impl<'a> StringWriter for CappedStringWriter<'a> {
+    fn writer_hint(&mut self, expectedlen: uint) {
+        self.inner.writer_hint(expectedlen);
+    }
+
+    fn write_char(&mut self, c: char) {
+        self.inner.write_char(c);
+        self.nchars += 1;
+    }
+
+    fn write_str(&mut self, s: &str) {
+        self.inner.write_str(s);
+        self.nchars += s.char_len();
+    }
+}
+
+/// A string writer which records the current input offset for every character written.
+/// When `consecutive` is set, each character is assumed to come from a single byte
+/// and advances the offset by one.
+struct OffsetRecordingWriter<'a> {
+    inner: &'a mut StringWriter,
+    offsets: &'a mut Vec<uint>,
+    offset: uint,
+    consecutive: bool,
+}
+
+impl<'a> StringWriter for OffsetRecordingWriter<'a> {
+    fn writer_hint(&mut self, expectedlen: uint) {
+        self.inner.writer_hint(expectedlen);
+    }
+
+    fn write_char(&mut self, c: char) {
+        self.inner.write_char(c);
+        self.offsets.push(self.offset);
+        if self.consecutive { self.offset += 1; }
+    }
+
+    fn write_str(&mut self, s: &str) {
+        self.inner.write_str(s);
+        for _ in s.chars() {
+            self.offsets.push(self.offset);
+            if self.consecutive { self.offset += 1; }
+        }
+    }
+}
+
+/// Decodes given input with given decoder until at least `max_chars` characters are produced.
+/// Return the result and the number of input bytes consumed.
+///
+/// The decoding stops only at the end of the sequence,
+/// so there can be more than `max_chars` characters
+/// when a single sequence (or the trap) produces multiple characters.
+/// The decoder keeps its state, so the caller can continue decoding
+/// by feeding the bytes after the consumed bytes to the same decoder.
+/// Any incomplete sequence at the end of input is kept in the decoder, and
+/// `raw_finish` is never called so the caller should call it at the end of input.
+///
+/// When an invalid sequence started in the prior call and the decoder backs up
+/// into the bytes of that call, those bytes cannot be fed again;
+/// the decoding resumes from the start of the current input
+/// and the trap only receives the part of the sequence in the current input.
+#[experimental]
+pub fn decode_capped(decoder: &mut Decoder, input: &[u8], trap: DecoderTrap, max_chars: uint)
+                    -> Result<(String, uint), SendStr> {
+    let mut ret = String::new();
+    let mut remaining = 0;
+    {
+        let mut output = CappedStringWriter { inner: &mut ret, nchars: 0, max_chars: max_chars };
+        let mut unprocessed = 0;
+
+        // a byte produces at most one character in most cases, so we feed as many bytes as
+        // the remaining room and go on with smaller chunks until the writer becomes full.
+        while remaining < input.len() && !output.is_full() {
+            let end = cmp::min(input.len(), remaining + output.room());
+            let (offset, err) = decoder.raw_feed(input.slice(remaining, end), &mut output);
+            if offset > 0 { unprocessed = remaining + offset; }
+            match err {
+                Some(err) => {
+                    let upto = remaining as int + err.upto;
+                    remaining = if upto < 0 {0} else {upto as uint};
+                    let problem = input.slice(cmp::min(unprocessed, remaining), remaining);
+                    if !trap.trap(decoder, problem, &mut output) {
+                        return Err(err.cause);
+                    }
+                    unprocessed = remaining;
+                }
+                None => { remaining = end; }
+            }
+        }
+    }
+    Ok((ret, remaining))
+}
+
+/// Decodes given input up to (but not including) the first byte 00, as a null-terminated buffer
+/// from the foreign function or the fixed-size record. Any bytes after 00 are ignored.
+/// The entire input is decoded if there is no byte 00.
+///
+/// The encoding should be ASCII-compatible in the sense that
+/// the byte 00 is never a part of other sequences, e.g. UTF-8, EUC-JP or Shift_JIS
+/// (where the trail bytes are no less than 0x40). The result is meaningless for
+/// encodings like UTF-16 where the byte 00 is a part of most characters.
+/// An incomplete sequence right before the byte 00 is handled by the trap.
+#[experimental]
+pub fn decode_cstr(encoding: &Encoding, input: &[u8],
+                   trap: DecoderTrap) -> Result<String,SendStr> {
+    let end = input.iter().position(|&b| b == 0).unwrap_or(input.len());
+    encoding.decode(input.slice_to(end), trap)
+}
+
+/// Returns true if decoding given input in both encodings results in the same string
+/// without any error. The input is decoded with `DecodeStrict`,
+/// so this is false when either encoding cannot decode the input.
+/// This is always true without decoding when the input is entirely in ASCII
+/// and `a.agrees_with_on_ascii(b)` is true.
+#[experimental]
+pub fn encodings_agree_on(a: &Encoding, b: &Encoding, input: &[u8]) -> bool {
+    if input.iter().all(|&byte| byte < 0x80) && a.agrees_with_on_ascii(b) {
+        return true;
+    }
+
+    let mut aret = String::new();
+    let mut bret = String::new();
+    decode_with_decoder(a.decoder(), input, DecodeStrict, false, &mut aret).is_ok() &&
+        decode_with_decoder(b.decoder(), input, DecodeStrict, false, &mut bret).is_ok() &&
+        aret == bret
+}
+
+/// An iterator adapter which lazily encodes characters from the inner iterator into bytes.
+/// Created by `encode_chars`.
+#[experimental]
+pub struct EncodedBytes<I> {
+    iter: I,
+    encoder: Box<Encoder>,
+    trap: EncoderTrap,
+    chbuf: String,
+    buf: Vec<u8>,
+    pos: uint,
+    finished: bool,
+}
+
+impl<I:Iterator<char>> Iterator<u8> for EncodedBytes<I> {
+    fn next(&mut self) -> Option<u8> {
+        loop {
+            if self.pos < self.buf.len() {
+                self.pos += 1;
+                return Some(self.buf[self.pos - 1]);
+            }
+            if self.finished { return None; }
+
+            self.buf.truncate(0);
+            self.pos = 0;
+            match self.iter.next() {
+                Some(ch) => {
+                    self.chbuf.truncate(0);
+                    self.chbuf.push_char(ch);
+                    let input = self.chbuf.as_slice();
+                    let (offset, err) = self.encoder.raw_feed(input, &mut self.buf);
+                    match err {
+                        Some(err) => {
+                            let upto = (offset as int + err.upto) as uint;
+                            if !self.trap.trap(&mut *self.encoder, input.slice(offset, upto),
+                                               &mut self.buf) {
+                                fail!("cannot encode {}: {}", ch, err.cause);
+                            }
+                        }
+                        None => {}
+                    }
+                }
+                None => {
+                    self.finished = true;
+                    match self.encoder.raw_finish(&mut self.buf) {
+                        Some(err) => {
+                            if !self.trap.trap(&mut *self.encoder, "", &mut self.buf) {
+                                fail!("cannot finish encoding: {}", err.cause);
+                            }
+                        }
+                        None => {}
+                    }
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (uint, Option<uint>) {
+        let (lower, _) = self.iter.size_hint();
+        (self.buf.len() - self.pos + lower, None)
+    }
+}
+
+/// Returns an iterator which lazily encodes characters from given iterator with given encoding.
+/// Neither the input nor the output is materialized; the characters are pulled from `iter`
+/// only when the previously encoded bytes have been consumed.
+///
+/// An unrepresentable character is handled by `trap` as in `Encoding::encode`,
+/// so `EncodeIgnore` skips it and `EncodeReplace` or `EncodeNcrEscape` yields a replacement.
+/// The iterator cannot return an error, so it fails the task when the trap fails
+/// (e.g. on any unrepresentable character with `EncodeStrict`).
+///
+/// The characters are fed to a single encoder one at a time, so stateful encodings
+/// like ISO-2022-JP keep their state (and emit escape sequences) across characters.
+/// The trap, however, only ever sees the character being encoded,
+/// and encodings which defer the output until the end of input (e.g. Punycode)
+/// only produce the bytes once the inner iterator is exhausted.
+#[experimental]
+pub fn encode_chars<I:Iterator<char>>(encoding: &Encoding, iter: I,
+                                      trap: EncoderTrap) -> EncodedBytes<I> {
+    EncodedBytes { iter: iter, encoder: encoding.encoder(), trap: trap,
+                   chbuf: String::with_capacity(4), buf: Vec::new(), pos: 0, finished: false }
+}
+
+/// Returns the first encoding in `candidates` which can represent every character in `input`.
+/// The candidates are tried in the given order, so the caller can put the narrowest
+/// or the most compatible encoding first (e.g. ASCII, ISO-8859-1, windows-1252 and then UTF-8).
+/// Encodings which do not support encoding are never chosen.
+/// Returns `None` if no candidate can encode `input`.
+#[experimental]
+pub fn choose_encoding(input: &str, candidates: &[EncodingRef]) -> Option<EncodingRef> {
+    for &encoding in candidates.iter() {
+        if !encoding.can_encode_direction() { continue; }
+        let mut encoder = encoding.encoder();
+        let mut output = CountingByteWriter { len: 0 };
+        match encoder.raw_feed(input, &mut output) {
+            (_, Some(_)) => continue,
+            (_, None) => {}
+        }
+        if encoder.raw_finish(&mut output).is_none() {
+            return Some(encoding);
+        }
+    }
+    None
+}
+
+/// Encodes `input` with every encoding in `candidates` which can represent it without any loss,
+/// and returns the encoding producing the fewest bytes along with its output.
+/// Unlike `choose_encoding` the order of candidates matters only for ties,
+/// in which case the earlier candidate is chosen.
+/// Encodings which do not support encoding are never chosen.
+/// Returns `None` if no candidate can encode `input`.
+#[experimental]
+pub fn encode_smallest(input: &str, candidates: &[EncodingRef])
+                      -> Option<(EncodingRef, Vec<u8>)> {
+    let mut best: Option<(EncodingRef, Vec<u8>)> = None;
+    for &encoding in candidates.iter() {
+        if !encoding.can_encode_direction() { continue; }
+        let output = match encoding.encode(input, EncodeStrict) {
+            Ok(output) => output,
+            Err(_) => continue,
+        };
+        let better = match best {
+            Some((_, ref bestoutput)) => output.len() < bestoutput.len(),
+            None => true,
+        };
+        if better {
+            best = Some((encoding, output));
+        }
+    }
+    best
+}
+
+/// Encodes HTML (or XML) text into given encoding without any loss,
+/// by escaping every unrepresentable character to a numeric character reference
+/// (e.g. `&#26085;`) with the `EncodeNcrEscape` trap.
+/// If the encoding cannot encode the references themselves
+/// (e.g. it does not support encoding at all), the whole input is encoded in UTF-8 instead.
+/// Returns the encoded bytes and the encoding actually used,
+/// which the caller should use for the charset label of the output.
+#[experimental]
+pub fn encode_html(encoding: EncodingRef, input: &str) -> (Vec<u8>, EncodingRef) {
+    use all::UTF_8;
+    match encoding.encode(input, EncodeNcrEscape) {
+        Ok(ret) => (ret, encoding),
+        Err(_) => (Vec::from_slice(input.as_bytes()), UTF_8 as EncodingRef),
+    }
+}
+
+/// Determine the encoding of Japanese text without a reliable label,
+/// which is most commonly either UTF-8 or Shift_JIS (Windows code page 932),
+/// and decoded a single string in memory.
+/// The input is decoded as UTF-8 only when it is entirely valid in UTF-8,
+/// otherwise it is decoded as Windows code page 932 with the replacement trap.
+/// Return the result and the used encoding.
+#[experimental]
+pub fn decode_japanese_auto(input: &[u8]) -> (String, EncodingRef) {
+    use all::{UTF_8, WINDOWS_31J};
+    match UTF_8.decode(input, DecodeStrict) {
+        Ok(s) => (s, UTF_8 as EncodingRef),
+        Err(_) => {
+            let s = WINDOWS_31J.decode(input, DecodeReplace);
+            (s.ok().expect("replacement trap should never fail"), WINDOWS_31J as EncodingRef)
+        }
+    }
+}
+
+/// Parses a numeric character reference (`&#NNN;` or `&#xHH;`) at the beginning of given string.
+/// Returns the referenced character and the length of the reference if it is well-formed.
+fn parse_numeric_entity(s: &str) -> Option<(char, uint)> {
+    let bytes = s.as_bytes();
+    if !bytes.starts_with(b"&#") { return None; }
+    let (radix, start) = match bytes.get(2) {
+        Some(&b'x') | Some(&b'X') => (16u, 3u),
+        _ => (10u, 2u),
+    };
+    let ndigits = bytes.slice_from(start).iter()
+                       .take_while(|&&b| (b as char).to_digit(radix).is_some()).count();
+    let end = start + ndigits;
+    if ndigits == 0 || bytes.get(end) != Some(&b';') { return None; }
+
+    let mut value = 0u32;
+    for &b in bytes.slice(start, end).iter() {
+        value = value * radix as u32 + (b as char).to_digit(radix).unwrap() as u32;
+        if value > 0x10ffff { return None; }
+    }
+    char::from_u32(value).map(|ch| (ch, end + 1)) // also rejects surrogates
+}
+
+/// Replaces numeric character references (`&#NNN;` and `&#xHH;`) in the decoded string
+/// with the corresponding characters.
+/// This is useful for legacy HTML which used references for characters not in its encoding.
+/// Malformed references, references to surrogates and references beyond U+10FFFF
+/// are left as is. Named references (like `&amp;`) are not expanded.
+#[experimental]
+pub fn expand_numeric_entities(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len());
+    let mut rest = s;
+    loop {
+        match rest.find_str("&#") {
+            Some(i) => {
+                ret.push_str(rest.slice_to(i));
+                rest = rest.slice_from(i);
+                match parse_numeric_entity(rest) {
+                    Some((ch, len)) => { ret.push_char(ch); rest = rest.slice_from(len); }
+                    None => { ret.push_str("&#"); rest = rest.slice_from(2); }
+                }
+            }
+            None => {
+                ret.push_str(rest);
+                return ret;
+            }
+        }
     }
 }
 
@@ -477,6 +2391,12 @@ mod tests {
                             prepend: self.prepend,
                             toggle: false } as Box<Encoder>
         }
+        fn clone_box(&self) -> Box<Encoder> {
+            box MyEncoder { flag: self.flag,
+                            prohibit: self.prohibit,
+                            prepend: self.prepend,
+                            toggle: self.toggle } as Box<Encoder>
+        }
         fn is_ascii_compatible(&self) -> bool { self.flag }
         fn raw_feed(&mut self, input: &str,
                     output: &mut ByteWriter) -> (uint, Option<CodecError>) {
@@ -491,7 +2411,8 @@ mod tests {
                     }
                 } else {
                     return (i, Some(CodecError { upto: j as int,
-                                                 cause: "!!!".into_maybe_owned() }));
+                                                 cause: "!!!".into_maybe_owned(),
+                                                 buffered: 0 }));
                 }
             }
             (input.len(), None)
@@ -546,4 +2467,959 @@ mod tests {
         // this should fail as this contrived encoding does not support `&` at all
         let _ = FAIL.encode("Hello\u203d I'm fine.", EncodeNcrEscape);
     }
+
+    #[test]
+    fn test_encoder_feed_all() {
+        let enc = MyEncoding { flag: true, prohibit: '&', prepend: "" };
+        let mut e = enc.encoder();
+        let mut buf = Vec::new();
+        assert!(e.feed_all("Hello", &mut buf).is_none());
+        assert_eq!(buf, Vec::from_slice(b"Hello"));
+
+        let mut buf = Vec::new();
+        let err = e.feed_all("Q&A", &mut buf);
+        assert_eq!(err.map(|err| err.upto), Some(2));
+        assert_eq!(buf, Vec::from_slice(b"Q"));
+    }
+
+    #[test]
+    fn test_decoder_feed_all() {
+        use all::UTF_8;
+
+        let mut d = UTF_8.decoder();
+        let mut buf = String::new();
+        assert!(d.feed_all([0x41, 0xe3, 0x81, 0x82], &mut buf).is_none());
+        assert_eq!(buf.as_slice(), "A\u3042");
+
+        // the error from `raw_finish` is relative to the input
+        let mut buf = String::new();
+        let err = d.feed_all([0x41, 0xe3, 0x81], &mut buf);
+        assert_eq!(err.map(|err| err.upto), Some(3));
+        assert_eq!(buf.as_slice(), "A");
+
+        let mut buf = String::new();
+        let err = d.feed_all([0x41, 0xff, 0x42], &mut buf);
+        assert_eq!(err.map(|err| err.upto), Some(2));
+        assert_eq!(buf.as_slice(), "A");
+    }
+
+    #[test]
+    fn test_null_string_writer() {
+        let mut w = NullStringWriter::new();
+        w.write_char('A');
+        w.write_str("\uac00\U0001f600!");
+        assert_eq!(w.nchars, 4);
+    }
+
+    #[test]
+    fn test_is_valid() {
+        use all::{UTF_8, ASCII, WINDOWS_31J};
+
+        assert!(UTF_8.is_valid([]));
+        assert!(UTF_8.is_valid([0x41, 0xe3, 0x81, 0x82]));
+        assert!(!UTF_8.is_valid([0x41, 0xff, 0x42]));
+        assert!(!UTF_8.is_valid([0x41, 0xe3, 0x81])); // incomplete at the end
+        assert!(ASCII.is_valid([0x41, 0x42]));
+        assert!(!ASCII.is_valid([0x41, 0x80]));
+        assert!(WINDOWS_31J.is_valid([0x93, 0xfa, 0x96, 0x7b]));
+        assert!(!WINDOWS_31J.is_valid([0x93, 0xfa, 0x96]));
+    }
+
+    #[test]
+    fn test_validate() {
+        use all::{UTF_8, ASCII, ISO_8859_6, WINDOWS_31J};
+
+        fn upto(res: Result<uint, CodecError>) -> Option<int> {
+            match res {
+                Ok(_) => None,
+                Err(err) => Some(err.upto),
+            }
+        }
+
+        assert_eq!(UTF_8.validate([]).ok(), Some(0));
+        assert_eq!(UTF_8.validate([0x41, 0xe3, 0x81, 0x82]).ok(), Some(4));
+        assert_eq!(upto(UTF_8.validate([0x41, 0xff, 0x42])), Some(2));
+        assert_eq!(upto(UTF_8.validate([0x41, 0xe3, 0x81])), Some(3)); // incomplete at the end
+        assert_eq!(ASCII.validate([0x41, 0x42]).ok(), Some(2));
+        assert_eq!(upto(ASCII.validate([0x41, 0x80, 0x42])), Some(2));
+        assert_eq!(ISO_8859_6.validate([0x41, 0xc1]).ok(), Some(2));
+        assert_eq!(upto(ISO_8859_6.validate([0x41, 0xc1, 0xa1, 0x42])), Some(3));
+        assert_eq!(WINDOWS_31J.validate([0x93, 0xfa, 0x96, 0x7b]).ok(), Some(4));
+        assert_eq!(upto(WINDOWS_31J.validate([0x93, 0xfa, 0xa0])), Some(3));
+    }
+
+    #[test]
+    fn test_validate_agrees_with_decoder() {
+        use all::{WINDOWS_31J, EUC_JP};
+
+        fn check(encoding: EncodingRef, input: &[u8]) {
+            let mut decoder = encoding.decoder();
+            let expected = decoder.feed_all(input, &mut NullStringWriter::new());
+            match (encoding.validate(input), expected) {
+                (Ok(len), None) => assert_eq!(len, input.len()),
+                (Err(err), Some(expected)) => {
+                    assert_eq!(err.upto, expected.upto);
+                    assert_eq!(err.cause.as_slice(), expected.cause.as_slice());
+                    assert_eq!(err.buffered, expected.buffered);
+                }
+                (Ok(_), Some(_)) => fail!("{} accepted {}", encoding.name(), input),
+                (Err(_), None) => fail!("{} rejected {}", encoding.name(), input),
+            }
+        }
+
+        for input in [b"\x41\x93\xFA\xB1", b"\x41\x93", b"\x93\x41", b"\x80\x41",
+                      b"\xA0", b"\xFD\x41", b"\x85\x40", b"\xF0\x40"].iter() {
+            check(WINDOWS_31J, *input);
+        }
+        for input in [b"\x41\xC6\xFC\x8E\xB1", b"\xC6", b"\x8E", b"\x8F", b"\x8F\xB0",
+                      b"\xC6\x41", b"\x8E\x41", b"\x8E\xE0", b"\x8F\x41", b"\x8F\x8F",
+                      b"\x8F\xB0\x41", b"\x8F\xB0\xA1", b"\x8F\xA2\xA1",
+                      b"\xA9\xA1", b"\x80", b"\xFF"].iter() {
+            check(EUC_JP, *input);
+        }
+    }
+
+    #[test]
+    fn test_replacement_bytes() {
+        use all::{ASCII, UTF_16LE, UTF_16BE, ERROR, SCSU};
+
+        let enc = MyEncoding { flag: true, prohibit: '&', prepend: "*" };
+        assert_eq!(enc.encoder().replacement_bytes(), Some(vec!(0x3f)));
+        assert_eq!(ASCII.encoder().replacement_bytes(), Some(vec!(0x3f)));
+        assert_eq!(UTF_16LE.encoder().replacement_bytes(), Some(vec!(0x3f, 0x00)));
+        assert_eq!(UTF_16BE.encoder().replacement_bytes(), Some(vec!(0x00, 0x3f)));
+
+        // the replacement is encoded in the current state
+        let mut e = enc.encoder();
+        assert_feed_ok!(e, "e", "", b"e");
+        assert_eq!(e.replacement_bytes(), Some(Vec::from_slice(b"*?")));
+        assert_eq!(enc.encode("e\u203de", EncodeReplace), Ok(Vec::from_slice(b"e*?*e")));
+
+        // no replacement for encoders which cannot encode it, and the trap fails
+        let enc = MyEncoding { flag: true, prohibit: '?', prepend: "" };
+        assert_eq!(enc.encoder().replacement_bytes(), None);
+        assert!(enc.encode("\u203d", EncodeReplace).is_err());
+        assert_eq!(ERROR.encoder().replacement_bytes(), None);
+        assert_eq!(SCSU.encoder().replacement_bytes(), None);
+    }
+
+    #[test]
+    fn test_decode_with_offsets() {
+        use all::{UTF_8, WINDOWS_31J, ISO_8859_6};
+
+        assert_eq!(UTF_8.decode_with_offsets([], DecodeStrict),
+                   Ok(("".to_string(), vec!())));
+        assert_eq!(UTF_8.decode_with_offsets([0x41, 0xc3, 0xa9, 0xe6, 0x97, 0xa5, 0x42],
+                                             DecodeStrict),
+                   Ok(("A\u00e9\u65e5B".to_string(), vec!(0, 1, 3, 6))));
+        assert_eq!(WINDOWS_31J.decode_with_offsets([0x41, 0xa0, 0x93, 0xfa, 0x42], DecodeReplace),
+                   Ok(("A\ufffd\u65e5B".to_string(), vec!(0, 1, 2, 4))));
+        assert_eq!(WINDOWS_31J.decode_with_offsets([0x41, 0x93], DecodeReplace),
+                   Ok(("A\ufffd".to_string(), vec!(0, 1))));
+        assert!(WINDOWS_31J.decode_with_offsets([0x41, 0x93], DecodeStrict).is_err());
+
+        // ASCII runs and single-byte encodings are fed at once
+        assert_eq!(UTF_8.decode_with_offsets([0x41, 0x42, 0xc3, 0xa9, 0x43, 0x44, 0x45],
+                                             DecodeStrict),
+                   Ok(("AB\u00e9CDE".to_string(), vec!(0, 1, 2, 4, 5, 6))));
+        assert_eq!(ISO_8859_6.decode_with_offsets([0x41, 0xc7, 0xa1, 0x42], DecodeReplace),
+                   Ok(("A\u0627\ufffdB".to_string(), vec!(0, 1, 2, 3))));
+        assert!(ISO_8859_6.decode_with_offsets([0x41, 0xa1], DecodeStrict).is_err());
+    }
+
+    #[test]
+    fn test_decode_with_positions() {
+        use all::{UTF_8, WINDOWS_31J};
+
+        assert_eq!(UTF_8.decode_with_positions(b"a\nb\n"), Ok("a\nb\n".to_string()));
+
+        // the error is on the line 3
+        let input = b"fn main() {\n    let s = \"\xc3\xa9\";\n    let t = \"\xff\";\n}\n";
+        let err = UTF_8.decode_with_positions(input).unwrap_err();
+        assert_eq!((err.offset, err.line, err.column), (43, 3, 14));
+
+        // columns are counted in characters
+        let err = WINDOWS_31J.decode_with_positions(b"\x93\xfa\x96\x7b\xa0\n").unwrap_err();
+        assert_eq!((err.offset, err.line, err.column), (4, 1, 3));
+        let err = WINDOWS_31J.decode_with_positions(b"\n\n\x93").unwrap_err();
+        assert_eq!((err.offset, err.line, err.column), (2, 3, 1));
+    }
+
+    #[test]
+    fn test_format_ncr() {
+        use super::format_ncr;
+
+        let mut buf = [0u8, ..12];
+        assert_eq!(format_ncr('\x00', &mut buf), b"&#0;".as_slice());
+        assert_eq!(format_ncr('\u00e9', &mut buf), b"&#233;".as_slice());
+        assert_eq!(format_ncr('\uac00', &mut buf), b"&#44032;".as_slice());
+        assert_eq!(format_ncr('\U0001f600', &mut buf), b"&#128512;".as_slice());
+        assert_eq!(format_ncr('\U0010ffff', &mut buf), b"&#1114111;".as_slice());
+    }
+
+    #[test]
+    fn test_ncr_escape_trap() {
+        use all::{ASCII, ISO_2022_JP};
+
+        assert_eq!(ASCII.encode("A\u00e9\U0001f600\U0010ffffB", EncodeNcrEscape),
+                   Ok(Vec::from_slice(b"A&#233;&#128512;&#1114111;B")));
+        // the reference should be encoded in the current state of the non-ASCII-compatible encoder
+        assert_eq!(ISO_2022_JP.encode("\u65e5\U0001f600", EncodeNcrEscape),
+                   Ok(Vec::from_slice(b"\x1b$BF|\x1b(B&#128512;")));
+    }
+
+    #[test]
+    fn test_decode_partial() {
+        use all::{UTF_8, WINDOWS_31J};
+
+        let (s, err) = UTF_8.decode_partial([0x41, 0xe3, 0x81, 0x82]);
+        assert_eq!(s.as_slice(), "A\u3042");
+        assert!(err.is_none());
+
+        let (s, err) = UTF_8.decode_partial([0x41, 0xe3, 0x81, 0x82, 0xff, 0x42]);
+        assert_eq!(s.as_slice(), "A\u3042");
+        assert_eq!(err.map(|err| err.upto), Some(5));
+
+        // an incomplete sequence at the end
+        let (s, err) = WINDOWS_31J.decode_partial([0x41, 0x93, 0xfa, 0x96]);
+        assert_eq!(s.as_slice(), "A\u65e5");
+        assert_eq!(err.map(|err| err.upto), Some(4));
+
+        let (s, err) = WINDOWS_31J.decode_partial([]);
+        assert_eq!(s.as_slice(), "");
+        assert!(err.is_none());
+    }
+
+    #[test]
+    fn test_decode_strict() {
+        use all::{UTF_8, WINDOWS_31J};
+
+        assert_eq!(UTF_8.decode_strict([0x41, 0xe3, 0x81, 0x82]).ok(), Some("A\u3042".to_string()));
+
+        let (s, err) = UTF_8.decode_strict([0x41, 0xe3, 0x81, 0x82, 0xff, 0x42]).unwrap_err();
+        assert_eq!(s.as_slice(), "A\u3042");
+        assert_eq!(err.upto, 5);
+
+        // an incomplete sequence at the end
+        let (s, err) = WINDOWS_31J.decode_strict([0x41, 0x93, 0xfa, 0x96]).unwrap_err();
+        assert_eq!(s.as_slice(), "A\u65e5");
+        assert_eq!(err.upto, 4);
+    }
+
+    #[test]
+    fn test_decode_collecting_errors() {
+        use all::{UTF_8, WINDOWS_31J};
+
+        let (s, errors) = UTF_8.decode_collecting_errors([0x41, 0xe3, 0x81, 0x82]);
+        assert_eq!(s.as_slice(), "A\u3042");
+        assert!(errors.is_empty());
+
+        let (s, errors) = UTF_8.decode_collecting_errors([0x41, 0xff, 0x42, 0xe3, 0x81,
+                                                          0x43, 0xc3, 0xa9, 0xfe]);
+        assert_eq!(s.as_slice(), "ABC\u00e9");
+        assert_eq!(errors.iter().map(|err| err.upto).collect::<Vec<int>>(), vec!(2, 5, 9));
+
+        // an incomplete sequence at the end
+        let (s, errors) = WINDOWS_31J.decode_collecting_errors([0xa0, 0x93, 0xfa, 0x96]);
+        assert_eq!(s.as_slice(), "\u65e5");
+        assert_eq!(errors.iter().map(|err| err.upto).collect::<Vec<int>>(), vec!(1, 4));
+        assert_eq!(errors.iter().map(|err| err.buffered).collect::<Vec<uint>>(), vec!(0, 1));
+    }
+
+    #[test]
+    fn test_decode_to_chars() {
+        use all::{ASCII, UTF_8, UTF_16LE, WINDOWS_949};
+        use testutils;
+
+        assert_eq!(UTF_8.decode_to_chars([], DecodeStrict), Ok(vec!()));
+        assert_eq!(UTF_8.decode_to_chars([0x41, 0xe3, 0x81, 0x82, 0xf0, 0x9f, 0x98, 0x80],
+                                         DecodeStrict),
+                   Ok(vec!('A', '\u3042', '\U0001f600')));
+        assert_eq!(UTF_16LE.decode_to_chars([0x41, 0x00, 0x3d, 0xd8, 0x00, 0xde], DecodeStrict),
+                   Ok(vec!('A', '\U0001f600')));
+        assert_eq!(ASCII.decode_to_chars([0x41, 0x80, 0x42], DecodeReplace),
+                   Ok(vec!('A', '\ufffd', 'B')));
+        assert!(ASCII.decode_to_chars([0x41, 0x80, 0x42], DecodeStrict).is_err());
+
+        let input = WINDOWS_949.encode(testutils::KOREAN_TEXT, EncodeStrict).unwrap();
+        let decoded = WINDOWS_949.decode(input.as_slice(), DecodeStrict).unwrap();
+        assert_eq!(WINDOWS_949.decode_to_chars(input.as_slice(), DecodeStrict),
+                   Ok(decoded.as_slice().chars().collect()));
+    }
+
+    #[test]
+    fn test_decode_to_with_string_pool() {
+        use all::{ASCII, UTF_8};
+
+        let mut pool = StringPool::new();
+        let mut buf = pool.take();
+        assert_eq!(UTF_8.decode_to([0x41, 0xe3, 0x81, 0x82], DecodeStrict, &mut buf), Ok(()));
+        assert_eq!(buf.as_slice(), "A\u3042");
+        let ptr = buf.as_slice().as_ptr();
+        pool.recycle(buf);
+        assert_eq!(pool.len(), 1);
+
+        // the recycled buffer is reused and cleared
+        let mut buf = pool.take();
+        assert_eq!(pool.len(), 0);
+        assert_eq!(buf.as_slice(), "");
+        assert_eq!(UTF_8.decode_to([0x42], DecodeStrict, &mut buf), Ok(()));
+        assert_eq!(buf.as_slice(), "B");
+        assert_eq!(buf.as_slice().as_ptr(), ptr);
+
+        // the output is appended to the existing contents
+        assert_eq!(ASCII.decode_to([0x43, 0x80, 0x44], DecodeReplace, &mut buf), Ok(()));
+        assert_eq!(buf.as_slice(), "BC\ufffdD");
+        assert!(ASCII.decode_to([0x45, 0x80], DecodeStrict, &mut buf).is_err());
+        assert_eq!(buf.as_slice(), "BC\ufffdDE");
+        pool.recycle(buf);
+
+        // a fresh buffer is allocated when the pool is empty
+        let a = pool.take();
+        let b = pool.take();
+        assert_eq!(a.as_slice(), "");
+        assert_eq!(b.as_slice(), "");
+        assert_eq!(pool.len(), 0);
+    }
+
+    #[test]
+    fn test_find_safe_truncation() {
+        use all::{ISO_8859_2, UTF_8, EUC_JP, WINDOWS_31J};
+
+        let input = [0x41, 0x42, 0xa1, 0xff];
+        for i in range(0u, 6) {
+            assert_eq!(ISO_8859_2.find_safe_truncation(input, i), if i < 4 {i} else {4});
+        }
+
+        let input = [0x41, 0xe3, 0x81, 0x82, 0x42];
+        assert_eq!(UTF_8.find_safe_truncation(input, 0), 0);
+        assert_eq!(UTF_8.find_safe_truncation(input, 1), 1);
+        assert_eq!(UTF_8.find_safe_truncation(input, 2), 1);
+        assert_eq!(UTF_8.find_safe_truncation(input, 3), 1);
+        assert_eq!(UTF_8.find_safe_truncation(input, 4), 4);
+        assert_eq!(UTF_8.find_safe_truncation(input, 100), 5);
+
+        // a trail byte in the ASCII range
+        let input = [0x41, 0x93, 0x5c, 0x5c];
+        assert_eq!(WINDOWS_31J.find_safe_truncation(input, 2), 1);
+        assert_eq!(WINDOWS_31J.find_safe_truncation(input, 3), 3);
+        assert_eq!(WINDOWS_31J.find_safe_truncation(input, 4), 4);
+
+        // invalid sequences are complete
+        let input = [0x41, 0xa0, 0x93, 0xfa];
+        assert_eq!(WINDOWS_31J.find_safe_truncation(input, 2), 2);
+        assert_eq!(WINDOWS_31J.find_safe_truncation(input, 3), 2);
+
+        let input = [0x8f, 0xcb, 0xc6, 0xa4, 0xa2];
+        assert_eq!(EUC_JP.find_safe_truncation(input, 1), 0);
+        assert_eq!(EUC_JP.find_safe_truncation(input, 2), 0);
+        assert_eq!(EUC_JP.find_safe_truncation(input, 3), 3);
+        assert_eq!(EUC_JP.find_safe_truncation(input, 4), 3);
+        assert_eq!(EUC_JP.find_safe_truncation(input, 5), 5);
+    }
+
+    #[test]
+    fn test_split_at_boundaries() {
+        use all::{ISO_8859_2, UTF_8, EUC_JP, WINDOWS_31J, ISO_2022_JP, HZ};
+        use testutils;
+
+        let input = [0x41, 0x42, 0xa1, 0xff, 0x43];
+        assert_eq!(ISO_8859_2.split_at_boundaries(input, 2), vec!(2, 4, 5));
+        assert_eq!(ISO_8859_2.split_at_boundaries(input, 5), vec!(5));
+        assert_eq!(ISO_8859_2.split_at_boundaries(input, 0), vec!(5));
+        assert_eq!(ISO_8859_2.split_at_boundaries([], 2), vec!(0));
+
+        let input = [0x41, 0xe3, 0x81, 0x82, 0x42, 0xe3, 0x81, 0x84];
+        assert_eq!(UTF_8.split_at_boundaries(input, 3), vec!(1, 4, 5, 8));
+        assert_eq!(UTF_8.split_at_boundaries(input, 1), vec!(1, 4, 5, 8));
+        assert_eq!(UTF_8.split_at_boundaries(input, 5), vec!(5, 8));
+
+        // a trail byte in the ASCII range
+        let input = [0x41, 0x93, 0x5c, 0x5c, 0x93, 0x5c];
+        assert_eq!(WINDOWS_31J.split_at_boundaries(input, 2), vec!(1, 3, 4, 6));
+
+        let input = [0x8f, 0xcb, 0xc6, 0xa4, 0xa2, 0x41];
+        assert_eq!(EUC_JP.split_at_boundaries(input, 2), vec!(3, 5, 6));
+
+        // every chunk decodes independently
+        let input = EUC_JP.encode(testutils::JAPANESE_TEXT, EncodeStrict).unwrap();
+        let mut start = 0;
+        let mut decoded = String::new();
+        for &end in EUC_JP.split_at_boundaries(input.as_slice(), 7).iter() {
+            assert!(end > start);
+            decoded.push_str(EUC_JP.decode(input.slice(start, end), DecodeStrict)
+                                   .unwrap().as_slice());
+            start = end;
+        }
+        assert_eq!(decoded.as_slice(), testutils::JAPANESE_TEXT);
+
+        // stateful encodings are never split
+        let input = [0x1b, 0x24, 0x42, 0x24, 0x22, 0x24, 0x24, 0x1b, 0x28, 0x42];
+        assert!(ISO_2022_JP.is_stateful());
+        assert_eq!(ISO_2022_JP.split_at_boundaries(input, 2), vec!(10));
+        assert!(HZ.is_stateful());
+        assert_eq!(HZ.split_at_boundaries(b"~{VP~}", 2), vec!(6));
+        assert!(!EUC_JP.is_stateful());
+    }
+
+    #[test]
+    fn test_encode_with_bom() {
+        use all::{UTF_8, UTF_16LE, UTF_16BE, WINDOWS_31J};
+
+        assert_eq!(UTF_8.encode_with_bom("A", true, EncodeStrict),
+                   Ok(vec!(0xef, 0xbb, 0xbf, 0x41)));
+        assert_eq!(UTF_8.encode_with_bom("A", false, EncodeStrict), Ok(vec!(0x41)));
+        assert_eq!(UTF_16LE.encode_with_bom("A", true, EncodeStrict),
+                   Ok(vec!(0xff, 0xfe, 0x41, 0x00)));
+        assert_eq!(UTF_16BE.encode_with_bom("A", true, EncodeStrict),
+                   Ok(vec!(0xfe, 0xff, 0x00, 0x41)));
+        assert_eq!(UTF_16BE.encode_with_bom("A", false, EncodeStrict), Ok(vec!(0x00, 0x41)));
+
+        // the flag is ignored for encodings without a BOM
+        assert_eq!(WINDOWS_31J.encode_with_bom("A", true, EncodeStrict), Ok(vec!(0x41)));
+        assert_eq!(WINDOWS_31J.encode_with_bom("\ufeff", true, EncodeStrict).is_err(), true);
+    }
+
+    #[test]
+    fn test_expand_numeric_entities() {
+        assert_eq!(expand_numeric_entities("").as_slice(), "");
+        assert_eq!(expand_numeric_entities("no references").as_slice(), "no references");
+        assert_eq!(expand_numeric_entities("&#12354;&#65;").as_slice(), "\u3042A");
+        assert_eq!(expand_numeric_entities("a&#x3042;b&#X1F600;c").as_slice(),
+                   "a\u3042b\U0001f600c");
+        assert_eq!(expand_numeric_entities("&#x10FFFF;").as_slice(), "\U0010ffff");
+
+        // malformed references are left as is
+        assert_eq!(expand_numeric_entities("&#;&#x;&#12354&#xZZ;").as_slice(),
+                   "&#;&#x;&#12354&#xZZ;");
+        assert_eq!(expand_numeric_entities("&#&#65;").as_slice(), "&#A");
+        assert_eq!(expand_numeric_entities("&amp;&#65 ;").as_slice(), "&amp;&#65 ;");
+        assert_eq!(expand_numeric_entities("&#").as_slice(), "&#");
+        assert_eq!(expand_numeric_entities("&#x110000;&#99999999999;").as_slice(),
+                   "&#x110000;&#99999999999;");
+        assert_eq!(expand_numeric_entities("&#xD800;&#57343;").as_slice(), "&#xD800;&#57343;");
+    }
+
+    #[test]
+    fn test_clone_box_and_reset() {
+        use all::{EUC_JP, ISO_2022_JP};
+
+        let mut d = EUC_JP.decoder();
+        assert_feed_ok!(d, [0x41], [0xa4], "A");
+        let mut d2 = d.clone_box();
+        // each copy continues from the same pending lead byte independently
+        assert_feed_ok!(d, [0xa2], [], "\u3042");
+        assert_feed_ok!(d2, [0xa4], [], "\u3044");
+        assert_finish_ok!(d, "");
+        assert_finish_ok!(d2, "");
+
+        let mut d = EUC_JP.decoder();
+        assert_feed_ok!(d, [], [0xa4], "");
+        d.reset();
+        assert_feed_ok!(d, [0x41], [], "A");
+        assert_finish_ok!(d, "");
+
+        let mut e = ISO_2022_JP.encoder();
+        assert_feed_ok!(e, "\u3042", "", [0x1b, 0x24, 0x42, 0x24, 0x22]);
+        let mut e2 = e.clone_box();
+        assert_feed_ok!(e2, "\u3044", "", [0x24, 0x24]);
+        assert_finish_ok!(e2, [0x1b, 0x28, 0x42]);
+        e.reset();
+        assert_feed_ok!(e, "A", "", [0x41]);
+        assert_finish_ok!(e, []);
+    }
+
+    #[test]
+    fn test_decode_coalescing() {
+        use all::{ASCII, UTF_8, EUC_JP};
+
+        let input = [0x41, 0x80, 0x81, 0x82, 0x42, 0xff, 0x43];
+        assert_eq!(ASCII.decode(input, DecodeReplace),
+                   Ok("A\ufffd\ufffd\ufffdB\ufffdC".to_string()));
+        assert_eq!(ASCII.decode_coalescing(input, DecodeReplace, false),
+                   Ok("A\ufffd\ufffd\ufffdB\ufffdC".to_string()));
+        assert_eq!(ASCII.decode_coalescing(input, DecodeReplace, true),
+                   Ok("A\ufffdB\ufffdC".to_string()));
+        assert_eq!(ASCII.decode_coalescing(input, DecodeIgnore, true), Ok("ABC".to_string()));
+        assert!(ASCII.decode_coalescing(input, DecodeStrict, true).is_err());
+
+        // an invalid run continuing to the incomplete sequence at the end
+        assert_eq!(UTF_8.decode_coalescing([0x41, 0xff, 0xfe, 0xe3, 0x81], DecodeReplace, true),
+                   Ok("A\ufffd".to_string()));
+        // errors separated by valid characters are not coalesced
+        assert_eq!(UTF_8.decode_coalescing([0xff, 0x41, 0xff], DecodeReplace, true),
+                   Ok("\ufffdA\ufffd".to_string()));
+        // a backed-up byte is a part of the next sequence
+        assert_eq!(EUC_JP.decode_coalescing([0xa4, 0x41, 0xff, 0xa4, 0xa2], DecodeReplace, true),
+                   Ok("\ufffdA\ufffd\u3042".to_string()));
+    }
+
+    #[test]
+    fn test_checksum_byte_writer() {
+        use all::{UTF_8, ISO_8859_1};
+
+        let mut buf = Vec::new();
+        let (len, crc) = {
+            let mut output = ChecksumByteWriter::new(&mut buf);
+            assert!(UTF_8.encoder().feed_all("123456789", &mut output).is_none());
+            output.finish()
+        };
+        assert_eq!(buf.as_slice(), b"123456789".as_slice());
+        assert_eq!((len, crc), (9, 0xcbf43926));
+
+        // the checksum is computed over the encoded bytes
+        let mut buf = Vec::new();
+        let (len, crc) = {
+            let mut output = ChecksumByteWriter::new(&mut buf);
+            assert!(ISO_8859_1.encoder().feed_all("caf\u00e9", &mut output).is_none());
+            output.finish()
+        };
+        assert_eq!(buf, vec!(0x63, 0x61, 0x66, 0xe9));
+        assert_eq!((len, crc), (4, 0xabb3b01b));
+
+        let mut buf = Vec::new();
+        assert_eq!(ChecksumByteWriter::new(&mut buf).finish(), (0, 0));
+    }
+
+    #[test]
+    fn test_encode_chars() {
+        use all::{ASCII, ISO_2022_JP, WINDOWS_949};
+
+        let bytes: Vec<u8> = encode_chars(ASCII, "hello".chars(), EncodeStrict).collect();
+        assert_eq!(bytes, Vec::from_slice(b"hello"));
+
+        let input = "A\uac00\u00e9B";
+        let bytes: Vec<u8> = encode_chars(WINDOWS_949, input.chars(), EncodeIgnore).collect();
+        assert_eq!(bytes, vec!(0x41, 0xb0, 0xa1, 0x42));
+        let bytes: Vec<u8> = encode_chars(WINDOWS_949, input.chars(), EncodeReplace).collect();
+        assert_eq!(bytes, vec!(0x41, 0xb0, 0xa1, 0x3f, 0x42));
+        let bytes: Vec<u8> = encode_chars(ASCII, input.chars(), EncodeNcrEscape).collect();
+        assert_eq!(bytes, Vec::from_slice(b"A&#44032;&#233;B"));
+
+        // stateful encoders keep their state across characters, and finish at the end
+        let input = "A\u3042\u3044B";
+        let bytes: Vec<u8> = encode_chars(ISO_2022_JP, input.chars(), EncodeStrict).collect();
+        assert_eq!(bytes, ISO_2022_JP.encode(input, EncodeStrict).unwrap());
+
+        // the input is pulled lazily
+        let mut pulled = 0u;
+        {
+            let iter = "abc".chars().inspect(|_| pulled += 1);
+            let mut bytes = encode_chars(ASCII, iter, EncodeStrict);
+            assert_eq!(bytes.next(), Some(b'a'));
+        }
+        assert_eq!(pulled, 1);
+    }
+
+    #[test]
+    #[should_fail]
+    fn test_encode_chars_strict_fails() {
+        use all::ASCII;
+        let _: Vec<u8> = encode_chars(ASCII, "A\u00e9".chars(), EncodeStrict).collect();
+    }
+
+    #[test]
+    fn test_percent_encoding_writer() {
+        use all::{UTF_8, WINDOWS_31J};
+
+        let mut buf = Vec::new();
+        {
+            let mut output = PercentEncodingWriter::new(&mut buf);
+            assert!(UTF_8.encoder().feed_all("a b-\u65e5~", &mut output).is_none());
+        }
+        assert_eq!(buf.as_slice(), b"a%20b-%E6%97%A5~".as_slice());
+
+        let mut buf = Vec::new();
+        {
+            let mut output = PercentEncodingWriter::new(&mut buf);
+            assert!(WINDOWS_31J.encoder().feed_all("\u65e5/", &mut output).is_none());
+        }
+        assert_eq!(buf.as_slice(), b"%93%FA%2F".as_slice());
+
+        // a custom set of unescaped bytes
+        fn is_printable(b: u8) -> bool { b >= 0x20 && b < 0x7f && b != b'%' }
+        let mut buf = Vec::new();
+        {
+            let mut output = PercentEncodingWriter::with_predicate(&mut buf, is_printable);
+            assert!(UTF_8.encoder().feed_all("a b%\u00e9\n", &mut output).is_none());
+        }
+        assert_eq!(buf.as_slice(), b"a b%25%C3%A9%0A".as_slice());
+    }
+
+    #[test]
+    fn test_char_cost() {
+        use all::{ASCII, UTF_8, UTF_16LE, WINDOWS_949};
+
+        let mut e = UTF_8.encoder();
+        assert_eq!(e.char_cost('A'), Some(1));
+        assert_eq!(e.char_cost('\u00e9'), Some(2));
+        assert_eq!(e.char_cost('\uac00'), Some(3));
+        assert_eq!(e.char_cost('\U0001F600'), Some(4));
+
+        let mut e = UTF_16LE.encoder();
+        assert_eq!(e.char_cost('A'), Some(2));
+        assert_eq!(e.char_cost('\U0001F600'), Some(4));
+
+        let mut e = WINDOWS_949.encoder();
+        assert_eq!(e.char_cost('A'), Some(1));
+        assert_eq!(e.char_cost('\uac00'), Some(2));
+        assert_eq!(e.char_cost('\u00e9'), None);
+
+        let mut e = ASCII.encoder();
+        assert_eq!(e.char_cost('A'), Some(1));
+        assert_eq!(e.char_cost('\u00e9'), None);
+    }
+
+    #[test]
+    fn test_decode_replace_with() {
+        use all::{ASCII, WINDOWS_31J};
+
+        fn replace_with(s: &str) -> DecoderTrap { DecodeReplaceWith(s.to_string()) }
+
+        let input = [0x41, 0x80, 0x42, 0xff, 0xfe];
+        assert_eq!(ASCII.decode(input, replace_with("?")), Ok("A?B??".to_string()));
+        assert_eq!(ASCII.decode(input, replace_with("[?]")), Ok("A[?]B[?][?]".to_string()));
+        assert_eq!(ASCII.decode(input, replace_with("\u3013")),
+                   Ok("A\u3013B\u3013\u3013".to_string()));
+        assert_eq!(ASCII.decode(input, replace_with("")), ASCII.decode(input, DecodeIgnore));
+
+        // the replacement may be built at runtime
+        let marker = format!("<{}>", 0u);
+        assert_eq!(ASCII.decode(input, DecodeReplaceWith(marker)),
+                   Ok("A<0>B<0><0>".to_string()));
+
+        // the decoder resumes after the problematic sequence
+        assert_eq!(WINDOWS_31J.decode([0x93, 0xfa, 0x81, 0x20, 0x41], replace_with("?")),
+                   Ok("\u65e5? A".to_string()));
+        assert_eq!(WINDOWS_31J.decode([0x41, 0x93], replace_with("<>")),
+                   Ok("A<>".to_string()));
+    }
+
+    #[test]
+    fn test_encode_with_loss_count() {
+        use all::{ASCII, ISO_8859_1, ISO_2022_JP};
+
+        let input = "caf\u00e9 \u65e5\u672c \U0001f600!";
+        assert_eq!(ASCII.encode_with_loss_count(input, EncodeIgnore),
+                   Ok((Vec::from_slice(b"caf  !"), 4)));
+        assert_eq!(ASCII.encode_with_loss_count(input, EncodeReplace),
+                   Ok((Vec::from_slice(b"caf? ?? ?!"), 4)));
+        assert_eq!(ISO_8859_1.encode_with_loss_count(input, EncodeReplace),
+                   Ok((Vec::from_slice(b"caf\xe9 ?? ?!"), 3)));
+        assert_eq!(ISO_2022_JP.encode_with_loss_count("\u65e5\u672c", EncodeIgnore),
+                   Ok((vec!(0x1b, 0x24, 0x42, 0x46, 0x7c, 0x4b, 0x5c, 0x1b, 0x28, 0x42), 0)));
+        assert_eq!(ASCII.encode_with_loss_count("plain", EncodeStrict),
+                   Ok((Vec::from_slice(b"plain"), 0)));
+        assert!(ASCII.encode_with_loss_count(input, EncodeStrict).is_err());
+    }
+
+    #[test]
+    fn test_encode_truncated() {
+        use all::{ASCII, UTF_8, WINDOWS_31J, ISO_2022_JP};
+
+        let input = "\u65e5\u672c\u8a9e";
+        assert_eq!(WINDOWS_31J.encode_truncated(input, 6, EncodeStrict),
+                   Ok((vec!(0x93, 0xfa, 0x96, 0x7b, 0x8c, 0xea), false)));
+        assert_eq!(WINDOWS_31J.encode_truncated(input, 5, EncodeStrict),
+                   Ok((vec!(0x93, 0xfa, 0x96, 0x7b), true)));
+        assert_eq!(WINDOWS_31J.encode_truncated(input, 1, EncodeStrict), Ok((vec!(), true)));
+        assert_eq!(WINDOWS_31J.encode_truncated("A\u65e5", 2, EncodeStrict),
+                   Ok((vec!(0x41), true)));
+        assert_eq!(WINDOWS_31J.encode_truncated("", 0, EncodeStrict), Ok((vec!(), false)));
+
+        // the budget falls in the middle of a four-byte sequence
+        assert_eq!(UTF_8.encode_truncated("a\U0001f600b", 4, EncodeStrict),
+                   Ok((vec!(0x61), true)));
+
+        // the closing escape sequence should fit in the limit
+        assert_eq!(ISO_2022_JP.encode_truncated("\u65e5\u672c", 9, EncodeStrict),
+                   Ok((vec!(0x1b, 0x24, 0x42, 0x46, 0x7c, 0x1b, 0x28, 0x42), true)));
+        assert_eq!(ISO_2022_JP.encode_truncated("\u65e5\u672c", 10, EncodeStrict),
+                   Ok((vec!(0x1b, 0x24, 0x42, 0x46, 0x7c, 0x4b, 0x5c, 0x1b, 0x28, 0x42), false)));
+
+        // replacements count toward the limit
+        assert_eq!(ASCII.encode_truncated("a\u00e9b", 2, EncodeReplace),
+                   Ok((Vec::from_slice(b"a?"), true)));
+        assert_eq!(ASCII.encode_truncated("a\u00e9b", 2, EncodeIgnore),
+                   Ok((Vec::from_slice(b"ab"), false)));
+        assert!(ASCII.encode_truncated("a\u00e9b", 2, EncodeStrict).is_err());
+    }
+
+    #[test]
+    fn test_encode_report() {
+        use all::{ASCII, WINDOWS_31J, ISO_2022_JP};
+
+        let report = WINDOWS_31J.encode_report("A\u65e5\u672c");
+        assert_eq!(report, EncodeReport { nbytes: 5, failures: vec!() });
+        assert!(report.is_fully_representable());
+
+        let report = WINDOWS_31J.encode_report("A\uac00\u65e5\uac01\uac02B");
+        assert_eq!(report, EncodeReport { nbytes: 4,
+                                          failures: vec!((1, '\uac00'), (7, '\uac01'),
+                                                         (10, '\uac02')) });
+        assert!(!report.is_fully_representable());
+
+        // the escape sequences are counted
+        let report = ISO_2022_JP.encode_report("\u65e5\uac00");
+        assert_eq!(report, EncodeReport { nbytes: 8, failures: vec!((3, '\uac00')) });
+
+        assert_eq!(ASCII.encode_report(""), EncodeReport { nbytes: 0, failures: vec!() });
+    }
+
+    #[test]
+    fn test_decode_html_auto() {
+        use all::WINDOWS_1252;
+
+        fn check(input: &[u8], expected: &str, expected_encoding: &str) {
+            let (ret, used) = decode_html_auto(input, WINDOWS_1252 as EncodingRef);
+            assert_eq!(used.name(), expected_encoding);
+            assert_eq!(ret.as_slice(), expected);
+        }
+
+        check(b"<meta charset=\"euc-kr\">\xc7\xd1", "<meta charset=\"euc-kr\">\ud55c",
+              "windows-949");
+        check(b"<META CHARSET=Shift_JIS>\x93\xfa", "<META CHARSET=Shift_JIS>\u65e5",
+              "windows-31j");
+        check(b"<meta content='text/html; charset = \"utf-8\"'>\xc3\xa9",
+              "<meta content='text/html; charset = \"utf-8\"'>\u00e9", "utf-8");
+
+        // unknown labels are skipped, and no declaration means the fallback
+        check(b"<meta charset=bogus><meta charset=koi8-r>\xc1",
+              "<meta charset=bogus><meta charset=koi8-r>\u0430", "koi8-r");
+        check(b"<p>caf\xe9", "<p>caf\u00e9", "windows-1252");
+
+        // the declaration is only looked for in the first 1024 bytes
+        let mut input = Vec::from_elem(1024, b' ');
+        input.push_all(b"<meta charset=utf-8>");
+        let (_, used) = decode_html_auto(input.as_slice(), WINDOWS_1252 as EncodingRef);
+        assert_eq!(used.name(), "windows-1252");
+
+        // the BOM wins, and UTF-16 in the declaration means UTF-8
+        check(b"\xef\xbb\xbf<meta charset=euc-kr>", "<meta charset=euc-kr>", "utf-8");
+        check(b"<meta charset=utf-16le>\xc3\xa9", "<meta charset=utf-16le>\u00e9", "utf-8");
+
+        // x-user-defined in the declaration means windows-1252
+        check(b"<meta charset=x-user-defined>\x80", "<meta charset=x-user-defined>\u20ac",
+              "windows-1252");
+    }
+
+    #[test]
+    fn test_restartable_decoder() {
+        use all::{WINDOWS_1252, WINDOWS_949, WINDOWS_31J, GB18030};
+
+        // the declaration appears after some content
+        let mut d = RestartableDecoder::new(WINDOWS_1252 as EncodingRef, 64);
+        let mut ret = String::new();
+        d.feed(b"<title>\xc7\xd1", &mut ret);
+        d.feed(b"</title><meta charset=euc-kr>", &mut ret);
+        assert_eq!(ret.as_slice(), "");
+        assert!(!d.is_committed());
+        assert_eq!(d.buffered(), b"<title>\xc7\xd1</title><meta charset=euc-kr>".as_slice());
+        assert!(d.restart(WINDOWS_949 as EncodingRef));
+        d.commit(&mut ret);
+        assert_eq!(ret.as_slice(), "<title>\ud55c</title><meta charset=euc-kr>");
+        assert!(d.is_committed());
+        assert_eq!(d.buffered(), b"".as_slice());
+
+        // once committed, the input is decoded as it arrives and the encoding is fixed
+        d.feed(b"\xb1", &mut ret);
+        d.feed(b"\xdb", &mut ret);
+        assert_eq!(ret.as_slice(), "<title>\ud55c</title><meta charset=euc-kr>\uae00");
+        assert!(!d.restart(WINDOWS_1252 as EncodingRef));
+        assert_eq!(d.encoding().name(), "windows-949");
+        d.finish(&mut ret);
+        assert_eq!(ret.as_slice(), "<title>\ud55c</title><meta charset=euc-kr>\uae00");
+
+        // the decoder commits to the tentative encoding after the buffering cap
+        let mut d = RestartableDecoder::new(WINDOWS_1252 as EncodingRef, 4);
+        let mut ret = String::new();
+        d.feed(b"caf\xe9", &mut ret);
+        assert!(!d.is_committed());
+        d.feed(b"!", &mut ret);
+        assert!(d.is_committed());
+        assert_eq!(ret.as_slice(), "caf\u00e9!");
+        assert!(!d.restart(WINDOWS_949 as EncodingRef));
+
+        // errors are replaced, even when the problematic sequence spans multiple feeds
+        let mut d = RestartableDecoder::new(GB18030 as EncodingRef, 0);
+        let mut ret = String::new();
+        d.feed(b"A\x81\x30", &mut ret);
+        d.feed(b"\x81\x20B", &mut ret);
+        assert_eq!(ret.as_slice(), "A\ufffd0\ufffd B");
+        d.finish(&mut ret);
+        assert_eq!(ret.as_slice(), "A\ufffd0\ufffd B");
+
+        let mut d = RestartableDecoder::new(WINDOWS_31J as EncodingRef, 0);
+        let mut ret = String::new();
+        d.feed(b"\x93\xfa\x96", &mut ret);
+        d.finish(&mut ret);
+        assert_eq!(ret.as_slice(), "\u65e5\ufffd");
+    }
+
+    #[test]
+    fn test_encode_html() {
+        use all::{ASCII, WINDOWS_1252, WINDOWS_31J, ERROR};
+
+        let input = "<p>caf\u00e9 \u201c\u65e5\u672c\u201d \U0001f600</p>";
+        let (ret, used) = encode_html(WINDOWS_1252 as EncodingRef, input);
+        assert_eq!(used.name(), "windows-1252");
+        assert_eq!(ret, Vec::from_slice(b"<p>caf\xe9 \x93&#26085;&#26412;\x94 &#128512;</p>"));
+
+        let (ret, used) = encode_html(WINDOWS_31J as EncodingRef, input);
+        assert_eq!(used.name(), "windows-31j");
+        assert_eq!(ret, Vec::from_slice(b"<p>caf&#233; \x81g\x93\xfa\x96{\x81h &#128512;</p>"));
+
+        let (ret, used) = encode_html(ASCII as EncodingRef, "plain");
+        assert_eq!(used.name(), "ascii");
+        assert_eq!(ret, Vec::from_slice(b"plain"));
+
+        // falls back to UTF-8 when even the references cannot be encoded
+        let (ret, used) = encode_html(ERROR as EncodingRef, input);
+        assert_eq!(used.name(), "utf-8");
+        assert_eq!(ret, Vec::from_slice(input.as_bytes()));
+    }
+
+    #[test]
+    fn test_encode_replace_with_byte() {
+        use all::{ASCII, ISO_8859_1};
+
+        // every unrepresentable character becomes exactly one byte
+        let rows = ["Name  | Caf\u00e9 |", "\u65e5\u672c | \U0001f600ok  |"];
+        for row in rows.iter() {
+            let encoded = ASCII.encode(*row, EncodeReplaceWithByte(0x20)).unwrap();
+            assert_eq!(encoded.len(), row.char_len());
+        }
+        assert_eq!(ASCII.encode("Caf\u00e9 |", EncodeReplaceWithByte(0x20)),
+                   Ok(Vec::from_slice(b"Caf  |")));
+        assert_eq!(ISO_8859_1.encode("\u00e9\u65e5\u672c\u00e9", EncodeReplaceWithByte(b'_')),
+                   Ok(vec!(0xe9, b'_', b'_', 0xe9)));
+    }
+
+    #[test]
+    fn test_decode_ascii_fast_path() {
+        use all::{ASCII, UTF_8, WINDOWS_1252, EUC_JP, ISO_2022_JP, UTF_16LE};
+        use codec::japanese::EUCJPEncoding;
+
+        let input = b"Hello, world! \\~\x00\x7f";
+        for &encoding in [ASCII as EncodingRef, UTF_8 as EncodingRef, WINDOWS_1252 as EncodingRef,
+                          EUC_JP as EncodingRef, ISO_2022_JP as EncodingRef].iter() {
+            let mut expected = String::new();
+            assert!(encoding.decode_to(input, DecodeStrict, &mut expected).is_ok());
+            assert_eq!(encoding.decode(input, DecodeStrict), Ok(expected));
+        }
+
+        // decoders not compatible to ASCII are not affected
+        static JIS_ROMAN: EUCJPEncoding = EUCJPEncoding { jis_roman: true,
+                                                          fullwidth_katakana: false };
+        assert_eq!(JIS_ROMAN.decode(b"\\~", DecodeStrict), Ok("\u00a5\u203e".to_string()));
+        assert_eq!(UTF_16LE.decode(b"AB", DecodeStrict), Ok("\u4241".to_string()));
+        assert_eq!(ISO_2022_JP.decode(b"\x1b$B$K", DecodeStrict), Ok("\u306b".to_string()));
+    }
+
+    #[test]
+    fn test_decode_preserve_bytes() {
+        use all::{ASCII, UTF_8, WINDOWS_31J, EUC_JP, ISO_8859_8, BIG5_HKSCS, ISO_2022_JP};
+
+        fn roundtrip(encoding: EncodingRef, input: &[u8]) -> String {
+            let decoded = encoding.decode(input, DecodePreserveBytes).unwrap();
+            let preserved = PreservedBytesEncoding { base: encoding };
+            assert_eq!(preserved.encode(decoded.as_slice(), EncodeStrict),
+                       Ok(Vec::from_slice(input)));
+            decoded
+        }
+
+        assert_eq!(roundtrip(ASCII as EncodingRef, [0x41, 0x80, 0xff, 0x42]),
+                   "A\uf780\uf7ffB".to_string());
+        assert_eq!(roundtrip(UTF_8 as EncodingRef,
+                             [0xe6, 0x97, 0xa5, 0xc3, 0x28, 0xed, 0xa0, 0x80]),
+                   "\u65e5\uf7c3(\uf7ed\uf7a0\uf780".to_string());
+        assert_eq!(roundtrip(UTF_8 as EncodingRef, [0xe6, 0x97]),
+                   "\uf7e6\uf797".to_string());
+        roundtrip(WINDOWS_31J as EncodingRef, [0x93, 0xfa, 0x81, 0x20, 0xa0, 0xfd, 0x96, 0x7b]);
+        roundtrip(EUC_JP as EncodingRef, [0xa4, 0xa2, 0x8f, 0x8f, 0xa1, 0xa1, 0xff]);
+        roundtrip(ISO_8859_8 as EncodingRef, [0xe0, 0xa1, 0xff, 0x41]);
+
+        // the base encoder is finished before the preserved byte
+        assert_eq!(roundtrip(BIG5_HKSCS as EncodingRef, [0x88, 0x66, 0xff, 0x88, 0x62]),
+                   "\u00ca\uf7ff\u00ca\u0304".to_string());
+        let preserved = PreservedBytesEncoding { base: ISO_2022_JP as EncodingRef };
+        assert_eq!(preserved.encode("\u3042\uf7ffA", EncodeStrict),
+                   Ok(vec!(0x1b, 0x24, 0x42, 0x24, 0x22, 0x1b, 0x28, 0x42, 0xff, 0x41)));
+
+        // other characters are still subject to the trap
+        let preserved = PreservedBytesEncoding { base: ASCII as EncodingRef };
+        assert!(preserved.encode("\uf780\u00e9", EncodeStrict).is_err());
+        assert_eq!(preserved.encode("\uf780\u00e9", EncodeReplace), Ok(vec!(0x80, 0x3f)));
+    }
+
+    #[test]
+    fn test_decode_lines() {
+        use all::{UTF_8, UTF_16LE, WINDOWS_31J};
+
+        let lines: Vec<String> = UTF_8.decode_lines(b"ab\ncd\n\nef").collect();
+        assert_eq!(lines, vec!("ab".to_string(), "cd".to_string(), "".to_string(),
+                               "ef".to_string()));
+        let lines: Vec<String> = UTF_8.decode_lines(b"ab\r\n").collect();
+        assert_eq!(lines, vec!("ab\r".to_string()));
+        assert_eq!(UTF_8.decode_lines([]).count(), 0);
+
+        // 0A as a part of the multibyte sequence is not a newline
+        let lines: Vec<String> = UTF_16LE.decode_lines([0x41, 0x0a, 0x0a, 0x00, 0x42, 0x00])
+                                         .collect();
+        assert_eq!(lines, vec!("\u0a41".to_string(), "B".to_string()));
+
+        // errors are replaced, and an incomplete sequence at the end is an error
+        let lines: Vec<String> = WINDOWS_31J.decode_lines([0x93, 0xfa, 0x81, 0x0a, 0x41, 0x93])
+                                            .collect();
+        assert_eq!(lines, vec!("\u65e5\ufffd".to_string(), "A\ufffd".to_string()));
+
+        // the decoder state is kept across chunks
+        let mut input = Vec::new();
+        for _ in range(0u, 300) { input.push_all([0x93, 0xfa]); }
+        input.push(0x0a);
+        input.push_all([0x96, 0x7b]);
+        let lines: Vec<String> = WINDOWS_31J.decode_lines(input.as_slice()).collect();
+        assert_eq!(lines, vec!(String::from_char(300, '\u65e5'), "\u672c".to_string()));
+
+        // a line spanning several chunks, followed by a short one in the same chunk
+        let mut input = Vec::from_elem(1000, b'a');
+        input.push_all(b"\nb\nc");
+        let lines: Vec<String> = UTF_8.decode_lines(input.as_slice()).collect();
+        assert_eq!(lines, vec!(String::from_char(1000, 'a'), "b".to_string(), "c".to_string()));
+    }
+
+    #[test]
+    fn test_encode_utf16() {
+        use all::{ASCII, UTF_8, UTF_16BE};
+
+        fn upto_and_cause(r: Result<Vec<u8>,CodecError>) -> (int, String) {
+            let err = r.unwrap_err();
+            (err.upto, err.cause.as_slice().to_string())
+        }
+
+        assert_eq!(UTF_8.encode_utf16([0x41, 0xd83d, 0xde00], EncodeStrict).ok(),
+                   Some(vec!(0x41, 0xf0, 0x9f, 0x98, 0x80)));
+        assert_eq!(UTF_16BE.encode_utf16([0xd83d, 0xde00], EncodeStrict).ok(),
+                   Some(vec!(0xd8, 0x3d, 0xde, 0x00)));
+        assert_eq!(UTF_8.encode_utf16([], EncodeStrict).ok(), Some(vec!()));
+
+        // a lone high surrogate, in the middle and at the end
+        assert_eq!(upto_and_cause(UTF_8.encode_utf16([0x41, 0xd800, 0x42], EncodeStrict)),
+                   (2, "unpaired surrogate".to_string()));
+        assert_eq!(upto_and_cause(UTF_8.encode_utf16([0x41, 0xd800], EncodeStrict)),
+                   (2, "unpaired surrogate".to_string()));
+        // a reversed pair
+        assert_eq!(upto_and_cause(UTF_8.encode_utf16([0xdc00, 0xd800], EncodeStrict)),
+                   (1, "unpaired surrogate".to_string()));
+        // unpaired surrogates are rejected even with a lenient trap
+        assert_eq!(upto_and_cause(UTF_16BE.encode_utf16([0xd83d, 0x41], EncodeReplace)),
+                   (1, "unpaired surrogate".to_string()));
+
+        // unrepresentable characters go through the trap
+        assert_eq!(ASCII.encode_utf16([0x41, 0xd83d, 0xde00, 0x42], EncodeReplace).ok(),
+                   Some(vec!(0x41, 0x3f, 0x42)));
+        assert_eq!(upto_and_cause(ASCII.encode_utf16([0x41, 0xd83d, 0xde00, 0x42],
+                                                     EncodeStrict)),
+                   (3, "unrepresentable character".to_string()));
+    }
 }
@@ -15,6 +15,11 @@ pub fn as_char<T:Int+NumCast>(ch: T) -> char {
     unsafe { mem::transmute(ch.to_u32().unwrap()) }
 }
 
+/// Every script reported by `Encoding::covered_scripts`, for encodings covering all of Unicode.
+pub static UNICODE_SCRIPTS: &'static [&'static str] =
+    &["Latin", "Greek", "Cyrillic", "Hebrew", "Arabic", "Thai",
+      "Hiragana", "Katakana", "Bopomofo", "Hangul", "CJK"];
+
 /// External iterator for a string's characters with its corresponding byte offset range.
 pub struct StrCharIndexIterator<'r> {
     index: uint,
@@ -47,6 +52,30 @@ impl<'r> StrCharIndex<'r> for &'r str {
     }
 }
 
+/// `Encoder::char_cost` for encoders which hold characters back until the next character
+/// or `raw_finish`. This is the number of bytes the output grows by when a copy of the encoder
+/// is fed given character and finished, compared to a copy which is finished right away.
+/// Unlike the default, this includes any bytes `raw_finish` emits because of the character.
+pub fn buffered_char_cost(encoder: &types::Encoder, ch: char) -> Option<uint> {
+    fn finished_len(encoder: &types::Encoder, input: &str) -> Option<uint> {
+        let mut encoder = encoder.clone_box();
+        let mut output = Vec::new();
+        match encoder.raw_feed(input, &mut output) {
+            (_, None) => {}
+            (_, Some(_)) => return None,
+        }
+        match encoder.raw_finish(&mut output) {
+            None => Some(output.len()),
+            Some(_) => None,
+        }
+    }
+
+    match (finished_len(encoder, ""), finished_len(encoder, String::from_char(1, ch).as_slice())) {
+        (Some(before), Some(after)) if after >= before => Some(after - before),
+        (_, _) => None,
+    }
+}
+
 /// A helper struct for the stateful decoder DSL.
 pub struct StatefulDecoderHelper<'a, St> {
     /// The current buffer.
@@ -85,6 +114,18 @@ impl<'a, St:Default> StatefulDecoderHelper<'a, St> {
         Default::default()
     }
 
+    /// Writes two Unicode scalar values to the output,
+    /// for the sequence mapped to a base character followed by a combining character.
+    /// There is intentionally no check for `c1` and `c2`, so the caller should ensure that
+    /// they're valid.
+    /// If this is the last expr in the rules, also resets back to the initial state.
+    #[inline(always)]
+    pub fn emit_pair(&mut self, c1: u32, c2: u32) -> St {
+        self.output.write_chars([unsafe {mem::transmute::<u32, char>(c1)},
+                                 unsafe {mem::transmute::<u32, char>(c2)}]);
+        Default::default()
+    }
+
     /// Writes a Unicode string to the output.
     /// If this is the last expr in the rules, also resets back to the initial state.
     #[inline(always)]
@@ -96,8 +137,10 @@ impl<'a, St:Default> StatefulDecoderHelper<'a, St> {
     /// Issues a codec error with given message at the current position.
     /// If this is the last expr in the rules, also resets back to the initial state.
     #[inline(always)]
-    pub fn err(&mut self, msg: &'static str) -> St {
-        self.err = Some(types::CodecError { upto: self.pos as int, cause: msg.into_maybe_owned() });
+    pub fn err<S:str::IntoMaybeOwned<'static>>(&mut self, msg: S) -> St {
+        self.err = Some(types::CodecError {
+            upto: self.pos as int, cause: msg.into_maybe_owned(), buffered: 0
+        });
         Default::default()
     }
 
@@ -107,9 +150,12 @@ impl<'a, St:Default> StatefulDecoderHelper<'a, St> {
     /// This should be used to implement "prepending byte to the stream" in the Encoding spec,
     /// which corresponds to `ctx.backup_and_err(1, ...)`.
     #[inline(always)]
-    pub fn backup_and_err(&mut self, backup: uint, msg: &'static str) -> St {
+    pub fn backup_and_err<S:str::IntoMaybeOwned<'static>>(&mut self, backup: uint,
+                                                          msg: S) -> St {
         let upto = self.pos as int - backup as int;
-        self.err = Some(types::CodecError { upto: upto, cause: msg.into_maybe_owned() });
+        self.err = Some(types::CodecError {
+            upto: upto, cause: msg.into_maybe_owned(), buffered: 0
+        });
         Default::default()
     }
 }
@@ -137,7 +183,9 @@ macro_rules! stateful_decoder(
     ) => (
         $(#[$decmeta])*
         pub struct $dec {
-            st: $stmod::State
+            st: $stmod::State,
+            // the number of bytes consumed since the last initial or checkpoint state
+            buffered: uint,
         }
 
         #[allow(non_snake_case_functions)]
@@ -219,13 +267,25 @@ macro_rules! stateful_decoder(
         }
 
         impl $dec {
-            pub fn new() -> Box<Decoder> { box $dec { st: $stmod::$inist } as Box<Decoder> }
+            pub fn new() -> Box<Decoder> {
+                box $dec { st: $stmod::$inist, buffered: 0 } as Box<Decoder>
+            }
         }
 
         impl Decoder for $dec {
             fn from_self(&self) -> Box<Decoder> { $dec::new() }
+            fn clone_box(&self) -> Box<Decoder> {
+                box $dec { st: self.st, buffered: self.buffered } as Box<Decoder>
+            }
             fn is_ascii_compatible(&self) -> bool { $asciicompat }
 
+            fn pending_len(&self) -> uint {
+                match self.st {
+                    $stmod::$inist $(| $stmod::$ckst(..))* => 0,
+                    _ => self.buffered,
+                }
+            }
+
             fn raw_feed(&mut self, input: &[u8],
                         output: &mut StringWriter) -> (uint, Option<CodecError>) {
                 use self::$stmod::{start, transient};
@@ -237,6 +297,10 @@ macro_rules! stateful_decoder(
                 };
                 let mut processed = 0;
                 let mut st = self.st;
+                let was_pending = match st {
+                    $stmod::$inist $(| $stmod::$ckst(..))* => false,
+                    _ => true,
+                };
 
                 let st_ = match st {
                     $stmod::$inist => $stmod::$inist,
@@ -251,7 +315,12 @@ macro_rules! stateful_decoder(
                     (None, $stmod::$inist) $(| (None, $stmod::$ckst(..)))* =>
                         { st = st_; processed = ctx.pos; }
                     // XXX splitting the match case improves the performance somehow, but why?
-                    (None, _) => { self.st = st_; return (processed, None); }
+                    (None, _) => {
+                        let buffered = if was_pending {self.buffered} else {0};
+                        self.st = st_;
+                        self.buffered = buffered + ctx.pos - processed;
+                        return (processed, None);
+                    }
                     (Some(err), _) => { self.st = st_; return (processed, Some(err)); }
                 }
 
@@ -267,7 +336,11 @@ macro_rules! stateful_decoder(
                         (None, $stmod::$inist) $(| (None, $stmod::$ckst(..)))* =>
                             { st = st_; processed = ctx.pos; }
                         // XXX splitting the match case improves the performance somehow, but why?
-                        (None, _) => { self.st = st_; return (processed, None); }
+                        (None, _) => {
+                            self.st = st_;
+                            self.buffered = ctx.pos - processed;
+                            return (processed, None);
+                        }
                         (Some(err), _) => { self.st = st_; return (processed, Some(err)); }
                     }
                 }
@@ -281,6 +354,11 @@ macro_rules! stateful_decoder(
                 let mut ctx = ::util::StatefulDecoderHelper {
                     buf: &[], pos: 0, output: output, err: None
                 };
+                let buffered = match self.st {
+                    $stmod::$inist $(| $stmod::$ckst(..))* => 0,
+                    _ => self.buffered,
+                };
+                self.buffered = 0;
                 self.st = match ::std::mem::replace(&mut self.st, $stmod::$inist) {
                     $stmod::$inist => { let $inictx = &mut ctx; $($inifin);+ },
                     $(
@@ -290,7 +368,8 @@ macro_rules! stateful_decoder(
                         $stmod::$st(() $(, $arg)*) => { let $ctx = &mut ctx; $($fin);+ },
                     )*
                 };
-                ctx.err.take()
+                // the error at finish reports how many bytes were pending
+                ctx.err.take().map(|err| CodecError { buffered: buffered, ..err })
             }
         }
     )
@@ -328,3 +407,73 @@ macro_rules! ascii_compatible_stateful_decoder(
     )
 )
 
+#[cfg(test)]
+mod tests {
+    use types::*;
+
+    // a synthetic double-byte encoding where some sequences map to two characters,
+    // i.e. a base character followed by a combining character.
+    stateful_decoder! {
+        struct TwoCharDecoder;
+
+        module twochar;
+
+        ascii_compatible true;
+
+        internal pub fn map_two_bytes(lead: u8, trail: u8) -> (u32, u32) {
+            match (lead, trail) {
+                (0x81, 0x80) => (0x304b, 0x309a), // KA with the semi-voiced sound mark
+                (0x81, 0x81) => (0x00e6, 0x0300), // AE with the grave accent
+                (0x82, 0x80) => (0x3042, 0),
+                (_, _) => (0xffff, 0),
+            }
+        }
+
+        initial state S0(ctx) {
+            case b @ 0x00..0x7f => ctx.emit(b as u32);
+            case b @ 0x81..0x82 => S1(ctx, b);
+            case _ => ctx.err("invalid sequence");
+            final => ctx.reset();
+        }
+
+        state S1(ctx, lead: u8) {
+            case b =>
+                match map_two_bytes(lead, b) {
+                    (0xffff, _) => ctx.backup_and_err(1, "invalid sequence"),
+                    (ch, 0) => ctx.emit(ch),
+                    (ch1, ch2) => ctx.emit_pair(ch1, ch2)
+                };
+            final => ctx.err("incomplete sequence");
+        }
+    }
+
+    #[test]
+    fn test_write_chars() {
+        let mut s = String::new();
+        s.write_chars(['a', '\u0300', 'b']);
+        assert_eq!(s.as_slice(), "a\u0300b");
+    }
+
+    #[test]
+    fn test_emit_pair() {
+        let mut d = TwoCharDecoder::new();
+        let (nprocessed, err, buf) = d.test_feed([0x41, 0x81, 0x80, 0x82, 0x80, 0x81]);
+        assert_eq!(nprocessed, 5);
+        assert!(err.is_none());
+        assert_eq!(buf.as_slice(), "A\u304b\u309a\u3042");
+
+        let (nprocessed, err, buf) = d.test_feed([0x81]);
+        assert_eq!(nprocessed, 1);
+        assert!(err.is_none());
+        assert_eq!(buf.as_slice(), "\xe6\u0300");
+
+        let (nprocessed, err, buf) = d.test_feed([0x81, 0x41]);
+        assert_eq!(nprocessed, 0);
+        assert_eq!(err.map(|e| e.upto), Some(1));
+        assert_eq!(buf.as_slice(), "");
+
+        let (err, buf) = d.test_finish();
+        assert!(err.is_none());
+        assert_eq!(buf.as_slice(), "");
+    }
+}